@@ -0,0 +1,14 @@
+use lsm::db::Database;
+use lsm::resp;
+use std::sync::{Arc, Mutex};
+
+/// Serve `db.txt` over the Redis protocol: `cargo run --bin resp_server [addr]`,
+/// then e.g. `redis-cli SET name Alice`. `RUST_LOG=info` logs what the server does.
+fn main() {
+    env_logger::init();
+    let addr = std::env::args().nth(1).unwrap_or_else(|| resp::DEFAULT_ADDR.to_string());
+    let db = Database::new("./db.txt").expect("Failed to load database");
+    if let Err(e) = resp::serve(&addr, Arc::new(Mutex::new(db))) {
+        eprintln!("RESP server error: {}", e);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::storage::SSTableMeta;
+
+/// A unit of compaction work: merge the SSTables with the given `seq`s into one table at `output_level`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionTask {
+    pub inputs: Vec<usize>,
+    pub output_level: usize,
+}
+
+/// Decides when and which SSTables the `LSMTree` should merge.
+/// The tree calls `pick` after every memtable flush and keeps running the returned tasks
+/// until the strategy returns `None`.
+pub trait CompactionStrategy: Send + Sync {
+    /// Short name used in log output.
+    fn name(&self) -> &str;
+
+    /// Inspect the current SSTables and return the next merge to perform, if any.
+    fn pick(&self, sstables: &[SSTableMeta]) -> Option<CompactionTask>;
+}
+
+/// **Size-tiered compaction** (good for write-heavy workloads)
+/// All tables live at level 0. Once `min_threshold` consecutive tables of similar size exist,
+/// they are merged into one bigger table. Writes are cheap, reads may have to check many tables.
+#[derive(Debug, Clone)]
+pub struct SizeTieredCompaction {
+    pub min_threshold: usize,  // How many similar-sized tables trigger a merge
+    pub bucket_low: f64,       // A table joins a bucket if its size is >= bucket_low * average
+    pub bucket_high: f64,      // ... and <= bucket_high * average
+}
+
+impl Default for SizeTieredCompaction {
+    fn default() -> Self {
+        SizeTieredCompaction {
+            min_threshold: 4,
+            bucket_low: 0.5,
+            bucket_high: 1.5,
+        }
+    }
+}
+
+impl CompactionStrategy for SizeTieredCompaction {
+    fn name(&self) -> &str {
+        "size-tiered"
+    }
+
+    fn pick(&self, sstables: &[SSTableMeta]) -> Option<CompactionTask> {
+        let mut tables: Vec<&SSTableMeta> = sstables.iter().collect();
+        tables.sort_by_key(|t| t.seq);
+
+        // Only contiguous runs (by recency) are merged, so the merged table can take the
+        // position of its newest input without reordering data relative to other tables.
+        let mut bucket: Vec<&SSTableMeta> = Vec::new();
+        for table in tables {
+            if !bucket.is_empty() {
+                let avg = bucket.iter().map(|t| t.entries).sum::<usize>() as f64 / bucket.len() as f64;
+                let size = table.entries as f64;
+                if size < avg * self.bucket_low || size > avg * self.bucket_high {
+                    bucket.clear();
+                }
+            }
+            bucket.push(table);
+            if bucket.len() >= self.min_threshold {
+                return Some(CompactionTask {
+                    inputs: bucket.iter().map(|t| t.seq).collect(),
+                    output_level: 0,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// **Leveled compaction** (good for read-heavy workloads)
+/// Flushed tables land in level 0. When level 0 holds `level0_file_limit` tables they are merged
+/// into level 1. Every level above 0 keeps non-overlapping key ranges and may hold at most
+/// `base_level_entries * level_multiplier^(level - 1)` entries; overflowing tables are pushed down.
+/// A lookup therefore touches at most one table per level, at the cost of rewriting data more often.
+#[derive(Debug, Clone)]
+pub struct LeveledCompaction {
+    pub level0_file_limit: usize,
+    pub base_level_entries: usize,
+    pub level_multiplier: usize,
+}
+
+impl Default for LeveledCompaction {
+    fn default() -> Self {
+        LeveledCompaction {
+            level0_file_limit: 4,
+            base_level_entries: 10,
+            level_multiplier: 10,
+        }
+    }
+}
+
+impl LeveledCompaction {
+    fn level_capacity(&self, level: usize) -> usize {
+        self.base_level_entries * self.level_multiplier.pow(level as u32 - 1)
+    }
+}
+
+impl CompactionStrategy for LeveledCompaction {
+    fn name(&self) -> &str {
+        "leveled"
+    }
+
+    fn pick(&self, sstables: &[SSTableMeta]) -> Option<CompactionTask> {
+        let level0: Vec<&SSTableMeta> = sstables.iter().filter(|t| t.level == 0).collect();
+        if !level0.is_empty() && level0.len() >= self.level0_file_limit {
+            // Level 0 tables may overlap each other, so take all of them plus whatever they touch in level 1.
+            let mut inputs: Vec<usize> = level0.iter().map(|t| t.seq).collect();
+            inputs.extend(
+                sstables
+                    .iter()
+                    .filter(|t| t.level == 1 && level0.iter().any(|l0| l0.overlaps(t)))
+                    .map(|t| t.seq),
+            );
+            return Some(CompactionTask { inputs, output_level: 1 });
+        }
+
+        let max_level = sstables.iter().map(|t| t.level).max().unwrap_or(0);
+        for level in 1..=max_level {
+            let tables: Vec<&SSTableMeta> = sstables.iter().filter(|t| t.level == level).collect();
+            let total: usize = tables.iter().map(|t| t.entries).sum();
+            if total <= self.level_capacity(level) {
+                continue;
+            }
+            // Push the oldest table of the overflowing level into the next one.
+            let victim = tables.iter().min_by_key(|t| t.seq)?;
+            let mut inputs = vec![victim.seq];
+            inputs.extend(
+                sstables
+                    .iter()
+                    .filter(|t| t.level == level + 1 && victim.overlaps(t))
+                    .map(|t| t.seq),
+            );
+            return Some(CompactionTask { inputs, output_level: level + 1 });
+        }
+        None
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::fs::{self, OpenOptions};
+use std::fs::OpenOptions;
 use std::io::{BufRead, BufReader, Write};
 
 #[derive(Debug)]
@@ -16,11 +16,12 @@ impl Database {
             .read(true)
             .write(true)  // Ensure we can write to the file
             .create(true) // Create if it doesn't exist
+            .truncate(false) // Existing data is loaded below
             .open(file_path)?;
 
         let reader = BufReader::new(file);
 
-        for line in reader.lines().filter_map(Result::ok) {
+        for line in reader.lines().map_while(Result::ok) {
             let parts: Vec<&str> = line.splitn(2, ',').collect();
             if parts.len() == 2 {
                 storage.insert(parts[0].to_string(), parts[1].to_string());
@@ -48,6 +49,24 @@ impl Database {
         self.storage.remove(key).is_some()
     }
 
+    // Check whether a key is present
+    pub fn exists(&self, key: &str) -> bool {
+        self.storage.contains_key(key)
+    }
+
+    // List keys matching a glob pattern (`*` any run of characters, `?` a single character)
+    pub fn keys(&self, pattern: &str) -> Vec<String> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let mut keys: Vec<String> = self
+            .storage
+            .keys()
+            .filter(|key| glob_match(&pattern, &key.chars().collect::<Vec<_>>()))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
     // Save database to disk
     pub fn save(&self) -> Result<(), std::io::Error> {
         let mut file = OpenOptions::new().write(true).truncate(true).open(&self.file_path)?;
@@ -57,3 +76,58 @@ impl Database {
         Ok(())
     }
 }
+
+// `*` matches any run of characters and `?` any one. Only the last `*` is ever backtracked to,
+// so a match takes at most pattern x text steps, however many stars the pattern has.
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The last `*` seen, and where in the text what it matches ends so far.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        glob_match(&pattern.chars().collect::<Vec<_>>(), &text.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(matches("user:*", "user:42"));
+        assert!(matches("h?llo", "hello"));
+        assert!(matches("*a*b", "xxaxxb"));
+        assert!(matches("**", ""));
+        assert!(!matches("user:*", "session:1"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(!matches("*a*b", "xxaxxbx"));
+    }
+
+    #[test]
+    fn many_stars_match_in_polynomial_time() {
+        let text = "a".repeat(10_000);
+        assert!(!matches(&format!("{}b", "*a".repeat(50)), &text));
+    }
+}
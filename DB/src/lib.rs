@@ -0,0 +1,7 @@
+//! Storage engines: the LSM tree (memtable + WAL, SSTables on disk, pluggable compaction)
+//! and the simple key-value store with its Redis-compatible (RESP) front end.
+pub mod compaction;
+pub mod db;
+pub mod merge;
+pub mod resp;
+pub mod storage;
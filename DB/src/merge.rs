@@ -0,0 +1,57 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::iter::Peekable;
+
+/// A sorted stream of `(key, value)` pairs, e.g. a memtable range or an SSTable file.
+pub type KvIter<'a> = Box<dyn Iterator<Item = (String, String)> + 'a>;
+
+/// **K-way merge over sorted sources**
+/// Every source must yield keys in ascending order. Sources are given newest first: when the same
+/// key appears in several sources only the entry from the newest one is returned ("latest wins").
+/// The output is again sorted by key with no duplicates, so it can feed compaction or range scans.
+pub struct MergeIterator<'a> {
+    sources: Vec<Peekable<KvIter<'a>>>,
+    // Min-heap of (next key, source index); ties pop the lowest (newest) index first.
+    heap: BinaryHeap<Reverse<(String, usize)>>,
+}
+
+impl<'a> MergeIterator<'a> {
+    pub fn new(sources: Vec<KvIter<'a>>) -> Self {
+        let mut merge = MergeIterator {
+            sources: sources.into_iter().map(|s| s.peekable()).collect(),
+            heap: BinaryHeap::new(),
+        };
+        for idx in 0..merge.sources.len() {
+            merge.refill(idx);
+        }
+        merge
+    }
+
+    /// Push the next key of source `idx` (if any) onto the heap.
+    fn refill(&mut self, idx: usize) {
+        if let Some((key, _)) = self.sources[idx].peek() {
+            self.heap.push(Reverse((key.clone(), idx)));
+        }
+    }
+}
+
+impl Iterator for MergeIterator<'_> {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((key, idx)) = self.heap.pop()?;
+        let item = self.sources[idx].next();
+        self.refill(idx);
+
+        // Drop shadowed versions of the same key from older sources.
+        while let Some(Reverse((next_key, _))) = self.heap.peek() {
+            if *next_key != key {
+                break;
+            }
+            let Reverse((_, older)) = self.heap.pop().unwrap();
+            self.sources[older].next();
+            self.refill(older);
+        }
+        item
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use log::{error, info};
+
+use crate::db::Database;
+
+/// Default Redis port, so `redis-cli` works without flags.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:6379";
+
+/// Most arguments one command may have, as Redis allows.
+pub const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+
+/// Most bytes one argument may have, as Redis allows.
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// **RESP (REdis Serialization Protocol) values**
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespValue {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>), // None is the null bulk string (`$-1`)
+    Array(Vec<RespValue>),
+}
+
+impl RespValue {
+    /// Serialize the value in RESP2 wire format.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            RespValue::Simple(s) => format!("+{}\r\n", s).into_bytes(),
+            RespValue::Error(e) => format!("-{}\r\n", e).into_bytes(),
+            RespValue::Integer(n) => format!(":{}\r\n", n).into_bytes(),
+            RespValue::Bulk(None) => b"$-1\r\n".to_vec(),
+            RespValue::Bulk(Some(s)) => format!("${}\r\n{}\r\n", s.len(), s).into_bytes(),
+            RespValue::Array(items) => {
+                let mut out = format!("*{}\r\n", items.len()).into_bytes();
+                for item in items {
+                    out.extend(item.encode());
+                }
+                out
+            }
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Read one client command: either a RESP array of bulk strings (what client libraries send)
+/// or an inline command such as `GET name` typed into telnet. Returns `None` on EOF.
+pub fn read_command<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<String>>> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+    let Some(count) = line.strip_prefix('*') else {
+        return Ok(Some(line.split_whitespace().map(str::to_string).collect()));
+    };
+
+    let count: usize = count
+        .parse()
+        .ok()
+        .filter(|count| *count <= MAX_MULTIBULK_LEN)
+        .ok_or_else(|| invalid("invalid multibulk length"))?;
+    // The counts come from the client, so memory is only taken as the data arrives.
+    let mut args = Vec::new();
+    for _ in 0..count {
+        let header = read_line(reader)?.ok_or_else(|| invalid("unexpected EOF"))?;
+        let len: usize = header
+            .strip_prefix('$')
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| invalid("expected bulk string"))?;
+        if len > MAX_BULK_LEN {
+            return Err(invalid("invalid bulk length"));
+        }
+        let mut buf = Vec::new();
+        reader.by_ref().take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() < len {
+            return Err(invalid("unexpected EOF"));
+        }
+        // The CRLF after the payload.
+        reader.read_exact(&mut [0; 2])?;
+        args.push(String::from_utf8_lossy(&buf).to_string());
+    }
+    Ok(Some(args))
+}
+
+fn wrong_args(cmd: &str) -> RespValue {
+    RespValue::Error(format!("ERR wrong number of arguments for '{}' command", cmd.to_lowercase()))
+}
+
+/// Execute one command against the key-value store.
+/// Supported: PING, ECHO, GET, SET, DEL, EXISTS, KEYS (plus COMMAND so `redis-cli` can connect).
+pub fn execute(db: &mut Database, args: &[String]) -> RespValue {
+    let Some(cmd) = args.first() else {
+        return RespValue::Error("ERR empty command".to_string());
+    };
+    let args = &args[1..];
+    match cmd.to_uppercase().as_str() {
+        "PING" => match args {
+            [] => RespValue::Simple("PONG".to_string()),
+            [msg] => RespValue::Bulk(Some(msg.clone())),
+            _ => wrong_args(cmd),
+        },
+        "ECHO" => match args {
+            [msg] => RespValue::Bulk(Some(msg.clone())),
+            _ => wrong_args(cmd),
+        },
+        "GET" => match args {
+            [key] => RespValue::Bulk(db.get(key)),
+            _ => wrong_args(cmd),
+        },
+        "SET" => match args {
+            [key, value] => {
+                db.set(key, value);
+                persist(db, RespValue::Simple("OK".to_string()))
+            }
+            _ => wrong_args(cmd),
+        },
+        "DEL" if !args.is_empty() => {
+            let removed = args.iter().filter(|key| db.delete(key)).count();
+            persist(db, RespValue::Integer(removed as i64))
+        }
+        "EXISTS" if !args.is_empty() => {
+            RespValue::Integer(args.iter().filter(|key| db.exists(key)).count() as i64)
+        }
+        "KEYS" => match args {
+            [pattern] => RespValue::Array(
+                db.keys(pattern).into_iter().map(|k| RespValue::Bulk(Some(k))).collect(),
+            ),
+            _ => wrong_args(cmd),
+        },
+        "COMMAND" => RespValue::Array(Vec::new()),
+        "DEL" | "EXISTS" => wrong_args(cmd),
+        _ => RespValue::Error(format!("ERR unknown command '{}'", cmd)),
+    }
+}
+
+/// Save after a write so the data survives a restart; report a failed save as an error reply.
+fn persist(db: &Database, reply: RespValue) -> RespValue {
+    match db.save() {
+        Ok(()) => reply,
+        Err(e) => RespValue::Error(format!("ERR failed to save database: {}", e)),
+    }
+}
+
+fn handle_client(stream: TcpStream, db: Arc<Mutex<Database>>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    while let Some(args) = read_command(&mut reader)? {
+        if args.is_empty() {
+            continue;
+        }
+        if args[0].eq_ignore_ascii_case("QUIT") {
+            writer.write_all(&RespValue::Simple("OK".to_string()).encode())?;
+            break;
+        }
+        let reply = execute(&mut db.lock().unwrap(), &args);
+        writer.write_all(&reply.encode())?;
+    }
+    Ok(())
+}
+
+/// Accept Redis clients on `addr`, one thread per connection.
+pub fn serve(addr: &str, db: Arc<Mutex<Database>>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!("RESP server listening on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        // A failed accept (e.g. out of file descriptors) only loses that client.
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("Failed to accept a client: {}", e);
+                continue;
+            }
+        };
+        let db = Arc::clone(&db);
+        thread::spawn(move || {
+            if let Err(e) = handle_client(stream, db) {
+                error!("Client error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_bulk_strings() {
+        let mut input = Cursor::new(b"*2\r\n$3\r\nGET\r\n$4\r\nname\r\n".to_vec());
+        assert_eq!(read_command(&mut input).unwrap(), Some(vec!["GET".to_string(), "name".to_string()]));
+    }
+
+    #[test]
+    fn rejects_oversized_counts_without_allocating() {
+        let mut input = Cursor::new(format!("*{}\r\n", usize::MAX).into_bytes());
+        assert!(read_command(&mut input).is_err());
+        let mut input = Cursor::new(format!("*1\r\n${}\r\nabc\r\n", usize::MAX).into_bytes());
+        assert!(read_command(&mut input).is_err());
+        let mut input = Cursor::new(b"*1\r\n$1000\r\nabc\r\n".to_vec());
+        assert!(read_command(&mut input).is_err());
+    }
+}
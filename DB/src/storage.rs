@@ -1,7 +1,523 @@
 use std::collections::BTreeMap;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{Write, BufReader, BufRead, BufWriter};
+use std::sync::Arc;
+use log::{debug, info, trace, warn};
 
-struct Memtable {
+use crate::compaction::{CompactionStrategy, SizeTieredCompaction};
+use crate::merge::{KvIter, MergeIterator};
+
+/// Value written in place of a deleted key; it shadows older versions until compaction.
+pub const TOMBSTONE: &str = "__tombstone__";
+
+/// How the tree stores each line of its WAL and SSTables (`key:value`) on disk, e.g. encrypted.
+/// `decode` must undo `encode`, and an encoded line must not contain a line break.
+pub trait LineCodec: Send + Sync {
+    fn encode(&self, line: &str) -> String;
+
+    /// The line `encode` was given, or why it cannot be recovered (e.g. the wrong key).
+    fn decode(&self, line: &str) -> Result<String, String>;
+}
+
+/// Lines stored as they are; what the tree uses unless it is given another codec.
+pub struct PlainLines;
+
+impl LineCodec for PlainLines {
+    fn encode(&self, line: &str) -> String {
+        line.to_string()
+    }
+
+    fn decode(&self, line: &str) -> Result<String, String> {
+        Ok(line.to_string())
+    }
+}
+
+/// Decode a line read from `path`. A line that cannot be decoded means the files are unreadable
+/// as a whole (e.g. opened with the wrong key), so unlike a malformed line it is not skipped.
+fn decode_line(codec: &dyn LineCodec, path: &str, line: &str) -> String {
+    codec.decode(line).unwrap_or_else(|e| panic!("Cannot read {}: {}", path, e))
+}
+
+/// **Memtable (In-Memory Storage)**
+pub struct Memtable {
     data: BTreeMap<String, String>,
-}
\ No newline at end of file
+}
+
+impl Default for Memtable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memtable {
+    pub fn new() -> Self {
+        Self { data: BTreeMap::new() }
+    }
+
+    pub fn insert(&mut self, key: String, value: String) {
+        self.data.insert(key, value);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.data.get(key)
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// **Write-Ahead Log (WAL)**
+pub struct Wal {
+    file: File,
+    codec: Arc<dyn LineCodec>,
+}
+
+impl Wal {
+    pub fn new(path: &str, codec: Arc<dyn LineCodec>) -> Self {
+        debug!("Opening WAL at {}", path);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        Self { file, codec }
+    }
+
+    pub fn log(&mut self, key: &str, value: &str) {
+        trace!("Logging key {} to WAL", key);
+        writeln!(self.file, "{}", self.codec.encode(&format!("{}:{}", key, value))).unwrap();
+    }
+
+    /// Drop every logged entry once it is persisted elsewhere (e.g. after a memtable flush).
+    pub fn truncate(&mut self) {
+        debug!("Truncating WAL");
+        self.file.set_len(0).unwrap();
+    }
+
+    pub fn read_logs(path: &str, codec: &dyn LineCodec) -> Vec<(String, String)> {
+        debug!("Reading WAL at {}", path);
+        let file = File::open(path).unwrap();
+        let reader = BufReader::new(file);
+        reader.lines()
+            .map_while(Result::ok)
+            .map(|line| decode_line(codec, path, &line))
+            .filter_map(|line| {
+                let parts: Vec<&str> = line.splitn(2, ':').collect();
+                if parts.len() == 2 {
+                    Some((parts[0].to_string(), parts[1].to_string()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Metadata the LSM tree keeps about every SSTable it owns.
+/// - `seq` orders tables by recency: a higher `seq` holds newer data.
+/// - `level` is only meaningful to leveled compaction; size-tiered keeps everything at level 0.
+/// - `min_key`/`max_key` bound the keys stored in the file, used to find overlapping tables.
+#[derive(Debug, Clone)]
+pub struct SSTableMeta {
+    pub path: String,
+    pub seq: usize,
+    pub level: usize,
+    pub entries: usize,
+    pub min_key: String,
+    pub max_key: String,
+}
+
+impl SSTableMeta {
+    /// Whether the key range of this table intersects the range of `other`.
+    pub fn overlaps(&self, other: &SSTableMeta) -> bool {
+        self.min_key <= other.max_key && other.min_key <= self.max_key
+    }
+}
+
+/// **SSTables (On-Disk Storage)**
+/// Write `memtable` to the SSTable `path` and wait for it to reach the disk, so the WAL it came
+/// from can be emptied.
+fn flush_to_sstable(memtable: &Memtable, path: &str, codec: &dyn LineCodec) {
+    info!("Flushing memtable to SSTable {}", path);
+    let mut file = BufWriter::new(File::create(path).unwrap());
+    for (key, value) in &memtable.data {
+        writeln!(file, "{}", codec.encode(&format!("{}:{}", key, value))).unwrap();
+    }
+    file.into_inner().unwrap().sync_all().unwrap();
+    sync_parent_dir(path);
+}
+
+/// Wait for the directory entries of `path`'s directory (files created, renamed or removed in
+/// it) to reach the disk.
+fn sync_parent_dir(path: &str) {
+    let dir = std::path::Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty());
+    // Directories cannot be opened for syncing everywhere (e.g. on Windows); the files are synced anyway.
+    if let Ok(dir) = File::open(dir.unwrap_or(std::path::Path::new("."))) {
+        let _ = dir.sync_all();
+    }
+}
+
+fn read_sstable(path: &str, key: &str, codec: &dyn LineCodec) -> Option<String> {
+    trace!("Reading SSTable {} for key {}", path, key);
+    let file = File::open(path).ok()?;
+    let reader = BufReader::new(file);
+
+    for line in reader.lines() {
+        let line = decode_line(codec, path, &line.unwrap());
+        let mut parts = line.splitn(2, ':');
+        if let (Some(k), Some(v)) = (parts.next(), parts.next()) {
+            if k == key {
+                return Some(v.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Stream every `(key, value)` pair of an SSTable in file (sorted) order.
+fn sstable_entries(path: &str, codec: Arc<dyn LineCodec>) -> KvIter<'static> {
+    let file = File::open(path).unwrap();
+    let reader = BufReader::new(file);
+    let path = path.to_string();
+    Box::new(reader.lines().map_while(Result::ok).map(move |line| decode_line(codec.as_ref(), &path, &line)).filter_map(|line| {
+        let mut parts = line.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(k), Some(v)) => Some((k.to_string(), v.to_string())),
+            _ => None,
+        }
+    }))
+}
+
+/// Rebuild SSTable metadata from the files left in `dir` by a previous run.
+/// File names encode level and seq (`sstable_L{level}_{seq}.txt`); leftover `.tmp` files are
+/// compaction outputs never renamed into place, and inputs are only removed after the rename,
+/// so they still exist and the `.tmp` files are removed.
+fn load_sstables(dir: &str, codec: &Arc<dyn LineCodec>) -> Vec<SSTableMeta> {
+    let mut sstables = Vec::new();
+    for entry in fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        let Some(file_name) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        if file_name.ends_with(".tmp") {
+            warn!("Removing incomplete compaction output {}", path.display());
+            fs::remove_file(&path).unwrap();
+            continue;
+        }
+        let Some((level, seq)) = file_name
+            .strip_prefix("sstable_L")
+            .and_then(|rest| rest.strip_suffix(".txt"))
+            .and_then(|rest| rest.split_once('_'))
+        else {
+            continue;
+        };
+        let (Ok(level), Ok(seq)) = (level.parse::<usize>(), seq.parse::<usize>()) else {
+            continue;
+        };
+
+        let path = path.to_string_lossy().to_string();
+        let mut entries = 0;
+        let mut min_key = String::new();
+        let mut max_key = String::new();
+        for (key, _) in sstable_entries(&path, Arc::clone(codec)) {
+            if entries == 0 {
+                min_key = key.clone();
+            }
+            max_key = key;
+            entries += 1;
+        }
+        debug!("Found SSTable {} (level {}, {} entries)", path, level, entries);
+        sstables.push(SSTableMeta { path, seq, level, entries, min_key, max_key });
+    }
+    sstables
+}
+
+/// **Compaction (Merge SSTables)**
+/// Inputs are merged in the order given, so later paths win when a key appears more than once.
+/// The output is written to `<output_path>.tmp`, synced and renamed into place before any input
+/// is removed, so a crash at any point leaves either the inputs or the output (or both, which
+/// hold the same data) on disk.
+fn compact_sstables(sstable_paths: Vec<&str>, output_path: &str, codec: &Arc<dyn LineCodec>) -> (usize, String, String) {
+    info!("Compacting SSTables {:?} into {}", sstable_paths, output_path);
+    // The merge iterator wants the newest source first.
+    let sources: Vec<KvIter> = sstable_paths.iter().rev().map(|path| sstable_entries(path, Arc::clone(codec))).collect();
+
+    let mut entries = 0;
+    let mut min_key = String::new();
+    let mut max_key = String::new();
+    let tmp_path = format!("{}.tmp", output_path);
+    let mut output_file = BufWriter::new(File::create(&tmp_path).unwrap());
+    for (key, value) in MergeIterator::new(sources) {
+        writeln!(output_file, "{}", codec.encode(&format!("{}:{}", key, value))).unwrap();
+        if entries == 0 {
+            min_key = key.clone();
+        }
+        max_key = key;
+        entries += 1;
+    }
+    output_file.into_inner().unwrap().sync_all().unwrap();
+    fs::rename(&tmp_path, output_path).unwrap();
+    sync_parent_dir(output_path);
+
+    // Remove old SSTables; an input at the output's path was just replaced by it.
+    for path in sstable_paths.into_iter().filter(|path| *path != output_path) {
+        fs::remove_file(path).unwrap();
+    }
+
+    (entries, min_key, max_key)
+}
+
+/// **LSM Tree (Main Database)**
+pub struct LSMTree {
+    memtable: Memtable,
+    wal: Wal,
+    sstable_dir: String,
+    sstables: Vec<SSTableMeta>,
+    next_seq: usize,
+    threshold: usize,
+    compaction: Box<dyn CompactionStrategy>,
+    codec: Arc<dyn LineCodec>,
+}
+
+impl LSMTree {
+    /// Create an LSM tree that uses size-tiered compaction.
+    pub fn new(wal_path: &str, sstable_dir: &str, threshold: usize) -> Self {
+        Self::with_compaction_strategy(wal_path, sstable_dir, threshold, Box::new(SizeTieredCompaction::default()))
+    }
+
+    /// Create an LSM tree with a caller-chosen compaction strategy.
+    pub fn with_compaction_strategy(
+        wal_path: &str,
+        sstable_dir: &str,
+        threshold: usize,
+        compaction: Box<dyn CompactionStrategy>,
+    ) -> Self {
+        Self::with_codec(wal_path, sstable_dir, threshold, compaction, Arc::new(PlainLines))
+    }
+
+    /// Create an LSM tree that stores its WAL and SSTable lines through `codec`. Files written
+    /// with another codec cannot be read.
+    pub fn with_codec(
+        wal_path: &str,
+        sstable_dir: &str,
+        threshold: usize,
+        compaction: Box<dyn CompactionStrategy>,
+        codec: Arc<dyn LineCodec>,
+    ) -> Self {
+        info!(
+            "Opening LSM tree with WAL {}, SSTable dir {}, threshold {}, {} compaction",
+            wal_path, sstable_dir, threshold, compaction.name()
+        );
+        fs::create_dir_all(sstable_dir).unwrap(); // Ensure directory exists
+        let sstables = load_sstables(sstable_dir, &codec);
+        let next_seq = sstables.iter().map(|t| t.seq + 1).max().unwrap_or(0);
+        let wal = Wal::new(wal_path, Arc::clone(&codec));
+        let memtable = Memtable::new();
+        let mut lsm = Self {
+            memtable,
+            wal,
+            sstable_dir: sstable_dir.to_string(),
+            sstables,
+            next_seq,
+            threshold,
+            compaction,
+            codec,
+        };
+
+        // Anything still in the WAL never made it into an SSTable: replay it into the memtable.
+        let pending = Wal::read_logs(wal_path, lsm.codec.as_ref());
+        info!("Replaying {} WAL entries into memtable", pending.len());
+        for (key, value) in pending {
+            lsm.memtable.insert(key, value);
+        }
+        if lsm.memtable.size() >= lsm.threshold {
+            lsm.flush();
+            lsm.compact();
+        }
+        lsm
+    }
+
+    pub fn insert(&mut self, key: String, value: String) {
+        trace!("Inserting key {}", key);
+        self.wal.log(&key, &value);
+        self.memtable.insert(key, value);
+
+        if self.memtable.size() >= self.threshold {
+            self.flush();
+            self.compact();
+        }
+    }
+
+    /// Delete a key by logging a tombstone for it.
+    pub fn delete(&mut self, key: String) {
+        trace!("Deleting key {}", key);
+        self.insert(key, TOMBSTONE.to_string());
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        trace!("Getting key {}", key);
+        let value = match self.memtable.get(key) {
+            Some(value) => Some(value.clone()),
+            None => self
+                .tables_newest_first()
+                .into_iter()
+                .filter(|t| t.min_key.as_str() <= key && key <= t.max_key.as_str())
+                .find_map(|t| read_sstable(&t.path, key, self.codec.as_ref())),
+        };
+        value.filter(|v| v != TOMBSTONE)
+    }
+
+    /// Range scan over `[start, end)` across the memtable and every SSTable, sorted by key.
+    /// When a key exists in several places the most recent value is returned.
+    pub fn scan<'a>(&'a self, start: &'a str, end: &'a str) -> impl Iterator<Item = (String, String)> + 'a {
+        trace!("Scanning range [{}, {})", start, end);
+        let mut sources: Vec<KvIter<'a>> = vec![Box::new(
+            self.memtable
+                .data
+                .range(start.to_string()..end.to_string())
+                .map(|(k, v)| (k.clone(), v.clone())),
+        )];
+
+        for table in self.tables_newest_first() {
+            if table.max_key.as_str() < start || table.min_key.as_str() >= end {
+                continue;
+            }
+            sources.push(Box::new(
+                sstable_entries(&table.path, Arc::clone(&self.codec))
+                    .skip_while(move |(k, _)| k.as_str() < start)
+                    .take_while(move |(k, _)| k.as_str() < end),
+            ));
+        }
+        MergeIterator::new(sources).filter(|(_, v)| v != TOMBSTONE)
+    }
+
+    /// Newest data lives in the shallowest level and, within a level, the highest seq.
+    fn tables_newest_first(&self) -> Vec<&SSTableMeta> {
+        let mut tables: Vec<&SSTableMeta> = self.sstables.iter().collect();
+        tables.sort_by(|a, b| a.level.cmp(&b.level).then(b.seq.cmp(&a.seq)));
+        tables
+    }
+
+    /// SSTables currently owned by the tree.
+    pub fn sstables(&self) -> &[SSTableMeta] {
+        &self.sstables
+    }
+
+    fn sstable_path(&self, level: usize, seq: usize) -> String {
+        format!("{}/sstable_L{}_{}.txt", self.sstable_dir, level, seq)
+    }
+
+    /// Write the memtable out as a new level-0 SSTable and start a fresh memtable.
+    fn flush(&mut self) {
+        if self.memtable.size() == 0 {
+            return;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let path = self.sstable_path(0, seq);
+        flush_to_sstable(&self.memtable, &path, self.codec.as_ref());
+
+        let min_key = self.memtable.data.keys().next().cloned().unwrap_or_default();
+        let max_key = self.memtable.data.keys().next_back().cloned().unwrap_or_default();
+        self.sstables.push(SSTableMeta {
+            path,
+            seq,
+            level: 0,
+            entries: self.memtable.size(),
+            min_key,
+            max_key,
+        });
+        self.memtable = Memtable::new(); // Clear memtable after flush
+        // Flushed entries are durable in the SSTable now, so the WAL can start over.
+        self.wal.truncate();
+    }
+
+    /// Ask the compaction strategy for work and run it until there is nothing left to merge.
+    fn compact(&mut self) {
+        while let Some(task) = self.compaction.pick(&self.sstables) {
+            if task.inputs.is_empty() {
+                break;
+            }
+            let mut inputs: Vec<SSTableMeta> = self
+                .sstables
+                .iter()
+                .filter(|t| task.inputs.contains(&t.seq))
+                .cloned()
+                .collect();
+            // Merge oldest first so newer values overwrite older ones.
+            inputs.sort_by(|a, b| b.level.cmp(&a.level).then(a.seq.cmp(&b.seq)));
+
+            // The merged table is as recent as the newest data it contains.
+            let seq = inputs.iter().map(|t| t.seq).max().unwrap();
+            let path = self.sstable_path(task.output_level, seq);
+            let input_paths: Vec<&str> = inputs.iter().map(|t| t.path.as_str()).collect();
+            let (entries, min_key, max_key) = compact_sstables(input_paths, &path, &self.codec);
+
+            self.sstables.retain(|t| !task.inputs.contains(&t.seq));
+            self.sstables.push(SSTableMeta {
+                path,
+                seq,
+                level: task.output_level,
+                entries,
+                min_key,
+                max_key,
+            });
+            info!("Compaction done: {} tables merged into level {}", inputs.len(), task.output_level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("lsm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn compacted_data_survives_a_reopen() {
+        let dir = scratch_dir("compact");
+        let wal = format!("{}/wal.log", dir);
+        let sstables = format!("{}/sstables", dir);
+        {
+            let mut tree = LSMTree::new(&wal, &sstables, 4);
+            for i in 0..100 {
+                tree.insert(format!("key{:03}", i), format!("v{}", i));
+            }
+            tree.insert("key007".to_string(), "new".to_string());
+        }
+        let leftovers = fs::read_dir(&sstables).unwrap().flatten().filter(|entry| entry.path().extension().is_some_and(|ext| ext == "tmp"));
+        assert_eq!(leftovers.count(), 0);
+        let tree = LSMTree::new(&wal, &sstables, 4);
+        assert_eq!(tree.get("key007").as_deref(), Some("new"));
+        for i in (0..100).filter(|i| *i != 7) {
+            assert_eq!(tree.get(&format!("key{:03}", i)), Some(format!("v{}", i)));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn an_unfinished_compaction_output_is_dropped_and_its_inputs_kept() {
+        let dir = scratch_dir("tmp");
+        let wal = format!("{}/wal.log", dir);
+        let sstables = format!("{}/sstables", dir);
+        {
+            let mut tree = LSMTree::new(&wal, &sstables, 4);
+            for i in 0..8 {
+                tree.insert(format!("key{}", i), format!("v{}", i));
+            }
+        }
+        fs::write(format!("{}/sstable_L1_99.txt.tmp", sstables), "key0:half written\n").unwrap();
+        let tree = LSMTree::new(&wal, &sstables, 4);
+        assert!(!std::path::Path::new(&format!("{}/sstable_L1_99.txt.tmp", sstables)).exists());
+        for i in 0..8 {
+            assert_eq!(tree.get(&format!("key{}", i)), Some(format!("v{}", i)));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
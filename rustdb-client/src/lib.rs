@@ -0,0 +1,332 @@
+//! Async client for the RustDB TCP server.
+//!
+//! The server speaks a line protocol: one command per line using the REPL syntax, answered by
+//! one JSON line (`{"status":"ok","data":...}` or `{"status":"error","message":"..."}`).
+//! This crate does the framing and decoding so applications work with typed values instead.
+//!
+//! ```no_run
+//! # async fn demo() -> rustdb_client::Result<()> {
+//! use std::collections::HashMap;
+//! let mut client = rustdb_client::Client::connect("127.0.0.1:7878").await?;
+//! client.create_table("users", &["name", "age"]).await?;
+//! let row = HashMap::from([("name".to_string(), "Alice".to_string()), ("age".to_string(), "7".to_string())]);
+//! client.insert("users", "1", &row).await?;
+//! let young = client.query("users", "age < 10").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With a replica attached, each read can trade freshness for latency:
+//!
+//! ```no_run
+//! # async fn demo() -> rustdb_client::Result<()> {
+//! use rustdb_client::ReadConsistency;
+//! use std::time::Duration;
+//! let mut client = rustdb_client::Client::connect("127.0.0.1:7878").await?;
+//! client.connect_replica("127.0.0.1:7880").await?;
+//! client.update("users", "1", "age", "8").await?;
+//! let mine = client.get_with("users", "1", ReadConsistency::ReadYourWrites).await?;
+//! let recent = client.scan_with("users", ReadConsistency::BoundedStaleness(Duration::from_secs(5))).await?;
+//! # Ok(())
+//! # }
+//! ```
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Connection error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Server error: {0}")]
+    Server(String),
+    #[error("Invalid response from server: {0}")]
+    Protocol(String),
+    #[error("Invalid argument '{0}': values sent over the line protocol must not be empty or contain whitespace")]
+    InvalidArgument(String),
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// One row of a table: its id and the `column -> value` map.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Row {
+    pub row_id: String,
+    pub data: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct TableContents {
+    rows: Vec<Row>,
+}
+
+/// How fresh a read must be. Anything but `Leader` may be answered by the replica; when the
+/// replica is not fresh enough the read goes to the primary instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadConsistency {
+    /// Always read from the primary.
+    #[default]
+    Leader,
+    /// Read from the replica if it was fully caught up within the given time.
+    BoundedStaleness(Duration),
+    /// Read from the replica however far behind it is.
+    AnyReplica,
+    /// Read from the replica only once it has applied this client's own writes.
+    ReadYourWrites,
+}
+
+/// One line-protocol connection.
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    async fn open(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        let (read_half, writer) = stream.into_split();
+        Ok(Connection { reader: BufReader::new(read_half), writer })
+    }
+
+    /// Send one command line and return the `data` of a successful response.
+    async fn request(&mut self, command: &str) -> Result<Value> {
+        self.writer.write_all(format!("{}\n", command).as_bytes()).await?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Err(ClientError::Protocol("connection closed".to_string()));
+        }
+        let mut response: Value =
+            serde_json::from_str(&line).map_err(|e| ClientError::Protocol(e.to_string()))?;
+        match response["status"].as_str() {
+            Some("ok") => Ok(response["data"].take()),
+            Some("error") => Err(ClientError::Server(
+                response["message"].as_str().unwrap_or_default().to_string(),
+            )),
+            _ => Err(ClientError::Protocol(line)),
+        }
+    }
+}
+
+/// A connection to a RustDB server, optionally paired with one of its replicas.
+/// Requests on one client are sequential; writes always go to the primary.
+pub struct Client {
+    primary: Connection,
+    // The replica and whether it has the client's current database (replicas follow `main` only).
+    replica: Option<(Connection, bool)>,
+    read_consistency: ReadConsistency,
+    // LSN the primary reported after this client's last write, fetched lazily for `ReadYourWrites`.
+    written_lsn: Option<u64>,
+    wrote_since_lsn: bool,
+}
+
+/// Reject tokens the whitespace-separated protocol cannot carry.
+fn token(value: &str) -> Result<&str> {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        Err(ClientError::InvalidArgument(value.to_string()))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Reject text that would end the request line early: anything after a line break would
+/// be sent as a command of its own.
+fn single_line(value: &str) -> Result<&str> {
+    if value.trim().is_empty() || value.contains(['\r', '\n']) {
+        Err(ClientError::InvalidArgument(value.to_string()))
+    } else {
+        Ok(value)
+    }
+}
+
+fn decode<T: for<'de> Deserialize<'de>>(data: Value) -> Result<T> {
+    serde_json::from_value(data).map_err(|e| ClientError::Protocol(e.to_string()))
+}
+
+impl Client {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Client {
+            primary: Connection::open(addr).await?,
+            replica: None,
+            read_consistency: ReadConsistency::Leader,
+            written_lsn: None,
+            wrote_since_lsn: false,
+        })
+    }
+
+    /// Attach a replica of the primary that reads may use, depending on their consistency.
+    pub async fn connect_replica(&mut self, addr: impl ToSocketAddrs) -> Result<()> {
+        self.replica = Some((Connection::open(addr).await?, true));
+        Ok(())
+    }
+
+    /// Consistency used by `get`, `query`, `scan` and `tables`. Defaults to `Leader`.
+    pub fn set_read_consistency(&mut self, consistency: ReadConsistency) {
+        self.read_consistency = consistency;
+    }
+
+    /// Send a command to the primary.
+    async fn request(&mut self, command: &str) -> Result<Value> {
+        self.primary.request(command).await
+    }
+
+    /// Send a write to the primary and remember that later `ReadYourWrites` reads must see it.
+    async fn write(&mut self, command: &str) -> Result<Value> {
+        let data = self.primary.request(command).await?;
+        self.wrote_since_lsn = true;
+        Ok(data)
+    }
+
+    /// Run a read-only command with the given consistency, on the replica when it qualifies.
+    async fn read(&mut self, command: &str, consistency: ReadConsistency) -> Result<Value> {
+        if !matches!(self.replica, Some((_, true))) {
+            return self.request(command).await;
+        }
+        let mode = match consistency {
+            ReadConsistency::Leader => return self.request(command).await,
+            ReadConsistency::AnyReplica => "ANY".to_string(),
+            ReadConsistency::BoundedStaleness(bound) => format!("STALE {}", bound.as_millis()),
+            ReadConsistency::ReadYourWrites => format!("AFTER {}", self.written_lsn().await?),
+        };
+        let Some((replica, _)) = self.replica.as_mut() else {
+            return self.request(command).await;
+        };
+        match replica.request(&format!("READ {} {}", mode, command)).await {
+            Err(ClientError::Server(message)) if message.starts_with("Read consistency not met") => {
+                self.request(command).await
+            }
+            result => result,
+        }
+    }
+
+    /// LSN of this client's latest write (0 before any), asking the primary only after new writes.
+    async fn written_lsn(&mut self) -> Result<u64> {
+        if self.wrote_since_lsn {
+            let data = self.request("LSN").await?;
+            self.written_lsn = Some(decode(data)?);
+            self.wrote_since_lsn = false;
+        }
+        Ok(self.written_lsn.unwrap_or_default())
+    }
+
+    /// Create a table and add the given columns to it.
+    pub async fn create_table(&mut self, table: &str, columns: &[&str]) -> Result<()> {
+        self.write(&format!("CREATE TABLE {}", token(table)?)).await?;
+        for column in columns {
+            self.add_column(table, column).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn add_column(&mut self, table: &str, column: &str) -> Result<()> {
+        self.write(&format!("ADD COLUMN {} {}", token(table)?, token(column)?)).await?;
+        Ok(())
+    }
+
+    pub async fn tables(&mut self) -> Result<Vec<String>> {
+        let data = self.read("TABLES", self.read_consistency).await?;
+        decode(data)
+    }
+
+    /// Insert a row, or merge the values into an existing row with the same id.
+    pub async fn insert(&mut self, table: &str, row_id: &str, data: &HashMap<String, String>) -> Result<()> {
+        let mut command = format!("INSERT {} {}", token(table)?, token(row_id)?);
+        for (column, value) in data {
+            command.push_str(&format!(" {}={}", token(column)?, token(value)?));
+        }
+        self.write(&command).await?;
+        Ok(())
+    }
+
+    pub async fn update(&mut self, table: &str, row_id: &str, column: &str, value: &str) -> Result<()> {
+        self.write(&format!("UPDATE {} {} {} {}", token(table)?, token(row_id)?, token(column)?, token(value)?))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get(&mut self, table: &str, row_id: &str) -> Result<Row> {
+        self.get_with(table, row_id, self.read_consistency).await
+    }
+
+    pub async fn get_with(&mut self, table: &str, row_id: &str, consistency: ReadConsistency) -> Result<Row> {
+        let data = self.read(&format!("GET {} {}", token(table)?, token(row_id)?), consistency).await?;
+        decode(data)
+    }
+
+    pub async fn delete(&mut self, table: &str, row_id: &str) -> Result<()> {
+        self.write(&format!("DELETE {} {}", token(table)?, token(row_id)?)).await?;
+        Ok(())
+    }
+
+    /// Rows matching a `"column operator value"` condition, e.g. `"age < 10"`. A condition with a
+    /// line break in it is rejected as an invalid argument.
+    pub async fn query(&mut self, table: &str, condition: &str) -> Result<Vec<Row>> {
+        self.query_with(table, condition, self.read_consistency).await
+    }
+
+    pub async fn query_with(&mut self, table: &str, condition: &str, consistency: ReadConsistency) -> Result<Vec<Row>> {
+        let data = self.read(&format!("SEARCH {} {}", token(table)?, single_line(condition)?), consistency).await?;
+        decode(data)
+    }
+
+    /// Every row of a table, ordered by row id.
+    pub async fn scan(&mut self, table: &str) -> Result<Vec<Row>> {
+        self.scan_with(table, self.read_consistency).await
+    }
+
+    pub async fn scan_with(&mut self, table: &str, consistency: ReadConsistency) -> Result<Vec<Row>> {
+        let data = self.read(&format!("PRINT {}", token(table)?), consistency).await?;
+        Ok(decode::<TableContents>(data)?.rows)
+    }
+
+    /// Create a new database on the server.
+    pub async fn create_database(&mut self, name: &str) -> Result<()> {
+        self.request(&format!("CREATE DATABASE {}", token(name)?)).await?;
+        Ok(())
+    }
+
+    /// Switch this connection to another database. If the replica does not have it,
+    /// reads go to the primary until the client switches back.
+    pub async fn use_database(&mut self, name: &str) -> Result<()> {
+        let command = format!("USE {}", token(name)?);
+        self.request(&command).await?;
+        if let Some((replica, has_database)) = self.replica.as_mut() {
+            *has_database = replica.request(&command).await.is_ok();
+        }
+        // LSNs are per database.
+        self.written_lsn = None;
+        self.wrote_since_lsn = false;
+        Ok(())
+    }
+
+    /// Start a transaction: writes are queued until `commit` and applied all-or-nothing.
+    pub async fn begin(&mut self) -> Result<()> {
+        self.request("BEGIN").await?;
+        Ok(())
+    }
+
+    pub async fn commit(&mut self) -> Result<()> {
+        self.write("COMMIT").await?;
+        Ok(())
+    }
+
+    pub async fn rollback(&mut self) -> Result<()> {
+        self.request("ROLLBACK").await?;
+        Ok(())
+    }
+
+    /// Tell the server we are done and close the connection.
+    pub async fn close(mut self) -> Result<()> {
+        if let Some((mut replica, _)) = self.replica.take() {
+            replica.request("EXIT").await?;
+            replica.writer.shutdown().await?;
+        }
+        self.request("EXIT").await?;
+        self.primary.writer.shutdown().await?;
+        Ok(())
+    }
+}
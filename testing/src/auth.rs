@@ -0,0 +1,230 @@
+use crate::commands::db::{Database, DatabaseError, Result};
+use crate::{privileges, tokens};
+use crate::table::table::ColumnSpec;
+use crate::table::value::{self, ColumnType, Value, NULL_TEXT};
+use argon2::Argon2;
+use log::info;
+use password_hash::rand_core::OsRng;
+use password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use std::collections::{BTreeMap, HashMap};
+
+/// Table holding the user accounts: one row per user name (row_id), with the argon2 hash of the
+/// password (in PHC form, salt and parameters included), when the account was created and its
+/// roles (comma-separated; see `privileges`), and its attributes once one is set (see
+/// `set_attribute`). It lives in the `main` database and is written like any table, so it is
+/// logged, persisted and replicated; sessions refuse to read or write it directly.
+pub const USER_TABLE: &str = "__users";
+
+/// The role that may do anything, including managing accounts and roles. The first account gets
+/// it.
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Whether `name` is one of the tables the database keeps for itself. Names are compared as
+/// storage would resolve them, so `./__users` or `data/__users.csv` count too.
+pub fn is_system_table(name: &str) -> bool {
+    let file = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let name = file.split('.').next().unwrap_or(file);
+    [USER_TABLE, privileges::ROLE_TABLE, tokens::TOKEN_TABLE].iter().any(|table| name.eq_ignore_ascii_case(table))
+}
+
+/// Load the user table if it has been stored, so `required` and `check_password` see every
+/// account. Accounts made before there were roles become admins, as they could do anything.
+pub fn load(db: &mut Database) -> Result<()> {
+    if !db.check_table(USER_TABLE) && db.storage.get_mut().table_names()?.iter().any(|name| name == USER_TABLE) {
+        db.ensure_table_loaded(USER_TABLE)?;
+        if !db.get_table(USER_TABLE)?.columns.contains("roles") {
+            db.add_typed_column(USER_TABLE, "roles", ColumnSpec::default())?;
+            for name in users(db)? {
+                db.update_row(USER_TABLE, &name, "roles", ADMIN_ROLE)?;
+            }
+            db.persist_table(USER_TABLE)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether sessions must log in. With no account anyone may connect, as before; once the first
+/// one exists every session (prompt, TCP connection, script) must `LOGIN` before any other
+/// command, HTTP requests must carry credentials, and replicas must log in as an admin (see
+/// `replication`). A shard router passes its clients' logins on to the nodes (see `sharding`). A user table that is stored but not in
+/// memory (see `load`) counts as holding accounts, so a table that failed to load locks
+/// everyone out rather than letting everyone in.
+pub fn required(db: &Database) -> bool {
+    match db.tables.get(USER_TABLE) {
+        Some(table) => !table.rows.is_empty(),
+        None => db.storage.lock().stored_size(USER_TABLE).ok().flatten().is_some(),
+    }
+}
+
+/// The account names, sorted.
+pub fn users(db: &mut Database) -> Result<Vec<String>> {
+    load(db)?;
+    let mut names: Vec<String> = db.tables.get(USER_TABLE).map(|table| table.rows.keys().cloned().collect()).unwrap_or_default();
+    names.sort();
+    Ok(names)
+}
+
+/// Create an account. Names are letters, digits, `_` and `-`; passwords may be anything but empty.
+pub fn create_user(db: &mut Database, name: &str, password: &str) -> Result<()> {
+    load(db)?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(DatabaseError::InvalidUserName(name.to_string()));
+    }
+    if stored_hash(db, name).is_some() {
+        return Err(DatabaseError::UserAlreadyExists(name.to_string()));
+    }
+    if !db.check_table(USER_TABLE) {
+        db.create_table(USER_TABLE)?;
+        db.add_typed_column(USER_TABLE, "password_hash", ColumnSpec { not_null: true, ..ColumnSpec::default() })?;
+        db.add_typed_column(USER_TABLE, "created_at", ColumnSpec { column_type: ColumnType::Timestamp, ..ColumnSpec::default() })?;
+        db.add_typed_column(USER_TABLE, "roles", ColumnSpec::default())?;
+    }
+    let roles = if required(db) { NULL_TEXT } else { ADMIN_ROLE };
+    let data = HashMap::from([
+        ("password_hash".to_string(), hash(password)?),
+        ("created_at".to_string(), value::now().to_string()),
+        ("roles".to_string(), roles.to_string()),
+    ]);
+    db.insert_row(USER_TABLE, name, data)?;
+    db.persist_table(USER_TABLE)?;
+    info!(user = name; "User created");
+    Ok(())
+}
+
+/// Give an account a new password.
+pub fn set_password(db: &mut Database, name: &str, password: &str) -> Result<()> {
+    load(db)?;
+    if stored_hash(db, name).is_none() {
+        return Err(DatabaseError::UserDoesNotExist(name.to_string()));
+    }
+    db.update_row(USER_TABLE, name, "password_hash", &hash(password)?)?;
+    db.persist_table(USER_TABLE)?;
+    info!(user = name; "Password changed");
+    Ok(())
+}
+
+/// Remove an account and its tokens. Dropping the last one turns authentication off again; the
+/// last admin can only go with it.
+pub fn drop_user(db: &mut Database, name: &str) -> Result<()> {
+    load(db)?;
+    if stored_hash(db, name).is_none() {
+        return Err(DatabaseError::UserDoesNotExist(name.to_string()));
+    }
+    if users(db)?.len() > 1 {
+        check_other_admin(db, name)?;
+    }
+    tokens::revoke_all(db, name)?;
+    db.delete_row(USER_TABLE, name)?;
+    db.persist_table(USER_TABLE)?;
+    info!(user = name; "User dropped");
+    Ok(())
+}
+
+/// The roles of account `name`; none for an unknown account.
+pub fn roles(db: &Database, name: &str) -> Vec<String> {
+    match db.tables.get(USER_TABLE).and_then(|table| table.rows.get(name)?.get("roles").cloned()) {
+        Some(Value::Text(roles)) => roles.split(',').filter(|role| !role.is_empty()).map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Give account `name` the role `role` when `granted`, else take it away.
+pub fn set_role(db: &mut Database, name: &str, role: &str, granted: bool) -> Result<()> {
+    load(db)?;
+    if stored_hash(db, name).is_none() {
+        return Err(DatabaseError::UserDoesNotExist(name.to_string()));
+    }
+    let mut roles = roles(db, name);
+    if !granted && role == ADMIN_ROLE {
+        check_other_admin(db, name)?;
+    }
+    roles.retain(|held| held != role);
+    if granted {
+        roles.push(role.to_string());
+    }
+    let roles = if roles.is_empty() { NULL_TEXT.to_string() } else { roles.join(",") };
+    db.update_row(USER_TABLE, name, "roles", &roles)?;
+    db.persist_table(USER_TABLE)?;
+    match granted {
+        true => info!(role, user = name; "Role granted to user"),
+        false => info!(role, user = name; "Role revoked from user"),
+    }
+    Ok(())
+}
+
+/// The attributes set on account `name`, e.g. `{"tenant": "42"}`, which row policies refer to as
+/// `$tenant` (see `policies`).
+pub fn attributes(db: &Database, name: &str) -> BTreeMap<String, String> {
+    match db.tables.get(USER_TABLE).and_then(|table| table.rows.get(name)?.get("attributes").cloned()) {
+        Some(Value::Text(attributes)) => serde_json::from_str(&attributes).unwrap_or_default(),
+        _ => BTreeMap::new(),
+    }
+}
+
+/// `ALTER USER <name> SET <attribute> <value>`: set an attribute of the account, or remove it when
+/// `value` is `None`. Names are letters, digits and `_`; `user` is taken by the account name.
+pub fn set_attribute(db: &mut Database, name: &str, attribute: &str, value: Option<&str>) -> Result<()> {
+    load(db)?;
+    if stored_hash(db, name).is_none() {
+        return Err(DatabaseError::UserDoesNotExist(name.to_string()));
+    }
+    if attribute.is_empty() || attribute == "user" || !attribute.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(DatabaseError::InvalidAttributeName(attribute.to_string()));
+    }
+    let mut attributes = attributes(db, name);
+    match value {
+        Some(value) => attributes.insert(attribute.to_string(), value.to_string()),
+        None => attributes.remove(attribute),
+    };
+    if !db.get_table(USER_TABLE)?.columns.contains("attributes") {
+        db.add_typed_column(USER_TABLE, "attributes", ColumnSpec::default())?;
+    }
+    db.update_row(USER_TABLE, name, "attributes", &serde_json::json!(attributes).to_string())?;
+    db.persist_table(USER_TABLE)?;
+    match value {
+        Some(value) => info!(attribute, user = name, value = db.redaction.value(USER_TABLE, "attributes", &value); "Attribute set"),
+        None => info!(attribute, user = name; "Attribute removed"),
+    }
+    Ok(())
+}
+
+/// Fail if account `name` is the only admin, so that accounts are never left without one.
+fn check_other_admin(db: &Database, name: &str) -> Result<()> {
+    let admins = db.tables.get(USER_TABLE).map_or(0, |table| {
+        table.rows.keys().filter(|user| roles(db, user).iter().any(|role| role == ADMIN_ROLE)).count()
+    });
+    match admins == 1 && roles(db, name).iter().any(|role| role == ADMIN_ROLE) {
+        true => Err(DatabaseError::LastAdmin(name.to_string())),
+        false => Ok(()),
+    }
+}
+
+/// Check `password` against the account `name`. An unknown account and a wrong password fail
+/// alike, so a failed login does not tell which accounts exist.
+pub fn check_password(db: &Database, name: &str, password: &str) -> Result<()> {
+    let stored = stored_hash(db, name).ok_or(DatabaseError::AuthenticationFailed)?;
+    let parsed = PasswordHash::new(&stored).map_err(|_| DatabaseError::AuthenticationFailed)?;
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .map_err(|_| DatabaseError::AuthenticationFailed)
+}
+
+/// The password hash stored for account `name`, which changes whenever its password does.
+pub fn stored_hash(db: &Database, name: &str) -> Option<String> {
+    match db.tables.get(USER_TABLE)?.rows.get(name)?.get("password_hash")? {
+        Value::Text(hash) => Some(hash.clone()),
+        _ => None,
+    }
+}
+
+/// Hash a password with argon2id and a fresh random salt.
+fn hash(password: &str) -> Result<String> {
+    if password.is_empty() {
+        return Err(DatabaseError::InvalidPassword);
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| DatabaseError::InvalidPassword)
+}
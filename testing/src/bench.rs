@@ -0,0 +1,259 @@
+use crate::commands::db::{Database, DatabaseError, Result};
+use crate::storage::csv::CsvStorage;
+use crate::table::table::ColumnSpec;
+use crate::table::value::ColumnType;
+use log::info;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Table the workload runs against; dropped again once it is done.
+pub const BENCH_TABLE: &str = "__bench";
+
+/// Rows put in the table before the timed operations start, unless told otherwise.
+const DEFAULT_ROWS: usize = 1000;
+
+/// Scratch directories made so far by this process, so that concurrent runs get their own.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// One kind of operation in a workload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// `GET` of a random row
+    Get,
+    /// `SEARCH` on a range of one column, which scans the table
+    Search,
+    Insert,
+    /// `UPDATE` of one column of a random row
+    Update,
+    /// `DELETE` of a random row
+    Delete,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 5] = [Operation::Get, Operation::Search, Operation::Insert, Operation::Update, Operation::Delete];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Get => "get",
+            Operation::Search => "search",
+            Operation::Insert => "insert",
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+        }
+    }
+
+    /// Parse an operation name in any case.
+    pub fn parse(word: &str) -> Option<Operation> {
+        Operation::ALL.into_iter().find(|operation| word.eq_ignore_ascii_case(operation.name()))
+    }
+}
+
+/// **Benchmark workload**
+/// `operations` operations drawn at random, each kind as often as its weight in `mix` says,
+/// against a table of `rows` rows. The draws start from a fixed seed, so two runs of the same
+/// workload do the same operations and their timings can be compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workload {
+    pub operations: usize,
+    pub rows: usize,
+    pub mix: Vec<(Operation, u32)>,
+}
+
+impl Workload {
+    /// `operations` operations of the default mix: mostly reads, some writes, a few scans.
+    pub fn new(operations: usize) -> Self {
+        Workload {
+            operations,
+            rows: DEFAULT_ROWS,
+            mix: vec![(Operation::Get, 50), (Operation::Search, 5), (Operation::Insert, 20), (Operation::Update, 20), (Operation::Delete, 5)],
+        }
+    }
+
+    /// Parse `<n> [ROWS <n>] [MIX <operation>=<weight>,...]` (as in `BENCH`); operations the mix
+    /// leaves out are not run.
+    pub fn parse(args: &[&str]) -> Option<Workload> {
+        let (operations, mut rest) = args.split_first()?;
+        let mut workload = Workload::new(operations.parse().ok().filter(|n| *n > 0)?);
+        while let [keyword, value, ref tail @ ..] = *rest {
+            if keyword.eq_ignore_ascii_case("rows") {
+                workload.rows = value.parse().ok()?;
+            } else if keyword.eq_ignore_ascii_case("mix") {
+                workload.mix = value
+                    .split(',')
+                    .map(|item| {
+                        let (operation, weight) = item.split_once('=')?;
+                        Some((Operation::parse(operation)?, weight.parse().ok()?))
+                    })
+                    .collect::<Option<_>>()?;
+            } else {
+                return None;
+            }
+            rest = tail;
+        }
+        (rest.is_empty() && workload.mix.iter().any(|(_, weight)| *weight > 0)).then_some(workload)
+    }
+
+    /// Draw the next operation with random number `draw`; `None` if every weight is 0.
+    fn pick(&self, draw: u64) -> Option<Operation> {
+        let total: u64 = self.mix.iter().map(|(_, weight)| u64::from(*weight)).sum();
+        let mut point = draw.checked_rem(total)?;
+        for (operation, weight) in &self.mix {
+            if point < u64::from(*weight) {
+                return Some(*operation);
+            }
+            point -= u64::from(*weight);
+        }
+        None
+    }
+}
+
+/// What a benchmark measured: how long each operation took, by kind, and in all.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub workload: Workload,
+    pub elapsed: Duration,
+    pub latencies: HashMap<Operation, Vec<Duration>>,
+}
+
+impl BenchReport {
+    /// For the whole run and for each kind of operation: the count, operations a second, and the
+    /// 50th, 95th and 99th percentile and worst latencies in microseconds.
+    pub fn to_json(&self) -> Value {
+        let seconds = self.elapsed.as_secs_f64();
+        let mut all: Vec<Duration> = self.latencies.values().flatten().copied().collect();
+        let mut operations = Map::new();
+        for operation in Operation::ALL {
+            let Some(latencies) = self.latencies.get(&operation) else {
+                continue;
+            };
+            let mut latencies = latencies.clone();
+            let mut stats = percentiles(&mut latencies);
+            stats["count"] = json!(latencies.len());
+            stats["ops_per_sec"] = json!(per_second(latencies.len(), seconds));
+            operations.insert(operation.name().to_string(), stats);
+        }
+        let mut total = percentiles(&mut all);
+        total["count"] = json!(all.len());
+        total["ops_per_sec"] = json!(per_second(all.len(), seconds));
+        json!({
+            "operations": self.workload.operations,
+            "rows": self.workload.rows,
+            "seconds": seconds,
+            "total": total,
+            "by_operation": operations,
+        })
+    }
+}
+
+fn per_second(count: usize, seconds: f64) -> f64 {
+    if seconds > 0.0 { (count as f64 / seconds).round() } else { 0.0 }
+}
+
+/// The 50th, 95th and 99th percentile and the largest of `latencies`, in microseconds.
+fn percentiles(latencies: &mut [Duration]) -> Value {
+    latencies.sort();
+    let at = |percent: usize| match latencies.len() {
+        0 => 0,
+        len => latencies[(len - 1) * percent / 100].as_micros(),
+    };
+    json!({ "p50_micros": at(50), "p95_micros": at(95), "p99_micros": at(99), "max_micros": at(100) })
+}
+
+/// Run `workload` against a table `BENCH_TABLE` of `db`, dropped again afterwards. Every
+/// operation goes through the regular write methods, so it is logged to the WAL and persisted
+/// like any other write; `run_scratch` keeps that away from a database in use.
+pub fn run(db: &mut Database, workload: &Workload) -> Result<BenchReport> {
+    if db.check_table(BENCH_TABLE) || db.storage.get_mut().table_names()?.iter().any(|name| name == BENCH_TABLE) {
+        return Err(DatabaseError::TableAlreadyExists(BENCH_TABLE.to_string()));
+    }
+    db.create_table(BENCH_TABLE)?;
+    let report = fill_and_run(db, workload);
+    db.drop_table(BENCH_TABLE)?;
+    report
+}
+
+fn fill_and_run(db: &mut Database, workload: &Workload) -> Result<BenchReport> {
+    for (column, column_type) in [("n", ColumnType::Int), ("name", ColumnType::Text), ("score", ColumnType::Float)] {
+        db.add_typed_column(BENCH_TABLE, column, ColumnSpec { column_type, ..ColumnSpec::default() })?;
+    }
+    let row = |n: usize| {
+        HashMap::from([
+            ("n".to_string(), n.to_string()),
+            ("name".to_string(), format!("row {}", n)),
+            ("score".to_string(), format!("{}.5", n % 100)),
+        ])
+    };
+    let mut live: Vec<String> = Vec::new();
+    for n in 0..workload.rows {
+        live.push(format!("r{}", n));
+        db.insert_row(BENCH_TABLE, &live[n], row(n))?;
+    }
+
+    // xorshift from a fixed seed: the same workload draws the same operations every run.
+    let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut draw = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    let mut next = workload.rows;
+    let mut latencies: HashMap<Operation, Vec<Duration>> = HashMap::new();
+    let started = Instant::now();
+    for _ in 0..workload.operations {
+        let Some(mut operation) = workload.pick(draw()) else {
+            break;
+        };
+        // Reads and changes of a row need one; with none left, insert instead.
+        if live.is_empty() && operation != Operation::Search {
+            operation = Operation::Insert;
+        }
+        let index = (draw() % live.len().max(1) as u64) as usize;
+        let at = Instant::now();
+        match operation {
+            Operation::Get => {
+                db.get_row(BENCH_TABLE, &live[index])?;
+            }
+            Operation::Search => {
+                db.search_rows_by_condition_in_table(BENCH_TABLE, &format!("score >= {}", draw() % 100))?;
+            }
+            Operation::Insert => {
+                let row_id = format!("r{}", next);
+                db.insert_row(BENCH_TABLE, &row_id, row(next))?;
+                live.push(row_id);
+                next += 1;
+            }
+            Operation::Update => {
+                db.update_row(BENCH_TABLE, &live[index], "score", &format!("{}.25", draw() % 100))?;
+            }
+            Operation::Delete => {
+                db.delete_row(BENCH_TABLE, &live.swap_remove(index))?;
+            }
+        }
+        latencies.entry(operation).or_default().push(at.elapsed());
+    }
+    let elapsed = started.elapsed();
+    info!(operations = workload.operations, rows = workload.rows, millis = elapsed.as_millis(); "Benchmark finished");
+    Ok(BenchReport { workload: workload.clone(), elapsed, latencies })
+}
+
+/// Run `workload` (see `run`) against a new database in a scratch directory under the system's
+/// temporary directory, which is removed afterwards: the tables, WAL and lock of the databases
+/// in use are left alone, and the timings are those of the CSV storage engine.
+pub fn run_scratch(workload: &Workload) -> Result<BenchReport> {
+    let dir = std::env::temp_dir().join(format!("rustdb-bench-{}-{}", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed)));
+    let report = scratch_database(&dir).and_then(|mut db| run(&mut db, workload));
+    let _ = std::fs::remove_dir_all(&dir);
+    report
+}
+
+fn scratch_database(dir: &Path) -> Result<Database> {
+    let dir = dir.to_string_lossy();
+    let mut db = Database::with_storage(Box::new(CsvStorage::in_dir(&dir)?));
+    db.wal_file = format!("{}/wal.log", dir);
+    db.wal_archive_file = format!("{}/wal_archive.log", dir);
+    Ok(db)
+}
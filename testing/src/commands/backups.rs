@@ -0,0 +1,134 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use log::info;
+use serde_json::Value;
+use crate::storage::backup;
+use crate::table::value;
+use super::db::{Database, DatabaseError, Result};
+
+/// Full backups a schedule keeps when not told otherwise.
+pub const DEFAULT_KEEP: usize = 7;
+
+/// When `WalEngine` backs its database up by itself: a full backup every `full_every`, and in
+/// between, if asked, an incremental one every `incremental_every` holding the WAL entries since
+/// the backup before it. Each backup is a new subdirectory of `dir` named after when it was taken
+/// (`20261016T030000Z-full`, `...-incremental`), and only the last `keep` full backups are kept,
+/// with the incremental ones that follow them. `dir` should hold nothing else.
+#[derive(Debug, Clone)]
+pub struct BackupSchedule {
+    dir: String,
+    full_every: Duration,
+    incremental_every: Option<Duration>,
+    keep: usize,
+}
+
+/// What `BackupSchedule::due` finds is to be done.
+#[derive(Debug)]
+enum Due {
+    Full,
+    /// The WAL entries after this LSN, where the last backup stands
+    Incremental(u64),
+}
+
+impl BackupSchedule {
+    pub fn new(dir: &str, full_every: Duration) -> Self {
+        BackupSchedule { dir: dir.to_string(), full_every, incremental_every: None, keep: DEFAULT_KEEP }
+    }
+
+    /// Take an incremental backup every `every` between the full ones.
+    pub fn with_incrementals(mut self, every: Duration) -> Self {
+        self.incremental_every = Some(every);
+        self
+    }
+
+    /// Keep the last `keep` full backups (at least one) and prune older ones.
+    pub fn keeping(mut self, keep: usize) -> Self {
+        self.keep = keep.max(1);
+        self
+    }
+
+    /// Parse an interval such as `90`, `30s`, `15m`, `1h` or `1d` (a bare number is seconds).
+    pub fn parse_interval(text: &str) -> Option<Duration> {
+        let text = text.trim();
+        let (number, unit) = match text.char_indices().last()? {
+            (at, unit) if unit.is_ascii_alphabetic() => (&text[..at], unit.to_ascii_lowercase()),
+            _ => (text, 's'),
+        };
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => 86_400,
+            _ => return None,
+        };
+        let number: u64 = number.parse().ok().filter(|number| *number > 0)?;
+        Some(Duration::from_secs(number.checked_mul(seconds)?))
+    }
+
+    /// Take the backup that is due, if one is, and prune what falls out of retention. Blocks
+    /// while the backup is written; the database is only locked to copy it out (see
+    /// `Database::snapshot` and `Database::wal_since`). Returns the new backup's directory.
+    pub fn run_due(&self, db: &Arc<RwLock<Database>>) -> Result<Option<String>> {
+        let now = value::now();
+        let due = match self.due(&backup::list(&self.dir), db.read().unwrap().current_lsn(), now) {
+            Some(due) => due,
+            None => return Ok(None),
+        };
+        std::fs::create_dir_all(&self.dir).map_err(|e| DatabaseError::FileCreationError(self.dir.clone(), e.to_string()))?;
+        // 2026-10-16T03:00:00Z becomes 20261016T030000Z, which sorts and is a valid file name anywhere.
+        let stamp: String = value::Value::Timestamp(now).to_string().chars().filter(|c| !matches!(c, '-' | ':')).collect();
+        let (path, lsn) = match due {
+            Due::Full => {
+                let path = format!("{}/{}-full", self.dir, stamp);
+                let snapshot = db.write().unwrap().snapshot()?;
+                snapshot.write(&path)?;
+                (path, snapshot.lsn())
+            }
+            Due::Incremental(since) => {
+                let path = format!("{}/{}-incremental", self.dir, stamp);
+                let increment = db.read().unwrap().wal_since(since);
+                increment.write(&path)?;
+                (path, increment.lsn())
+            }
+        };
+        info!("Scheduled backup written to '{}' (WAL at LSN {}).", path, lsn);
+        self.prune()?;
+        Ok(Some(path))
+    }
+
+    /// The backup due at `now` for a database at `lsn`, given the `backups` already taken: a
+    /// full one if there is none yet, the last one is older than `full_every`, or the database is
+    /// behind the last backup (it is not the database that was backed up); else an incremental
+    /// one if they are asked for, the last backup is older than `incremental_every` and something
+    /// was logged since.
+    fn due(&self, backups: &[(String, Value)], lsn: u64, now: i64) -> Option<Due> {
+        let age = |manifest: &Value| Duration::from_secs(now.saturating_sub(manifest["created_at"].as_i64().unwrap_or(0)).max(0) as u64);
+        let Some((_, full)) = backups.iter().rev().find(|(_, manifest)| manifest["kind"] != "incremental") else {
+            return Some(Due::Full);
+        };
+        let (_, latest) = backups.last()?;
+        let since = latest["wal_lsn"].as_u64().unwrap_or(0);
+        if age(full) >= self.full_every || since > lsn {
+            return Some(Due::Full);
+        }
+        match self.incremental_every {
+            Some(every) if age(latest) >= every && lsn > since => Some(Due::Incremental(since)),
+            _ => None,
+        }
+    }
+
+    /// Remove the backups older than the last `keep` full ones; the incremental backups after a
+    /// full one are kept as long as it is, since they only make sense on top of it.
+    fn prune(&self) -> Result<()> {
+        let backups = backup::list(&self.dir);
+        let fulls: Vec<usize> = backups.iter().enumerate().filter(|(_, (_, manifest))| manifest["kind"] != "incremental").map(|(at, _)| at).collect();
+        let Some(oldest_kept) = fulls.len().checked_sub(self.keep).map(|first| fulls[first]) else {
+            return Ok(());
+        };
+        for (path, _) in &backups[..oldest_kept] {
+            std::fs::remove_dir_all(path).map_err(|e| DatabaseError::StorageError(path.clone(), e.to_string()))?;
+            info!("Pruned backup '{}'.", path);
+        }
+        Ok(())
+    }
+}
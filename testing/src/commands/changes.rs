@@ -0,0 +1,206 @@
+use crate::table::table::ColumnSpec;
+use crate::table::value::ColumnType;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// What a committed WAL entry did to its table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    CreateTable,
+    RenameTable { new_name: String },
+    DropTable,
+    CopyTable { new_name: String, condition: Option<String> },
+    AddColumn { column: String, spec: ColumnSpec },
+    DropColumn { column: String },
+    RenameColumn { column: String, new_name: String },
+    AlterColumn { column: String, column_type: ColumnType },
+    Insert { row_id: String, data: HashMap<String, String> },
+    Upsert { row_id: String, data: HashMap<String, String> },
+    Replace { row_id: String, data: HashMap<String, String> },
+    Update { row_id: String, column: String, value: String },
+    UpdateMulti { row_id: String, values: HashMap<String, String> },
+    Delete { row_id: String },
+    Truncate,
+}
+
+/// One committed change, as delivered to `Database::subscribe_changes` subscribers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+    pub lsn: u64,
+    pub table: String,
+    pub kind: ChangeKind,
+}
+
+impl ChangeKind {
+    /// Parse a WAL entry (`insert_row:<table>:<row_id>:<json>` etc.) into its table and change.
+    /// Only the last field may contain ':', so row JSON and values survive intact.
+    pub fn parse(entry: &str) -> Option<(String, ChangeKind)> {
+        let (op, args) = entry.split_once(':')?;
+        let parts: Vec<&str> = match op {
+            "create_table" | "drop_table" | "truncate_table" => vec![args],
+            "rename_table" | "add_column" | "drop_column" | "delete_row" => args.splitn(2, ':').collect(),
+            "insert_row" | "upsert_row" | "replace_row" | "update_row_multi" | "rename_column" | "alter_column" | "copy_table" => args.splitn(3, ':').collect(),
+            "update_row" => args.splitn(4, ':').collect(),
+            _ => return None,
+        };
+        let kind = match (op, &parts[..]) {
+            ("create_table", [_]) => ChangeKind::CreateTable,
+            ("drop_table", [_]) => ChangeKind::DropTable,
+            ("copy_table", [_, new_name, condition @ ..]) => ChangeKind::CopyTable {
+                new_name: new_name.to_string(),
+                condition: condition.first().map(|c| c.to_string()),
+            },
+            ("rename_table", [_, new_name]) => ChangeKind::RenameTable { new_name: new_name.to_string() },
+            ("add_column", [_, declaration]) => {
+                let (column, spec) = ColumnSpec::parse_declaration(declaration)?;
+                ChangeKind::AddColumn { column: column.to_string(), spec }
+            }
+            ("drop_column", [_, column]) => ChangeKind::DropColumn { column: column.to_string() },
+            ("rename_column", [_, column, new_name]) => ChangeKind::RenameColumn {
+                column: column.to_string(),
+                new_name: new_name.to_string(),
+            },
+            ("alter_column", [_, column, column_type]) => ChangeKind::AlterColumn {
+                column: column.to_string(),
+                column_type: ColumnType::parse(column_type)?,
+            },
+            ("insert_row", [_, row_id, data]) => ChangeKind::Insert {
+                row_id: row_id.to_string(),
+                data: serde_json::from_str(data).ok()?,
+            },
+            ("upsert_row", [_, row_id, data]) => ChangeKind::Upsert {
+                row_id: row_id.to_string(),
+                data: serde_json::from_str(data).ok()?,
+            },
+            ("replace_row", [_, row_id, data]) => ChangeKind::Replace {
+                row_id: row_id.to_string(),
+                data: serde_json::from_str(data).ok()?,
+            },
+            ("update_row", [_, row_id, column, value]) => ChangeKind::Update {
+                row_id: row_id.to_string(),
+                column: column.to_string(),
+                value: serde_json::from_str(value).ok()?,
+            },
+            ("update_row_multi", [_, row_id, values]) => ChangeKind::UpdateMulti {
+                row_id: row_id.to_string(),
+                values: serde_json::from_str(values).ok()?,
+            },
+            ("delete_row", [_, row_id]) => ChangeKind::Delete { row_id: row_id.to_string() },
+            ("truncate_table", [_]) => ChangeKind::Truncate,
+            _ => return None,
+        };
+        Some((parts[0].to_string(), kind))
+    }
+}
+
+impl ChangeEvent {
+    /// Encode the event as `{"lsn":..,"table":..,"op":..}` plus the fields of its kind.
+    pub fn to_json(&self) -> Value {
+        let mut event = match &self.kind {
+            ChangeKind::CreateTable => json!({ "op": "create_table" }),
+            ChangeKind::RenameTable { new_name } => json!({ "op": "rename_table", "new_name": new_name }),
+            ChangeKind::DropTable => json!({ "op": "drop_table" }),
+            ChangeKind::CopyTable { new_name, condition } => {
+                json!({ "op": "copy_table", "new_name": new_name, "condition": condition })
+            }
+            ChangeKind::AddColumn { column, spec } => json!({
+                "op": "add_column",
+                "column": column,
+                "type": spec.column_type.name(),
+                "not_null": spec.not_null,
+                "primary_key": spec.primary_key,
+                "default": spec.default,
+            }),
+            ChangeKind::DropColumn { column } => json!({ "op": "drop_column", "column": column }),
+            ChangeKind::RenameColumn { column, new_name } => {
+                json!({ "op": "rename_column", "column": column, "new_name": new_name })
+            }
+            ChangeKind::AlterColumn { column, column_type } => {
+                json!({ "op": "alter_column", "column": column, "type": column_type.name() })
+            }
+            ChangeKind::Insert { row_id, data } => json!({ "op": "insert", "row_id": row_id, "data": data }),
+            ChangeKind::Upsert { row_id, data } => json!({ "op": "upsert", "row_id": row_id, "data": data }),
+            ChangeKind::Replace { row_id, data } => json!({ "op": "replace", "row_id": row_id, "data": data }),
+            ChangeKind::Update { row_id, column, value } => {
+                json!({ "op": "update", "row_id": row_id, "column": column, "value": value })
+            }
+            ChangeKind::UpdateMulti { row_id, values } => {
+                json!({ "op": "update_multi", "row_id": row_id, "values": values })
+            }
+            ChangeKind::Delete { row_id } => json!({ "op": "delete", "row_id": row_id }),
+            ChangeKind::Truncate => json!({ "op": "truncate" }),
+        };
+        event["lsn"] = json!(self.lsn);
+        event["table"] = json!(self.table);
+        event
+    }
+}
+
+/// Callback run for each committed change to a table (see `Database::on_change`).
+pub type ChangeHook = Box<dyn Fn(&ChangeEvent) + Send + Sync>;
+
+/// Subscribers waiting for committed changes, each interested in one table, and the hooks run
+/// for them, each for one table or "*" for every table. Subscribers whose receiver was dropped
+/// are forgotten at the next subscribe or publish, whatever table it is for.
+#[derive(Default)]
+pub struct ChangeFeed {
+    subscribers: Vec<(String, UnboundedSender<ChangeEvent>)>,
+    pub(super) hooks: Vec<(String, ChangeHook)>,
+}
+
+impl ChangeFeed {
+    pub fn subscribe(&mut self, table: &str) -> UnboundedReceiver<ChangeEvent> {
+        self.prune();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers.push((table.to_string(), sender));
+        receiver
+    }
+
+    /// Deliver committed WAL entries, the first of which has LSN `first_lsn`, to the subscribers
+    /// and hooks of their tables.
+    pub fn publish(&mut self, first_lsn: u64, entries: &[String]) {
+        self.prune();
+        if self.subscribers.is_empty() && self.hooks.is_empty() {
+            return;
+        }
+        for (offset, entry) in entries.iter().enumerate() {
+            let Some((table, kind)) = ChangeKind::parse(entry) else {
+                continue;
+            };
+            let event = ChangeEvent { lsn: first_lsn + offset as u64, table, kind };
+            for (wanted, hook) in &self.hooks {
+                if *wanted == event.table || wanted == "*" {
+                    hook(&event);
+                }
+            }
+            self.subscribers
+                .retain(|(wanted, sender)| *wanted != event.table || sender.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Forget the subscribers whose receiver was dropped.
+    fn prune(&mut self) {
+        self.subscribers.retain(|(_, sender)| !sender.is_closed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_subscribers_are_forgotten_whatever_their_table() {
+        let mut feed = ChangeFeed::default();
+        let quiet = feed.subscribe("quiet");
+        let mut busy = feed.subscribe("busy");
+        drop(quiet);
+        feed.publish(1, &["create_table:busy".to_string()]);
+        assert_eq!(feed.subscribers.len(), 1);
+        assert_eq!(busy.try_recv().map(|event| event.table), Ok("busy".to_string()));
+
+        drop(feed.subscribe("quiet"));
+        let _other = feed.subscribe("other");
+        assert_eq!(feed.subscribers.len(), 2);
+    }
+}
@@ -1,12 +1,38 @@
-//// filepath: c:\Users\srija\Documents\GitHub\Rust_DB\testing\src\commands\db.rs
-use crate::table::table::Table;
-use std::collections::HashMap;
+use super::changes::{ChangeEvent, ChangeFeed, ChangeKind};
+use super::redaction::Redaction;
+use super::walengine::CycleMetrics;
+use crate::storage::csv;
+use crate::storage::encryption;
+use crate::storage::archive::{self, WalPosition};
+use crate::storage::backup::{self, Increment, Snapshot};
+use crate::storage::progress::{self, Tracker};
+use crate::storage::verify::{self, Issue};
+use crate::storage::wal_segments::{self, Segment};
+use crate::storage::{self, json, StorageEngine};
+use crate::table::merge::{self, Conflict, Resolution};
+use crate::table::pattern::TextPattern;
+use crate::table::table::{self, ColumnSpec, Conversion, Row, Table, DELETED_COLUMN, EXPIRES_COLUMN};
+use crate::table::rows::MAX_ROW_SHARDS;
+use crate::table::tables::{TableRef, TableWriteRef, Tables};
+use crate::table::value::{self, ColumnType, Value, NULL_TEXT};
+use crate::statistics;
+use crate::views::{self, View};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+use parking_lot::Mutex;
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::fs::File;
-use std::io::{Write, BufWriter, BufRead, BufReader};
-use std::fs;
+use std::io::{Write, BufWriter, BufRead};
 use thiserror::Error;
-use log::{info, error};
-use serde_json;
+use log::{debug, error, info, warn};
+use tracing::field::Empty;
+use tracing::instrument;
 use std::fs::OpenOptions;
 
 #[derive(Error, Debug)]
@@ -19,28 +45,377 @@ pub enum DatabaseError {
     RowDoesNotExist(String, String),
     #[error("Row '{0}' not found in table '{1}'.")]
     RowNotFound(String, String),
+    #[error("Row '{0}' already exists in table '{1}'.")]
+    RowAlreadyExists(String, String),
+    #[error("Row '{0}' in table '{1}' is not deleted.")]
+    RowNotDeleted(String, String),
     #[error("Error creating file '{0}': {1}")]
     FileCreationError(String, String),
+    #[error("Storage error for table '{0}': {1}")]
+    StorageError(String, String),
+    #[error("Column '{0}' does not exist in table '{1}'.")]
+    ColumnDoesNotExist(String, String),
+    #[error("Column '{0}' already exists in table '{1}'.")]
+    ColumnAlreadyExists(String, String),
+    #[error("Invalid condition '{0}'.")]
+    InvalidCondition(String),
+    #[error("Database '{0}' already exists.")]
+    DatabaseAlreadyExists(String),
+    #[error("Database '{0}' does not exist.")]
+    DatabaseDoesNotExist(String),
+    #[error("Invalid database name '{0}': use letters, digits, '_' or '-'.")]
+    InvalidDatabaseName(String),
+    #[error("Invalid table name '{0}': use letters, digits, '_' or '-'.")]
+    InvalidTableName(String),
+    #[error("Transaction error: {0}")]
+    TransactionError(String),
+    #[error("Malformed WAL entry '{0}'.")]
+    InvalidWalEntry(String),
+    #[error("This database is a read-only replica; send writes to the primary.")]
+    ReadOnlyReplica,
+    #[error("Invalid read consistency '{0}': use LEADER, ANY, STALE <ms> or AFTER <lsn>.")]
+    InvalidConsistency(String),
+    #[error("Read consistency not met: {0}.")]
+    ConsistencyNotMet(String),
+    #[error("Invalid value '{0}' for column '{1}' of type {2}.")]
+    InvalidValue(String, String, ColumnType),
+    #[error("Unknown column type '{0}': use int, float, bool, text or timestamp.")]
+    UnknownColumnType(String),
+    #[error("Unknown dot-command '{0}'; .help lists them.")]
+    UnknownDotCommand(String),
+    #[error("Constraint violation: {0}.")]
+    ConstraintViolation(String),
+    #[error("Table '{0}' has no primary key.")]
+    NoPrimaryKey(String),
+    #[error("Migration error: {0}")]
+    MigrationError(String),
+    #[error("Invalid page cursor '{0}'.")]
+    InvalidCursor(String),
+    #[error("Invalid page size '{0}': use a positive number of rows.")]
+    InvalidPageSize(String),
+    #[error("Invalid output format '{0}': use json, csv or table.")]
+    InvalidFormat(String),
+    #[error("Invalid TTL '{0}': use a number of seconds.")]
+    InvalidTtl(String),
+    #[error("Cannot add '{0}' to column '{1}': it must be a number that fits the column's value.")]
+    InvalidIncrement(String, String),
+    #[error("View '{0}' already exists.")]
+    ViewAlreadyExists(String),
+    #[error("View '{0}' does not exist.")]
+    ViewDoesNotExist(String),
+    #[error("Cannot convert row '{0}': {1}.")]
+    RowConversionError(String, String),
+    #[error("Malformed CSV in '{0}': {1}.")]
+    MalformedCsv(String, String),
+    #[error("Malformed JSON in '{0}': {1}.")]
+    MalformedJson(String, String),
+    #[error("{0} needs the `{1}` feature (cargo build --features {1}).")]
+    FeatureDisabled(String, String),
+    #[error("SQLite error in '{0}': {1}")]
+    SqliteError(String, String),
+    #[error("Malformed archive '{0}': {1}.")]
+    MalformedArchive(String, String),
+    #[error("Backup '{0}' failed: {1}.")]
+    BackupFailed(String, String),
+    #[error("Table '{0}' is not in backup '{1}'.")]
+    TableNotInBackup(String, String),
+    #[error("WAL archive '{0}' cannot be used: {1}.")]
+    InvalidWalArchive(String, String),
+    #[error("Invalid user name '{0}': use letters, digits, '_' or '-'.")]
+    InvalidUserName(String),
+    #[error("Passwords cannot be empty.")]
+    InvalidPassword,
+    #[error("User '{0}' already exists.")]
+    UserAlreadyExists(String),
+    #[error("User '{0}' does not exist.")]
+    UserDoesNotExist(String),
+    #[error("Log in first: LOGIN <user> <password> or TOKEN <token>.")]
+    AuthenticationRequired,
+    #[error("Wrong user name or password.")]
+    AuthenticationFailed,
+    #[error("'{0}' is kept by the database itself and cannot be used directly.")]
+    SystemTable(String),
+    #[error("Cannot decrypt '{0}': {1}.")]
+    Undecryptable(String, String),
+    #[error("Permission denied: {0}.")]
+    PermissionDenied(String),
+    #[error("Invalid privilege '{0}': use SELECT, INSERT, UPDATE, DELETE, DDL or ALL.")]
+    InvalidPrivilege(String),
+    #[error("Invalid role name '{0}': use letters, digits, '_' or '-'.")]
+    InvalidRoleName(String),
+    #[error("Role '{0}' already exists.")]
+    RoleAlreadyExists(String),
+    #[error("Role '{0}' does not exist.")]
+    RoleDoesNotExist(String),
+    #[error("Role '{0}' is built in and always holds every privilege.")]
+    BuiltinRole(String),
+    #[error("User '{0}' is the last admin; make another user admin first.")]
+    LastAdmin(String),
+    #[error("Invalid row policy '{0}': use <column> <operator> <value> or <column> IS [NOT] NULL, as in SEARCH.")]
+    InvalidPolicy(String),
+    #[error("Role '{1}' already has a row policy on '{0}'.")]
+    PolicyAlreadyExists(String, String),
+    #[error("Role '{1}' has no row policy on '{0}'.")]
+    PolicyDoesNotExist(String, String),
+    #[error("Invalid attribute name '{0}': use letters, digits or '_' (and not 'user').")]
+    InvalidAttributeName(String),
+    #[error("Wrong passphrase for '{0}'.")]
+    WrongPassphrase(String),
+    #[error("Invalid passphrase: it must not be empty.")]
+    InvalidPassphrase,
+    #[error("No keyring is unlocked: open the database with RUSTDB_PASSPHRASE to rotate its keys.")]
+    NoKeyring,
+    #[error("Invalid token name '{0}': use letters, digits, '_' or '-'.")]
+    InvalidTokenName(String),
+    #[error("Token '{0}' already exists.")]
+    TokenAlreadyExists(String),
+    #[error("Token '{0}' does not exist.")]
+    TokenDoesNotExist(String),
+    #[error("Unknown or revoked token.")]
+    InvalidToken,
+    #[error("User '{0}' does not hold role '{1}'.")]
+    RoleNotHeld(String, String),
+    #[error("Too many requests from {0}: retry in {1} ms.")]
+    RateLimited(String, u64),
+    #[error("Too many connections from {0}: at most {1} at a time.")]
+    TooManyConnections(String, usize),
+    #[error("Invalid shard count '{0}': use 1 to {1} shards.")]
+    InvalidRowShards(String, usize),
 }
 
 pub type Result<T> = std::result::Result<T, DatabaseError>;
 
+/// Rows of a table read in place, (row_id, row_data), as returned by `Database::rows_matching`.
+pub type RowIter<'a> = Box<dyn Iterator<Item = (&'a String, &'a Row)> + 'a>;
+
+/// Whether a row matches a condition; see `Database::row_matcher`.
+pub type RowMatcher = Box<dyn Fn(&Row) -> bool + Send + Sync>;
+
+/// One page of rows, as returned by `Database::page_rows`.
+#[derive(Debug, Default)]
+pub struct Page {
+    /// (row_id, row) in row_id order
+    pub rows: Vec<(String, Row)>,
+    /// Cursor of the next page; `None` on the last page
+    pub next: Option<String>,
+}
+
+/// What `Database::stats` reports about one table.
+#[derive(Debug, Default)]
+pub struct TableStats {
+    /// Rows that are not soft-deleted
+    pub rows: usize,
+    pub columns: BTreeMap<String, ColumnStats>,
+    /// Bytes the table takes in storage; `None` if it was never persisted
+    pub stored_bytes: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct ColumnStats {
+    /// Rows with a value other than NULL
+    pub non_null: usize,
+    /// Approximate number of distinct non-NULL values
+    pub cardinality: usize,
+}
+
+impl TableStats {
+    /// Encode as `{"rows":..,"column_count":..,"columns":{<column>:{"non_null":..,"cardinality":..}},"stored_bytes":..}`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let columns: serde_json::Map<String, serde_json::Value> = self
+            .columns
+            .iter()
+            .map(|(name, column)| {
+                (name.clone(), serde_json::json!({ "non_null": column.non_null, "cardinality": column.cardinality }))
+            })
+            .collect();
+        serde_json::json!({
+            "rows": self.rows,
+            "column_count": self.columns.len(),
+            "columns": columns,
+            "stored_bytes": self.stored_bytes,
+        })
+    }
+}
+
+/// When the process started, for the uptime `Database::status` reports; `main` sets it first thing.
+pub static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// What `Database::status` reports about the whole database, e.g. to a load balancer.
+#[derive(Debug, Default)]
+pub struct Status {
+    pub uptime: Duration,
+    /// Tables in memory or in storage
+    pub tables: usize,
+    pub tables_in_memory: usize,
+    /// Rows that are not soft-deleted, in the tables in memory
+    pub rows: usize,
+    /// WAL entries not archived yet
+    pub wal_backlog: usize,
+    pub wal_lsn: u64,
+    /// When the WAL was last archived, in seconds since the Unix epoch
+    pub last_checkpoint: Option<i64>,
+    /// Bytes the stored tables and both WAL files take up
+    pub disk_bytes: u64,
+    /// About how many bytes the tables in memory take, and how many they may
+    pub memory_bytes: usize,
+    pub memory_limit: Option<usize>,
+    pub wal_engine: Option<Arc<CycleMetrics>>,
+}
+
+impl Status {
+    /// Whether the database keeps up with its writes: true unless its WalEngine is stalled or failing.
+    pub fn is_healthy(&self) -> bool {
+        self.wal_engine.as_ref().is_none_or(|metrics| metrics.is_healthy())
+    }
+
+    /// Encode as `{"healthy":..,"uptime_secs":..,"tables":..,"tables_in_memory":..,"rows":..,"wal_backlog":..,
+    /// "wal_lsn":..,"last_checkpoint":"<timestamp>"|null,"disk_bytes":..,"memory_bytes":..,"memory_limit":..|null,
+    /// "wal_engine":<CycleMetrics::report>|null}`.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "healthy": self.is_healthy(),
+            "uptime_secs": self.uptime.as_secs(),
+            "tables": self.tables,
+            "tables_in_memory": self.tables_in_memory,
+            "rows": self.rows,
+            "wal_backlog": self.wal_backlog,
+            "wal_lsn": self.wal_lsn,
+            "last_checkpoint": self.last_checkpoint.map(|secs| Value::Timestamp(secs).to_string()),
+            "disk_bytes": self.disk_bytes,
+            "memory_bytes": self.memory_bytes,
+            "memory_limit": self.memory_limit,
+            "wal_engine": self.wal_engine.as_ref().map(|metrics| metrics.report()),
+        })
+    }
+}
+
+/// What `Database::explain_analyze` measured while running one search.
+#[derive(Debug)]
+pub struct QueryReport {
+    pub table: String,
+    pub condition: String,
+    /// `primary key lookup` or `full scan`
+    pub access_path: &'static str,
+    /// Rows the planner expected to match, from the table's `ANALYZE` statistics; `None` without them
+    pub estimated_rows: Option<usize>,
+    /// Rows the condition was tested against, or fetched through the index
+    pub rows_scanned: usize,
+    /// Keys found in the primary key index
+    pub index_hits: usize,
+    pub rows_matched: usize,
+    /// (stage, time spent in it), in the order they ran
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+impl QueryReport {
+    /// Encode as `{"table":..,"condition":..,"access_path":..,"estimated_rows":..,"rows_scanned":..,
+    /// "index_hits":..,"rows_matched":..,"stages":[{"stage":..,"micros":..}],"total_micros":..}`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let stages: Vec<serde_json::Value> = self
+            .stages
+            .iter()
+            .map(|(stage, time)| serde_json::json!({ "stage": stage, "micros": time.as_micros() as u64 }))
+            .collect();
+        let total: Duration = self.stages.iter().map(|(_, time)| *time).sum();
+        serde_json::json!({
+            "table": self.table,
+            "condition": self.condition,
+            "access_path": self.access_path,
+            "estimated_rows": self.estimated_rows,
+            "rows_scanned": self.rows_scanned,
+            "index_hits": self.index_hits,
+            "rows_matched": self.rows_matched,
+            "stages": stages,
+            "total_micros": total.as_micros() as u64,
+        })
+    }
+}
+
+/// Rows from which a table is scanned in parallel unless the database says otherwise (see
+/// `Database::parallel_scan`).
+pub const PARALLEL_SCAN_ROWS: usize = 50_000;
+
+/// How often, by default, the entries logged meanwhile are written to the WAL file together (see
+/// `Database::group_commit`).
+pub const GROUP_COMMIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// **Database**
+/// The tables of one database and its WAL. Shared as `Arc<RwLock<Database>>`: commands that add,
+/// load, rename or drop tables, and the WAL's own upkeep, hold that lock exclusively, while reads
+/// and row writes hold it shared; reads then take the current version of the tables they read,
+/// and row writes (see `row_writer`) hold only the table they change (see `Tables`). What row
+/// writes change besides their table, the WAL and the bookkeeping of unsaved tables, has locks of
+/// its own, taken briefly.
 pub struct Database {
-    pub tables: HashMap<String, Table>,
-    pub operations_since_save: usize,
+    pub tables: Tables,
+    pub operations_since_save: AtomicUsize,
+    // Row changes after which the dirty tables are saved: by the WalEngine in the background, or
+    // by the writer itself in a database without one.
     pub save_threshold: usize,
-    pub wal: Vec<String>,
+    pub wal: Mutex<Vec<String>>,
     pub wal_file: String,
+    pub wal_archive_file: String,
+    // LSN of the last entry moved to the archive; `wal[i]` will be archived as LSN `wal_lsn + i + 1`.
+    pub wal_lsn: u64,
+    // LSN of the last entry the WAL file holds, synced to disk (see `persist_wal`). Held while the
+    // WAL file is appended to or truncated, so group commits and archiving take turns at it.
+    pub wal_synced: Arc<Mutex<u64>>,
+    // The WalEngine writes whatever was logged since its last group commit to the WAL file this
+    // often, in one write and one fsync however many writers logged it (see `persist_wal`); `None`
+    // leaves entries in memory until they are archived.
+    pub group_commit: Option<Duration>,
+    pub storage: Mutex<Box<dyn StorageEngine>>,
+    pub changes: ChangeFeed,
+    // Tables with row changes not yet persisted to storage; `checkpoint` writes them out.
+    pub dirty: Mutex<HashSet<String>>,
+    // Held by `save_dirty` while it writes, so a save that finds nothing dirty still waits for
+    // one already writing the tables it took.
+    pub saving: Mutex<()>,
+    // Told how far imports and exports (LOAD, SAVE, EXPORT) have got, e.g. to draw a progress bar.
+    pub progress: Option<progress::Listener>,
+    // Columns whose values are kept out of what the database prints about its work.
+    pub redaction: Redaction,
+    // When the WAL was last archived (`commit_wal` or a WalEngine cycle), as `value::now()`.
+    pub last_checkpoint: Option<i64>,
+    // How the cycles of the WalEngine running for this database go; `None` without one.
+    pub wal_engine: Option<Arc<CycleMetrics>>,
+    // Tables with at least this many rows are scanned (SEARCH, DISTINCT, STATS) by the threads of
+    // the rayon pool, each taking a range of rows, and their results merged; `None` scans every
+    // table on the calling thread.
+    pub parallel_scan: Option<usize>,
+    // Most tables kept in memory: loading one more evicts the least recently used (see
+    // `evict_tables`); `None` keeps every table loaded.
+    pub table_cache: Option<usize>,
+    // Bytes the tables in memory may take (see `memory_bytes`) before the least recently used
+    // spill to disk: when a table is loaded, and as the WalEngine finds them over it; `None` for
+    // no limit.
+    pub memory_limit: Option<usize>,
 }
 
 impl Database {
-    pub fn new() -> Self {
+    /// Create a database that persists its tables through the given storage engine.
+    pub fn with_storage(storage: Box<dyn StorageEngine>) -> Self {
         Database {
-            tables: HashMap::new(),
-            operations_since_save: 0,
+            tables: Tables::default(),
+            operations_since_save: AtomicUsize::new(0),
             save_threshold: 5,
-            wal: Vec::new(),
+            wal: Mutex::new(Vec::new()),
             wal_file: "wal.log".to_string(),
+            wal_archive_file: "wal_archive.log".to_string(),
+            wal_lsn: 0,
+            wal_synced: Arc::new(Mutex::new(0)),
+            group_commit: Some(GROUP_COMMIT_INTERVAL),
+            storage: Mutex::new(storage),
+            changes: ChangeFeed::default(),
+            dirty: Mutex::new(HashSet::new()),
+            saving: Mutex::new(()),
+            progress: None,
+            redaction: Redaction::default(),
+            last_checkpoint: None,
+            wal_engine: None,
+            parallel_scan: Some(PARALLEL_SCAN_ROWS),
+            table_cache: None,
+            memory_limit: None,
         }
     }
 
@@ -48,8 +423,22 @@ impl Database {
         self.tables.contains_key(table_name)
     }
 
+    /// Every table, in memory or only in storage (not loaded yet, or evicted), sorted.
+    pub fn table_names(&self) -> Result<BTreeSet<String>> {
+        let mut names: BTreeSet<String> = self.storage.lock().table_names()?.into_iter().collect();
+        names.extend(self.tables.keys().cloned());
+        Ok(names)
+    }
+
+    /// Whether scanning `table` is split across threads (see `parallel_scan`).
+    pub fn scans_in_parallel(&self, table: &Table) -> bool {
+        self.parallel_scan.is_some_and(|rows| table.rows.len() >= rows)
+    }
+
     // Create table: update in-memory state and log to WAL.
+    #[instrument(skip_all, fields(table = table_name))]
     pub fn create_table(&mut self, table_name: &str) -> Result<String> {
+        check_table_name(table_name)?;
         if self.check_table(table_name) {
             error!("Table '{}' already exists.", table_name);
             Err(DatabaseError::TableAlreadyExists(table_name.to_string()))
@@ -58,110 +447,650 @@ impl Database {
             self.tables.insert(table_name.to_string(), Table::new());
             // Log the operation
             let op = format!("create_table:{}", table_name);
-            self.wal.push(op.clone());
-            println!("Table '{}' created and logged to WAL", table_name);
+            self.log_change(op);
+            info!(table = table_name; "Table created and logged to WAL");
+            // Store the (empty) schema right away, so the table survives a restart without rows.
+            self.persist_table(table_name)?;
+            self.make_room();
             Ok(table_name.to_string())
         }
     }
 
 
-        // New helper function to load table from CSV file into memory.
-        pub fn load_table_from_file(&mut self, table_name: &str, file_name: &str) -> Result<()> {
-            let file = File::open(file_name)
+    // Rename a table in memory and in storage, and log it to the WAL.
+    // The table keeps its columns, constraints and primary key index. Unsaved changes are
+    // written out under the old name first, so the stored table that moves is up to date.
+    #[instrument(skip_all, fields(table = old_name, new_name = new_name))]
+    pub fn rename_table(&mut self, old_name: &str, new_name: &str) -> Result<Vec<String>> {
+        check_table_name(new_name)?;
+        self.ensure_table_loaded(old_name)?;
+        if self.check_table(new_name) || self.storage.get_mut().table_names()?.iter().any(|name| name == new_name) {
+            error!("Table '{}' already exists.", new_name);
+            return Err(DatabaseError::TableAlreadyExists(new_name.to_string()));
+        }
+        if self.dirty.get_mut().contains(old_name) {
+            self.persist_table(old_name)?;
+        }
+        self.storage.get_mut().rename_table(old_name, new_name)?;
+        let table = self.tables.remove(old_name)
+            .ok_or(DatabaseError::TableDoesNotExist(old_name.to_string()))?;
+        let shards = self.tables.row_shards(old_name);
+        self.tables.set_row_shards(old_name, 1);
+        self.tables.set_row_shards(new_name, shards);
+        self.tables.insert(new_name.to_string(), table);
+        self.log_change(format!("rename_table:{}:{}", old_name, new_name));
+        info!(table = old_name, new_name; "Table renamed and logged to WAL");
+        Ok(vec![old_name.to_string(), new_name.to_string()])
+    }
+
+    // Copy a table's schema and rows (only those matching `condition`, if given; see
+    // `search_rows_by_condition_in_table`) to a new table, persist it and log it to the WAL.
+    #[instrument(skip_all, fields(table = source, destination = destination, rows = Empty))]
+    pub fn copy_table(&mut self, source: &str, destination: &str, condition: Option<&str>) -> Result<Vec<String>> {
+        check_table_name(destination)?;
+        self.ensure_table_loaded(source)?;
+        if self.check_table(destination) || self.storage.get_mut().table_names()?.iter().any(|name| name == destination) {
+            error!("Table '{}' already exists.", destination);
+            return Err(DatabaseError::TableAlreadyExists(destination.to_string()));
+        }
+        let rows = match condition {
+            Some(condition) => self.search_rows_by_condition_in_table(source, condition)?,
+            None => self.get_table(source)?.rows.iter().map(|(id, row)| (id.clone(), row.clone())).collect(),
+        };
+        let mut copy = (*self.get_table(source)?).clone();
+        copy.truncate();
+        let copied = rows.len();
+        record_rows(copied);
+        for (row_id, row) in rows {
+            copy.insert_values(&row_id, row);
+        }
+        self.tables.insert(destination.to_string(), copy);
+        let op = match condition {
+            Some(condition) => format!("copy_table:{}:{}:{}", source, destination, condition),
+            None => format!("copy_table:{}:{}", source, destination),
+        };
+        self.log_change(op);
+        info!(table = source, destination, rows = copied; "Table copied and logged to WAL");
+        self.persist_table(destination)?;
+        self.make_room();
+        Ok(vec![destination.to_string(), copied.to_string()])
+    }
+
+    // Drop a table from memory and storage, and log it to the WAL.
+    #[instrument(skip_all, fields(table = table_name))]
+    pub fn drop_table(&mut self, table_name: &str) -> Result<Vec<String>> {
+        self.ensure_table_loaded(table_name)?;
+        self.storage.get_mut().drop_table(table_name)?;
+        self.tables.remove(table_name);
+        self.tables.set_row_shards(table_name, 1);
+        self.log_change(format!("drop_table:{}", table_name));
+        info!(table = table_name; "Table dropped and logged to WAL");
+        Ok(vec![table_name.to_string()])
+    }
+
+    // Load a table from a CSV file into memory (or a JSON one, see `import_table_json`).
+    // Files that declare no column types (from other tools) get them inferred from their values;
+    // `types` then sets the type of any column, failing on a value that does not fit it.
+    #[instrument(skip_all, fields(table = table_name, file = file_name, rows = Empty))]
+    pub fn load_table_from_file(&mut self, table_name: &str, file_name: &str, types: &[(String, ColumnType)]) -> Result<()> {
+        check_table_name(table_name)?;
+        if json::Layout::of(file_name).is_some() {
+            return self.import_table_json(table_name, file_name, types);
+        }
+        let mut table = csv::import_table(file_name, &mut self.tracker(format!("import {}", file_name)))?;
+        override_types(table_name, &mut table, types)?;
+        record_rows(table.rows.len());
+        self.tables.insert(table_name.to_string(), table);
+        self.make_room();
+        info!(table = table_name, file = file_name; "Table loaded from file");
+        Ok(())
+    }
+
+    // Load a table from a JSON file into memory: an array of objects or JSON Lines, each object a
+    // row. Files written by `export_table_json` keep their column types, keys and defaults.
+    pub fn import_table_json(&mut self, table_name: &str, file_name: &str, types: &[(String, ColumnType)]) -> Result<()> {
+        check_table_name(table_name)?;
+        let mut table = json::read_table(file_name, &mut self.tracker(format!("import {}", file_name)))?;
+        override_types(table_name, &mut table, types)?;
+        record_rows(table.rows.len());
+        self.tables.insert(table_name.to_string(), table);
+        self.make_room();
+        info!(table = table_name, file = file_name; "Table imported from JSON file");
+        Ok(())
+    }
+
+    // Save a table to a JSON file: JSON Lines if it is named `.jsonl` or `.ndjson`, else an array
+    // of objects. Column declarations go first and NULLs are written as `null`. A view's result
+    // is streamed from its table without being copied.
+    pub fn export_table_json(&self, table_name: &str, file_name: &str) -> Result<Vec<String>> {
+        self.export_to_file(table_name, None, file_name, json::Layout::of(file_name).unwrap_or(json::Layout::Array))?;
+        info!(table = table_name, file = file_name; "Table exported to JSON file");
+        Ok(vec![table_name.to_string(), file_name.to_string()])
+    }
+
+    // Write the rows of a table or view, those matching `condition` if given, to a JSON file
+    // in `layout`, one row at a time (see `export_rows`). Returns the number of rows.
+    #[instrument(skip_all, fields(table = name, file = file_name, rows = Empty))]
+    pub fn export_to_file(&self, name: &str, condition: Option<&str>, file_name: &str, layout: json::Layout) -> Result<usize> {
+        storage::replace_file(file_name, |partial| {
+            let file = File::create(partial)
                 .map_err(|e| DatabaseError::FileCreationError(file_name.to_string(), e.to_string()))?;
-            let reader = BufReader::new(file);
-            let mut lines = reader.lines();
-            // Read header line.
-            if let Some(Ok(header_line)) = lines.next() {
-                let headers: Vec<String> = header_line.split(',')
-                    .map(|s| s.to_string())
-                    .collect();
-                let mut table = Table::new();
-                // Add columns if header has more than one value.
-                if headers.len() > 1 {
-                    for col in headers.iter().skip(1) {
-                        table.add_column(col);
-                    }
-                }
-                // Process rows.
-                for line in lines {
-                    if let Ok(row_line) = line {
-                        let values: Vec<&str> = row_line.split(',').collect();
-                        if let Some((row_id, row_values)) = values.split_first() {
-                            let mut data = HashMap::new();
-                            for (col, val) in headers.iter().skip(1).zip(row_values.iter()) {
-                                data.insert(col.to_string(), (*val).to_string());
-                            }
-                            table.insert_row(row_id, data);
-                        }
-                    }
+            self.export_rows(name, condition, BufWriter::new(file), layout, file_name)
+        })
+        .inspect(|rows| record_rows(*rows))
+    }
+
+    // Stream the rows of a table (every stored row) or of a view's result, only those matching
+    // `condition` if given, as JSON to `writer` (see `json::write_rows`). Rows are read in place
+    // and written one by one, so exporting never copies the table. `destination` names the
+    // writer in errors.
+    pub fn export_rows(
+        &self,
+        name: &str,
+        condition: Option<&str>,
+        writer: impl Write,
+        layout: json::Layout,
+        destination: &str,
+    ) -> Result<usize> {
+        let (table, view) = self.export_source(name)?;
+        let (schema, rows) = self.export_rows_in(&table, view.as_ref(), condition)?;
+        json::write_rows(writer, &schema, rows, layout, &mut self.tracker(format!("export {}", name)))
+            .map_err(|e| DatabaseError::FileCreationError(destination.to_string(), e.to_string()))
+    }
+
+    // Write an Excel workbook with a sheet per table or view of `names`, named after it, holding
+    // its rows (those matching `condition` if given) typed as its columns are, for people who
+    // open results in a spreadsheet. Returns the number of rows. Needs the `xlsx` feature.
+    pub fn export_xlsx(&self, names: &[&str], condition: Option<&str>, file_name: &str) -> Result<usize> {
+        let sources = names.iter().map(|name| self.export_source(name)).collect::<Result<Vec<_>>>()?;
+        let sources = sources.iter().map(|(table, view)| self.export_rows_in(table, view.as_ref(), condition)).collect::<Result<Vec<_>>>()?;
+        let (schemas, rows): (Vec<_>, Vec<_>) = sources.into_iter().unzip();
+        let sheets = names.iter().zip(&schemas).zip(rows).map(|((name, schema), rows)| (name.to_string(), schema.as_ref(), rows));
+        let sheets = sheets.collect();
+        let mut tracker = self.tracker(format!("export {}", names.join(",")));
+        let rows = storage::replace_file(file_name, |partial| storage::write_xlsx(partial, sheets, &mut tracker))?;
+        info!(rows, tables = names.join(","), file = file_name; "Tables exported to Excel workbook");
+        Ok(rows)
+    }
+
+    // The table a table or view exports from, as it is now, with the view if it is one.
+    fn export_source(&self, name: &str) -> Result<(TableRef, Option<View>)> {
+        match (self.tables.get(name), views::get(self, name)) {
+            (Some(table), _) => Ok((table, None)),
+            (None, Some(view)) => Ok((self.get_table(&view.table)?, Some(view))),
+            (None, None) => {
+                error!("Table '{}' does not exist.", name);
+                Err(DatabaseError::TableDoesNotExist(name.to_string()))
+            }
+        }
+    }
+
+    // Save a table to a Parquet file, typed as its columns are, for analytics tools.
+    // Needs the `parquet` feature.
+    pub fn export_table_parquet(&self, table_name: &str, file_name: &str) -> Result<Vec<String>> {
+        self.with_table_to_save(table_name, |table| storage::replace_file(file_name, |partial| storage::write_parquet(table, partial)))?;
+        info!(table = table_name, file = file_name; "Table exported to Parquet file");
+        Ok(vec![table_name.to_string(), file_name.to_string()])
+    }
+
+    // Copy every table of a SQLite database in (see `storage::sqlite::read_tables`), persisting
+    // each straight away. Fails before copying anything if a table name is already taken or cannot name a table.
+    // Needs the `sqlite` feature.
+    pub fn import_sqlite(&mut self, path: &str) -> Result<Vec<String>> {
+        let tables = storage::read_sqlite(path)?;
+        tables.iter().try_for_each(|(name, _)| check_table_name(name))?;
+        let stored = self.storage.get_mut().table_names()?;
+        if let Some((name, _)) = tables.iter().find(|(name, _)| self.check_table(name) || stored.contains(name)) {
+            error!("Table '{}' already exists.", name);
+            return Err(DatabaseError::TableAlreadyExists(name.clone()));
+        }
+        let mut names = Vec::new();
+        for (name, table) in tables {
+            let rows = table.rows.len();
+            self.tables.insert(name.clone(), table);
+            self.persist_table(&name)?;
+            info!(table = name, rows, file = path; "Table imported from SQLite database");
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    // Follows the rows of a long import or export for the `progress` listener, if any.
+    fn tracker(&self, operation: String) -> Tracker {
+        Tracker::new(self.progress.clone(), operation)
+    }
+
+    // Write the whole database to one archive (see `storage::archive::write`): every table,
+    // in memory or stored, and the WAL with its position. Returns the archive's manifest.
+    pub fn dump(&mut self, path: &str) -> Result<serde_json::Value> {
+        self.load_all_tables()?;
+        let snapshot = self.tables.snapshot();
+        let tables: Vec<(&String, &Table)> = snapshot.iter().map(|(name, table)| (name, &**table)).collect();
+        let wal = WalPosition {
+            lsn: self.wal_lsn,
+            pending: self.wal.get_mut().clone(),
+            archive: encryption::open_text(&std::fs::read_to_string(&self.wal_archive_file).unwrap_or_default(), &self.wal_archive_file)?,
+        };
+        let manifest = archive::write(path, &tables, &wal)?;
+        info!(file = path, tables = tables.len(), lsn = self.current_lsn(); "Database dumped");
+        Ok(manifest)
+    }
+
+    // Take every table (stored or in memory) as it is now and copy out the WAL position, for a
+    // backup to write without holding the database (see `backup::Snapshot::write`). The tables
+    // are their current versions (see `Tables::snapshot`), so no rows are copied.
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        self.load_all_tables()?;
+        Ok(Snapshot {
+            tables: self.tables.snapshot(),
+            archived_lsn: self.wal_lsn,
+            pending: self.wal.get_mut().clone(),
+            archive_file: self.wal_archive_file.clone(),
+        })
+    }
+
+    // The WAL entries logged after LSN `since` (see `backup::Increment`). Only the entries not
+    // archived yet are copied under the lock; archived ones are read from the archive file later.
+    pub fn wal_since(&self, since: u64) -> Increment {
+        Increment {
+            since,
+            archived_lsn: self.wal_lsn,
+            pending: self.wal.lock().clone(),
+            archive_file: self.wal_archive_file.clone(),
+        }
+    }
+
+    // Back the database `db` up into the new directory `dir`: a snapshot of every table plus the
+    // WAL up to its LSN, checked against checksums once written. Only the snapshot is taken under
+    // the lock; the files are written after releasing it, so writes go on during a backup.
+    // Returns the manifest.
+    #[instrument(skip_all, fields(dir = dir))]
+    pub fn backup(db: &std::sync::RwLock<Database>, dir: &str) -> Result<serde_json::Value> {
+        let snapshot = db.write().unwrap().snapshot()?;
+        let manifest = snapshot.write(dir)?;
+        info!(dir, tables = snapshot.tables.len(), lsn = snapshot.lsn(); "Database backed up");
+        Ok(manifest)
+    }
+
+    // Replace the whole database with an archive written by `dump`: its tables (tables it does
+    // not hold are dropped) and its WAL. The archive is read and checked in full before anything
+    // changes, so a damaged one leaves the database as it was. The tables are stored under
+    // temporary names first, and only once all of them are take the place of the old ones, so
+    // failing to store one leaves the database as it was too. Returns the restored tables.
+    #[instrument(skip_all, fields(path = path))]
+    pub fn restore(&mut self, path: &str) -> Result<Vec<String>> {
+        let (tables, wal) = archive::read(path)?;
+        tables.iter().try_for_each(|(name, _)| check_table_name(name))?;
+        let staged = |name: &str| format!("__restore_{}", name);
+        let mut old: Vec<String> = self.storage.get_mut().table_names()?;
+        old.extend(self.tables.keys().cloned());
+        for (done, (name, table)) in tables.iter().enumerate() {
+            if let Err(e) = self.storage.get_mut().save_table(&staged(name), table) {
+                for (name, _) in &tables[..=done] {
+                    let _ = self.storage.get_mut().drop_table(&staged(name));
                 }
+                return Err(e);
+            }
+        }
+        for name in &old {
+            self.storage.get_mut().drop_table(name)?;
+        }
+        for (name, _) in &tables {
+            self.storage.get_mut().rename_table(&staged(name), name)?;
+        }
+        std::fs::write(&self.wal_archive_file, encryption::seal_text(&wal.archive))
+            .map_err(|e| DatabaseError::FileCreationError(self.wal_archive_file.clone(), e.to_string()))?;
+        let names: Vec<String> = tables.iter().map(|(name, _)| name.clone()).collect();
+        self.tables = tables.into_iter().collect();
+        self.dirty.get_mut().clear();
+        *self.operations_since_save.get_mut() = 0;
+        self.wal_lsn = wal.lsn;
+        self.clear_wal()?;
+        *self.wal.get_mut() = wal.pending;
+        self.persist_wal()?;
+        info!(file = path, tables = names.len(), lsn = self.current_lsn(); "Database restored");
+        Ok(names)
+    }
+
+    // Bring back one table from a DUMP DATABASE archive or a BACKUP directory, as `as_name` if
+    // given, next to the tables there are now. Like IMPORT SQLITE it goes straight to storage.
+    // Fails if the name is taken, so nothing is overwritten. Returns the name and the row count.
+    pub fn restore_table(&mut self, source: &str, table_name: &str, as_name: Option<&str>) -> Result<Vec<String>> {
+        let name = as_name.unwrap_or(table_name);
+        check_table_name(name)?;
+        if self.check_table(name) || self.storage.get_mut().table_names()?.iter().any(|stored| stored == name) {
+            error!("Table '{}' already exists.", name);
+            return Err(DatabaseError::TableAlreadyExists(name.to_string()));
+        }
+        let table = if std::path::Path::new(source).is_dir() {
+            backup::read_table(source, table_name)?
+        } else {
+            archive::read_table(source, table_name)?
+        };
+        let rows = table.rows.len();
+        self.tables.insert(name.to_string(), table);
+        self.persist_table(name)?;
+        info!(table = table_name, source, name, rows; "Table restored from backup");
+        Ok(vec![name.to_string(), rows.to_string()])
+    }
+
+    /// Run `save` on what saving `name` writes: the table as it is now, or the
+    /// result of the view called so (see `views::query`), whose table must be in memory.
+    fn with_table_to_save<T>(&self, name: &str, save: impl FnOnce(&Table) -> Result<T>) -> Result<T> {
+        match (self.tables.get(name), views::get(self, name)) {
+            (Some(table), _) => save(&table),
+            (None, Some(view)) => save(&views::query(self, &view, None)?),
+            (None, None) => {
+                error!("Table '{}' does not exist.", name);
+                Err(DatabaseError::TableDoesNotExist(name.to_string()))
+            }
+        }
+    }
+
+    /// Make sure a table is in memory, loading it from the storage engine if needed.
+    #[instrument(skip_all, fields(table = table_name, rows = Empty))]
+    pub fn ensure_table_loaded(&mut self, table_name: &str) -> Result<()> {
+        if self.check_table(table_name) {
+            return Ok(());
+        }
+        check_table_name(table_name)?;
+        match self.storage.get_mut().load_table(table_name) {
+            Ok(Some(table)) => {
+                record_rows(table.rows.len());
                 self.tables.insert(table_name.to_string(), table);
-                println!("Loaded table '{}' from file '{}'", table_name, file_name);
+                debug!(table = table_name, storage = self.storage.get_mut().name(); "Table loaded from storage");
+                self.make_room();
                 Ok(())
-            } else {
-                println!("File '{}' is empty.", file_name);
+            }
+            Ok(None) => {
+                error!("Table '{}' does not exist in memory or on disk.", table_name);
                 Err(DatabaseError::TableDoesNotExist(table_name.to_string()))
             }
+            Err(e) => {
+                error!("Failed to load table '{}' from storage: {}", table_name, e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop the least recently used tables from memory until at most `table_cache` are left and
+    /// they take no more than `memory_limit`, first persisting any that storage does not hold as
+    /// they are: they spill to disk, and are loaded again when next used. The table used last (the
+    /// one in use) and the database's own `__` tables stay. Returns the tables evicted.
+    pub fn evict_tables(&mut self) -> Result<Vec<String>> {
+        let (table_cache, memory_limit) = (self.table_cache, self.memory_limit);
+        let over = |tables: usize, bytes: usize| {
+            table_cache.is_some_and(|limit| tables > limit) || memory_limit.is_some_and(|limit| bytes > limit)
+        };
+        let mut in_memory = self.tables.len();
+        let mut bytes = if memory_limit.is_some() { self.memory_bytes() } else { 0 };
+        if !over(in_memory, bytes) {
+            return Ok(Vec::new());
+        }
+        let stored: HashSet<String> = self.storage.get_mut().table_names()?.into_iter().collect();
+        let mut coldest = self.tables.least_recently_used();
+        coldest.pop();
+        let mut evicted = Vec::new();
+        for name in coldest {
+            if !over(in_memory, bytes) {
+                break;
+            }
+            if name.starts_with("__") {
+                continue;
+            }
+            let Some(table) = self.tables.get(&name) else {
+                continue;
+            };
+            if self.dirty.get_mut().contains(&name) || !stored.contains(&name) {
+                self.persist_table(&name)?;
+            }
+            self.tables.remove(&name);
+            in_memory -= 1;
+            bytes = bytes.saturating_sub(table.approximate_bytes());
+            debug!(table = name; "Table evicted from memory");
+            evicted.push(name);
+        }
+        if !evicted.is_empty() {
+            info!(tables = evicted.len(), in_memory, memory_bytes = bytes; "Cold tables evicted from memory");
         }
+        Ok(evicted)
+    }
 
+    /// About how many bytes the rows of the tables in memory take (see `Table::approximate_bytes`).
+    pub fn memory_bytes(&self) -> usize {
+        self.tables.iter().map(|(_, table)| table.approximate_bytes()).sum()
+    }
 
-    // Add a column: log and update in-memory.
-    pub fn add_column(&mut self, table_name: &str, column_name: &str) -> Result<Vec<String>> {
-        // Check if the table is in-memory.
-        if !self.check_table(table_name) {
-            // Table not found: try to load it from file.
-            let file_name = format!("{}.csv", table_name);
-            if fs::metadata(&file_name).is_ok() {
-                match self.load_table_from_file(table_name, &file_name) {
-                    Ok(_) => println!("Table '{}' loaded from file '{}'.", table_name, file_name),
-                    Err(e) => {
-                        error!("Failed to load table from file: {}", e);
-                        return Err(e);
-                    }
-                }
-            } else {
-                error!("Table '{}' does not exist in memory or on disk.", table_name);
-                return Err(DatabaseError::TableDoesNotExist(table_name.to_string()));
+    /// Whether the tables in memory take more than `memory_limit`, so `evict_tables` is due.
+    pub fn over_memory_limit(&self) -> bool {
+        self.memory_limit.is_some_and(|limit| self.memory_bytes() > limit)
+    }
+
+    /// Load every stored table, evicting none meanwhile, so they are all in `tables` together
+    /// until the next table is loaded or made (e.g. for a snapshot of the whole database).
+    pub fn load_all_tables(&mut self) -> Result<()> {
+        let limits = (self.table_cache.take(), self.memory_limit.take());
+        let loaded = self.storage.get_mut().table_names().and_then(|names| names.iter().try_for_each(|name| self.ensure_table_loaded(name)));
+        (self.table_cache, self.memory_limit) = limits;
+        loaded
+    }
+
+    /// `evict_tables` once a table is loaded or made; failing to evict only costs memory, so it
+    /// is logged rather than failing the command.
+    fn make_room(&mut self) {
+        if let Err(e) = self.evict_tables() {
+            error!("Failed to evict tables from memory: {}", e);
+        }
+    }
+
+    /// Persist a table through the storage engine.
+    pub fn persist_table(&self, table_name: &str) -> Result<()> {
+        let table = self.tables.get(table_name)
+            .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+        self.store(table_name, &table)
+    }
+
+    /// Persist `table`, held by the caller, as `table_name`.
+    #[instrument(name = "persist_table", skip_all, fields(table = table_name, rows = Empty))]
+    fn store(&self, table_name: &str, table: &Table) -> Result<()> {
+        record_rows(table.rows.len());
+        let mut storage = self.storage.lock();
+        storage.save_table(table_name, table)?;
+        self.dirty.lock().remove(table_name);
+        debug!(table = table_name, storage = storage.name(); "Table persisted to storage");
+        Ok(())
+    }
+
+    /// Persist every table with changes not yet in storage, returning their names.
+    #[instrument(skip_all, fields(tables = Empty))]
+    pub fn checkpoint(&mut self) -> Result<Vec<String>> {
+        let mut names: Vec<String> = self.dirty.get_mut().iter().filter(|name| self.tables.contains_key(name)).cloned().collect();
+        names.sort();
+        tracing::Span::current().record("tables", names.len());
+        for name in &names {
+            self.persist_table(name)?;
+        }
+        // Dropped and renamed tables have nothing left to persist.
+        self.dirty.get_mut().clear();
+        Ok(names)
+    }
+
+    /// Whether enough rows changed since the tables were last saved (`save_threshold`) for
+    /// `save_dirty` to be due.
+    pub fn save_due(&self) -> bool {
+        self.operations_since_save.load(Ordering::Relaxed) >= self.save_threshold
+    }
+
+    /// Persist every table with changes not yet in storage as it is now, while rows go on being
+    /// written: `checkpoint` for a caller holding the database lock shared, as the WalEngine does.
+    /// A table written meanwhile stays dirty for the next save. Returns the tables saved.
+    #[instrument(skip_all, fields(tables = Empty))]
+    pub fn save_dirty(&self) -> Result<Vec<String>> {
+        let _saving = self.saving.lock();
+        self.operations_since_save.store(0, Ordering::Relaxed);
+        let mut names: Vec<String> = std::mem::take(&mut *self.dirty.lock()).into_iter().collect();
+        names.sort();
+        tracing::Span::current().record("tables", names.len());
+        for (saved, name) in names.iter().enumerate() {
+            // Dropped and renamed tables have nothing left to persist.
+            let Some(table) = self.tables.get(name) else {
+                continue;
+            };
+            if let Err(e) = self.storage.lock().save_table(name, &table) {
+                self.dirty.lock().extend(names[saved..].iter().cloned());
+                return Err(e);
             }
+            debug!(table = name; "Table saved in the background");
+        }
+        Ok(names)
+    }
+
+    /// Write every stored table and both WAL files again, so they are sealed with the key that
+    /// seals now (see `keyring::rotate_key`). Returns how many files were written.
+    pub fn reseal(&mut self) -> Result<usize> {
+        let names = self.storage.get_mut().table_names()?;
+        for name in &names {
+            self.ensure_table_loaded(name)?;
+            self.persist_table(name)?;
+        }
+        let mut files = names.len();
+        for file in [self.wal_archive_file.clone(), self.wal_file.clone()] {
+            let Ok(text) = std::fs::read_to_string(&file) else { continue };
+            let text = encryption::seal_text(&encryption::open_text(&text, &file)?);
+            storage::replace_file(&file, |partial| {
+                std::fs::write(partial, &text).map_err(|e| DatabaseError::FileCreationError(partial.to_string(), e.to_string()))
+            })?;
+            files += 1;
         }
+        Ok(files)
+    }
+
+    /// Append `entry` to the WAL. Subscribers and change hooks hear of it once it is committed.
+    fn log_change(&self, entry: String) {
+        self.wal.lock().push(entry);
+    }
+
+    /// Count an insert/update; once `save_threshold` is reached the dirty tables are due to be
+    /// saved (see `save_dirty`).
+    fn record_operation(&mut self, table_name: &str) {
+        if self.count_operation(table_name) {
+            if let Some(table) = self.tables.get(table_name) {
+                self.save_counted(table_name, &table);
+            }
+        }
+    }
+
+    /// Mark `table_name` dirty and count the operation. A WalEngine saves the tables in the
+    /// background, off the write path; without one the writer that reaches the threshold saves
+    /// its table (see `save_counted`), which this then returns true for.
+    fn count_operation(&self, table_name: &str) -> bool {
+        self.dirty.lock().insert(table_name.to_string());
+        if self.operations_since_save.fetch_add(1, Ordering::Relaxed) + 1 >= self.save_threshold && self.wal_engine.is_none() {
+            self.operations_since_save.store(0, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    fn save_counted(&self, table_name: &str, table: &Table) {
+        if let Err(e) = self.store(table_name, table) {
+            error!("Failed to save table '{}': {}", table_name, e);
+        }
+    }
+
+    // Add a text column: log and update in-memory.
+    // With a default, existing rows without a value and rows inserted later without one get it.
+    pub fn add_column(&mut self, table_name: &str, column_name: &str, default: Option<&str>) -> Result<Vec<String>> {
+        let spec = ColumnSpec { default: default.map(str::to_string), ..ColumnSpec::default() };
+        self.add_typed_column(table_name, column_name, spec)
+    }
+
+    // Add a column declared by `spec`: log and update in-memory.
+    // An existing column keeps its type but takes on the default and NOT NULL constraint.
+    // NOT NULL is refused while some row would still lack a value after the default is filled in;
+    // a primary key also while two rows share a value (see `check_new_primary_key`).
+    pub fn add_typed_column(&mut self, table_name: &str, column_name: &str, spec: ColumnSpec) -> Result<Vec<String>> {
+        self.ensure_table_loaded(table_name)?;
         // At this point the table should be in memory.
-        if let Some(table) = self.tables.get_mut(table_name) {
-            table.add_column(column_name);
-            let op = format!("add_column:{}:{}", table_name, column_name);
-            self.wal.push(op.clone());
-            println!("Column '{}' added to table '{}' and logged to WAL", column_name, table_name);
-            Ok(vec![column_name.to_string(), table_name.to_string()])
-        } else {
+        let Some(mut table) = self.tables.get_mut(table_name) else {
             error!("Table '{}' is still not found after attempting to load.", table_name);
-            Err(DatabaseError::TableDoesNotExist(table_name.to_string()))
+            return Err(DatabaseError::TableDoesNotExist(table_name.to_string()));
+        };
+        // Check the default and the constraint before changing anything.
+        let column_type = if table.columns.contains(column_name) { table.column_type(column_name) } else { spec.column_type };
+        let default = match &spec.default {
+            Some(default) => Value::parse(default, column_type).ok_or_else(|| {
+                DatabaseError::InvalidValue(default.to_string(), column_name.to_string(), column_type)
+            })?,
+            None => table.defaults.get(column_name).cloned().unwrap_or(Value::Null),
+        };
+        if spec.primary_key {
+            check_new_primary_key(&table, column_name, &spec)?;
+        }
+        if (spec.not_null || spec.primary_key) && default.is_null() {
+            if let Some(row_id) = table.rows.iter().find(|(_, row)| !row.contains_key(column_name)).map(|(id, _)| id) {
+                return Err(DatabaseError::ConstraintViolation(format!(
+                    "column '{}' cannot be NOT NULL without a default while row '{}' has no value for it",
+                    column_name, row_id
+                )));
+            }
         }
+        table.apply_spec(column_name, &spec)?;
+        table.fill_default(column_name);
+        // Plain text columns keep the old entry format: `add_column:<table>:<column>`.
+        let op = format!("add_column:{}:{}", table_name, table.declaration(column_name));
+        drop(table);
+        self.log_change(op);
+        info!(table = table_name, column = column_name, column_type:%; "Column added and logged to WAL");
+        // Schema changes are persisted at once; rows only wait for `save_threshold`.
+        self.persist_table(table_name)?;
+        Ok(vec![column_name.to_string(), table_name.to_string()])
     }
 
-    // Get row from table.
-    pub fn get_row(&mut self, table_name: &str, row_id: &str) -> Result<Vec<String>> {
-        // If the table isn't in memory, try to load it from file.
-        if !self.check_table(table_name) {
-            let file_name = format!("{}.csv", table_name);
-            if fs::metadata(&file_name).is_ok() {
-                match self.load_table_from_file(table_name, &file_name) {
-                    Ok(_) => println!("Table '{}' loaded from file '{}'.", table_name, file_name),
-                    Err(e) => {
-                        error!("Failed to load table from file: {}", e);
-                        return Err(e);
-                    }
-                }
-            } else {
-                error!("Table '{}' does not exist in memory or on disk.", table_name);
-                return Err(DatabaseError::TableDoesNotExist(table_name.to_string()));
-            }
+    // Drop a column: strip it from every row, persist the table and log it to the WAL.
+    pub fn drop_column(&mut self, table_name: &str, column_name: &str) -> Result<Vec<String>> {
+        self.ensure_table_loaded(table_name)?;
+        let mut table = self.tables.get_mut(table_name)
+            .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+        if !table.drop_column(column_name) {
+            error!("Column '{}' not found in table '{}'.", column_name, table_name);
+            return Err(DatabaseError::ColumnDoesNotExist(column_name.to_string(), table_name.to_string()));
+        }
+        drop(table);
+        self.log_change(format!("drop_column:{}:{}", table_name, column_name));
+        info!(table = table_name, column = column_name; "Column dropped and logged to WAL");
+        self.persist_table(table_name)?;
+        Ok(vec![column_name.to_string(), table_name.to_string()])
+    }
+
+    // Rename a column in every row, persist the table and log it to the WAL.
+    pub fn rename_column(&mut self, table_name: &str, old_name: &str, new_name: &str) -> Result<Vec<String>> {
+        self.ensure_table_loaded(table_name)?;
+        let mut table = self.tables.get_mut(table_name)
+            .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+        if table.columns.contains(new_name) {
+            error!("Column '{}' already exists in table '{}'.", new_name, table_name);
+            return Err(DatabaseError::ColumnAlreadyExists(new_name.to_string(), table_name.to_string()));
         }
+        if !table.rename_column(old_name, new_name) {
+            error!("Column '{}' not found in table '{}'.", old_name, table_name);
+            return Err(DatabaseError::ColumnDoesNotExist(old_name.to_string(), table_name.to_string()));
+        }
+        drop(table);
+        self.log_change(format!("rename_column:{}:{}:{}", table_name, old_name, new_name));
+        info!(table = table_name, column = old_name, new_name; "Column renamed and logged to WAL");
+        self.persist_table(table_name)?;
+        Ok(vec![old_name.to_string(), new_name.to_string(), table_name.to_string()])
+    }
+
+    // Get row from table, unless it is soft-deleted.
+    pub fn get_row(&mut self, table_name: &str, row_id: &str) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
         // Now the table must be in memory.
         if let Some(table) = self.tables.get(table_name) {
-            if let Some(row) = table.get_row(row_id) {
-                println!("Row '{}': {:?}", row_id, row);
-                let row_string = format!("{:?}", row);
-                Ok(vec![row_id.to_string(), row_string])
+            if let Some(row) = table.live_row(row_id) {
+                debug!(table = table_name, row_id, row:? = self.redaction.row(table_name, &table::row_to_text(row)); "Row read");
+                Ok(row.clone())
             } else {
                 error!("Row '{}' does not exist in '{}'.", row_id, table_name);
                 Err(DatabaseError::RowDoesNotExist(row_id.to_string(), table_name.to_string()))
@@ -172,369 +1101,1101 @@ impl Database {
         }
     }
 
-    // Insert row: update in-memory table and log the operation.
-    pub fn insert_row(&mut self, table_name: &str, row_id: &str, data: HashMap<String, String>) -> Result<Vec<String>> {
-        // If the table isn't in memory, try to load it from file.
-        if !self.check_table(table_name) {
-            let file_name = format!("{}.csv", table_name);
-            if fs::metadata(&file_name).is_ok() {
-                match self.load_table_from_file(table_name, &file_name) {
-                    Ok(_) => println!("Table '{}' loaded from file '{}'.", table_name, file_name),
-                    Err(e) => {
-                        error!("Failed to load table from file: {}", e);
-                        return Err(e);
-                    }
-                }
-            } else {
-                error!("Table '{}' does not exist in memory or on disk.", table_name);
-                return Err(DatabaseError::TableDoesNotExist(table_name.to_string()));
-            }
-        }
-        // Now perform the row insertion.
-        if let Some(table) = self.tables.get_mut(table_name) {
-            table.insert_row(row_id, data.clone());
-            let op = format!(
-                "insert_row:{}:{}:{}",
-                table_name,
-                row_id,
-                serde_json::to_string(&data).unwrap()
-            );
-            self.wal.push(op);
-            println!("Inserted row '{}' in table '{}' and logged to WAL", row_id, table_name);
-    
-            self.operations_since_save += 1;
-            if self.operations_since_save >= self.save_threshold {
-                let file_name = format!("{}.csv", table_name);
-                if let Err(e) = self.save_table(table_name, &file_name) {
-                    error!("Failed to save table '{}': {}", table_name, e);
-                }
-                self.operations_since_save = 0;
-            }
-            Ok(vec![row_id.to_string(), table_name.to_string()])
-        } else {
-            error!("Table '{}' is still not found after attempting to load.", table_name);
-            Err(DatabaseError::TableDoesNotExist(table_name.to_string()))
-        }
+    // Insert a new row: update in-memory table and log the operation. Fails if the row exists.
+    // A new row gets the column defaults for the columns it does not supply; they are logged too.
+    pub fn insert_row(&mut self, table_name: &str, row_id: &str, data: HashMap<String, String>) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.insert_row(row_id, data)
+    }
+
+    // Insert a row made of the fields of `value` (e.g. a struct), which must serialize to a JSON object.
+    pub fn insert_struct<T: Serialize>(&mut self, table_name: &str, row_id: &str, value: &T) -> Result<Row> {
+        let cells = match serde_json::to_value(value) {
+            Ok(serde_json::Value::Object(cells)) => cells,
+            Ok(other) => return Err(DatabaseError::RowConversionError(row_id.to_string(), format!("{} is not an object", other))),
+            Err(e) => return Err(DatabaseError::RowConversionError(row_id.to_string(), e.to_string())),
+        };
+        self.insert_row(table_name, row_id, table::json_to_text(cells))
+    }
+
+    // Insert a row, or merge the given cells into it if it exists (other cells are kept).
+    pub fn upsert_row(&mut self, table_name: &str, row_id: &str, data: HashMap<String, String>) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.upsert_row(row_id, data)
+    }
+
+    // Insert a row, or overwrite it entirely if it exists: cells not given become their
+    // default or NULL, as for a new row.
+    pub fn replace_row(&mut self, table_name: &str, row_id: &str, data: HashMap<String, String>) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.replace_row(row_id, data)
     }
 
     // Update a value in a row for a specific column.
-    pub fn update_row(&mut self, table_name: &str, row_id: &str, column_name: &str, new_value: &str) -> Result<Vec<String>> {
-        // Ensure the table is in memory, loading from file if needed.
-        if !self.check_table(table_name) {
-            let file_name = format!("{}.csv", table_name);
-            if fs::metadata(&file_name).is_ok() {
-                match self.load_table_from_file(table_name, &file_name) {
-                    Ok(_) => println!("Table '{}' loaded from file '{}'.", table_name, file_name),
-                    Err(e) => {
-                        error!("Failed to load table '{}' from file: {}", table_name, e);
-                        return Err(e);
-                    }
-                }
-            } else {
-                error!("Table '{}' does not exist in memory or on disk.", table_name);
-                return Err(DatabaseError::TableDoesNotExist(table_name.to_string()));
-            }
+    // Returns the whole row after the update.
+    pub fn update_row(&mut self, table_name: &str, row_id: &str, column_name: &str, new_value: &str) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.update_row(row_id, column_name, new_value)
+    }
+
+    // Update several columns of a row at once: every value is checked before any is applied, and
+    // the change is logged as one WAL entry. Missing columns are added as for update_row.
+    // Returns the whole row after the update.
+    pub fn update_row_multi(&mut self, table_name: &str, row_id: &str, values: HashMap<String, String>) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.update_row_multi(row_id, values)
+    }
+
+    // Add `delta` to a numeric cell (a missing one counts as 0) while the table is held for
+    // writing, so concurrent counters never lose an update. Logged as an update_row of the result.
+    // Returns the whole row.
+    pub fn increment(&mut self, table_name: &str, row_id: &str, column_name: &str, delta: &str) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.increment(row_id, column_name, delta)
+    }
+
+    // Compare-and-swap: set the column only if its current value equals `expected` (NULL for a
+    // missing value), compared as the column's type. Returns whether the update was applied.
+    pub fn update_row_if(&mut self, table_name: &str, row_id: &str, column_name: &str, new_value: &str, expected: &str) -> Result<bool> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.update_row_if(row_id, column_name, new_value, expected)
+    }
+
+    // Delete a row: update in-memory table and log the operation.
+    pub fn delete_row(&mut self, table_name: &str, row_id: &str) -> Result<Vec<String>> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.delete_row(row_id)
+    }
+
+    /// Hold `table_name`, which must be in memory (see `ensure_table_loaded`), for writing its
+    /// rows. Needs only `&self`, so writes to different tables go on side by side while the
+    /// database is shared; readers of this table keep getting it as it was until the writer is
+    /// dropped, and then get every write it made at once. The first write copies the table.
+    pub fn row_writer<'a>(&'a self, table_name: &'a str) -> Result<RowWriter<'a>> {
+        let table = self.tables.write(table_name).ok_or_else(|| DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+        Ok(RowWriter { db: self, name: table_name, table })
+    }
+
+    /// `row_writer` for a write to row `row_id` alone. If the rows of the table are sharded (see
+    /// `Tables::set_row_shards`), only the shard of that row is held, so point writes to rows of
+    /// other shards go on side by side; a write that changes more than the row, e.g. adds a
+    /// column or keeps a primary key unique, waits to hold the whole table instead.
+    pub fn point_writer<'a>(&'a self, table_name: &'a str, row_id: &str) -> Result<RowWriter<'a>> {
+        let table = self.tables.write_row(table_name, row_id).ok_or_else(|| DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+        Ok(RowWriter { db: self, name: table_name, table })
+    }
+
+    // `row_writer` for the methods that hold the database exclusively: the table is written in
+    // place rather than copied (see `Tables::take`).
+    fn row_writer_mut<'a>(&'a mut self, table_name: &'a str) -> Result<RowWriter<'a>> {
+        let missing = || DatabaseError::TableDoesNotExist(table_name.to_string());
+        let table = self.tables.take(table_name).ok_or_else(missing)?;
+        let db = &*self;
+        let table = db.tables.write_taken(table_name, table).ok_or_else(missing)?;
+        Ok(RowWriter { db, name: table_name, table })
+    }
+
+    /// Split the rows of `table_name` across `shards` maps in memory, each written under a lock of
+    /// its own (see `point_writer`), so that point writes to a hot table scale with the cores
+    /// writing it. Kept while the database is open, also when the table is evicted and loaded
+    /// again, but not stored; 1 puts the rows back in one map.
+    pub fn set_row_shards(&mut self, table_name: &str, shards: usize) -> Result<usize> {
+        if !(1..=MAX_ROW_SHARDS).contains(&shards) {
+            return Err(DatabaseError::InvalidRowShards(shards.to_string(), MAX_ROW_SHARDS));
         }
-        // Now the table should be in memory.
-        if let Some(table) = self.tables.get_mut(table_name) {
-            // Ensure the column exists; add it if not.
-            if !table.columns.contains(&column_name.to_string()) {
-                table.add_column(column_name);
-                println!("Column '{}' was added to table '{}'", column_name, table_name);
-            }
-            if let Some(row) = table.rows.get_mut(row_id) {
-                // Update the row in place.
-                row.insert(column_name.to_string(), new_value.to_string());
-                
-                // Log the update operation in the WAL.
-                let op = format!(
-                    "update_row:{}:{}:{}:{}",
-                    table_name,
-                    row_id,
-                    column_name,
-                    serde_json::to_string(new_value).unwrap()
-                );
-                self.wal.push(op);
-                println!("Updated row '{}' in table '{}', column '{}' set to '{}'.", row_id, table_name, column_name, new_value);
-                self.save_table(table_name, &format!("{}.csv", table_name))?;
-                self.operations_since_save += 1;
-                if self.operations_since_save >= self.save_threshold {
-                    let file_name = format!("{}.csv", table_name);
-                    if let Err(e) = self.save_table(table_name, &file_name) {
-                        error!("Failed to save table '{}': {}", table_name, e);
-                    }
-                    self.operations_since_save = 0;
+        self.ensure_table_loaded(table_name)?;
+        self.tables.set_row_shards(table_name, shards);
+        info!(table = table_name, shards; "Row shards set");
+        Ok(shards)
+    }
+
+    // Change the type of a column, converting its values where possible (see `Value::convert`),
+    // persist the table and log it to the WAL. Cells that do not convert become NULL and are
+    // returned as (row_id, old value); that is refused for a NOT NULL column, as are a default
+    // that does not convert and a primary key whose converted values collide.
+    pub fn alter_column_type(&mut self, table_name: &str, column_name: &str, column_type: ColumnType) -> Result<Vec<(String, String)>> {
+        self.ensure_table_loaded(table_name)?;
+        let mut table = self.tables.get_mut(table_name)
+            .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+        if !table.columns.contains(column_name) {
+            error!("Column '{}' not found in table '{}'.", column_name, table_name);
+            return Err(DatabaseError::ColumnDoesNotExist(column_name.to_string(), table_name.to_string()));
+        }
+        let Conversion { converted, failed } = table.convert_column(column_name, column_type);
+        if let Some(default) = table.defaults.get(column_name).filter(|d| d.convert(column_type).is_none()) {
+            return Err(DatabaseError::InvalidValue(default.to_string(), column_name.to_string(), column_type));
+        }
+        if let Some((row_id, value)) = failed.first().filter(|_| table.not_null.contains(column_name)) {
+            return Err(DatabaseError::ConstraintViolation(format!(
+                "column '{}' is NOT NULL but the value '{}' of row '{}' cannot be converted to {}",
+                column_name, value, row_id, column_type
+            )));
+        }
+        if table.primary_key.as_deref() == Some(column_name) {
+            let mut seen: HashMap<String, &String> = HashMap::new();
+            for (row_id, key) in &converted {
+                if let Some(other) = seen.insert(key.to_string(), row_id) {
+                    return Err(DatabaseError::ConstraintViolation(format!(
+                        "rows '{}' and '{}' would share primary key '{}' as {}",
+                        other, row_id, key, column_type
+                    )));
                 }
-                Ok(vec![row_id.to_string(), column_name.to_string(), new_value.to_string()])
-            } else {
-                error!("Row '{}' does not exist in table '{}'.", row_id, table_name);
-                Err(DatabaseError::RowDoesNotExist(row_id.to_string(), table_name.to_string()))
             }
-        } else {
-            error!("Table '{}' is still not found after attempting to load.", table_name);
-            Err(DatabaseError::TableDoesNotExist(table_name.to_string()))
         }
+        table.set_column_type(column_name, column_type, converted);
+        drop(table);
+        self.log_change(format!("alter_column:{}:{}:{}", table_name, column_name, column_type));
+        info!(table = table_name, column = column_name, column_type:%, unconverted = failed.len(); "Column type changed and logged to WAL");
+        self.persist_table(table_name)?;
+        Ok(failed)
     }
 
-    // Save the table to a CSV file.
-    pub fn save_table(&self, table_name: &str, file_name: &str) -> Result<Vec<String>> {
-        match self.tables.get(table_name) {
-            Some(table) => {
-                let mut columns_in_order: Vec<_> = table.columns.iter().cloned().collect();
-                columns_in_order.sort();
-                let file_result = File::create(file_name);
-                match file_result {
-                    Ok(file) => {
-                        let mut writer = BufWriter::new(file);
-                        let header = {
-                            let mut hdr = vec!["row_id".to_string()];
-                            hdr.extend(columns_in_order.iter().cloned());
-                            hdr.join(",")
-                        };
-                        writeln!(writer, "{}", header).unwrap();
-                        for (row_id, row_data) in &table.rows {
-                            let mut row_vec = vec![row_id.clone()];
-                            for col in &columns_in_order {
-                                row_vec.push(row_data.get(col).cloned().unwrap_or_default());
-                            }
-                            writeln!(writer, "{}", row_vec.join(",")).unwrap();
-                        }
-                        println!("Table '{}' saved to '{}'.", table_name, file_name);
-                        Ok(vec![table_name.to_string(), file_name.to_string()])
-                    }
-                    Err(e) => {
-                        error!("Error creating file '{}': {}", file_name, e);
-                        Err(DatabaseError::FileCreationError(file_name.to_string(), e.to_string()))
-                    }
-                }
+    // Truncate a table: drop every row at once, persist the now empty table and log one WAL entry.
+    #[instrument(skip_all, fields(table = table_name))]
+    pub fn truncate_table(&mut self, table_name: &str) -> Result<Vec<String>> {
+        self.ensure_table_loaded(table_name)?;
+        let removed = self.tables.get_mut(table_name)
+            .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?
+            .truncate();
+        self.log_change(format!("truncate_table:{}", table_name));
+        info!(table = table_name, rows = removed; "Table truncated and logged to WAL");
+        self.persist_table(table_name)?;
+        Ok(vec![table_name.to_string(), removed.to_string()])
+    }
+
+    // Delete every row matching `condition` (see `search_rows_by_condition_in_table`), logging one
+    // delete_row entry per row. Returns how many rows were deleted.
+    pub fn delete_rows_where(&mut self, table_name: &str, condition: &str) -> Result<usize> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.delete_rows_where(condition)
+    }
+
+    // Give the table the expiry column if it does not have it yet.
+    pub fn enable_expiry(&mut self, table_name: &str) -> Result<()> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.enable_expiry()
+    }
+
+    // Delete the rows of the loaded tables that expired at or before `now`, logging one delete_row
+    // entry per row. Returns how many rows were deleted.
+    #[instrument(skip_all, fields(rows = Empty))]
+    pub fn delete_expired_rows(&mut self, now: i64) -> usize {
+        let names: Vec<String> = self.tables.keys().cloned().collect();
+        let mut deleted = 0;
+        for table_name in names {
+            let Some(mut table) = self.tables.get_mut(&table_name) else { continue };
+            let expired = table.expired_rows(now);
+            if expired.is_empty() {
+                continue;
             }
-            None => {
-                error!("Table '{}' does not exist.", table_name);
-                Err(DatabaseError::TableDoesNotExist(table_name.to_string()))
+            for row_id in &expired {
+                table.delete_row(row_id);
+            }
+            drop(table);
+            for row_id in &expired {
+                self.log_change(format!("delete_row:{}:{}", table_name, row_id));
             }
+            info!(table = table_name, rows = expired.len(); "Expired rows deleted and logged to WAL");
+            self.record_operation(&table_name);
+            deleted += expired.len();
+        }
+        record_rows(deleted);
+        deleted
+    }
+
+    // Soft-delete a row: stamp it in the tombstone column so queries skip it, keeping it stored
+    // until it is restored or purged.
+    pub fn soft_delete_row(&mut self, table_name: &str, row_id: &str) -> Result<Vec<String>> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.soft_delete_row(row_id)
+    }
+
+    // Bring back a soft-deleted row. Returns the row.
+    pub fn restore_row(&mut self, table_name: &str, row_id: &str) -> Result<Row> {
+        self.ensure_table_loaded(table_name)?;
+        self.row_writer_mut(table_name)?.restore_row(row_id)
+    }
+
+    // Delete every soft-deleted row for good, logging one delete_row entry per row.
+    // Returns how many rows were purged.
+    #[instrument(skip_all, fields(table = table_name, rows = Empty))]
+    pub fn purge_deleted(&mut self, table_name: &str) -> Result<usize> {
+        self.ensure_table_loaded(table_name)?;
+        let mut table = self.tables.get_mut(table_name)
+            .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+        let deleted: Vec<String> = table.rows.iter()
+            .filter(|(_, row)| row.contains_key(DELETED_COLUMN))
+            .map(|(row_id, _)| row_id.clone())
+            .collect();
+        for row_id in &deleted {
+            table.delete_row(row_id);
+        }
+        drop(table);
+        for row_id in &deleted {
+            self.log_change(format!("delete_row:{}:{}", table_name, row_id));
+        }
+        info!(table = table_name, rows = deleted.len(); "Deleted rows purged and logged to WAL");
+        record_rows(deleted.len());
+        if !deleted.is_empty() {
+            self.record_operation(table_name);
+        }
+        Ok(deleted.len())
+    }
+
+    // The columns and rows exported from `table` (see `export_source`): every stored row of a
+    // table, or a view's result, only those matching `condition` if given. Rows are read in place.
+    fn export_rows_in<'a>(&self, table: &'a Table, view: Option<&View>, condition: Option<&str>) -> Result<(Cow<'a, Table>, RowIter<'a>)> {
+        let Some(view) = view else {
+            let rows = match condition {
+                Some(condition) => Self::rows_matching(table, condition)?,
+                None => Box::new(table.rows.iter()),
+            };
+            return Ok((Cow::Borrowed(table), rows));
+        };
+        let (schema, rows) = views::scan(self, table, view, condition)?;
+        Ok((Cow::Owned(schema), rows))
+    }
+
+    // Save the table, or a view's result, to a CSV file (or a JSON, Parquet or Excel one, see
+    // `export_table_json`, `export_table_parquet` and `export_xlsx`). The file is replaced only
+    // once the new one is complete (see `storage::replace_file`).
+    pub fn save_table(&self, table_name: &str, file_name: &str) -> Result<Vec<String>> {
+        if json::Layout::of(file_name).is_some() {
+            return self.export_table_json(table_name, file_name);
+        }
+        if file_name.to_lowercase().ends_with(".parquet") {
+            return self.export_table_parquet(table_name, file_name);
+        }
+        if file_name.to_lowercase().ends_with(".xlsx") {
+            self.export_xlsx(&[table_name], None, file_name)?;
+            return Ok(vec![table_name.to_string(), file_name.to_string()]);
         }
+        self.with_table_to_save(table_name, |table| {
+            let written = storage::replace_file(file_name, |partial| {
+                File::create(partial)
+                    .and_then(|file| csv::write_to(table, BufWriter::new(file), &mut self.tracker(format!("export {}", table_name))))
+                    .map_err(|e| DatabaseError::FileCreationError(file_name.to_string(), e.to_string()))
+            });
+            if let Err(e) = &written {
+                error!("Error creating file '{}': {}", file_name, e);
+            }
+            written
+        })?;
+        info!(table = table_name, file = file_name; "Table saved to file");
+        Ok(vec![table_name.to_string(), file_name.to_string()])
     }
 
-    pub fn get_table(&self, table_name: &str) -> Result<&Table> {
+    /// The table as it is now: writes made meanwhile leave the returned version as it is.
+    pub fn get_table(&self, table_name: &str) -> Result<TableRef> {
         self.tables.get(table_name).ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))
     }
 
-    /// Finds rows by the given column having a specific value.
-    /// Returns a vector of tuples: (table_name, row_id, row_data).
-    /// If `return_many` is false, stops at the first match.
-    pub fn find_rows_by_value_in_table(&self, table_name: &str, column: &str, value: &str, return_many: bool) -> Result<Vec<(String, HashMap<String, String>)>> {
-        if let Some(table) = self.tables.get(table_name) {
-            let mut results = Vec::new();
-            for (row_id, row_data) in &table.rows {
-                if let Some(v) = row_data.get(column) {
-                    if v == value {
-                        results.push((row_id.clone(), row_data.clone()));
-                        if !return_many {
-                            break;
-                        }
-                    }
+    /// The table's declarations of `columns` only, without rows (see `Table::projection`).
+    /// Fails on a column the table does not have.
+    pub fn projection(&self, table_name: &str, columns: &[&str]) -> Result<Table> {
+        let table = self.get_table(table_name)?;
+        if let Some(missing) = columns.iter().find(|column| !table.columns.contains(**column)) {
+            return Err(DatabaseError::ColumnDoesNotExist(missing.to_string(), table_name.to_string()));
+        }
+        Ok(table.projection(columns))
+    }
+
+    /// A copy of the table with only `columns`, holding the rows that are not soft-deleted.
+    pub fn get_table_projected(&self, table_name: &str, columns: &[&str]) -> Result<Table> {
+        let mut projected = self.projection(table_name, columns)?;
+        for (row_id, row) in self.get_table(table_name)?.live_rows() {
+            let cells = projected.project_row(row);
+            projected.insert_values(row_id, cells);
+        }
+        Ok(projected)
+    }
+
+    /// A row with only the cells of `columns`.
+    pub fn get_row_projected(&self, table_name: &str, row_id: &str, columns: &[&str]) -> Result<Row> {
+        let projection = self.projection(table_name, columns)?;
+        let table = self.get_table(table_name)?;
+        let row = table.live_row(row_id)
+            .ok_or(DatabaseError::RowDoesNotExist(row_id.to_string(), table_name.to_string()))?;
+        Ok(projection.project_row(row))
+    }
+
+    /// A row deserialized into `T`, from its cells as `Table::row_to_json` gives them. NULL cells
+    /// are JSON `null`, so they fit `Option` fields.
+    pub fn get_row_as<T: DeserializeOwned>(&self, table_name: &str, row_id: &str) -> Result<T> {
+        let table = self.get_table(table_name)?;
+        let row = table.live_row(row_id).ok_or(DatabaseError::RowDoesNotExist(row_id.to_string(), table_name.to_string()))?;
+        serde_json::from_value(table.row_to_json(row)).map_err(|e| DatabaseError::RowConversionError(row_id.to_string(), e.to_string()))
+    }
+
+    /// The row whose primary key is `key`, found through the primary key index.
+    #[instrument(skip_all, fields(table = table_name))]
+    pub fn get_row_by_key(&self, table_name: &str, key: &str) -> Result<(String, Row)> {
+        let table = self.get_table(table_name)?;
+        let column = table.primary_key.as_deref().ok_or(DatabaseError::NoPrimaryKey(table_name.to_string()))?;
+        let key_value = table.parse_value(column, key)?;
+        table
+            .row_id_for_key(&key_value)
+            .and_then(|row_id| Some((row_id.clone(), table.live_row(row_id)?.clone())))
+            .ok_or(DatabaseError::RowDoesNotExist(key.to_string(), table_name.to_string()))
+    }
+
+    /// The distinct values of a column among the rows that are not soft-deleted, with how many rows
+    /// hold each, in ascending order (text by its characters). Rows without a value count towards
+    /// `Value::Null`, which comes last.
+    #[instrument(skip_all, fields(table = table_name, column = column_name))]
+    pub fn distinct(&self, table_name: &str, column_name: &str) -> Result<Vec<(Value, usize)>> {
+        let table = self.get_table(table_name)?;
+        if !table.columns.contains(column_name) {
+            return Err(DatabaseError::ColumnDoesNotExist(column_name.to_string(), table_name.to_string()));
+        }
+        // textual value -> (value, rows holding it)
+        type Counts = HashMap<String, (Value, usize)>;
+        let count = |mut counts: Counts, (_, row): (&String, &Row)| {
+            let value = row.get(column_name).cloned().unwrap_or(Value::Null);
+            counts.entry(value.to_string()).or_insert((value, 0)).1 += 1;
+            counts
+        };
+        let counts = if self.scans_in_parallel(&table) {
+            table.par_rows(false).fold(Counts::new, count).reduce(Counts::new, |mut counts, more| {
+                for (text, (value, n)) in more {
+                    counts.entry(text).or_insert((value, 0)).1 += n;
+                }
+                counts
+            })
+        } else {
+            table.live_rows().fold(Counts::new(), count)
+        };
+        let mut values: Vec<(Value, usize)> = counts.into_values().collect();
+        values.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Value::Null, _) | (_, Value::Null) => a.is_null().cmp(&b.is_null()),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            _ => a.compare(b).unwrap_or_else(|| a.to_string().cmp(&b.to_string())),
+        });
+        Ok(values)
+    }
+
+    /// Row count, per-column statistics and stored size of a table. Soft-deleted rows are left out.
+    /// Cardinality counts hashes of the values rather than the values themselves, so distinct values
+    /// may (rarely) collide.
+    #[instrument(skip_all, fields(table = table_name, rows = Empty))]
+    pub fn stats(&self, table_name: &str) -> Result<TableStats> {
+        let table = self.get_table(table_name)?;
+        // (rows, column -> (non-NULL rows, hashes of the values))
+        type Seen = (usize, HashMap<String, (usize, HashSet<u64>)>);
+        let none = || -> Seen { (0, table.columns.iter().map(|column| (column.clone(), (0, HashSet::new()))).collect()) };
+        let add = |(rows, mut seen): Seen, (_, row): (&String, &Row)| {
+            for (column, value) in row.iter().filter(|(_, value)| !value.is_null()) {
+                if let Some((non_null, hashes)) = seen.get_mut(&column[..]) {
+                    let mut hasher = DefaultHasher::new();
+                    value.to_string().hash(&mut hasher);
+                    hashes.insert(hasher.finish());
+                    *non_null += 1;
                 }
             }
-            Ok(results)
+            (rows + 1, seen)
+        };
+        let (rows, seen) = if self.scans_in_parallel(&table) {
+            table.par_rows(false).fold(none, add).reduce(none, |(rows, mut seen), (more_rows, more)| {
+                for (column, (non_null, hashes)) in more {
+                    let (total, all) = seen.entry(column).or_default();
+                    *total += non_null;
+                    all.extend(hashes);
+                }
+                (rows + more_rows, seen)
+            })
         } else {
-            Err(DatabaseError::TableDoesNotExist(table_name.to_string()))
-        }
+            table.live_rows().fold(none(), add)
+        };
+        let mut stats = TableStats {
+            rows,
+            stored_bytes: self.storage.lock().stored_size(table_name)?,
+            ..TableStats::default()
+        };
+        stats.columns = seen
+            .into_iter()
+            .map(|(column, (non_null, hashes))| (column, ColumnStats { non_null, cardinality: hashes.len() }))
+            .collect();
+        record_rows(stats.rows);
+        Ok(stats)
     }
 
+    /// One page of a table's rows in row_id order: up to `limit` rows after the row `cursor` (from
+    /// `page_cursor`) points at, or from the first row without one, keeping only the rows matching
+    /// `condition` if given.
+    #[instrument(skip_all, fields(table = table_name, rows = Empty))]
+    pub fn page_rows(&self, table_name: &str, limit: usize, cursor: Option<&str>, condition: Option<&str>) -> Result<Page> {
+        let table = self.get_table(table_name)?;
+        let after = cursor.map(decode_cursor).transpose()?;
+        let is_after = |row_id: &String| after.as_ref().is_none_or(|after| row_id > after);
+        let mut rows: Vec<(String, Row)> = match condition {
+            Some(condition) => self
+                .search_rows_by_condition_in_table(table_name, condition)?
+                .into_iter()
+                .filter(|(row_id, _)| is_after(row_id))
+                .take(limit + 1)
+                .collect(),
+            None => table
+                .live_rows()
+                .filter(|(row_id, _)| is_after(row_id))
+                .take(limit + 1)
+                .map(|(row_id, row)| (row_id.clone(), row.clone()))
+                .collect(),
+        };
+        let next = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|(row_id, _)| page_cursor(row_id))
+        } else {
+            None
+        };
+        record_rows(rows.len());
+        Ok(Page { rows, next })
+    }
 
     /// Searches rows by a simple condition.
     /// The condition should be in the format "column operator value", e.g., "age > 10" or "name == Alice".
-    /// Supported operators: "==", ">", "<", ">=", "<=".
-    /// Returns a vector of tuples: (table_name, row_id, row_data) for rows matching the condition.
-    pub fn search_rows_by_condition_in_table(&self, table_name: &str, condition: &str) -> Result<Vec<(String, HashMap<String, String>)>> {
-        if let Some(table) = self.tables.get(table_name) {
-            let parts: Vec<&str> = condition.split_whitespace().collect();
-            if parts.len() != 3 {
-                println!("Condition format invalid. Expected format: \"column operator value\"");
-                return Ok(Vec::new());
-            }
-            let col = parts[0];
-            let operator = parts[1];
-            let cond_value = parts[2];
-            let mut results = Vec::new();
-            for (row_id, row_data) in &table.rows {
-                if let Some(val) = row_data.get(col) {
-                    let condition_met = match operator {
-                        "==" => val == cond_value,
-                        ">" => {
-                            if let (Ok(num_val), Ok(num_cond)) = (val.parse::<f64>(), cond_value.parse::<f64>()) {
-                                num_val > num_cond
-                            } else {
-                                val.as_str() > cond_value
-                            }
-                        },
-                        "<" => {
-                            if let (Ok(num_val), Ok(num_cond)) = (val.parse::<f64>(), cond_value.parse::<f64>()) {
-                                num_val < num_cond
-                            } else {
-                                val.as_str() < cond_value
-                            }
-                        },
-                        ">=" => {
-                            if let (Ok(num_val), Ok(num_cond)) = (val.parse::<f64>(), cond_value.parse::<f64>()) {
-                                num_val >= num_cond
-                            } else {
-                                val.as_str() >= cond_value
-                            }
-                        },
-                        "<=" => {
-                            if let (Ok(num_val), Ok(num_cond)) = (val.parse::<f64>(), cond_value.parse::<f64>()) {
-                                num_val <= num_cond
-                            } else {
-                                val.as_str() <= cond_value
-                            }
-                        },
-                        _ => {
-                            println!("Unsupported operator: {}", operator);
-                            false
-                        }
-                    };
-                    if condition_met {
-                        results.push((row_id.clone(), row_data.clone()));
+    /// Supported operators: "==", "!=", ">", "<", ">=", "<=".
+    /// The value is parsed with the column's type, so typed columns compare as numbers, booleans
+    /// or timestamps (an int column also against a fractional number); a value that does not fit
+    /// the type is an invalid condition. Text columns compare numbers and dates by value, see
+    /// `Value::compare`.
+    /// "column IS NULL" and "column IS NOT NULL" test for missing values, which never match a
+    /// comparison.
+    /// Text operators (`CONTAINS`, `LIKE`, `~` and their case-insensitive forms) match the textual
+    /// form of the values instead; see `TextPattern`.
+    /// Returns a vector of tuples: (row_id, row_data) for rows matching the condition.
+    /// A large table (see `parallel_scan`) is scanned by several threads, with the same result.
+    #[instrument(skip_all, fields(table = table_name, rows = Empty))]
+    pub fn search_rows_by_condition_in_table(&self, table_name: &str, condition: &str) -> Result<Vec<(String, Row)>> {
+        let table = self.get_table(table_name)?;
+        let parts: Vec<&str> = condition.split_whitespace().collect();
+        let rows: Vec<(String, Row)> = if self.scans_in_parallel(&table) && Self::key_lookup(&table, &parts).is_none() {
+            let matches = Self::matcher(&table, condition)?;
+            table
+                .par_rows(parts.first() == Some(&DELETED_COLUMN))
+                .filter(|(_, row_data)| matches(row_data))
+                .map(|(row_id, row_data)| (row_id.clone(), row_data.clone()))
+                .collect()
+        } else {
+            Self::rows_matching(&table, condition)?
+                .map(|(row_id, row_data)| (row_id.clone(), row_data.clone()))
+                .collect()
+        };
+        record_rows(rows.len());
+        Ok(rows)
+    }
+
+    /// The rows of `table`, held by the caller, matching `condition` (as in
+    /// `search_rows_by_condition_in_table`), borrowed and filtered as the iterator is advanced,
+    /// so a caller can stream a large result without copying it.
+    pub fn rows_matching<'a>(table: &'a Table, condition: &str) -> Result<RowIter<'a>> {
+        let parts: Vec<&str> = condition.split_whitespace().collect();
+        // Soft-deleted rows only show up when searching on the tombstone column itself.
+        let rows: RowIter<'a> = if parts.first() == Some(&DELETED_COLUMN) {
+            Box::new(table.rows.iter())
+        } else {
+            Box::new(table.live_rows())
+        };
+        if let Some(key) = Self::key_lookup(table, &parts) {
+            let found = table.row_id_for_key(&key)
+                .and_then(|row_id| table.live_row(row_id).map(|row_data| (row_id, row_data)));
+            return Ok(Box::new(found.into_iter()));
+        }
+        let matches = Self::matcher(table, condition)?;
+        Ok(Box::new(rows.filter(move |(_, row_data)| matches(row_data))))
+    }
+
+    /// The primary key a condition (split into words) looks up, if `rows_matching` answers it
+    /// from the index rather than by a scan.
+    fn key_lookup(table: &Table, parts: &[&str]) -> Option<Value> {
+        // Equality on the primary key goes through its index, unless numeric-looking text
+        // could equal a key spelled differently (e.g. "1.0" and "1").
+        let [col, "==", operand] = parts[..] else {
+            return None;
+        };
+        let cond_value = Value::parse_operand(operand, table.column_type(col));
+        let numeric_text = matches!(&cond_value, Some(Value::Text(text)) if text.parse::<f64>().is_ok());
+        cond_value.filter(|_| table.primary_key.as_deref() == Some(col) && !numeric_text)
+    }
+
+    /// Run a search as `search_rows_by_condition_in_table` does, and report how: the access path
+    /// taken, how many rows the statistics (see `statistics::selectivity`) predicted and how many
+    /// it went through and matched, and the time spent planning, scanning
+    /// and copying out the matches. Callers that do more with the rows add their own stages.
+    #[instrument(skip_all, fields(table = table_name, rows = Empty))]
+    pub fn explain_analyze(&self, table_name: &str, condition: &str) -> Result<(Vec<(String, Row)>, QueryReport)> {
+        enum Access {
+            Key(Value),
+            Scan(RowMatcher),
+        }
+        let started = Instant::now();
+        let table = self.get_table(table_name)?;
+        let parts: Vec<&str> = condition.split_whitespace().collect();
+        let access = match Self::key_lookup(&table, &parts) {
+            Some(key) => Access::Key(key),
+            None => Access::Scan(Self::matcher(&table, condition)?),
+        };
+        let mut report = QueryReport {
+            table: table_name.to_string(),
+            condition: self.redaction.condition(table_name, condition),
+            access_path: match access {
+                Access::Key(_) => "primary key lookup",
+                Access::Scan(_) if self.scans_in_parallel(&table) => "parallel scan",
+                Access::Scan(_) => "full scan",
+            },
+            estimated_rows: statistics::selectivity(self, table_name, condition)
+                .map(|share| (share * table.live_rows().count() as f64).round() as usize),
+            rows_scanned: 0,
+            index_hits: 0,
+            rows_matched: 0,
+            stages: vec![("plan", started.elapsed())],
+        };
+
+        let started = Instant::now();
+        let mut found: Vec<(&String, &Row)> = Vec::new();
+        match access {
+            Access::Key(key) => {
+                if let Some(row_id) = table.row_id_for_key(&key) {
+                    report.index_hits += 1;
+                    report.rows_scanned += 1;
+                    found.extend(table.live_row(row_id).map(|row_data| (row_id, row_data)));
+                }
+            }
+            Access::Scan(matches) if self.scans_in_parallel(&table) => {
+                let deleted = parts.first() == Some(&DELETED_COLUMN);
+                report.rows_scanned = table.par_rows(deleted).count();
+                found = table.par_rows(deleted).filter(|(_, row_data)| matches(row_data)).collect();
+            }
+            Access::Scan(matches) => {
+                let rows: RowIter = if parts.first() == Some(&DELETED_COLUMN) {
+                    Box::new(table.rows.iter())
+                } else {
+                    Box::new(table.live_rows())
+                };
+                for (row_id, row_data) in rows {
+                    report.rows_scanned += 1;
+                    if matches(row_data) {
+                        found.push((row_id, row_data));
                     }
                 }
             }
-            Ok(results)
-        } else {
-            Err(DatabaseError::TableDoesNotExist(table_name.to_string()))
         }
+        report.rows_matched = found.len();
+        record_rows(found.len());
+        report.stages.push(("scan", started.elapsed()));
+
+        let started = Instant::now();
+        let rows = found.into_iter().map(|(row_id, row_data)| (row_id.clone(), row_data.clone())).collect();
+        report.stages.push(("fetch", started.elapsed()));
+        Ok((rows, report))
+    }
+
+    /// Health of the whole database: uptime, tables, rows, WAL backlog, last checkpoint and disk
+    /// usage. Cheap enough to poll, so rows are only counted in the tables already in memory.
+    pub fn status(&self) -> Result<Status> {
+        let mut names: HashSet<String> = self.storage.lock().table_names()?.into_iter().collect();
+        let mut disk_bytes = 0;
+        for name in &names {
+            disk_bytes += self.storage.lock().stored_size(name)?.unwrap_or(0);
+        }
+        for file in [&self.wal_file, &self.wal_archive_file] {
+            disk_bytes += std::fs::metadata(file).map_or(0, |metadata| metadata.len());
+        }
+        names.extend(self.tables.keys().cloned());
+        Ok(Status {
+            uptime: STARTED.elapsed(),
+            tables: names.len(),
+            tables_in_memory: self.tables.keys().len(),
+            rows: self.tables.iter().map(|(_, table)| table.live_rows().count()).sum(),
+            wal_backlog: self.wal.lock().len(),
+            wal_lsn: self.wal_lsn,
+            last_checkpoint: self.last_checkpoint,
+            disk_bytes,
+            memory_bytes: self.memory_bytes(),
+            memory_limit: self.memory_limit,
+            wal_engine: self.wal_engine.clone(),
+        })
+    }
+
+    /// A test of one row of the table against `condition` (as in
+    /// `search_rows_by_condition_in_table`), whether or not the row is soft-deleted.
+    pub fn row_matcher(&self, table_name: &str, condition: &str) -> Result<RowMatcher> {
+        Self::matcher(&*self.get_table(table_name)?, condition)
+    }
+
+    /// `row_matcher` for `table`, held by the caller.
+    fn matcher(table: &Table, condition: &str) -> Result<RowMatcher> {
+        let parts: Vec<String> = condition.split_whitespace().map(str::to_string).collect();
+        let null_test = match &parts[..] {
+            [_, is, null] if is.eq_ignore_ascii_case("is") && null.eq_ignore_ascii_case("null") => Some(true),
+            [_, is, not, null]
+                if is.eq_ignore_ascii_case("is") && not.eq_ignore_ascii_case("not") && null.eq_ignore_ascii_case("null") =>
+            {
+                Some(false)
+            }
+            _ => None,
+        };
+        if let Some(want_null) = null_test {
+            let col = parts[0].clone();
+            return Ok(Box::new(move |row_data: &Row| row_data.contains_key(col.as_str()) != want_null));
+        }
+        if parts.len() != 3 {
+            warn!("Condition format invalid. Expected format: \"column operator value\"");
+            return Ok(Box::new(|_: &Row| false));
+        }
+        let col = parts[0].clone();
+        let operator = parts[1].clone();
+        if let Some(pattern) = TextPattern::parse(&operator, &parts[2]) {
+            let pattern = pattern.map_err(|_| DatabaseError::InvalidCondition(condition.to_string()))?;
+            return Ok(Box::new(move |row_data: &Row| row_data.get(col.as_str()).is_some_and(|val| pattern.matches(val))));
+        }
+        let cond_value = Value::parse_operand(&parts[2], table.column_type(&col))
+            .ok_or_else(|| DatabaseError::InvalidCondition(condition.to_string()))?;
+        if !matches!(operator.as_str(), "==" | "!=" | ">" | "<" | ">=" | "<=") {
+            warn!(operator; "Unsupported operator");
+            return Ok(Box::new(|_: &Row| false));
+        }
+        Ok(Box::new(move |row_data: &Row| {
+            let Some(ordering) = row_data.get(col.as_str()).and_then(|val| val.compare(&cond_value)) else {
+                return false;
+            };
+            match operator.as_str() {
+                "==" => ordering.is_eq(),
+                "!=" => ordering.is_ne(),
+                ">" => ordering.is_gt(),
+                "<" => ordering.is_lt(),
+                ">=" => ordering.is_ge(),
+                _ => ordering.is_le(),
+            }
+        }))
     }
 
     // --- WAL functions ---
-    // flush_wal() replays all in‑memory operations.
+    // flush_wal() replays all in‑memory operations onto the tables, loading the ones storage holds
+    // but memory does not yet, e.g. after a restart. Every table replay touches is marked dirty,
+    // so the next save writes it before the WAL is cleared.
     pub fn flush_wal(&mut self) -> Result<()> {
-        for entry in &self.wal {
-            let parts: Vec<&str> = entry.split(':').collect();
-            match parts[0] {
-                "create_table" => {
-                    // Already applied during create_table.
-                    println!("Replay: Table '{}' exists.", parts[1]);
+        // Taken out while replaying, since replay reads tables through `self`.
+        let wal = std::mem::take(self.wal.get_mut());
+        for entry in &wal {
+            let Some((table_name, kind)) = ChangeKind::parse(entry) else {
+                warn!(entry = self.redaction.wal_entry(entry); "Unknown WAL entry");
+                continue;
+            };
+            if let Err(e) = self.replay_change(&table_name, kind) {
+                error!("Replay: {}", e);
+            }
+        }
+        *self.wal.get_mut() = wal;
+        Ok(())
+    }
+
+    /// Whether `table_name` is in memory or in storage.
+    fn table_exists(&mut self, table_name: &str) -> bool {
+        self.check_table(table_name) || matches!(self.storage.get_mut().stored_size(table_name), Ok(Some(_)))
+    }
+
+    /// Load `table_name` for replay and mark it dirty; false if it exists nowhere, e.g. because a
+    /// later entry renamed or dropped it.
+    fn replay_table(&mut self, table_name: &str) -> bool {
+        if !self.table_exists(table_name) || self.ensure_table_loaded(table_name).is_err() {
+            debug!(table = table_name; "Replay: table gone");
+            return false;
+        }
+        self.dirty.get_mut().insert(table_name.to_string());
+        true
+    }
+
+    /// Apply one change to the tables as `flush_wal` replays it: changes already applied are
+    /// skipped or applied again to the same effect, and nothing is logged.
+    fn replay_change(&mut self, table_name: &str, kind: ChangeKind) -> Result<()> {
+        match kind {
+            ChangeKind::CreateTable => {
+                if !self.replay_table(table_name) {
+                    self.tables.insert(table_name.to_string(), Table::new());
+                    self.dirty.get_mut().insert(table_name.to_string());
+                    debug!(table = table_name; "Replay: table created");
                 }
-                "add_column" => {
-                    if let Some(table) = self.tables.get_mut(parts[1]) {
-                        table.add_column(parts[2]);
-                        println!("Replay: Column '{}' added to table '{}'.", parts[2], parts[1]);
+            }
+            ChangeKind::RenameTable { new_name } => {
+                if !self.table_exists(&new_name) && self.replay_table(table_name) {
+                    if self.storage.get_mut().stored_size(table_name)?.is_some() {
+                        self.storage.get_mut().rename_table(table_name, &new_name)?;
                     }
+                    let table = self.tables.remove(table_name)
+                        .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+                    let shards = self.tables.row_shards(table_name);
+                    self.tables.set_row_shards(table_name, 1);
+                    self.tables.set_row_shards(&new_name, shards);
+                    self.tables.insert(new_name.clone(), table);
+                    self.dirty.get_mut().remove(table_name);
+                    self.dirty.get_mut().insert(new_name.clone());
+                    debug!(table = table_name, new_name; "Replay: table renamed");
                 }
-                "insert_row" => {
-                    let table_name = parts[1];
-                    let row_id = parts[2];
-                    match serde_json::from_str::<HashMap<String, String>>(parts[3]) {
-                        Ok(data) => {
-                            if let Some(table) = self.tables.get_mut(table_name) {
-                                table.insert_row(row_id, data);
-                                println!("Replay: Row '{}' inserted into table '{}'.", row_id, table_name);
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to deserialize row data for table '{}': {}", table_name, e);
+            }
+            ChangeKind::DropTable => {
+                self.dirty.get_mut().remove(table_name);
+                if self.tables.remove(table_name).is_some() {
+                    debug!(table = table_name; "Replay: table dropped");
+                }
+            }
+            ChangeKind::CopyTable { new_name, condition } => {
+                if !self.table_exists(&new_name) && self.replay_table(table_name) {
+                    let mut copy = (*self.get_table(table_name)?).clone();
+                    if let Some(condition) = condition {
+                        let rows = self.search_rows_by_condition_in_table(table_name, &condition)?;
+                        copy.truncate();
+                        for (row_id, row) in rows {
+                            copy.insert_values(&row_id, row);
                         }
                     }
+                    self.tables.insert(new_name.clone(), copy);
+                    self.dirty.get_mut().insert(new_name.clone());
+                    debug!(table = table_name, destination = new_name; "Replay: table copied");
+                }
+            }
+            kind => {
+                if !self.replay_table(table_name) {
+                    return Ok(());
                 }
-                "update_row" => {
-                    // Expected format: update_row:{table_name}:{row_id}:{column_name}:{new_value_json}
-                    if parts.len() < 5 {
-                        error!("Malformed WAL entry: {}", entry);
-                        continue;
+                let mut table = self.tables.get_mut(table_name)
+                    .ok_or(DatabaseError::TableDoesNotExist(table_name.to_string()))?;
+                match kind {
+                    ChangeKind::AddColumn { column, spec } => {
+                        table.apply_spec(&column, &spec)?;
+                        table.fill_default(&column);
+                        debug!(table = table_name, column; "Replay: column added");
                     }
-                    let table_name = parts[1];
-                    let row_id = parts[2];
-                    let column_name = parts[3];
-                    // Deserialize the new_value
-                    let new_value: String = serde_json::from_str(parts[4])
-                        .unwrap_or_else(|_| parts[4].to_string());
-                    if let Some(table) = self.tables.get_mut(table_name) {
-                        if let Some(row) = table.rows.get_mut(row_id) {
-                            row.insert(column_name.to_string(), new_value.clone());
-                            println!("Replay: Row '{}' in table '{}' updated column '{}' to '{}'.",
-                                row_id, table_name, column_name, new_value);
-                        } else {
-                            error!("Replay: Row '{}' not found in table '{}'.", row_id, table_name);
+                    ChangeKind::DropColumn { column } => {
+                        table.drop_column(&column);
+                        debug!(table = table_name, column; "Replay: column dropped");
+                    }
+                    ChangeKind::RenameColumn { column, new_name } => {
+                        if !table.columns.contains(&new_name) && table.rename_column(&column, &new_name) {
+                            debug!(table = table_name, column, new_name; "Replay: column renamed");
                         }
-                    } else {
-                        error!("Replay: Table '{}' not found.", table_name);
                     }
-                }
-                _ => {
-                    println!("Unknown WAL entry: {}", entry);
+                    ChangeKind::AlterColumn { column, column_type } => {
+                        let conversion = table.convert_column(&column, column_type);
+                        table.set_column_type(&column, column_type, conversion.converted);
+                        debug!(table = table_name, column, column_type:%; "Replay: column type changed");
+                    }
+                    ChangeKind::Truncate => {
+                        table.truncate();
+                        debug!(table = table_name; "Replay: table truncated");
+                    }
+                    ChangeKind::Insert { row_id, data } | ChangeKind::Upsert { row_id, data } => {
+                        table.insert_row(&row_id, data)?;
+                        debug!(table = table_name, row_id; "Replay: row inserted");
+                    }
+                    ChangeKind::Replace { row_id, data } => {
+                        table.restore_row(&row_id, None);
+                        table.insert_row(&row_id, data)?;
+                        debug!(table = table_name, row_id; "Replay: row replaced");
+                    }
+                    ChangeKind::Update { row_id, column, value } => {
+                        if !table.set_value(&row_id, &column, &value)? {
+                            return Err(DatabaseError::RowDoesNotExist(row_id, table_name.to_string()));
+                        }
+                        debug!(table = table_name, row_id, column,
+                            value = self.redaction.value(table_name, &column, &value); "Replay: row updated");
+                    }
+                    ChangeKind::UpdateMulti { row_id, values } => {
+                        for column in values.keys() {
+                            table.add_column(column);
+                        }
+                        table.insert_row(&row_id, values)?;
+                        debug!(table = table_name, row_id; "Replay: row updated");
+                    }
+                    ChangeKind::Delete { row_id } => {
+                        table.delete_row(&row_id);
+                        debug!(table = table_name, row_id; "Replay: row deleted");
+                    }
+                    ChangeKind::CreateTable | ChangeKind::RenameTable { .. } | ChangeKind::DropTable | ChangeKind::CopyTable { .. } => {}
                 }
             }
         }
         Ok(())
     }
 
-        // Call this after a set of operations has been committed.
-        pub fn commit_wal(&mut self) -> Result<()> {
-            // Append the current in‑memory WAL entries to the archive file.
-            let archive_file = "wal_archive.log".to_string();
-            let archive = OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(&archive_file)
-                .map_err(|err| DatabaseError::FileCreationError(archive_file.clone(), err.to_string()))?;
-            let mut archive_writer = BufWriter::new(archive);
-            for entry in &self.wal {
-                writeln!(archive_writer, "{}", entry)
-                    .map_err(|err| DatabaseError::FileCreationError(archive_file.clone(), err.to_string()))?;
-            }
-            archive_writer.flush().unwrap();
-            println!("WAL entries committed to archive '{}'.", archive_file);
-    
-            // Now clear the persistent WAL:
-            self.wal.clear();
-            // Truncate the working persistent WAL file by creating a new file.
-            File::create(&self.wal_file)
-                .map_err(|err| DatabaseError::FileCreationError(self.wal_file.clone(), err.to_string()))?;
-            println!("Persistent WAL '{}' cleared.", self.wal_file);
-            Ok(())
-        }
-
-    // persist_wal() writes the in‑memory WAL to disk in append mode.
-    pub fn persist_wal(&self) -> Result<()> {
-        let file = OpenOptions::new()
+    // Call this after a set of operations has been committed.
+    #[instrument(skip_all, fields(rows = Empty))]
+    pub fn commit_wal(&mut self) -> Result<()> {
+        // Append the current in‑memory WAL entries to the archive file.
+        record_rows(self.wal.get_mut().len());
+        let archive_file = self.wal_archive_file.clone();
+        let archive = OpenOptions::new()
             .append(true)
             .create(true)
-            .open(&self.wal_file)
-            .map_err(|err| DatabaseError::FileCreationError(self.wal_file.to_string(), err.to_string()))?;
-        let mut writer = BufWriter::new(file);
-        for entry in &self.wal {
-            writeln!(writer, "{}", entry)
-                .map_err(|err| DatabaseError::FileCreationError(self.wal_file.to_string(), err.to_string()))?;
+            .open(&archive_file)
+            .map_err(|err| DatabaseError::FileCreationError(archive_file.clone(), err.to_string()))?;
+        let mut archive_writer = BufWriter::new(archive);
+        for entry in self.wal.get_mut().iter() {
+            writeln!(archive_writer, "{}", encryption::seal(entry))
+                .map_err(|err| DatabaseError::FileCreationError(archive_file.clone(), err.to_string()))?;
         }
-        writer.flush().unwrap();
-        println!("WAL persisted to {}", self.wal_file);
+        archive_writer.flush().map_err(|err| DatabaseError::FileCreationError(archive_file.clone(), err.to_string()))?;
+        debug!(file = archive_file; "WAL entries committed to archive");
+
+        // Now clear the persistent WAL:
+        self.changes.publish(self.wal_lsn + 1, self.wal.get_mut());
+        self.wal_lsn += self.wal.get_mut().len() as u64;
+        self.wal.get_mut().clear();
+        // Truncate the working persistent WAL file by creating a new file.
+        File::create(&self.wal_file)
+            .map_err(|err| DatabaseError::FileCreationError(self.wal_file.clone(), err.to_string()))?;
+        *self.wal_synced.lock() = self.wal_lsn;
+        debug!(file = self.wal_file; "Persistent WAL cleared");
+        self.last_checkpoint = Some(value::now());
         Ok(())
     }
 
+    /// How many entries are waiting to be archived, and how many bytes they take, unencrypted.
+    pub fn wal_pending(&self) -> (usize, usize) {
+        let wal = self.wal.lock();
+        (wal.len(), wal.iter().map(|entry| entry.len() + 1).sum())
+    }
+
+    // persist_wal() appends the in‑memory WAL entries the WAL file does not hold yet to it, in one
+    // buffered write and one fsync: everything concurrent writers logged since the last call is
+    // made durable together (group commit). Returns how many entries were written.
+    #[instrument(skip_all, fields(rows = Empty))]
+    pub fn persist_wal(&self) -> Result<usize> {
+        let mut synced = self.wal_synced.lock();
+        let from = synced.saturating_sub(self.wal_lsn) as usize;
+        let entries: Vec<String> = self.wal.lock().get(from..).unwrap_or_default().to_vec();
+        record_rows(entries.len());
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        append_lines(&self.wal_file, &entries)?;
+        *synced = self.wal_lsn + (from + entries.len()) as u64;
+        debug!(file = self.wal_file, lsn = *synced; "WAL persisted");
+        Ok(entries.len())
+    }
+
+    // Check the stored tables and both WAL files for torn writes, damage and entries replay
+    // would not understand (see `StorageEngine::verify` and `verify::check_wal`). With `repair`
+    // interrupted writes are finished or thrown away and a torn last WAL entry is cut off;
+    // only do that while nothing else writes, e.g. before `load_wal`.
+    pub fn verify(&mut self, repair: bool) -> Result<Vec<Issue>> {
+        let mut issues = self.storage.get_mut().verify(repair)?;
+        issues.extend(verify::check_wal(&self.wal_file, repair)?);
+        issues.extend(verify::check_wal(&self.wal_archive_file, repair)?);
+        Ok(issues)
+    }
+
+    // Copy the archived WAL entries after LSN `from` into a new segment file in `dir`, for
+    // shipping the archive elsewhere (see `wal_segments`). Without `from` the export carries on
+    // after the last segment already in `dir`, so exporting to the same place again only adds
+    // what was archived since. Entries not archived yet are left for a later export. Returns the
+    // segment written, or None if there was nothing new.
+    pub fn export_wal(&self, dir: &str, from: Option<u64>) -> Result<Option<Segment>> {
+        let from = match from {
+            Some(from) => from,
+            None => wal_segments::list(dir)?.last().map_or(0, |segment| segment.last),
+        };
+        let entries: Vec<String> = match File::open(&self.wal_archive_file) {
+            Ok(archive) => std::io::BufReader::new(archive)
+                .lines()
+                .skip(from as usize)
+                .take(self.wal_lsn.saturating_sub(from) as usize)
+                .map(|line| {
+                    let line = line.map_err(|e| DatabaseError::FileCreationError(self.wal_archive_file.clone(), e.to_string()))?;
+                    let entry = encryption::open(&line).map_err(|e| DatabaseError::Undecryptable(self.wal_archive_file.clone(), e))?;
+                    Ok(entry.into_owned())
+                })
+                .collect::<Result<_>>()?,
+            Err(_) => Vec::new(),
+        };
+        if entries.is_empty() {
+            info!(lsn = from; "No WAL entries archived after LSN to export");
+            return Ok(None);
+        }
+        let segment = wal_segments::write(dir, from + 1, &entries)?;
+        info!(first_lsn = segment.first, last_lsn = segment.last, file = segment.path; "WAL entries exported");
+        Ok(Some(segment))
+    }
+
+    // Bring the database up to date from the WAL segments in `dir`: every entry after the current
+    // LSN is applied as a replica applies it (see `apply_wal_entry`) and logged again exactly as
+    // it was, so the entries keep their LSNs. A fresh database is rebuilt from the archive alone.
+    // The segments are checked for gaps and unreadable entries before anything is applied.
+    // Returns the number of entries applied.
+    pub fn import_wal(&mut self, dir: &str) -> Result<usize> {
+        let entries = wal_segments::read_after(dir, self.current_lsn())?;
+        for entry in &entries {
+            let logged = self.wal.get_mut().len();
+            self.apply_wal_entry(entry)?;
+            self.wal.get_mut().truncate(logged);
+            self.wal.get_mut().push(entry.clone());
+        }
+        info!(entries = entries.len(), dir, lsn = self.current_lsn(); "WAL entries applied");
+        Ok(entries.len())
+    }
+
     // load_wal() reads existing WAL operations from disk.
+    #[instrument(skip_all, fields(rows = Empty))]
     pub fn load_wal(&mut self) -> Result<()> {
+        // Every archived line is one committed entry, so the archive length is the last LSN.
+        if let Ok(archive) = File::open(&self.wal_archive_file) {
+            self.wal_lsn = std::io::BufReader::new(archive).lines().count() as u64;
+        }
         let file = File::open(&self.wal_file);
         if let Ok(file) = file {
             let reader = std::io::BufReader::new(file);
-            for line in reader.lines() {
-                if let Ok(entry) = line {
-                    self.wal.push(entry);
-                }
+            for line in reader.lines().map_while(std::result::Result::ok) {
+                let entry = encryption::open(&line).map_err(|e| DatabaseError::Undecryptable(self.wal_file.clone(), e))?;
+                self.wal.get_mut().push(entry.into_owned());
             }
+            *self.wal_synced.lock() = self.wal_lsn + self.wal.get_mut().len() as u64;
             // Replay loaded WAL to update in‑memory state.
+            record_rows(self.wal.get_mut().len());
             self.flush_wal()?;
         } else {
-            println!("No WAL file found. Starting fresh.");
+            info!("No WAL file found. Starting fresh.");
         }
         Ok(())
     }
 
     // clear_wal() clears both the in‑memory WAL and truncates the WAL file.
     pub fn clear_wal(&mut self) -> Result<()> {
-        self.wal.clear();
+        self.wal.get_mut().clear();
         File::create(&self.wal_file)
             .map_err(|err| DatabaseError::FileCreationError(self.wal_file.to_string(), err.to_string()))?;
-        println!("WAL cleared.");
+        *self.wal_synced.lock() = self.wal_lsn;
+        info!("WAL cleared.");
+        Ok(())
+    }
+
+    /// LSN of the newest logged operation, including entries not archived yet.
+    pub fn current_lsn(&self) -> u64 {
+        self.wal_lsn + self.wal.lock().len() as u64
+    }
+
+    /// Merge a divergent copy of a table into it: `theirs_file` is a CSV snapshot, or a WAL segment
+    /// (`.log`) replayed on the base. `base_file` is the common ancestor; without it the current
+    /// table is the base for WAL segments and CSV snapshots are merged two-way.
+    /// Conflicts are settled last-writer-wins in favour of the incoming copy (see
+    /// `merge_table_with`). The differences are applied through the regular write methods, so
+    /// they are logged like any other write.
+    pub fn merge_table(&mut self, table_name: &str, theirs_file: &str, base_file: Option<&str>) -> Result<Vec<(Conflict, Resolution)>> {
+        self.merge_table_with(table_name, theirs_file, base_file, merge::last_writer_wins)
+    }
+
+    /// `merge_table`, with every conflict settled by `resolve` instead.
+    pub fn merge_table_with(
+        &mut self,
+        table_name: &str,
+        theirs_file: &str,
+        base_file: Option<&str>,
+        resolve: impl Fn(&Conflict) -> Resolution,
+    ) -> Result<Vec<(Conflict, Resolution)>> {
+        self.ensure_table_loaded(table_name)?;
+        let ours = self.get_table(table_name)?.clone();
+        let base = base_file.map(csv::read_table).transpose()?;
+        let theirs = if theirs_file.ends_with(".log") {
+            let file = File::open(theirs_file)
+                .map_err(|e| DatabaseError::FileCreationError(theirs_file.to_string(), e.to_string()))?;
+            let entries: Vec<String> = std::io::BufReader::new(file)
+                .lines()
+                .map_while(std::result::Result::ok)
+                .map(|line| encryption::open(&line).map(Cow::into_owned).map_err(|e| DatabaseError::Undecryptable(theirs_file.to_string(), e)))
+                .collect::<Result<_>>()?;
+            merge::apply_wal_segment(base.as_ref().unwrap_or(&ours), table_name, &entries)?
+        } else {
+            csv::read_table(theirs_file)?
+        };
+
+        let outcome = merge::merge_tables(base.as_ref(), &ours, &theirs, resolve);
+        for column in outcome.table.columns.difference(&ours.columns) {
+            self.add_typed_column(table_name, column, outcome.table.spec(column))?;
+        }
+        for row_id in ours.rows.keys().filter(|id| !outcome.table.rows.contains_key(id)) {
+            self.delete_row(table_name, row_id)?;
+        }
+        for (row_id, row) in &outcome.table.rows {
+            match ours.rows.get(row_id) {
+                Some(old) if old == row => continue,
+                // `insert_row` merges cells, so drop the old row first when cells went away.
+                Some(old) if old.keys().any(|col| !row.contains_key(col)) => {
+                    self.delete_row(table_name, row_id)?;
+                }
+                _ => {}
+            }
+            self.upsert_row(table_name, row_id, table::row_to_text(row))?;
+        }
+        info!(table = table_name, file = theirs_file, conflicts = outcome.conflicts.len(); "Merged file into table");
+        Ok(outcome.conflicts)
+    }
+
+    /// Receive every change to `table` once its WAL entry is committed to the archive.
+    /// Dropping the receiver ends the subscription.
+    pub fn subscribe_changes(&mut self, table: &str) -> tokio::sync::mpsc::UnboundedReceiver<ChangeEvent> {
+        self.changes.subscribe(table)
+    }
+
+    /// Run `callback` for every change to `table` ("*" for every table) once its WAL entry is
+    /// committed to the archive, as `subscribe_changes` delivers it, e.g. to invalidate a cache.
+    /// Changes rolled back before then are never seen. It runs with the database locked, so it
+    /// must not use the database; hand the event to another thread for anything slow. A hook
+    /// stays with the table name, so after `RENAME TABLE` it follows the name, not the table.
+    pub fn on_change(&mut self, table: &str, callback: impl Fn(&ChangeEvent) + Send + Sync + 'static) {
+        self.changes.hooks.push((table.to_string(), Box::new(callback)));
+    }
+
+    /// Apply one WAL entry through the regular write methods, so it is logged and persisted
+    /// like a local write (used by replicas).
+    pub fn apply_wal_entry(&mut self, entry: &str) -> Result<()> {
+        let (table_name, kind) =
+            ChangeKind::parse(entry).ok_or_else(|| DatabaseError::InvalidWalEntry(entry.to_string()))?;
+        match kind {
+            ChangeKind::CreateTable => {
+                // The table may exist on disk without being loaded yet.
+                if self.ensure_table_loaded(&table_name).is_err() {
+                    self.create_table(&table_name)?;
+                }
+            }
+            ChangeKind::RenameTable { new_name } => {
+                self.rename_table(&table_name, &new_name)?;
+            }
+            ChangeKind::DropTable => {
+                self.drop_table(&table_name)?;
+            }
+            ChangeKind::CopyTable { new_name, condition } => {
+                self.copy_table(&table_name, &new_name, condition.as_deref())?;
+            }
+            ChangeKind::AddColumn { column, spec } => {
+                self.add_typed_column(&table_name, &column, spec)?;
+            }
+            ChangeKind::DropColumn { column } => {
+                self.drop_column(&table_name, &column)?;
+            }
+            ChangeKind::RenameColumn { column, new_name } => {
+                self.rename_column(&table_name, &column, &new_name)?;
+            }
+            ChangeKind::AlterColumn { column, column_type } => {
+                self.alter_column_type(&table_name, &column, column_type)?;
+            }
+            ChangeKind::Truncate => {
+                self.truncate_table(&table_name)?;
+            }
+            // Entries logged before inserts became strict may overwrite, so replay them as upserts.
+            ChangeKind::Insert { row_id, data } | ChangeKind::Upsert { row_id, data } => {
+                self.upsert_row(&table_name, &row_id, data)?;
+            }
+            ChangeKind::Replace { row_id, data } => {
+                self.replace_row(&table_name, &row_id, data)?;
+            }
+            ChangeKind::Update { row_id, column, value } => {
+                self.update_row(&table_name, &row_id, &column, &value)?;
+            }
+            ChangeKind::UpdateMulti { row_id, values } => {
+                self.update_row_multi(&table_name, &row_id, values)?;
+            }
+            ChangeKind::Delete { row_id } => {
+                self.delete_row(&table_name, &row_id)?;
+            }
+        }
         Ok(())
     }
 
@@ -543,4 +2204,394 @@ impl Database {
         self.flush_wal()?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// **Row writer**
+/// One table in memory held for writing its rows, as handed out by `Database::row_writer`. Its
+/// writes are checked, logged to the WAL, persisted and counted towards `save_threshold` exactly
+/// as the `Database` methods of the same names describe, which go through it.
+pub struct RowWriter<'a> {
+    db: &'a Database,
+    name: &'a str,
+    table: TableWriteRef<'a>,
+}
+
+impl RowWriter<'_> {
+    #[instrument(skip_all, fields(table = self.name, row_id = row_id))]
+    pub fn insert_row(&mut self, row_id: &str, data: HashMap<String, String>) -> Result<Row> {
+        self.write_row("insert_row", row_id, data)
+    }
+
+    #[instrument(skip_all, fields(table = self.name, row_id = row_id))]
+    pub fn upsert_row(&mut self, row_id: &str, data: HashMap<String, String>) -> Result<Row> {
+        self.write_row("upsert_row", row_id, data)
+    }
+
+    #[instrument(skip_all, fields(table = self.name, row_id = row_id))]
+    pub fn replace_row(&mut self, row_id: &str, data: HashMap<String, String>) -> Result<Row> {
+        self.write_row("replace_row", row_id, data)
+    }
+
+    // Shared by insert_row, upsert_row and replace_row; `op` is also the WAL op-code.
+    // Returns the row as stored, defaults included.
+    fn write_row(&mut self, op: &str, row_id: &str, mut data: HashMap<String, String>) -> Result<Row> {
+        self.hold(row_id, self.table.primary_key.is_some());
+        let (table_name, table) = (self.name, &mut *self.table);
+        let previous = table.get_row(row_id).cloned();
+        if previous.is_some() && op == "insert_row" {
+            error!("Row '{}' already exists in table '{}'.", row_id, table_name);
+            return Err(DatabaseError::RowAlreadyExists(row_id.to_string(), table_name.to_string()));
+        }
+        let merge = previous.is_some() && op == "upsert_row";
+        if !merge {
+            for (col, default) in &table.defaults {
+                data.entry(col.clone()).or_insert_with(|| default.to_string());
+            }
+        }
+        if let Some(pk) = &table.primary_key {
+            let key = match data.get(pk) {
+                Some(text) => Some(table.parse_value(pk, text)?),
+                None if merge => previous.as_ref().and_then(|row| row.get(pk.as_str())).cloned(),
+                None => None,
+            };
+            if let Err(e) = table.check_primary_key(table_name, row_id, key.as_ref()) {
+                error!("Rejected row '{}' in table '{}': {}", row_id, table_name, self.db.redaction.scrub(table_name, &data, &e.to_string()));
+                return Err(e);
+            }
+        }
+        if !merge {
+            table.restore_row(row_id, None);
+        }
+        let written = table.insert_row(row_id, data.clone()).and_then(|()| table.check_not_null(table_name, row_id));
+        if let Err(e) = written {
+            table.restore_row(row_id, previous);
+            error!("Rejected row '{}' in table '{}': {}", row_id, table_name, self.db.redaction.scrub(table_name, &data, &e.to_string()));
+            return Err(e);
+        }
+        let op = format!(
+            "{}:{}:{}:{}",
+            op,
+            table_name,
+            row_id,
+            serde_json::to_string(&data).unwrap()
+        );
+        let stored = table.get_row(row_id).cloned().unwrap_or_default();
+        self.db.log_change(op);
+        info!(table = table_name, row_id; "Row written and logged to WAL");
+
+        self.record();
+        Ok(stored)
+    }
+
+    #[instrument(skip_all, fields(table = self.name, row_id = row_id))]
+    pub fn update_row(&mut self, row_id: &str, column_name: &str, new_value: &str) -> Result<Row> {
+        self.hold(row_id, self.changes_more_than_cell(column_name));
+        let (table_name, table) = (self.name, &mut *self.table);
+        // Ensure the column exists; add it if not.
+        if !table.columns.contains(column_name) {
+            table.add_column(column_name);
+            info!(table = table_name, column = column_name; "Column added");
+        }
+        let value = table.parse_value(column_name, new_value)?;
+        if table.get_row(row_id).is_none() {
+            error!("Row '{}' does not exist in table '{}'.", row_id, table_name);
+            return Err(DatabaseError::RowDoesNotExist(row_id.to_string(), table_name.to_string()));
+        }
+        table.check_cell(table_name, row_id, column_name, &value)?;
+        // Update the cell in place.
+        table.update_cell(row_id, column_name, value);
+        let stored = table.get_row(row_id).cloned().unwrap_or_default();
+        // Log the update operation in the WAL.
+        let op = format!(
+            "update_row:{}:{}:{}:{}",
+            table_name,
+            row_id,
+            column_name,
+            serde_json::to_string(new_value).unwrap()
+        );
+        self.db.log_change(op);
+        info!(table = table_name, row_id, column = column_name, value = self.db.redaction.value(table_name, column_name, &new_value);
+            "Row updated and logged to WAL");
+        self.record();
+        Ok(stored)
+    }
+
+    #[instrument(skip_all, fields(table = self.name, row_id = row_id))]
+    pub fn update_row_multi(&mut self, row_id: &str, values: HashMap<String, String>) -> Result<Row> {
+        self.hold(row_id, values.keys().any(|column_name| self.changes_more_than_cell(column_name)));
+        let (table_name, table) = (self.name, &mut *self.table);
+        if table.get_row(row_id).is_none() {
+            error!("Row '{}' does not exist in table '{}'.", row_id, table_name);
+            return Err(DatabaseError::RowDoesNotExist(row_id.to_string(), table_name.to_string()));
+        }
+        let mut cells = Vec::with_capacity(values.len());
+        for (column_name, new_value) in &values {
+            let value = table.parse_value(column_name, new_value)?;
+            table.check_cell(table_name, row_id, column_name, &value)?;
+            cells.push((column_name, value));
+        }
+        for (column_name, value) in cells {
+            if !table.columns.contains(column_name) {
+                table.add_column(column_name);
+                info!(table = table_name, column = column_name; "Column added");
+            }
+            table.update_cell(row_id, column_name, value);
+        }
+        let stored = table.get_row(row_id).cloned().unwrap_or_default();
+        let op = format!(
+            "update_row_multi:{}:{}:{}",
+            table_name,
+            row_id,
+            serde_json::to_string(&values).unwrap()
+        );
+        self.db.log_change(op);
+        info!(table = table_name, row_id, columns = values.len(); "Row updated");
+        self.record();
+        Ok(stored)
+    }
+
+    pub fn increment(&mut self, row_id: &str, column_name: &str, delta: &str) -> Result<Row> {
+        self.hold(row_id, false);
+        let row = self.table.get_row(row_id)
+            .ok_or(DatabaseError::RowDoesNotExist(row_id.to_string(), self.name.to_string()))?;
+        let current = row.get(column_name).cloned().unwrap_or(Value::Null);
+        let new_value = add_delta(&current, delta)
+            .ok_or(DatabaseError::InvalidIncrement(delta.to_string(), column_name.to_string()))?;
+        self.update_row(row_id, column_name, &new_value.to_string())
+    }
+
+    pub fn update_row_if(&mut self, row_id: &str, column_name: &str, new_value: &str, expected: &str) -> Result<bool> {
+        self.hold(row_id, false);
+        let (table_name, table) = (self.name, &*self.table);
+        let row = table.get_row(row_id)
+            .ok_or(DatabaseError::RowDoesNotExist(row_id.to_string(), table_name.to_string()))?;
+        let expected = table.parse_value(column_name, expected)?;
+        if row.get(column_name).unwrap_or(&Value::Null) != &expected {
+            info!(table = table_name, row_id, column = column_name, expected = self.db.redaction.value(table_name, column_name, &expected);
+                "Row not updated: column does not hold the expected value");
+            return Ok(false);
+        }
+        self.update_row(row_id, column_name, new_value)?;
+        Ok(true)
+    }
+
+    #[instrument(skip_all, fields(table = self.name, row_id = row_id))]
+    pub fn delete_row(&mut self, row_id: &str) -> Result<Vec<String>> {
+        self.hold(row_id, self.table.primary_key.is_some());
+        if !self.table.delete_row(row_id) {
+            error!("Row '{}' not found in table '{}'.", row_id, self.name);
+            return Err(DatabaseError::RowNotFound(row_id.to_string(), self.name.to_string()));
+        }
+        self.db.log_change(format!("delete_row:{}:{}", self.name, row_id));
+        info!(table = self.name, row_id; "Row deleted and logged to WAL");
+        self.record();
+        Ok(vec![row_id.to_string(), self.name.to_string()])
+    }
+
+    #[instrument(skip_all, fields(table = self.name, rows = Empty))]
+    pub fn delete_rows_where(&mut self, condition: &str) -> Result<usize> {
+        self.table.widen();
+        let table_name = self.name;
+        let rows: Vec<String> = Database::rows_matching(&self.table, condition)?.map(|(row_id, _)| row_id.clone()).collect();
+        for row_id in &rows {
+            self.table.delete_row(row_id);
+        }
+        for row_id in &rows {
+            self.db.log_change(format!("delete_row:{}:{}", table_name, row_id));
+        }
+        info!(table = table_name, rows = rows.len(), condition = self.db.redaction.condition(table_name, condition); "Rows deleted and logged to WAL");
+        record_rows(rows.len());
+        if !rows.is_empty() {
+            self.record();
+        }
+        Ok(rows.len())
+    }
+
+    // Add the timestamp column if the table does not have it yet, logged and persisted as
+    // `Database::add_typed_column` would.
+    fn ensure_timestamp_column(&mut self, column_name: &str) -> Result<()> {
+        if self.table.columns.contains(column_name) {
+            return Ok(());
+        }
+        self.table.widen();
+        let spec = ColumnSpec { column_type: ColumnType::Timestamp, ..ColumnSpec::default() };
+        self.table.apply_spec(column_name, &spec)?;
+        self.db.log_change(format!("add_column:{}:{}", self.name, self.table.declaration(column_name)));
+        info!(table = self.name, column = column_name, column_type:% = spec.column_type; "Column added and logged to WAL");
+        self.db.store(self.name, &self.table)
+    }
+
+    pub fn enable_expiry(&mut self) -> Result<()> {
+        self.ensure_timestamp_column(EXPIRES_COLUMN)
+    }
+
+    pub fn expire_row(&mut self, row_id: &str, ttl: u64) -> Result<Row> {
+        self.enable_expiry()?;
+        let expires_at = value::now().saturating_add_unsigned(ttl);
+        self.update_row(row_id, EXPIRES_COLUMN, &expires_at.to_string())
+    }
+
+    pub fn soft_delete_row(&mut self, row_id: &str) -> Result<Vec<String>> {
+        self.hold(row_id, false);
+        if self.table.live_row(row_id).is_none() {
+            error!("Row '{}' not found in table '{}'.", row_id, self.name);
+            return Err(DatabaseError::RowNotFound(row_id.to_string(), self.name.to_string()));
+        }
+        self.ensure_timestamp_column(DELETED_COLUMN)?;
+        self.update_row(row_id, DELETED_COLUMN, &value::now().to_string())?;
+        Ok(vec![row_id.to_string(), self.name.to_string()])
+    }
+
+    pub fn restore_row(&mut self, row_id: &str) -> Result<Row> {
+        self.hold(row_id, false);
+        if self.table.get_row(row_id).is_none() {
+            error!("Row '{}' not found in table '{}'.", row_id, self.name);
+            return Err(DatabaseError::RowNotFound(row_id.to_string(), self.name.to_string()));
+        }
+        if self.table.live_row(row_id).is_some() {
+            return Err(DatabaseError::RowNotDeleted(row_id.to_string(), self.name.to_string()));
+        }
+        self.update_row(row_id, DELETED_COLUMN, NULL_TEXT)
+    }
+
+    // Make sure row `row_id` may be written, holding the whole table if only another row's shard
+    // is held (see `Database::point_writer`) or the write changes more than the row (`whole`).
+    fn hold(&mut self, row_id: &str, whole: bool) {
+        if whole || !self.table.covers(row_id) {
+            self.table.widen();
+        }
+    }
+
+    // Whether setting `column_name` changes more than the row: the column is added, or it is
+    // the primary key, whose index spans every shard.
+    fn changes_more_than_cell(&self, column_name: &str) -> bool {
+        !self.table.columns.contains(column_name) || self.table.primary_key.as_deref() == Some(column_name)
+    }
+
+    // Count the write (see `Database::count_operation`), saving the table if that is due. With
+    // one shard held, the whole table is taken first, so the save has every row written meanwhile.
+    fn record(&mut self) {
+        if self.db.count_operation(self.name) {
+            self.table.widen();
+            self.db.save_counted(self.name, &self.table);
+        }
+    }
+}
+
+/// Fail if `column_name` cannot become the primary key of `table` as declared by `spec`:
+/// the table has another one, it has a default, or two rows share a value of it.
+fn check_new_primary_key(table: &Table, column_name: &str, spec: &ColumnSpec) -> Result<()> {
+    if let Some(existing) = table.primary_key.as_deref().filter(|pk| *pk != column_name) {
+        return Err(DatabaseError::ConstraintViolation(format!(
+            "table already has primary key '{}'", existing
+        )));
+    }
+    if spec.default.is_some() || table.defaults.contains_key(column_name) {
+        return Err(DatabaseError::ConstraintViolation(format!(
+            "primary key '{}' cannot have a default", column_name
+        )));
+    }
+    let mut seen: HashMap<String, &String> = HashMap::new();
+    for (row_id, row) in &table.rows {
+        if let Some(key) = row.get(column_name) {
+            if let Some(other) = seen.insert(key.to_string(), row_id) {
+                return Err(DatabaseError::ConstraintViolation(format!(
+                    "rows '{}' and '{}' share value '{}' of '{}', so it cannot be the primary key",
+                    other, row_id, key, column_name
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `current + delta`, or `None` if either is not a number or the sum overflows. Integers stay
+/// integers; numeric text stays text, as untyped columns hold it. NULL counts as 0, so the result
+/// is `delta` itself, checked against the column's type when it is stored.
+fn add_delta(current: &Value, delta: &str) -> Option<Value> {
+    let as_number = |text: &str| match text.parse::<i64>() {
+        Ok(n) => Some(Value::Int(n)),
+        Err(_) => text.parse::<f64>().ok().filter(|x| x.is_finite()).map(Value::Float),
+    };
+    let sum = |a: Value, b: Value| match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a.checked_add(b).map(Value::Int),
+        (Value::Int(a), Value::Float(b)) | (Value::Float(b), Value::Int(a)) => Some(Value::Float(a as f64 + b)),
+        (Value::Float(a), Value::Float(b)) => Some(Value::Float(a + b)),
+        _ => None,
+    };
+    let delta_value = as_number(delta)?;
+    match current {
+        Value::Null => Some(delta_value),
+        Value::Int(n) => match delta_value {
+            Value::Int(_) => sum(Value::Int(*n), delta_value),
+            _ => None,
+        },
+        Value::Float(x) => sum(Value::Float(*x), delta_value),
+        Value::Text(text) => sum(as_number(text)?, delta_value).map(|total| Value::Text(total.to_string())),
+        _ => None,
+    }
+}
+
+/// The cursor of the page that follows row `row_id`. It is opaque to callers (hex of the row_id),
+/// and the same on every shard since they all order rows by row_id.
+pub fn page_cursor(row_id: &str) -> String {
+    row_id.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_cursor(cursor: &str) -> Result<String> {
+    let invalid = || DatabaseError::InvalidCursor(cursor.to_string());
+    if !cursor.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| cursor.get(i..i + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+/// Fail unless `name` can name a table: letters, digits, `_` and `-`. Storage keeps a table in a
+/// file (or under a key) named after it, so anything else could reach outside the data directory.
+pub fn check_table_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(DatabaseError::InvalidTableName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Record `rows` as the row count of the current span (see the `#[instrument]`s on `Database`).
+fn record_rows(rows: usize) {
+    tracing::Span::current().record("rows", rows);
+}
+
+/// Append `entries` to the WAL file `path`, one sealed entry a line, in one write, and wait for
+/// them to reach the disk.
+pub(crate) fn append_lines(path: &str, entries: &[String]) -> Result<()> {
+    let to_err = |err: std::io::Error| DatabaseError::FileCreationError(path.to_string(), err.to_string());
+    let mut buf = String::new();
+    for entry in entries {
+        buf.push_str(&encryption::seal(entry));
+        buf.push('\n');
+    }
+    let mut file = OpenOptions::new().append(true).create(true).open(path).map_err(to_err)?;
+    file.write_all(buf.as_bytes()).map_err(to_err)?;
+    file.sync_data().map_err(to_err)
+}
+
+/// Give the columns of an imported table the types asked for, converting their values (see
+/// `Table::convert_column`); an empty value becomes NULL. Fails on a column the table does not
+/// have or a value that does not convert.
+fn override_types(table_name: &str, table: &mut Table, types: &[(String, ColumnType)]) -> Result<()> {
+    for (column, column_type) in types {
+        if !table.columns.contains(column) {
+            return Err(DatabaseError::ColumnDoesNotExist(column.clone(), table_name.to_string()));
+        }
+        let Conversion { converted, failed } = table.convert_column(column, *column_type);
+        if let Some((_, value)) = failed.into_iter().find(|(_, value)| !value.is_empty()) {
+            return Err(DatabaseError::InvalidValue(value, column.clone(), *column_type));
+        }
+        table.set_column_type(column, *column_type, converted);
+    }
+    Ok(())
+}
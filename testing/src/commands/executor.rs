@@ -0,0 +1,994 @@
+use super::db::{Database, DatabaseError, RowWriter};
+use super::registry::{Builtin, Registry, Run};
+use crate::bench::{self, Workload};
+use crate::migrations;
+use crate::statistics::{self, ColumnStatistics};
+use crate::storage::json;
+use crate::views::{self, View, VIEW_TABLE};
+use crate::table::merge::Resolution;
+use crate::table::rows::MAX_ROW_SHARDS;
+use crate::table::table::{ColumnSpec, RenderOptions, Row, Table, EXPIRES_COLUMN};
+use crate::table::value::{self, ColumnType, NULL_TEXT};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+/// Usage lines for what is not a registered command: session and prompt commands, and the
+/// FORMAT flag every command takes.
+const OTHER_USAGE: &[&str] = &[
+    "<command> FORMAT json|csv|table (rows in the response as JSON, CSV or a text grid)",
+    "CREATE DATABASE <name> / USE <name> / DATABASES (server sessions)",
+    "BEGIN / COMMIT / ROLLBACK (server sessions)",
+    "SUBSCRIBE <tablename> (server sessions; streams committed changes until EXIT)",
+    "LSN / READ <LEADER|ANY|STALE <ms>|AFTER <lsn>> <read command> (server sessions)",
+    "REPLICATION (server sessions: replica lag in LSNs and seconds)",
+    "COPY DATABASE <dir> [AS <name>] (clones the current database into a new data directory while writes go on; AS opens it as database <name>)",
+    "BACKUP <dir> (online backup: every table and the WAL up to one LSN, with checksums) / BACKUP VERIFY <dir>",
+    "LOGIN <user> <password> / TOKEN <token> / LOGOUT / WHOAMI (required before anything else once a user exists)",
+    "CREATE USER <name> PASSWORD <password> / ALTER USER <name> PASSWORD <password> / DROP USER <name> / USERS",
+    "CREATE ROLE <name> / DROP ROLE <name> / ROLES / GRANT ROLE <role> TO <user> / REVOKE ROLE <role> FROM <user>",
+    "GRANT <SELECT,INSERT,UPDATE,DELETE,DDL|ALL> ON <tablename|*> TO <role> / REVOKE ... FROM <role> / GRANTS [<user>]",
+    "CREATE POLICY ON <tablename> FOR <role> WHERE <column> <operator> <value|$user|$attribute> / DROP POLICY ON <tablename> FOR <role>",
+    "CREATE TOKEN <name> FOR <user> [ROLES <role,...>] / REVOKE TOKEN <name> / TOKENS [<user>] (API tokens; the secret is shown once)",
+    "ALTER USER <name> SET <attribute> <value|NULL> (attributes row policies refer to as $<attribute>)",
+    "ROTATE KEY / ROTATE PASSPHRASE <passphrase> (databases opened with RUSTDB_PASSPHRASE: seal with a new data key, or lock the keyring with a new passphrase)",
+    "EXIT",
+    ".tables / .schema / .import / .dump / .wal status (at the prompt; .help describes them)",
+];
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(builtins);
+
+/// The commands `execute` dispatches to.
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+/// Usage lines for every command, as HELP lists them.
+pub fn command_usage() -> Vec<&'static str> {
+    registry().usage().into_iter().chain(OTHER_USAGE.iter().copied()).collect()
+}
+
+/// The built-in commands, in the order HELP lists them.
+fn builtins() -> Registry {
+    let commands = [
+        write("create", create, &[
+            "CREATE TABLE <tablename>",
+            "CREATE VIEW <name> AS <tablename> [WHERE <condition>] [COLUMNS <col1,col2,...>] (a stored query, run on every read)",
+        ]),
+        write("rename", rename, &["RENAME TABLE <tablename> <newname>", "RENAME COLUMN <tablename> <columnname> <newname>"]),
+        write("drop", drop, &[
+            "DROP TABLE <tablename>",
+            "DROP COLUMN <tablename> <columnname> (removes it from every row)",
+            "DROP VIEW <name>",
+        ]),
+        write("copy", copy, &["COPY TABLE <tablename> <newname> [WHERE <column> <operator> <value> | WHERE <column> IS [NOT] NULL]"]),
+        write("add", add, &["ADD COLUMN <tablename> <columnname> [int|float|bool|text|timestamp] [PRIMARY KEY] [NOT NULL] [DEFAULT <value>]"]),
+        write("alter", alter, &[
+            "ALTER COLUMN <tablename> <columnname> TYPE <type> (converts values; lists those that could not be)",
+            "ALTER TABLE <tablename> SHARDS <n> (splits its rows across n maps in memory, so point writes to them go on side by side)",
+        ]),
+        row_write("insert", insert, &["INSERT <tablename> <row_id> <col1=value1> <col2=value2> ... [TTL <seconds>] (value NULL clears a cell; fails if the row exists)"]),
+        row_write("upsert", upsert, &["UPSERT <tablename> <row_id> <col1=value1> ... [TTL <seconds>] (inserts, or updates the given cells of an existing row)"]),
+        row_write("replace", replace, &["REPLACE <tablename> <row_id> <col1=value1> ... [TTL <seconds>] (inserts, or overwrites the whole row)"]),
+        row_write("expire", expire, &["EXPIRE <tablename> <row_id> <seconds> (deletes the row once the time is up)"]),
+        row_write("update", update, &[
+            "UPDATE <tablename> <row_id> <column> <value|NULL> / UPDATE <tablename> <row_id> <col1=value1> <col2=value2> ...",
+            "UPDATE <tablename> <row_id> <column> <value|NULL> IF <current value|NULL> (returns whether it was updated)",
+        ]),
+        row_write("increment", increment, &["INCREMENT|DECREMENT <tablename> <row_id> <column> [amount] (adds or subtracts a number, default 1)"]),
+        row_write("decrement", decrement, &[]),
+        read("get", get, &["GET <tablename> <row_id> [COLUMNS <col1,col2,...>]"]),
+        read("lookup", lookup, &["LOOKUP <tablename> <key> (finds a row by its primary key)"]),
+        row_write("delete", delete, &[
+            "DELETE <tablename> <row_id> / DELETE <tablename> WHERE <condition> (as in SEARCH; returns the count)",
+            "DELETE <tablename> <row_id> SOFT (hides the row from queries until RESTORE or PURGE)",
+        ]),
+        write("restore", restore, &[
+            "RESTORE <tablename> <row_id> (undoes a soft delete)",
+            "RESTORE DATABASE <archive> (replaces every table and the WAL with a DUMP DATABASE archive)",
+            "RESTORE TABLE <tablename> FROM <archive|backup dir> [AS <newname>] (brings back one table of a DUMP or BACKUP)",
+        ]),
+        write("purge", purge, &["PURGE <tablename> (deletes the soft-deleted rows for good; returns the count)"]),
+        write("truncate", truncate, &["TRUNCATE TABLE <tablename> (deletes every row, keeps the columns)"]),
+        read("search", search, &[
+            "SEARCH <tablename> <column> <operator> <value> / SEARCH <tablename> <column> IS [NOT] NULL",
+            "  text operators: CONTAINS, LIKE ('%' any run, '_' one character), ~ (regex); ICONTAINS, ILIKE, ~* ignore case",
+        ]),
+        read("explain", explain, &["EXPLAIN ANALYZE SEARCH <tablename> <condition> (runs the search; reports the access path, rows scanned, index hits and time per stage)"]),
+        read("distinct", distinct, &["DISTINCT <tablename> <column> [COUNTS] (unique values, with how many rows hold each)"]),
+        read("stats", stats, &["STATS <tablename> (row count, per-column non-NULL counts and cardinality, stored size)"]),
+        read("page", page, &["PAGE <tablename> <limit> [AFTER <cursor>] [WHERE <condition>] (rows in row_id order; `next` is the cursor of the next page)"]),
+        write("analyze", analyze, &["ANALYZE <tablename> (stores per-column NULL and distinct counts, common values and histograms for EXPLAIN's estimates)"]),
+        read("bench", bench, &[
+            "BENCH <n> [ROWS <n>] [MIX get=50,search=5,insert=20,update=20,delete=5] (runs n operations against a scratch table; reports throughput and latency percentiles)",
+        ]),
+        read("status", status, &["STATUS (uptime, table and row counts, WAL backlog, last checkpoint and disk usage; GET /status over HTTP)"]),
+        read("tables", tables, &["TABLES (lists all tables)"]),
+        write("views", list_views, &[
+            "VIEWS (lists views with their definitions)",
+            "  PRINT <view>, SEARCH <view> <condition> and GET <view> <row_id> read a view like a table",
+        ]),
+        read("print", print, &["PRINT <tablename> [COLUMNS <col1,col2,...>] [LIMIT <n>] [OFFSET <n>] (prints table contents; `total` counts every row)"]),
+        write("save", save, &[
+            "SAVE <tablename|view> <filename> (CSV; JSON for .json, JSON Lines for .jsonl, Parquet for .parquet files)",
+            "SAVE <name1,name2,...> <filename>.xlsx (an Excel workbook with a sheet per table or view; needs the xlsx feature)",
+        ]),
+        write("export", export, &[
+            "EXPORT <tablename|view> <filename> [WHERE <condition>] (matching rows as JSON Lines, or a JSON array for .json, written one at a time)",
+            "EXPORT <name1,name2,...> <filename>.xlsx [WHERE <condition>] (matching rows as an Excel workbook, a sheet per table or view)",
+            "EXPORT <tablename|view> [WHERE <condition>] (server sessions: streams them to the connection, then `exported` with the row count)",
+        ]),
+        write("load", load, &["LOAD <tablename> <filename> [TYPES <col:type,...>] (replaces the table in memory with a CSV, .json or .jsonl file; untyped files get types from their values)"]),
+        write("import", import, &["IMPORT SQLITE <file> (copies in every table of a SQLite database; needs the sqlite feature)"]),
+        write("dump", dump, &["DUMP DATABASE <archive> (every table, its schema and the WAL position in one tar file)"]),
+        write("verify", verify, &["VERIFY (checks the table files and WAL for torn writes, damage and unreadable entries; `testing verify --repair` fixes them offline)"]),
+        write("wal", wal, &[
+            "WAL (pending entries) / WAL PERSIST|COMMIT|REPLAY|CLEAR (write them to the WAL file, archive them, re-apply or discard them)",
+            "WAL EXPORT <dir> [FROM <lsn>] (archived entries after the last segment in <dir>, or <lsn>, as a new segment file there)",
+            "WAL IMPORT <dir> (checks the segments in <dir> for gaps and applies the entries past this database's LSN)",
+        ]),
+        write("migrate", migrate, &["MIGRATE UP [<version>] / MIGRATE DOWN <version> / MIGRATE STATUS (scripts in ./migrations)"]),
+        write("merge", merge, &["MERGE <tablename> <theirs.csv|theirs.log> [<base.csv>] (last writer wins, lists conflicts)"]),
+        read("help", help, &[]),
+    ];
+    let mut registry = Registry::default();
+    for command in commands {
+        registry.register(Box::new(command));
+    }
+    registry
+}
+
+type Outcome = Option<Result<Value, DatabaseError>>;
+
+fn read(name: &'static str, run: fn(&Database, &[&str]) -> Outcome, usage: &'static [&'static str]) -> Builtin {
+    Builtin { name, usage, run: Run::Read(run) }
+}
+
+fn write(name: &'static str, run: fn(&mut Database, &[&str]) -> Outcome, usage: &'static [&'static str]) -> Builtin {
+    Builtin { name, usage, run: Run::Write(run) }
+}
+
+fn row_write(name: &'static str, run: fn(&Database, &[&str]) -> Outcome, usage: &'static [&'static str]) -> Builtin {
+    Builtin { name, usage, run: Run::Rows(run) }
+}
+
+/// Outcome of a single command line.
+#[derive(Debug)]
+pub enum Response {
+    Ok(Value),
+    Error(String),
+    Exit,
+}
+
+impl Response {
+    /// Encode the response as a single JSON line:
+    /// `{"status":"ok","data":...}` or `{"status":"error","message":"..."}`.
+    pub fn to_line(&self) -> String {
+        match self {
+            Response::Ok(data) => json!({ "status": "ok", "data": data }).to_string(),
+            Response::Error(message) => json!({ "status": "error", "message": message }).to_string(),
+            Response::Exit => json!({ "status": "ok", "data": "bye" }).to_string(),
+        }
+    }
+}
+
+fn rows_to_json(table: &Table, rows: Vec<(String, Row)>) -> Value {
+    Value::Array(
+        rows.into_iter()
+            .map(|(row_id, data)| json!({ "row_id": row_id, "data": table.row_to_json(&data) }))
+            .collect(),
+    )
+}
+
+/// PRINT output: the columns with their types, the rows that are not soft-deleted (only those within
+/// the options' `offset` and `limit`) and how many such rows there are in all.
+fn print_table(table: &Table, options: &RenderOptions) -> Value {
+    let mut columns: Vec<&String> = table.columns.iter().collect();
+    columns.sort();
+    let types: serde_json::Map<String, Value> =
+        columns.iter().map(|col| (col.to_string(), json!(table.column_type(col).name()))).collect();
+    let rows = table
+        .live_rows()
+        .skip(options.offset)
+        .take(options.limit.unwrap_or(usize::MAX))
+        .map(|(id, data)| (id.clone(), data.clone()))
+        .collect();
+    json!({ "columns": columns, "types": types, "rows": rows_to_json(table, rows), "total": table.live_rows().count() })
+}
+
+/// `[COLUMNS <col1,col2,...>] [LIMIT <n>] [OFFSET <n>]` of a PRINT command.
+pub fn print_options<'a>(mut words: &[&'a str]) -> Option<(Option<Vec<&'a str>>, RenderOptions)> {
+    let mut columns = None;
+    let mut options = RenderOptions::default();
+    if let [keyword, list, rest @ ..] = words {
+        if keyword.eq_ignore_ascii_case("columns") {
+            columns = Some(list.split(',').collect());
+            words = rest;
+        }
+    }
+    if let [keyword, limit, rest @ ..] = words {
+        if keyword.eq_ignore_ascii_case("limit") {
+            options.limit = Some(limit.parse().ok()?);
+            words = rest;
+        }
+    }
+    match words {
+        [] => {}
+        [keyword, offset] if keyword.eq_ignore_ascii_case("offset") => options.offset = offset.parse().ok()?,
+        _ => return None,
+    }
+    Some((columns, options))
+}
+
+/// `[AFTER <cursor>] [WHERE <condition>]` of a PAGE command, the condition as in SEARCH.
+fn page_options<'a>(words: &[&'a str]) -> Option<(Option<&'a str>, Option<String>)> {
+    let (cursor, words) = match words {
+        [after, cursor, rest @ ..] if after.eq_ignore_ascii_case("after") => (Some(*cursor), rest),
+        _ => (None, words),
+    };
+    match words {
+        [] => Some((cursor, None)),
+        [keyword, condition @ ..] if keyword.eq_ignore_ascii_case("where") && (3..=4).contains(&condition.len()) => {
+            Some((cursor, Some(condition.join(" "))))
+        }
+        _ => None,
+    }
+}
+
+/// The row a write returns, shaped like a `GET` response.
+fn stored_row(db: &Database, table: &str, row_id: &str, row: Result<Row, DatabaseError>) -> Result<Value, DatabaseError> {
+    let row = row?;
+    Ok(json!({ "row_id": row_id, "data": db.get_table(table)?.row_to_json(&row) }))
+}
+
+/// The cells of an INSERT, UPSERT or REPLACE (`col=value` words), with an optional trailing
+/// `TTL <seconds>` that sets the row's expiry, adding the expiry column to the table if needed.
+fn row_data(writer: &mut RowWriter, words: &[&str]) -> Result<HashMap<String, String>, DatabaseError> {
+    let (words, ttl_words) = match words {
+        [cells @ .., keyword, seconds] if keyword.eq_ignore_ascii_case("ttl") => (cells, Some(*seconds)),
+        _ => (words, None),
+    };
+    let mut data = HashMap::new();
+    for kv_pair in words {
+        if let Some((key, val)) = kv_pair.split_once('=') {
+            data.insert(key.to_string(), literal(val));
+        }
+    }
+    if let Some(seconds) = ttl_words {
+        let expires_at = value::now().saturating_add_unsigned(ttl(seconds)?);
+        writer.enable_expiry()?;
+        data.insert(EXPIRES_COLUMN.to_string(), expires_at.to_string());
+    }
+    Ok(data)
+}
+
+fn ttl(text: &str) -> Result<u64, DatabaseError> {
+    text.parse().map_err(|_| DatabaseError::InvalidTtl(text.to_string()))
+}
+
+/// A value typed in a command: the bare word `NULL` (any case) stands for NULL.
+fn literal(text: &str) -> String {
+    if text.eq_ignore_ascii_case("null") {
+        NULL_TEXT.to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// The table a command works on: the word after `TABLE`/`COLUMN` (`ADD COLUMN <table> ...`),
+/// the view table for `CREATE VIEW`/`DROP VIEW`, otherwise the second word (`INSERT <table> ...`).
+pub fn table_name(line: &str) -> Option<&str> {
+    let mut words = line.split_whitespace().skip(1);
+    match words.next()? {
+        word if word.eq_ignore_ascii_case("table") || word.eq_ignore_ascii_case("column") => words.next(),
+        word if word.eq_ignore_ascii_case("view") => Some(VIEW_TABLE),
+        word => Some(word),
+    }
+}
+
+/// Whether `line` is a command that never modifies the database, so it may run under a shared lock.
+pub fn is_read_only(line: &str) -> bool {
+    let keyword = line.split_whitespace().next().unwrap_or_default();
+    registry().get(keyword).is_some_and(|command| command.read_only())
+}
+
+/// Run a read-only command without mutating the database (e.g. under a read lock).
+/// Returns `None` when `execute` is needed instead: the command writes, is malformed,
+/// or its table still has to be loaded from storage.
+pub fn execute_read(db: &Database, line: &str) -> Option<Response> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let command = registry().get(parts.first()?).filter(|command| command.read_only())?;
+    let table = table_word(&parts).and_then(|word| parts.get(word)).copied();
+    if let Some(table) = table.filter(|table| !db.check_table(table)) {
+        return views::get(db, table).and_then(|view| read_view(db, &view, &parts));
+    }
+    Some(match command.read(db, &parts[1..])? {
+        Ok(data) => Response::Ok(data),
+        Err(e) => Response::Error(e.to_string()),
+    })
+}
+
+/// Run a command that only writes rows of one table (see `Command::writes_rows`) without
+/// mutating the database as a whole (e.g. under a read lock), holding just that table for
+/// writing. Returns `None` when `execute` is needed instead: the command does more, is
+/// malformed, or its table still has to be loaded from storage.
+pub fn execute_rows(db: &Database, line: &str) -> Option<Response> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let command = registry().get(parts.first()?).filter(|command| command.writes_rows())?;
+    if !db.check_table(parts.get(1)?) {
+        return None;
+    }
+    Some(match command.write_rows(db, &parts[1..])? {
+        Ok(data) => Response::Ok(data),
+        Err(e) => Response::Error(e.to_string()),
+    })
+}
+
+/// Where a read command names its table (or view): right after the command, or for
+/// `EXPLAIN ANALYZE SEARCH <tablename>` after the command it explains. `BENCH` names none.
+fn table_word(parts: &[&str]) -> Option<usize> {
+    match parts[0].to_lowercase().as_str() {
+        "explain" => Some(3),
+        "bench" => None,
+        _ => Some(1),
+    }
+}
+
+fn help(_db: &Database, _args: &[&str]) -> Outcome {
+    Some(Ok(json!(command_usage())))
+}
+
+fn get(db: &Database, args: &[&str]) -> Outcome {
+    Some(match *args {
+        [table, row_id] => db.get_table(table).and_then(|t| match t.live_row(row_id) {
+            Some(row) => Ok(json!({ "row_id": row_id, "data": t.row_to_json(row) })),
+            None => Err(DatabaseError::RowDoesNotExist(row_id.to_string(), table.to_string())),
+        }),
+        [table, row_id, keyword, columns] if keyword.eq_ignore_ascii_case("columns") => {
+            let columns: Vec<&str> = columns.split(',').collect();
+            db.get_row_projected(table, row_id, &columns).and_then(|row| {
+                Ok(json!({ "row_id": row_id, "data": db.projection(table, &columns)?.row_to_json(&row) }))
+            })
+        }
+        _ => return None,
+    })
+}
+
+fn lookup(db: &Database, args: &[&str]) -> Outcome {
+    let [table, key] = *args else {
+        return None;
+    };
+    Some(db.get_row_by_key(table, key).and_then(|(row_id, row)| {
+        Ok(json!({ "row_id": row_id, "data": db.get_table(table)?.row_to_json(&row) }))
+    }))
+}
+
+fn search(db: &Database, args: &[&str]) -> Outcome {
+    let [table, ref condition @ ..] = *args else {
+        return None;
+    };
+    if !(3..=4).contains(&condition.len()) {
+        return None;
+    }
+    Some(
+        db.search_rows_by_condition_in_table(table, &condition.join(" "))
+            .and_then(|rows| Ok(rows_to_json(&*db.get_table(table)?, rows))),
+    )
+}
+
+fn explain(db: &Database, args: &[&str]) -> Outcome {
+    let [analyze, command, table, ref condition @ ..] = *args else {
+        return None;
+    };
+    if !analyze.eq_ignore_ascii_case("analyze") || !command.eq_ignore_ascii_case("search") || !(3..=4).contains(&condition.len()) {
+        return None;
+    }
+    Some(db.explain_analyze(table, &condition.join(" ")).and_then(|(rows, mut report)| {
+        // What SEARCH would do next with the rows, timed but not returned.
+        let started = Instant::now();
+        rows_to_json(&*db.get_table(table)?, rows);
+        report.stages.push(("render", started.elapsed()));
+        Ok(report.to_json())
+    }))
+}
+
+fn page(db: &Database, args: &[&str]) -> Outcome {
+    let [table, limit, ref rest @ ..] = *args else {
+        return None;
+    };
+    match (limit.parse::<usize>(), page_options(rest)) {
+        (Ok(limit), Some((cursor, condition))) if limit > 0 => Some(
+            db.page_rows(table, limit, cursor, condition.as_deref())
+                .and_then(|page| Ok(json!({ "rows": rows_to_json(&*db.get_table(table)?, page.rows), "next": page.next }))),
+        ),
+        _ => None,
+    }
+}
+
+fn distinct(db: &Database, args: &[&str]) -> Outcome {
+    Some(match *args {
+        [table, column] => db
+            .distinct(table, column)
+            .map(|values| Value::Array(values.iter().map(|(value, _)| value.to_json()).collect())),
+        [table, column, counts] if counts.eq_ignore_ascii_case("counts") => db.distinct(table, column).map(|values| {
+            let values = values.iter().map(|(value, count)| json!({ "value": value.to_json(), "count": count }));
+            Value::Array(values.collect())
+        }),
+        _ => return None,
+    })
+}
+
+fn stats(db: &Database, args: &[&str]) -> Outcome {
+    let [table] = *args else {
+        return None;
+    };
+    Some(db.stats(table).map(|stats| stats.to_json()))
+}
+
+fn analyze(db: &mut Database, args: &[&str]) -> Outcome {
+    let [table] = *args else {
+        return None;
+    };
+    Some(statistics::analyze(db, table).map(|columns| Value::Array(columns.iter().map(ColumnStatistics::to_json).collect())))
+}
+
+fn bench(_db: &Database, args: &[&str]) -> Outcome {
+    let workload = Workload::parse(args)?;
+    Some(bench::run_scratch(&workload).map(|report| report.to_json()))
+}
+
+fn status(db: &Database, args: &[&str]) -> Outcome {
+    if !args.is_empty() {
+        return None;
+    }
+    Some(db.status().map(|status| status.to_json()))
+}
+
+fn tables(db: &Database, _args: &[&str]) -> Outcome {
+    Some(db.table_names().map(|names| json!(names)))
+}
+
+fn print(db: &Database, args: &[&str]) -> Outcome {
+    let [table, ref rest @ ..] = *args else {
+        return None;
+    };
+    Some(match print_options(rest)? {
+        (None, options) => db.get_table(table).map(|table| print_table(&table, &options)),
+        (Some(columns), options) => db.get_table_projected(table, &columns).map(|table| print_table(&table, &options)),
+    })
+}
+
+/// Run a read-only command on a view. Returns `None` when `execute` has to load the view's table first.
+fn read_view(db: &Database, view: &View, parts: &[&str]) -> Option<Response> {
+    if !db.check_table(&view.table) {
+        return None;
+    }
+    let result = match parts[0].to_lowercase().as_str() {
+        "print" => match print_options(&parts[2..]) {
+            Some((None, options)) => views::query(db, view, None).map(|table| print_table(&table, &options)),
+            _ => return Some(unknown_command()),
+        },
+
+        "search" if parts.len() == 5 || parts.len() == 6 => views::query(db, view, Some(&parts[2..].join(" "))).map(|table| {
+            let rows = table.rows.iter().map(|(row_id, row)| (row_id.clone(), row.clone())).collect();
+            rows_to_json(&table, rows)
+        }),
+
+        "get" if parts.len() == 3 => views::query(db, view, None).and_then(|table| match table.rows.get(parts[2]) {
+            Some(row) => Ok(json!({ "row_id": parts[2], "data": table.row_to_json(row) })),
+            None => Err(DatabaseError::RowDoesNotExist(parts[2].to_string(), view.name.clone())),
+        }),
+
+        _ => return Some(Response::Error(format!("'{}' does not work on views.", parts.join(" ")))),
+    };
+    Some(match result {
+        Ok(data) => Response::Ok(data),
+        Err(e) => Response::Error(e.to_string()),
+    })
+}
+
+/// Parse `CREATE VIEW <name> AS <table> [WHERE <condition>] [COLUMNS <col1,col2,...>]` from the word
+/// after `VIEW`. Returns `None` if the words do not have that shape.
+fn view_definition(words: &[&str]) -> Option<View> {
+    let [name, as_, table, rest @ ..] = words else {
+        return None;
+    };
+    let mut rest = rest;
+    if !as_.eq_ignore_ascii_case("as") {
+        return None;
+    }
+    let mut view = View { name: name.to_string(), table: table.to_string(), condition: None, columns: None };
+    if let [columns_, columns] = &rest[rest.len().saturating_sub(2)..] {
+        if columns_.eq_ignore_ascii_case("columns") {
+            view.columns = Some(columns.split(',').map(str::to_string).collect());
+            rest = &rest[..rest.len() - 2];
+        }
+    }
+    match rest {
+        [] => {}
+        [where_, condition @ ..] if where_.eq_ignore_ascii_case("where") && (3..=4).contains(&condition.len()) => {
+            view.condition = Some(condition.join(" "));
+        }
+        _ => return None,
+    }
+    Some(view)
+}
+
+/// Parse `[type] [PRIMARY KEY] [NOT NULL] [DEFAULT <value>]`, the words after `ADD COLUMN <table> <column>`.
+/// Returns `None` if the words do not have that shape.
+fn column_spec(mut words: &[&str]) -> Option<Result<ColumnSpec, DatabaseError>> {
+    let mut spec = ColumnSpec::default();
+    let keywords = ["primary", "not", "default"];
+    if let Some(word) = words.first().filter(|w| !keywords.iter().any(|k| w.eq_ignore_ascii_case(k))) {
+        match ColumnType::parse(word) {
+            Some(column_type) => spec.column_type = column_type,
+            None => return Some(Err(DatabaseError::UnknownColumnType(word.to_string()))),
+        }
+        words = &words[1..];
+    }
+    if let [primary, key, rest @ ..] = words {
+        if primary.eq_ignore_ascii_case("primary") && key.eq_ignore_ascii_case("key") {
+            spec.primary_key = true;
+            spec.not_null = true;
+            words = rest;
+        }
+    }
+    if let [not, null, rest @ ..] = words {
+        if not.eq_ignore_ascii_case("not") && null.eq_ignore_ascii_case("null") {
+            spec.not_null = true;
+            words = rest;
+        }
+    }
+    match words {
+        [] => {}
+        [keyword, value] if keyword.eq_ignore_ascii_case("default") => spec.default = Some(literal(value)),
+        _ => return None,
+    }
+    Some(Ok(spec))
+}
+
+/// Parse one command line (same syntax as the REPL) and run it against the database.
+pub fn execute(db: &mut Database, line: &str) -> Response {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.is_empty() {
+        return Response::Error("Empty command.".to_string());
+    }
+    if let Some(response) = execute_read(db, line) {
+        return response;
+    }
+    if parts[0].eq_ignore_ascii_case("exit") || parts[0].eq_ignore_ascii_case("quit") {
+        return Response::Exit;
+    }
+    let Some(command) = registry().get(parts[0]) else {
+        return unknown_command();
+    };
+
+    // Read-only commands end up here only when malformed or their table (or view) is not in memory yet.
+    if let Some(&name) = table_word(&parts).and_then(|word| parts.get(word)).filter(|_| command.read_only()) {
+        let table = views::load(db).map(|()| views::get(db, name).map_or(name.to_string(), |view| view.table));
+        return match table.and_then(|table| db.ensure_table_loaded(&table)) {
+            Ok(()) => execute_read(db, line).unwrap_or_else(unknown_command),
+            Err(e) => Response::Error(e.to_string()),
+        };
+    }
+    // Row writes hold their table, which has to be in memory.
+    if let Some(table) = parts.get(1).filter(|_| command.writes_rows()) {
+        if let Err(e) = db.ensure_table_loaded(table) {
+            return Response::Error(e.to_string());
+        }
+    }
+
+    match command.execute(db, &parts[1..]) {
+        Some(Ok(data)) => Response::Ok(data),
+        Some(Err(e)) => Response::Error(e.to_string()),
+        None => unknown_command(),
+    }
+}
+
+fn create(db: &mut Database, args: &[&str]) -> Outcome {
+    Some(match *args {
+        [kind, table] if kind.eq_ignore_ascii_case("table") => db.create_table(table).map(|name| json!(name)),
+        [kind, ref definition @ ..] if kind.eq_ignore_ascii_case("view") => {
+            let view = view_definition(definition)?;
+            views::create(db, &view).map(|()| view.to_json())
+        }
+        _ => return None,
+    })
+}
+
+fn rename(db: &mut Database, args: &[&str]) -> Outcome {
+    Some(match *args {
+        [kind, table, new_name] if kind.eq_ignore_ascii_case("table") => db.rename_table(table, new_name).map(|res| json!(res)),
+        [kind, table, column, new_name] if kind.eq_ignore_ascii_case("column") => {
+            db.rename_column(table, column, new_name).map(|res| json!(res))
+        }
+        _ => return None,
+    })
+}
+
+fn drop(db: &mut Database, args: &[&str]) -> Outcome {
+    Some(match *args {
+        [kind, name] if kind.eq_ignore_ascii_case("view") => views::drop(db, name).map(|()| json!(name)),
+        [kind, table] if kind.eq_ignore_ascii_case("table") => {
+            db.drop_table(table).and_then(|res| statistics::forget(db, table).map(|()| json!(res)))
+        }
+        [kind, table, column] if kind.eq_ignore_ascii_case("column") => db.drop_column(table, column).map(|res| json!(res)),
+        _ => return None,
+    })
+}
+
+fn copy(db: &mut Database, args: &[&str]) -> Outcome {
+    let [kind, table, new_name, ref rest @ ..] = *args else {
+        return None;
+    };
+    if !kind.eq_ignore_ascii_case("table") {
+        return None;
+    }
+    Some(match rest {
+        [] => db.copy_table(table, new_name, None).map(|res| json!(res)),
+        [keyword, condition @ ..] if keyword.eq_ignore_ascii_case("where") && (3..=4).contains(&condition.len()) => {
+            db.copy_table(table, new_name, Some(&condition.join(" "))).map(|res| json!(res))
+        }
+        _ => return None,
+    })
+}
+
+fn add(db: &mut Database, args: &[&str]) -> Outcome {
+    let [kind, table, column, ref spec @ ..] = *args else {
+        return None;
+    };
+    if !kind.eq_ignore_ascii_case("column") {
+        return None;
+    }
+    Some(column_spec(spec)?.and_then(|spec| db.add_typed_column(table, column, spec)).map(|res| json!(res)))
+}
+
+fn alter(db: &mut Database, args: &[&str]) -> Outcome {
+    if let [kind, table, keyword, shards] = *args {
+        if !kind.eq_ignore_ascii_case("table") || !keyword.eq_ignore_ascii_case("shards") {
+            return None;
+        }
+        return Some(
+            shards
+                .parse()
+                .map_err(|_| DatabaseError::InvalidRowShards(shards.to_string(), MAX_ROW_SHARDS))
+                .and_then(|shards| db.set_row_shards(table, shards))
+                .map(|shards| json!({ "table": table, "shards": shards })),
+        );
+    }
+    let [kind, table, column, keyword, type_name] = *args else {
+        return None;
+    };
+    if !kind.eq_ignore_ascii_case("column") || !keyword.eq_ignore_ascii_case("type") {
+        return None;
+    }
+    Some(
+        ColumnType::parse(type_name)
+            .ok_or_else(|| DatabaseError::UnknownColumnType(type_name.to_string()))
+            .and_then(|column_type| db.alter_column_type(table, column, column_type))
+            .map(|failed| {
+                let failed: Vec<Value> =
+                    failed.into_iter().map(|(row_id, value)| json!({ "row_id": row_id, "value": value })).collect();
+                json!({ "column": column, "type": type_name.to_lowercase(), "unconverted": failed })
+            }),
+    )
+}
+
+fn list_views(db: &mut Database, args: &[&str]) -> Outcome {
+    if !args.is_empty() {
+        return None;
+    }
+    Some(views::list(db).map(|views| Value::Array(views.iter().map(View::to_json).collect())))
+}
+
+fn insert(db: &Database, args: &[&str]) -> Outcome {
+    // Example: INSERT table row_id col1=val1 col2=val2 [TTL 60]
+    let [table, row_id, ref cells @ ..] = *args else {
+        return None;
+    };
+    if cells.is_empty() {
+        return None;
+    }
+    let row = db.point_writer(table, row_id).and_then(|mut writer| {
+        let data = row_data(&mut writer, cells)?;
+        writer.insert_row(row_id, data)
+    });
+    Some(stored_row(db, table, row_id, row))
+}
+
+fn upsert(db: &Database, args: &[&str]) -> Outcome {
+    // Example: UPSERT table row_id col1=val1
+    let [table, row_id, ref cells @ ..] = *args else {
+        return None;
+    };
+    if cells.is_empty() {
+        return None;
+    }
+    let row = db.point_writer(table, row_id).and_then(|mut writer| {
+        let data = row_data(&mut writer, cells)?;
+        writer.upsert_row(row_id, data)
+    });
+    Some(stored_row(db, table, row_id, row))
+}
+
+fn replace(db: &Database, args: &[&str]) -> Outcome {
+    // Example: REPLACE table row_id col1=val1 (clears the cells not given)
+    let [table, row_id, ref cells @ ..] = *args else {
+        return None;
+    };
+    if cells.is_empty() {
+        return None;
+    }
+    let row = db.point_writer(table, row_id).and_then(|mut writer| {
+        let data = row_data(&mut writer, cells)?;
+        writer.replace_row(row_id, data)
+    });
+    Some(stored_row(db, table, row_id, row))
+}
+
+fn expire(db: &Database, args: &[&str]) -> Outcome {
+    let [table, row_id, seconds] = *args else {
+        return None;
+    };
+    let row = ttl(seconds).and_then(|ttl| db.point_writer(table, row_id)?.expire_row(row_id, ttl));
+    Some(stored_row(db, table, row_id, row))
+}
+
+fn increment(db: &Database, args: &[&str]) -> Outcome {
+    step(db, args, false)
+}
+
+fn decrement(db: &Database, args: &[&str]) -> Outcome {
+    step(db, args, true)
+}
+
+/// INCREMENT, or DECREMENT when `negate` is set.
+fn step(db: &Database, args: &[&str], negate: bool) -> Outcome {
+    // Example: INCREMENT table row_id column [delta] (default 1; DECREMENT subtracts it)
+    let (table, row_id, column, delta) = match *args {
+        [table, row_id, column] => (table, row_id, column, "1"),
+        [table, row_id, column, delta] => (table, row_id, column, delta),
+        _ => return None,
+    };
+    let delta = if negate { delta.strip_prefix('-').map_or_else(|| format!("-{}", delta), str::to_string) } else { delta.to_string() };
+    let row = db.point_writer(table, row_id).and_then(|mut writer| writer.increment(row_id, column, &delta));
+    Some(stored_row(db, table, row_id, row))
+}
+
+fn update(db: &Database, args: &[&str]) -> Outcome {
+    Some(match *args {
+        [table, row_id, ref cells @ ..] if cells.first().is_some_and(|cell| cell.contains('=')) => {
+            // Example: UPDATE table row_id col1=val1 col2=val2 (all or nothing)
+            let mut values = HashMap::new();
+            for kv_pair in cells {
+                if let Some((key, val)) = kv_pair.split_once('=') {
+                    values.insert(key.to_string(), literal(val));
+                }
+            }
+            let row = db.point_writer(table, row_id).and_then(|mut writer| writer.update_row_multi(row_id, values));
+            stored_row(db, table, row_id, row)
+        }
+        [table, row_id, column, value, keyword, expected] if keyword.eq_ignore_ascii_case("if") => db
+            .point_writer(table, row_id)
+            .and_then(|mut writer| writer.update_row_if(row_id, column, &literal(value), &literal(expected)))
+            .map(|updated| json!(updated)),
+        [table, row_id, column, value] => {
+            let row = db.point_writer(table, row_id).and_then(|mut writer| writer.update_row(row_id, column, &literal(value)));
+            stored_row(db, table, row_id, row)
+        }
+        _ => return None,
+    })
+}
+
+fn delete(db: &Database, args: &[&str]) -> Outcome {
+    Some(match *args {
+        [table, keyword, ref condition @ ..] if keyword.eq_ignore_ascii_case("where") && (3..=4).contains(&condition.len()) => {
+            db.row_writer(table).and_then(|mut writer| writer.delete_rows_where(&condition.join(" "))).map(|count| json!(count))
+        }
+        [table, row_id] => db.point_writer(table, row_id).and_then(|mut writer| writer.delete_row(row_id)).map(|res| json!(res)),
+        [table, row_id, soft] if soft.eq_ignore_ascii_case("soft") => {
+            db.point_writer(table, row_id).and_then(|mut writer| writer.soft_delete_row(row_id)).map(|res| json!(res))
+        }
+        _ => return None,
+    })
+}
+
+fn restore(db: &mut Database, args: &[&str]) -> Outcome {
+    if let [keyword, table, from, source, ref rest @ ..] = *args {
+        let as_name = match *rest {
+            [] => None,
+            [keyword, name] if keyword.eq_ignore_ascii_case("as") => Some(name),
+            _ => return None,
+        };
+        if keyword.eq_ignore_ascii_case("table") && from.eq_ignore_ascii_case("from") {
+            return Some(db.restore_table(source, table, as_name).map(|res| json!(res)));
+        }
+    }
+    let [table, row_id] = *args else {
+        return None;
+    };
+    if table.eq_ignore_ascii_case("database") {
+        return Some(db.restore(row_id).map(|names| json!({ "restored": names })));
+    }
+    let row = db.restore_row(table, row_id);
+    Some(stored_row(db, table, row_id, row))
+}
+
+fn dump(db: &mut Database, args: &[&str]) -> Outcome {
+    match *args {
+        [keyword, file] if keyword.eq_ignore_ascii_case("database") => Some(db.dump(file)),
+        _ => None,
+    }
+}
+
+fn purge(db: &mut Database, args: &[&str]) -> Outcome {
+    let [table] = *args else {
+        return None;
+    };
+    Some(db.purge_deleted(table).map(|count| json!(count)))
+}
+
+fn truncate(db: &mut Database, args: &[&str]) -> Outcome {
+    match *args {
+        [kind, table] if kind.eq_ignore_ascii_case("table") => Some(db.truncate_table(table).map(|res| json!(res))),
+        _ => None,
+    }
+}
+
+fn save(db: &mut Database, args: &[&str]) -> Outcome {
+    let [table, file] = *args else {
+        return None;
+    };
+    if is_xlsx(file) {
+        return Some(export_xlsx(db, table, file, None));
+    }
+    Some(load_source(db, table).and_then(|()| db.save_table(table, file)).map(|res| json!(res)))
+}
+
+fn export(db: &mut Database, args: &[&str]) -> Outcome {
+    let (name, file, condition) = match *args {
+        [name, file] => (name, file, None),
+        [name, file, keyword, ref condition @ ..] if keyword.eq_ignore_ascii_case("where") && (3..=4).contains(&condition.len()) => {
+            (name, file, Some(condition.join(" ")))
+        }
+        _ => return None,
+    };
+    if is_xlsx(file) {
+        return Some(export_xlsx(db, name, file, condition.as_deref()));
+    }
+    let layout = json::Layout::of(file).unwrap_or(json::Layout::Lines);
+    let exported = load_source(db, name).and_then(|()| db.export_to_file(name, condition.as_deref(), file, layout));
+    Some(exported.map(|rows| json!({ "file": file, "exported": rows })))
+}
+
+fn is_xlsx(file: &str) -> bool {
+    file.to_lowercase().ends_with(".xlsx")
+}
+
+/// Write the tables and views of the comma-separated `names` as one Excel workbook.
+fn export_xlsx(db: &mut Database, names: &str, file: &str, condition: Option<&str>) -> Result<Value, DatabaseError> {
+    let names: Vec<&str> = names.split(',').filter(|name| !name.is_empty()).collect();
+    for name in &names {
+        load_source(db, name)?;
+    }
+    let rows = db.export_xlsx(&names, condition, file)?;
+    Ok(json!({ "file": file, "sheets": names, "exported": rows }))
+}
+
+/// Bring a table into memory, or for a view the table it reads, so its rows can be written out.
+pub fn load_source(db: &mut Database, name: &str) -> Result<(), DatabaseError> {
+    views::load(db)?;
+    match views::get(db, name) {
+        Some(view) if !db.check_table(name) => db.ensure_table_loaded(&view.table),
+        _ => db.ensure_table_loaded(name),
+    }
+}
+
+fn load(db: &mut Database, args: &[&str]) -> Outcome {
+    let (table, file, types) = match *args {
+        [table, file] => (table, file, Ok(Vec::new())),
+        [table, file, keyword, types] if keyword.eq_ignore_ascii_case("types") => (table, file, column_types(types)),
+        _ => return None,
+    };
+    Some(types.and_then(|types| db.load_table_from_file(table, file, &types)).map(|()| json!(table)))
+}
+
+/// The `<col:type,...>` list of a LOAD or `.import`, the types given to those columns.
+pub fn column_types(list: &str) -> Result<Vec<(String, ColumnType)>, DatabaseError> {
+    list.split(',')
+        .map(|pair| {
+            let (column, type_name) = pair.split_once(':').unwrap_or((pair, ""));
+            let column_type = ColumnType::parse(type_name).ok_or_else(|| DatabaseError::UnknownColumnType(type_name.to_string()))?;
+            Ok((column.to_string(), column_type))
+        })
+        .collect()
+}
+
+fn import(db: &mut Database, args: &[&str]) -> Outcome {
+    match *args {
+        [kind, file] if kind.eq_ignore_ascii_case("sqlite") => Some(db.import_sqlite(file).map(|names| json!(names))),
+        _ => None,
+    }
+}
+
+fn wal(db: &mut Database, args: &[&str]) -> Outcome {
+    let done = match *args {
+        [] => {
+            let pending: Vec<String> = db.wal.get_mut().iter().map(|entry| db.redaction.wal_entry(entry)).collect();
+            return Some(Ok(json!({ "lsn": db.current_lsn(), "pending": pending })));
+        }
+        [action] => match action.to_lowercase().as_str() {
+            "persist" => db.persist_wal().map(|_| ()),
+            "commit" => db.checkpoint().and_then(|_| db.commit_wal()),
+            "replay" => db.replay_wal(),
+            "clear" => db.clear_wal(),
+            _ => return None,
+        },
+        [action, dir] if action.eq_ignore_ascii_case("import") => {
+            return Some(db.import_wal(dir).map(|applied| json!({ "applied": applied, "lsn": db.current_lsn() })));
+        }
+        [action, dir, ref from @ ..] if action.eq_ignore_ascii_case("export") => {
+            let from = match from {
+                [] => None,
+                [keyword, lsn] if keyword.eq_ignore_ascii_case("from") => Some(lsn.parse().ok()?),
+                _ => return None,
+            };
+            return Some(db.export_wal(dir, from).map(|segment| match segment {
+                Some(segment) => json!({ "file": segment.path, "first_lsn": segment.first, "last_lsn": segment.last }),
+                None => json!({ "file": null, "first_lsn": null, "last_lsn": null }),
+            }));
+        }
+        _ => return None,
+    };
+    Some(done.map(|()| json!({ "lsn": db.current_lsn(), "pending": db.wal.get_mut().len() })))
+}
+
+fn verify(db: &mut Database, args: &[&str]) -> Outcome {
+    if !args.is_empty() {
+        return None;
+    }
+    Some(db.verify(false).map(|issues| json!({ "issues": issues.iter().map(|issue| issue.to_json()).collect::<Vec<_>>() })))
+}
+
+fn merge(db: &mut Database, args: &[&str]) -> Outcome {
+    let (table, theirs, base) = match *args {
+        [table, theirs] => (table, theirs, None),
+        [table, theirs, base] => (table, theirs, Some(base)),
+        _ => return None,
+    };
+    Some(db.merge_table(table, theirs, base).map(|conflicts| {
+        let conflicts: Vec<Value> = conflicts
+            .into_iter()
+            .map(|(c, resolution)| {
+                let kept = if resolution == Resolution::Ours { "ours" } else { "theirs" };
+                json!({ "row_id": c.row_id, "column": c.column, "ours": c.ours, "theirs": c.theirs, "kept": kept })
+            })
+            .collect();
+        json!({ "conflicts": conflicts })
+    }))
+}
+
+/// `MIGRATE UP [<version>]`, `MIGRATE DOWN <version>` or `MIGRATE STATUS`, with the scripts in
+/// `migrations::DEFAULT_DIR`.
+fn migrate(db: &mut Database, args: &[&str]) -> Outcome {
+    let version = |arg: &str| arg.parse::<u64>().map_err(|_| DatabaseError::MigrationError(format!("bad version '{}'", arg)));
+    let action = args.first()?.to_lowercase();
+    Some(migrations::load_dir(migrations::DEFAULT_DIR).and_then(|scripts| match (action.as_str(), &args[1..]) {
+        ("up", []) => migrations::migrate_up(db, &scripts, None).map(|done| json!({ "applied": done })),
+        ("up", [target]) => migrations::migrate_up(db, &scripts, Some(version(target)?)).map(|done| json!({ "applied": done })),
+        ("down", [target]) => migrations::migrate_down(db, &scripts, version(target)?).map(|done| json!({ "reverted": done })),
+        ("status", []) => {
+            let applied = migrations::applied(db)?;
+            let current = applied.last().map_or(0, |(version, _)| *version);
+            let pending: Vec<u64> = scripts.iter().map(|m| m.version).filter(|v| *v > current).collect();
+            let applied: Vec<Value> = applied.into_iter().map(|(version, name)| json!({ "version": version, "name": name })).collect();
+            Ok(json!({ "version": current, "applied": applied, "pending": pending }))
+        }
+        _ => Err(DatabaseError::MigrationError("use MIGRATE UP [<version>], MIGRATE DOWN <version> or MIGRATE STATUS".to_string())),
+    }))
+}
+
+fn unknown_command() -> Response {
+    Response::Error("Unknown command. Type 'help' for a list of commands.".to_string())
+}
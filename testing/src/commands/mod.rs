@@ -1,4 +1,8 @@
-pub mod command1;
-pub mod command2;
+pub mod backups;
+pub mod changes;
 pub mod db;
+pub mod executor;
+pub mod redaction;
+pub mod registry;
+pub mod sweeper;
 pub mod walengine;
\ No newline at end of file
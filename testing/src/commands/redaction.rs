@@ -0,0 +1,100 @@
+use crate::auth::USER_TABLE;
+use crate::tokens::TOKEN_TABLE;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+
+/// Shown in logs and diagnostic output in place of a redacted value.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Columns redacted whatever the configuration says: the password and token hashes.
+const ALWAYS: &[(&str, &str)] = &[(USER_TABLE, "password_hash"), (TOKEN_TABLE, "token_hash")];
+
+/// **Redaction**
+/// Columns whose values must not appear in what the database logs or prints about its work
+/// (`Updated row ...`, WAL replay, the pending entries `WAL` shows, rejected rows), as opposed to
+/// what a command returns. Each is `<table>.<column>`, or `*.<column>` for that column in every
+/// table; the hashes of the accounts and tokens are always redacted.
+#[derive(Clone, Debug, Default)]
+pub struct Redaction {
+    // (table, column); table "*" for every table.
+    columns: HashSet<(String, String)>,
+}
+
+impl Redaction {
+    /// Parse a comma-separated list of `<table>.<column>` (e.g. RUSTDB_REDACT=users.email,*.ssn).
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut redaction = Redaction::default();
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            match item.split_once('.') {
+                Some((table, column)) if !table.is_empty() && !column.is_empty() => redaction.redact(table, column),
+                _ => return Err(format!("Invalid redacted column '{}': use <table>.<column> or *.<column>.", item)),
+            }
+        }
+        Ok(redaction)
+    }
+
+    /// Redact `column` of `table` ("*" for every table) from now on.
+    pub fn redact(&mut self, table: &str, column: &str) {
+        self.columns.insert((table.to_string(), column.to_string()));
+    }
+
+    /// Whether values of `column` in `table` are redacted.
+    pub fn hides(&self, table: &str, column: &str) -> bool {
+        ALWAYS.contains(&(table, column))
+            || [table, "*"].iter().any(|table| self.columns.contains(&(table.to_string(), column.to_string())))
+    }
+
+    /// `value` of `column` in `table` as it may be shown.
+    pub fn value(&self, table: &str, column: &str, value: &impl Display) -> String {
+        if self.hides(table, column) {
+            REDACTED.to_string()
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// A row of `table` as it may be shown, with its columns in order.
+    pub fn row<V: Display>(&self, table: &str, row: &HashMap<String, V>) -> BTreeMap<String, String> {
+        row.iter().map(|(column, value)| (column.clone(), self.value(table, column, value))).collect()
+    }
+
+    /// A `<column> <operator> <value>` condition on `table` as it may be shown: the value goes
+    /// if the column is redacted, since it says what the column holds.
+    pub fn condition(&self, table: &str, condition: &str) -> String {
+        let words: Vec<&str> = condition.split_whitespace().collect();
+        match words[..] {
+            [column, operator, _, ..] if self.hides(table, column) => format!("{} {} {}", column, operator, REDACTED),
+            _ => condition.to_string(),
+        }
+    }
+
+    /// `text` (e.g. an error about `row`) with the redacted values of the row of `table` replaced.
+    pub fn scrub(&self, table: &str, row: &HashMap<String, String>, text: &str) -> String {
+        row.iter()
+            .filter(|(column, value)| !value.is_empty() && self.hides(table, column))
+            .fold(text.to_string(), |text, (_, value)| text.replace(value.as_str(), REDACTED))
+    }
+
+    /// A WAL entry (see `ChangeKind::parse`) as it may be shown: row data, updated values and
+    /// copy conditions lose what redacted columns hold.
+    pub fn wal_entry(&self, entry: &str) -> String {
+        let parts: Vec<&str> = entry.splitn(4, ':').collect();
+        match parts[..] {
+            [op @ ("insert_row" | "upsert_row" | "replace_row" | "update_row_multi"), table, row_id, data] => {
+                match serde_json::from_str::<HashMap<String, String>>(data) {
+                    Ok(data) => format!("{}:{}:{}:{}", op, table, row_id, serde_json::json!(self.row(table, &data))),
+                    Err(_) => format!("{}:{}:{}:{}", op, table, row_id, REDACTED),
+                }
+            }
+            ["update_row", table, row_id, update] => match update.split_once(':') {
+                Some((column, _)) if self.hides(table, column) => {
+                    format!("update_row:{}:{}:{}:{}", table, row_id, column, serde_json::json!(REDACTED))
+                }
+                Some(_) => entry.to_string(),
+                None => format!("update_row:{}:{}:{}", table, row_id, REDACTED),
+            },
+            ["copy_table", table, new_name, condition] => format!("copy_table:{}:{}:{}", table, new_name, self.condition(table, condition)),
+            _ => entry.to_string(),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::db::{Database, Result};
+use serde_json::Value;
+use tracing::info_span;
+
+/// **Commands**
+/// One statement of the command language, picked by its first word. `execute` gets the words after
+/// it and returns `None` when they do not have any shape the command takes, which callers report
+/// as an unknown command. Commands that never modify the database say so with `read_only` and
+/// implement `read`, so they can run under a shared lock. Commands that only write rows of the
+/// table named by their first argument say so with `writes_rows` and implement `write_rows`, so
+/// they too can run under a shared lock, holding just that table for writing.
+pub trait Command: Send + Sync {
+    /// The first word, lowercase, e.g. `insert`
+    fn name(&self) -> &str;
+
+    /// Lines for HELP; indented ones explain the line above
+    fn usage(&self) -> &[&str];
+
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// Run a read-only command. Only called if `read_only` is true.
+    fn read(&self, _db: &Database, _args: &[&str]) -> Option<Result<Value>> {
+        None
+    }
+
+    fn writes_rows(&self) -> bool {
+        false
+    }
+
+    /// Run a row-writing command; its table is in memory. Only called if `writes_rows` is true.
+    fn write_rows(&self, _db: &Database, _args: &[&str]) -> Option<Result<Value>> {
+        None
+    }
+
+    fn execute(&self, db: &mut Database, args: &[&str]) -> Option<Result<Value>> {
+        match self.writes_rows() {
+            true => self.write_rows(db, args),
+            false => self.read(db, args),
+        }
+    }
+}
+
+/// A command that runs a plain function, as every built-in one does.
+pub struct Builtin {
+    pub name: &'static str,
+    pub usage: &'static [&'static str],
+    pub run: Run,
+}
+
+pub enum Run {
+    Read(fn(&Database, &[&str]) -> Option<Result<Value>>),
+    Write(fn(&mut Database, &[&str]) -> Option<Result<Value>>),
+    /// Writes rows of the table named by the first argument only; see `Database::row_writer`
+    Rows(fn(&Database, &[&str]) -> Option<Result<Value>>),
+}
+
+impl Command for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn usage(&self) -> &[&str] {
+        self.usage
+    }
+
+    fn read_only(&self) -> bool {
+        matches!(self.run, Run::Read(_))
+    }
+
+    fn read(&self, db: &Database, args: &[&str]) -> Option<Result<Value>> {
+        let _span = info_span!("command", operation = self.name).entered();
+        match self.run {
+            Run::Read(run) => run(db, args),
+            Run::Write(_) | Run::Rows(_) => None,
+        }
+    }
+
+    fn writes_rows(&self) -> bool {
+        matches!(self.run, Run::Rows(_))
+    }
+
+    fn write_rows(&self, db: &Database, args: &[&str]) -> Option<Result<Value>> {
+        let _span = info_span!("command", operation = self.name).entered();
+        match self.run {
+            Run::Rows(run) => run(db, args),
+            Run::Read(_) | Run::Write(_) => None,
+        }
+    }
+
+    fn execute(&self, db: &mut Database, args: &[&str]) -> Option<Result<Value>> {
+        let _span = info_span!("command", operation = self.name).entered();
+        match self.run {
+            Run::Read(run) | Run::Rows(run) => run(db, args),
+            Run::Write(run) => run(db, args),
+        }
+    }
+}
+
+/// Commands by name, in the order they were registered (which is the order HELP lists them in).
+#[derive(Default)]
+pub struct Registry {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl Registry {
+    /// Add a command; one registered earlier under the same name is replaced.
+    pub fn register(&mut self, command: Box<dyn Command>) {
+        match self.commands.iter_mut().find(|existing| existing.name() == command.name()) {
+            Some(existing) => *existing = command,
+            None => self.commands.push(command),
+        }
+    }
+
+    /// The command whose name is `word`, in any case.
+    pub fn get(&self, word: &str) -> Option<&dyn Command> {
+        let word = word.to_lowercase();
+        self.commands.iter().find(|command| command.name() == word).map(|command| command.as_ref())
+    }
+
+    /// Every command's usage lines.
+    pub fn usage(&self) -> Vec<&str> {
+        self.commands.iter().flat_map(|command| command.usage().iter().copied()).collect()
+    }
+}
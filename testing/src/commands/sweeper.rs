@@ -0,0 +1,36 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use log::info;
+use tokio::task::JoinHandle;
+use super::db::Database;
+use crate::table::value;
+
+/// Background task that deletes expired rows (see `table::EXPIRES_COLUMN`) every `interval`.
+/// Deletions are logged like any other, so replicas follow them instead of sweeping themselves.
+pub struct Sweeper {
+    db: Arc<RwLock<Database>>,
+    interval: Duration,
+}
+
+impl Sweeper {
+    pub fn new(db: Arc<RwLock<Database>>, interval: Duration) -> Self {
+        Sweeper { db, interval }
+    }
+
+    /// Spawn the sweeper onto the current tokio runtime.
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    /// Sweep every `interval`, forever.
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            ticker.tick().await;
+            let deleted = self.db.write().unwrap().delete_expired_rows(value::now());
+            if deleted > 0 {
+                info!("Swept {} expired rows.", deleted);
+            }
+        }
+    }
+}
@@ -1,49 +1,467 @@
-//// filepath: c:\Users\srija\Documents\GitHub\Rust_DB\testing\src\commands\walengine.rs
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use log::{info, error};
-use super::db::Database;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Interval, MissedTickBehavior};
+use tracing::field::Empty;
+use tracing::instrument;
+use super::backups::BackupSchedule;
+use super::db::{self, Database, DatabaseError, Result};
+use crate::table::value::{self, Value};
 
+/// How long a WalEngine goes without a cycle by default.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often a WalEngine with an entry or byte limit (see `WalEngineConfig`) checks the WAL
+/// against it.
+const LIMIT_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// **Checkpoint policy**
+/// When a WalEngine runs a cycle, archiving the WAL and emptying the WAL file: `interval` after
+/// the last one, or as soon as more than `max_entries` entries or `max_bytes` bytes are waiting
+/// to be archived, whichever comes first. Without limits it cycles on time alone however busy the
+/// database is; with them a burst of writes is archived early, so the WAL file stays small.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalEngineConfig {
+    pub interval: Duration,
+    pub max_entries: Option<usize>,
+    /// Bytes of the pending entries, one line each (before any encryption)
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for WalEngineConfig {
+    fn default() -> Self {
+        WalEngineConfig::every(DEFAULT_INTERVAL)
+    }
+}
+
+impl WalEngineConfig {
+    /// A cycle every `interval`, however many entries are waiting.
+    pub fn every(interval: Duration) -> Self {
+        WalEngineConfig { interval, max_entries: None, max_bytes: None }
+    }
+
+    fn has_limits(&self) -> bool {
+        self.max_entries.is_some() || self.max_bytes.is_some()
+    }
+
+    /// The limit that `entries` pending entries of `bytes` bytes in all are over, if any.
+    fn exceeded(&self, entries: usize, bytes: usize) -> Option<Trigger> {
+        if self.max_entries.is_some_and(|max| entries > max) {
+            Some(Trigger::Entries)
+        } else if self.max_bytes.is_some_and(|max| bytes > max) {
+            Some(Trigger::Bytes)
+        } else {
+            None
+        }
+    }
+}
+
+/// What started a WalEngine cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// `WalEngineConfig::interval` went by
+    Interval,
+    /// More than `WalEngineConfig::max_entries` entries were waiting
+    Entries,
+    /// More than `WalEngineConfig::max_bytes` bytes were waiting
+    Bytes,
+    /// The engine was stopped (see `WalEngineHandle::stop`)
+    Shutdown,
+}
+
+impl Trigger {
+    pub fn name(self) -> &'static str {
+        match self {
+            Trigger::Interval => "interval",
+            Trigger::Entries => "entries",
+            Trigger::Bytes => "bytes",
+            Trigger::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// Background task that periodically moves the in-memory WAL to disk.
+/// Every `Database::group_commit` it writes the entries logged since to the WAL file with one
+/// write and one fsync (group commit), so writers never wait for the disk and a burst of writes
+/// costs one fsync, not one each. When its `WalEngineConfig` says so it saves the dirty tables,
+/// archives the entries and empties the WAL file. The database lock is only held long enough to
+/// take the pending entries (shared, for a group commit); the file writes happen on a blocking
+/// thread.
+/// Once enough rows changed (`Database::save_due`) it saves the dirty tables on a blocking thread
+/// too, so writers only ever change memory and log to the WAL, and once the tables take more than
+/// `Database::memory_limit` it spills the coldest to disk.
+/// With `with_backups` it also backs the database up on a schedule.
+/// `start` hands back a `WalEngineHandle` that stops it, after a last cycle, whenever asked.
 pub struct WalEngine {
-    db: Arc<Mutex<Database>>,
-    interval: Duration,
+    db: Arc<RwLock<Database>>,
+    config: WalEngineConfig,
+    group_commit: Option<Duration>,
+    backups: Option<Arc<BackupSchedule>>,
+    metrics: Arc<CycleMetrics>,
+}
+
+/// **Running WalEngine**
+/// What `WalEngine::start` hands back. `stop` asks the engine to finish: it archives what is left
+/// of the WAL in a last cycle, lets a backup or save being written finish, and saves the tables
+/// with unsaved changes, so nothing logged before is left behind. `join` waits until it has.
+/// Dropping the handle leaves the engine running for as long as the runtime does.
+pub struct WalEngineHandle {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl WalEngineHandle {
+    /// Ask the engine to stop after a last cycle; returns at once (see `join`).
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    /// Wait until the engine has ended, i.e. after `stop` has written everything out.
+    pub async fn join(self) {
+        if let Err(e) = self.task.await {
+            error!("WAL engine failed: {}", e);
+        }
+    }
+}
+
+/// How one WalEngine cycle went.
+#[derive(Debug, Clone)]
+pub struct Cycle {
+    /// When it finished, in seconds since the Unix epoch
+    pub at: i64,
+    pub took: Duration,
+    pub trigger: Trigger,
+    /// Entries persisted and archived
+    pub entries: usize,
+    /// Why it failed; its entries are retried by the next cycle
+    pub error: Option<String>,
+}
+
+/// What a WalEngine reports about its cycles, so a stuck or failing background loop shows up
+/// (see `Database::status`, `.wal status`). The engine keeps it up to date and hands it to its
+/// database as `wal_engine`, where it can be read without the database lock.
+#[derive(Debug)]
+pub struct CycleMetrics {
+    config: WalEngineConfig,
+    group_commit: Option<Duration>,
+    started: Instant,
+    state: Mutex<CycleState>,
+}
+
+#[derive(Debug, Default)]
+struct CycleState {
+    last: Option<Cycle>,
+    finished_at: Option<Instant>,
+    cycles: u64,
+    // Failed cycles since the last one that succeeded
+    failures: usize,
+    // Group commits that wrote anything, and the entries they wrote
+    group_commits: u64,
+    entries_synced: u64,
+}
+
+impl CycleMetrics {
+    /// Cycles missed before the engine counts as stalled.
+    const STALLED_AFTER: u32 = 3;
+
+    fn new(config: WalEngineConfig, group_commit: Option<Duration>) -> Self {
+        CycleMetrics { config, group_commit, started: Instant::now(), state: Mutex::default() }
+    }
+
+    fn record_group_commit(&self, entries: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.group_commits += 1;
+        state.entries_synced += entries as u64;
+    }
+
+    fn record(&self, started: Instant, trigger: Trigger, result: &Result<usize>) {
+        let mut state = self.state.lock().unwrap();
+        state.last = Some(Cycle {
+            at: value::now(),
+            took: started.elapsed(),
+            trigger,
+            entries: *result.as_ref().unwrap_or(&0),
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+        state.finished_at = Some(Instant::now());
+        state.cycles += 1;
+        state.failures = if result.is_ok() { 0 } else { state.failures + 1 };
+    }
+
+    /// The last cycle to finish, if any has.
+    pub fn last_cycle(&self) -> Option<Cycle> {
+        self.state.lock().unwrap().last.clone()
+    }
+
+    /// Cycles that failed in a row, up to the last one; 0 if it succeeded.
+    pub fn consecutive_failures(&self) -> usize {
+        self.state.lock().unwrap().failures
+    }
+
+    /// Whether no cycle has finished for `STALLED_AFTER` intervals, e.g. because one hangs on
+    /// the disk or the task died.
+    pub fn is_stalled(&self) -> bool {
+        let since = self.state.lock().unwrap().finished_at.unwrap_or(self.started);
+        since.elapsed() > self.config.interval * Self::STALLED_AFTER
+    }
+
+    /// Whether the engine is keeping up: not stalled, and its last cycle did not fail.
+    pub fn is_healthy(&self) -> bool {
+        !self.is_stalled() && self.consecutive_failures() == 0
+    }
+
+    /// Encode as `{"interval_ms":..,"max_entries":..|null,"max_bytes":..|null,"cycles":..,
+    /// "last_cycle":{"at":..,"took_ms":..,"trigger":..,"entries":..,"error":..}|null,
+    /// "consecutive_failures":..,"stalled":..,"group_commit_ms":..|null,"group_commits":..,
+    /// "entries_synced":..}`.
+    pub fn report(&self) -> serde_json::Value {
+        let stalled = self.is_stalled();
+        let state = self.state.lock().unwrap();
+        let last_cycle = state.last.as_ref().map(|cycle| {
+            serde_json::json!({
+                "at": Value::Timestamp(cycle.at).to_string(),
+                "took_ms": cycle.took.as_millis() as u64,
+                "trigger": cycle.trigger.name(),
+                "entries": cycle.entries,
+                "error": cycle.error,
+            })
+        });
+        serde_json::json!({
+            "interval_ms": self.config.interval.as_millis() as u64,
+            "max_entries": self.config.max_entries,
+            "max_bytes": self.config.max_bytes,
+            "cycles": state.cycles,
+            "last_cycle": last_cycle,
+            "consecutive_failures": state.failures,
+            "stalled": stalled,
+            "group_commit_ms": self.group_commit.map(|every| every.as_millis() as u64),
+            "group_commits": state.group_commits,
+            "entries_synced": state.entries_synced,
+        })
+    }
 }
 
 impl WalEngine {
-    pub fn new(db: Arc<Mutex<Database>>, interval: Duration) -> Self {
-        WalEngine { db, interval }
-    }
-
-    pub fn start(&self) {
-        let db_clone = Arc::clone(&self.db);
-        let interval = self.interval;
-
-        thread::spawn(move || {
-            loop {
-                {
-                    let mut db = db_clone.lock().unwrap();
-                    // Persist the working WAL.
-                    if let Err(e) = db.persist_wal() {
-                        error!("Failed to persist WAL: {}", e);
-                    } else {
-                        info!("WAL persisted successfully.");
-                    }
-                    // Replay the WAL to update in-memory state.
-                    if let Err(e) = db.replay_wal() {
-                        error!("Failed to replay WAL: {}", e);
-                    } else {
-                        info!("WAL replayed successfully.");
-                    }
-                    // Now commit the WAL: archive the logged operations and clear the working log.
-                    if let Err(e) = db.commit_wal() {
-                        error!("Failed to commit WAL: {}", e);
-                    } else {
-                        info!("WAL commit completed.");
-                    }
+    /// An engine for `db` that cycles as `config` says, group committing as often as `db` says
+    /// (`Database::group_commit`), which it hands its `metrics` (`Database::wal_engine`).
+    pub fn new(db: Arc<RwLock<Database>>, config: WalEngineConfig) -> Self {
+        let group_commit = db.read().unwrap().group_commit;
+        let metrics = Arc::new(CycleMetrics::new(config, group_commit));
+        db.write().unwrap().wal_engine = Some(Arc::clone(&metrics));
+        WalEngine { db, config, group_commit, backups: None, metrics }
+    }
+
+    /// After each cycle, take the backup `schedule` has due. Backups are written on a blocking
+    /// thread, so WAL cycles go on meanwhile; a backup still running when the next one is due
+    /// delays it.
+    pub fn with_backups(mut self, schedule: BackupSchedule) -> Self {
+        self.backups = Some(Arc::new(schedule));
+        self
+    }
+
+    /// Spawn the engine onto the current tokio runtime.
+    pub fn start(self) -> WalEngineHandle {
+        let (stop, stopped) = watch::channel(false);
+        WalEngineHandle { stop, task: tokio::spawn(self.run(stopped)) }
+    }
+
+    /// Run a WAL cycle whenever the `WalEngineConfig` says (its limits are checked every
+    /// `LIMIT_CHECK_INTERVAL`), and a group commit every `group_commit` in between, until `stop`
+    /// turns true (or forever, once its sender is gone).
+    pub async fn run(self, stop: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.config.interval);
+        let mut group_commits = self.group_commit.map(|every| {
+            let mut group_commits = tokio::time::interval(every);
+            // After a slow fsync, commit what piled up once rather than catch up tick by tick.
+            group_commits.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            group_commits
+        });
+        let mut limits = self.config.has_limits().then(|| {
+            let mut limits = tokio::time::interval(LIMIT_CHECK_INTERVAL);
+            limits.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            limits
+        });
+        let mut stop = Some(stop);
+        let mut backup: Option<JoinHandle<()>> = None;
+        let mut save: Option<JoinHandle<()>> = None;
+        loop {
+            let trigger = tokio::select! {
+                _ = ticker.tick() => Trigger::Interval,
+                _ = tick(&mut group_commits) => {
+                    self.group_commit().await;
+                    self.save_if_due(&mut save);
+                    continue;
                 }
-                thread::sleep(interval);
+                _ = tick(&mut limits) => match self.over_limit() {
+                    Some(trigger) => trigger,
+                    None => continue,
+                },
+                _ = shut_down(&mut stop) => Trigger::Shutdown,
+            };
+            // The next cycle on time comes a whole interval after this one, whatever started it.
+            if trigger != Trigger::Interval {
+                ticker.reset();
             }
-        });
+            let started = Instant::now();
+            let result = self.cycle().await;
+            if let Err(e) = &result {
+                error!("Failed to commit WAL: {}", e);
+            }
+            self.metrics.record(started, trigger, &result);
+            if trigger == Trigger::Shutdown {
+                // Let a backup or save that is being written finish rather than leave it half done.
+                for task in [backup, save].into_iter().flatten() {
+                    let _ = task.await;
+                }
+                let db = Arc::clone(&self.db);
+                match tokio::task::spawn_blocking(move || db.read().unwrap().save_dirty()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => error!("Failed to save tables on shutdown: {}", e),
+                    Err(e) => error!("Failed to save tables on shutdown: {}", e),
+                }
+                info!("WAL engine stopped.");
+                return;
+            }
+            self.save_if_due(&mut save);
+            if let Some(schedule) = &self.backups {
+                if backup.as_ref().is_none_or(JoinHandle::is_finished) {
+                    let (schedule, db) = (Arc::clone(schedule), Arc::clone(&self.db));
+                    backup = Some(tokio::task::spawn_blocking(move || {
+                        if let Err(e) = schedule.run_due(&db) {
+                            error!("Scheduled backup failed: {}", e);
+                        }
+                    }));
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+
+    /// The limit of the `WalEngineConfig` the pending entries are over, if any. After a failed
+    /// cycle none is, so a WAL that cannot be archived is retried on time rather than at every
+    /// check.
+    fn over_limit(&self) -> Option<Trigger> {
+        if self.metrics.consecutive_failures() > 0 {
+            return None;
+        }
+        let (entries, bytes) = self.db.read().unwrap().wal_pending();
+        self.config.exceeded(entries, bytes)
+    }
+
+    /// Write the entries logged since the last group commit to the WAL file (see
+    /// `Database::persist_wal`). A failure is logged and the entries are written by the next one.
+    async fn group_commit(&self) {
+        let db = Arc::clone(&self.db);
+        match tokio::task::spawn_blocking(move || db.read().unwrap().persist_wal()).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(entries)) => self.metrics.record_group_commit(entries),
+            Ok(Err(e)) => error!("Group commit failed: {}", e),
+            Err(e) => error!("Group commit failed: {}", e),
+        }
+    }
+
+    /// On a blocking thread, save the dirty tables once they are due (`Database::save_dirty`) and
+    /// spill the coldest to disk once over the memory limit (`Database::evict_tables`), unless the
+    /// last save is still being written. The dirty tables are saved first under the shared lock,
+    /// so evicting, which takes it exclusively, mostly just drops them.
+    fn save_if_due(&self, save: &mut Option<JoinHandle<()>>) {
+        if save.as_ref().is_some_and(|save| !save.is_finished()) {
+            return;
+        }
+        let (due, over) = {
+            let db = self.db.read().unwrap();
+            (db.save_due(), db.over_memory_limit())
+        };
+        if !due && !over {
+            return;
+        }
+        let db = Arc::clone(&self.db);
+        *save = Some(tokio::task::spawn_blocking(move || {
+            if let Err(e) = db.read().unwrap().save_dirty() {
+                error!("Background save failed: {}", e);
+            }
+            if over {
+                if let Err(e) = db.write().unwrap().evict_tables() {
+                    error!("Failed to spill tables to disk: {}", e);
+                }
+            }
+        }));
+    }
+
+    /// Save the dirty tables, then archive the pending entries and clear the working WAL file.
+    /// The entries were already applied in memory when they were logged, so no replay is needed here.
+    /// Returns how many entries were archived.
+    #[instrument(name = "wal_cycle", skip_all, fields(rows = Empty))]
+    async fn cycle(&self) -> Result<usize> {
+        let (entries, first_lsn, wal_file, archive_file, wal_synced) = {
+            let mut db = self.db.write().unwrap();
+            let entries = std::mem::take(db.wal.get_mut());
+            if entries.is_empty() {
+                db.last_checkpoint = Some(value::now());
+            }
+            let first_lsn = db.wal_lsn + 1;
+            db.wal_lsn += entries.len() as u64;
+            (entries, first_lsn, db.wal_file.clone(), db.wal_archive_file.clone(), Arc::clone(&db.wal_synced))
+        };
+        tracing::Span::current().record("rows", entries.len());
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let entries = Arc::new(entries);
+        let (archived, archive, db) = (Arc::clone(&entries), archive_file.clone(), Arc::clone(&self.db));
+        let result = tokio::task::spawn_blocking(move || {
+            // Startup replays only the WAL file, so the tables must hold these entries before it
+            // is emptied. They were applied when taken, so saving the dirty tables now covers them.
+            db.read().unwrap().save_dirty()?;
+            // Group commits wait meanwhile, so none appends to the WAL file just before it is emptied.
+            let mut synced = wal_synced.lock();
+            db::append_lines(&archive, &archived)?;
+            info!("WAL entries archived.");
+            std::fs::File::create(&wal_file).map_err(|err| DatabaseError::FileCreationError(wal_file.clone(), err.to_string()))?;
+            // Entries logged after these are not in the emptied file, so the next group commit writes them.
+            *synced = first_lsn + archived.len() as u64 - 1;
+            info!("WAL commit completed.");
+            Ok(archived.len())
+        })
+        .await
+        .unwrap_or_else(|e| Err(DatabaseError::FileCreationError(archive_file, e.to_string())));
+
+        if result.is_ok() {
+            // Only now are the entries committed, so subscribers never see a change that could be lost.
+            let mut db = self.db.write().unwrap();
+            db.changes.publish(first_lsn, &entries);
+            db.last_checkpoint = Some(value::now());
+        } else {
+            // Put the entries back in front of anything logged meanwhile so the next cycle retries them.
+            let mut db = self.db.write().unwrap();
+            db.wal_lsn -= entries.len() as u64;
+            let wal = db.wal.get_mut();
+            let newer = std::mem::replace(wal, Arc::unwrap_or_clone(entries));
+            wal.extend(newer);
+        }
+        result
+    }
+}
+
+/// Resolves at `interval`'s next tick; never without one.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once `shutdown` turns true; never without a receiver or once its sender is gone.
+async fn shut_down(shutdown: &mut Option<watch::Receiver<bool>>) {
+    if let Some(receiver) = shutdown {
+        if receiver.wait_for(|stop| *stop).await.is_ok() {
+            return;
+        }
+    }
+    std::future::pending().await
+
+}
@@ -0,0 +1,135 @@
+use crate::commands::executor;
+use crate::dot_commands::DOT_USAGE;
+use crate::session::SharedDb;
+use crate::views;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::collections::BTreeSet;
+
+/// **Tab completion at the prompt**
+/// The first word completes to a command, later words to keywords, the tables and views of the
+/// current database, and the columns of any table already named on the line (also after a `,`,
+/// as in `COLUMNS a,b`). Keywords keep the case typed so far. Names are read from the database on
+/// every Tab, so new tables and columns complete straight away.
+pub struct Completion {
+    /// The session's current database; the prompt updates it after `USE`
+    pub db: SharedDb,
+    commands: BTreeSet<String>,
+    keywords: BTreeSet<String>,
+}
+
+impl Completion {
+    /// Complete against `db`, with the commands and keywords taken from the command usage and the
+    /// dot-commands from `DOT_USAGE`.
+    pub fn new(db: SharedDb) -> Self {
+        let is_keyword = |word: &&str| word.len() > 1 && word.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+        let mut commands = BTreeSet::from(["HELP".to_string()]);
+        let mut keywords = BTreeSet::new();
+        // Lines indented in the usage explain the line above rather than start a command.
+        for usage in executor::command_usage().into_iter().filter(|usage| !usage.starts_with(' ')) {
+            for form in usage.split(" / ") {
+                let (first, rest) = form.split_once(' ').unwrap_or((form, ""));
+                commands.extend(first.split('|').filter(is_keyword).map(str::to_string));
+                let words = rest.split(|c: char| !c.is_ascii_alphanumeric() && c != '_');
+                keywords.extend(words.filter(is_keyword).map(str::to_string));
+            }
+        }
+        commands.extend(DOT_USAGE.iter().filter_map(|usage| usage.split_whitespace().next()).map(str::to_string));
+        Completion { db, commands, keywords }
+    }
+
+    /// Everything the word at `index` on a line starting with `words` could be: columns of the
+    /// tables named so far, then tables and views, then keywords.
+    fn candidates(&self, words: &[&str], index: usize) -> Vec<String> {
+        if index == 0 {
+            return self.commands.iter().cloned().collect();
+        }
+        let db = self.db.read().unwrap();
+        let columns: BTreeSet<String> = words[..index]
+            .iter()
+            .filter_map(|word| db.tables.get(word))
+            .flat_map(|table| table.columns.iter().cloned().collect::<Vec<_>>())
+            .collect();
+        let mut tables: BTreeSet<String> = db.tables.keys().cloned().collect();
+        tables.extend(db.storage.lock().table_names().unwrap_or_default());
+        tables.extend(views::names(&db));
+        let mut candidates: Vec<String> = Vec::new();
+        for name in columns.into_iter().chain(tables).chain(self.keywords.iter().cloned()) {
+            // System tables and columns are not meant to be typed.
+            if !name.starts_with('_') && !candidates.contains(&name) {
+                candidates.push(name);
+            }
+        }
+        candidates
+    }
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(|c: char| c.is_whitespace() || c == ',').map_or(0, |i| i + 1);
+        let prefix = &before[start..];
+        // `col=value` is a value being typed; nothing to complete.
+        if prefix.contains('=') {
+            return Ok((pos, Vec::new()));
+        }
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+        let index = if before[..start].ends_with(',') { words.len().saturating_sub(1) } else { words.len() };
+        let lowercase = prefix.starts_with(|c: char| c.is_ascii_lowercase());
+        let matches = self
+            .candidates(&words, index)
+            .into_iter()
+            .filter(|candidate| candidate.to_lowercase().starts_with(&prefix.to_lowercase()))
+            .map(|candidate| {
+                let keyword = self.keywords.contains(&candidate) || self.commands.contains(&candidate);
+                if lowercase && keyword { candidate.to_lowercase() } else { candidate }
+            })
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::Database;
+    use crate::storage::csv::CsvStorage;
+    use rustyline::history::DefaultHistory;
+    use std::sync::{Arc, RwLock};
+
+    fn complete(completion: &Completion, line: &str) -> Vec<String> {
+        let history = DefaultHistory::new();
+        completion.complete(line, line.len(), &Context::new(&history)).unwrap().1
+    }
+
+    #[test]
+    fn completes_commands_tables_and_their_columns() {
+        let dir = std::env::temp_dir().join(format!("rustdb-completion-{}", std::process::id()));
+        let mut db = Database::with_storage(Box::new(CsvStorage::in_dir(&dir.display().to_string()).unwrap()));
+        db.create_table("users").unwrap();
+        db.add_column("users", "name", None).unwrap();
+        db.add_column("users", "nickname", None).unwrap();
+        let completion = Completion::new(Arc::new(RwLock::new(db)));
+        assert_eq!(complete(&completion, "pri"), ["print"]);
+        assert_eq!(complete(&completion, "PRINT us")[0], "users", "tables come before keywords");
+        assert_eq!(complete(&completion, "PRINT users COLUMNS name,ni"), ["nickname"]);
+        assert_eq!(complete(&completion, "SEARCH users n")[..2], ["name", "nickname"], "columns come first");
+        assert!(complete(&completion, "INSERT users 1 name=n").is_empty(), "completed a value");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::commands::db::{Database, DatabaseError, Result};
+use crate::commands::executor;
+use crate::table::table::{Row, Table};
+use crate::table::value::{ColumnType, Value};
+
+/// Usage lines for the dot-commands, shown by `.help`.
+pub const DOT_USAGE: &[&str] = &[
+    ".tables (every table, in memory or stored)",
+    ".schema [<tablename>] (the commands that recreate the table, or every table)",
+    ".import <filename> <tablename> [<col:type,...>] (loads a CSV or JSON file into the table, as LOAD does)",
+    ".dump (the commands that recreate every table and its rows; `testing run` replays them)",
+    ".wal status (the WAL files, the current LSN, how many entries are pending and how the WAL engine's last cycle went)",
+    ".help",
+];
+
+/// **Dot-commands**
+/// sqlite-style introspection at the prompt, e.g. `.schema users`. They answer with plain text
+/// rather than JSON and never go through the WAL themselves.
+pub fn execute(db: &mut Database, line: &str) -> Result<String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    match parts[..] {
+        [".tables"] => Ok(db.table_names()?.into_iter().collect::<Vec<_>>().join("\n")),
+        [".schema"] => {
+            let names = db.table_names()?;
+            let schemas = names.iter().map(|name| schema(db, name)).collect::<Result<Vec<_>>>()?;
+            Ok(schemas.join("\n\n"))
+        }
+        [".schema", table] => schema(db, table),
+        [".import", file, table, ref types @ ..] if types.len() <= 1 => {
+            let types = types.first().map(|types| executor::column_types(types)).transpose()?.unwrap_or_default();
+            db.load_table_from_file(table, file, &types)?;
+            let rows = db.get_table(table)?.rows.len();
+            Ok(format!("Imported {} rows from '{}' into table '{}'.", rows, file, table))
+        }
+        [".dump"] => dump(db),
+        [".wal", status] if status.eq_ignore_ascii_case("status") => Ok(wal_status(db)),
+        [".help"] => Ok(DOT_USAGE.join("\n")),
+        _ => Err(DatabaseError::UnknownDotCommand(line.to_string())),
+    }
+}
+
+/// `CREATE TABLE` and an `ADD COLUMN` per column, the primary key first and then by name.
+fn schema(db: &mut Database, table_name: &str) -> Result<String> {
+    db.ensure_table_loaded(table_name)?;
+    let table = db.get_table(table_name)?;
+    let mut columns: Vec<&String> = table.columns.iter().collect();
+    columns.sort_by_key(|column| (table.primary_key.as_ref() != Some(*column), *column));
+    let mut statements = vec![format!("CREATE TABLE {};", table_name)];
+    for column in columns {
+        let spec = table.spec(column);
+        let mut statement = format!("ADD COLUMN {} {}", table_name, column);
+        if spec.column_type != ColumnType::Text {
+            statement += &format!(" {}", spec.column_type.name());
+        }
+        if spec.primary_key {
+            statement += " PRIMARY KEY";
+        } else if spec.not_null {
+            statement += " NOT NULL";
+        }
+        if let Some(default) = &spec.default {
+            statement += &format!(" DEFAULT {}", default);
+        }
+        statements.push(statement + ";");
+    }
+    Ok(statements.join("\n"))
+}
+
+/// Every table's schema followed by an `INSERT` per row, soft-deleted ones included. Commands
+/// split on whitespace, so a row with whitespace in a value cannot be written as one and is
+/// left out with a comment saying so.
+fn dump(db: &mut Database) -> Result<String> {
+    let mut dump = Vec::new();
+    for name in db.table_names()? {
+        dump.push(schema(db, &name)?);
+        let table = db.get_table(&name)?;
+        let rows: Vec<String> = table.rows.iter().map(|(row_id, row)| insert(&table, &name, row_id, row)).collect();
+        if !rows.is_empty() {
+            dump.push(rows.join("\n"));
+        }
+    }
+    Ok(dump.join("\n\n"))
+}
+
+fn insert(table: &Table, table_name: &str, row_id: &str, row: &Row) -> String {
+    let mut columns: Vec<&String> = table.columns.iter().filter(|column| row.contains_key(column.as_str())).collect();
+    columns.sort();
+    let cells: Vec<String> = columns.iter().map(|column| format!("{}={}", column, row[column.as_str()])).collect();
+    if row_id.contains(char::is_whitespace) || cells.iter().any(|cell| cell.contains(char::is_whitespace)) {
+        return format!("-- row '{}' of table '{}' skipped: a value holds whitespace", row_id, table_name);
+    }
+    // A row without values still needs a cell to be an INSERT; a NULL one adds nothing.
+    let cells = match (cells.is_empty(), table.columns.iter().min()) {
+        (true, Some(column)) => format!("{}=NULL", column),
+        _ => cells.join(" "),
+    };
+    format!("INSERT {} {} {};", table_name, row_id, cells)
+}
+
+fn wal_status(db: &Database) -> String {
+    let lines = [
+        format!("wal file: {}", db.wal_file),
+        format!("archive: {}", db.wal_archive_file),
+        format!("lsn: {} ({} archived)", db.current_lsn(), db.wal_lsn),
+        format!("pending entries: {}", db.wal.lock().len()),
+    ];
+    let engine = db.wal_engine.as_ref().map(|metrics| match metrics.last_cycle() {
+        _ if metrics.is_stalled() => "wal engine: stalled (no cycle finished for several intervals)".to_string(),
+        Some(cycle) => match cycle.error {
+            Some(error) => format!("wal engine: last cycle at {} failed ({} in a row): {}", Value::Timestamp(cycle.at), metrics.consecutive_failures(), error),
+            None => format!("wal engine: last cycle at {} archived {} entries in {} ms", Value::Timestamp(cycle.at), cycle.entries, cycle.took.as_millis()),
+        },
+        None => "wal engine: no cycle yet".to_string(),
+    });
+    lines.into_iter().chain(engine).collect::<Vec<_>>().join("\n")
+}
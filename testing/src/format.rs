@@ -0,0 +1,133 @@
+use crate::commands::executor::Response;
+use crate::storage::csv;
+use crate::table::table::{render_grid, RenderOptions};
+use crate::table::value::NULL_TEXT;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// **Output formats**
+/// How a response is written out: `Json` is the usual `{"status": ..., "data": ...}` line, `Csv`
+/// and `Table` lay rows out as CSV (as `SAVE` writes it, so `LOAD` reads it back) or as a grid.
+/// Responses that are not rows, and errors, stay JSON in every format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "table" => Some(OutputFormat::Table),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Split a trailing `FORMAT json|csv|table` off a command line, e.g. `PRINT users FORMAT csv`.
+pub fn split_flag(line: &str) -> (&str, Option<OutputFormat>) {
+    let line = line.trim_end();
+    let Some((rest, name)) = line.rsplit_once(char::is_whitespace) else {
+        return (line, None);
+    };
+    let rest = rest.trim_end();
+    match (rest.rsplit_once(char::is_whitespace), OutputFormat::parse(name)) {
+        (Some((command, keyword)), Some(format)) if keyword.eq_ignore_ascii_case("format") => (command.trim_end(), Some(format)),
+        _ => (line, None),
+    }
+}
+
+/// Rows in a response: the headers, then each row's cells, `None` for NULL.
+type Rows = (Vec<String>, Vec<Vec<Option<String>>>);
+
+/// The rows in `data`, if it holds any: PRINT output (`columns` and `rows`), a PAGE (`rows`), a list
+/// of rows as SEARCH returns it, or a single row, each headed by `row_id` and then the columns.
+fn rows_in(data: &Value) -> Option<Rows> {
+    let items: Vec<&Value> = match data {
+        Value::Object(object) if object.contains_key("row_id") => vec![data],
+        Value::Object(object) => object.get("rows")?.as_array()?.iter().collect(),
+        // An empty list could be of anything, e.g. TABLES with no tables.
+        Value::Array(items) if items.is_empty() => return None,
+        Value::Array(items) => items.iter().collect(),
+        _ => return None,
+    };
+    let text = |value: &Value| match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    };
+    if !items.iter().all(|item| item["row_id"].is_string() && item["data"].is_object()) {
+        return None;
+    }
+    let mut columns: Vec<String> = match data["columns"].as_array() {
+        Some(columns) => columns.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        None => {
+            let names: BTreeSet<&String> = items.iter().flat_map(|item| item["data"].as_object().into_iter().flat_map(|data| data.keys())).collect();
+            names.into_iter().cloned().collect()
+        }
+    };
+    let rows = items
+        .into_iter()
+        .map(|item| std::iter::once(text(&item["row_id"])).chain(columns.iter().map(|col| text(&item["data"][col]))).collect())
+        .collect();
+    columns.insert(0, "row_id".to_string());
+    Some((columns, rows))
+}
+
+/// `rows` as CSV under a header line, NULL written as `SAVE` writes it.
+fn to_csv((headers, rows): Rows) -> String {
+    let mut csv = csv::record(&headers);
+    for cells in rows {
+        csv += "\n";
+        csv += &csv::record(&cells.iter().map(|cell| cell.as_deref().unwrap_or(NULL_TEXT)).collect::<Vec<_>>());
+    }
+    csv
+}
+
+/// `rows` as a grid laid out by `options`. When `data` says how many rows there are in `total`
+/// (PRINT with LIMIT) and that is more than it holds, a last line says so.
+fn to_grid((mut headers, rows): Rows, data: &Value, options: &RenderOptions) -> String {
+    if headers[0] == "row_id" {
+        headers[0] = "Row ID".to_string();
+    }
+    let cells: Vec<Vec<String>> = rows
+        .into_iter()
+        .map(|cells| cells.into_iter().map(|cell| cell.unwrap_or_else(|| "NULL".to_string())).collect())
+        .collect();
+    let mut grid = render_grid(&headers, &cells, options);
+    match data["total"].as_u64() {
+        Some(total) if total as usize > cells.len() => {
+            grid += &format!("({} rows in all; PRINT ... LIMIT <n> OFFSET <n> pages through them)\n", total)
+        }
+        _ => {}
+    }
+    grid.trim_end().to_string()
+}
+
+/// `data` in `format`, or `None` if it holds no rows (see `rows_in`) or the format is `Json`.
+pub fn render_data(data: &Value, format: OutputFormat, options: &RenderOptions) -> Option<String> {
+    match format {
+        OutputFormat::Json => None,
+        OutputFormat::Csv => rows_in(data).map(to_csv),
+        OutputFormat::Table => rows_in(data).map(|rows| to_grid(rows, data, options)),
+    }
+}
+
+/// `response` in `format`, without a trailing newline.
+pub fn render(response: &Response, format: OutputFormat, options: &RenderOptions) -> String {
+    match response {
+        Response::Ok(data) => render_data(data, format, options).unwrap_or_else(|| response.to_line()),
+        _ => response.to_line(),
+    }
+}
@@ -0,0 +1,661 @@
+use crate::auth;
+use crate::commands::db::{Database, DatabaseError};
+use crate::format::{self, OutputFormat};
+use crate::policies;
+use crate::privileges::{self, Needs, Privilege};
+use crate::quotas::Quotas;
+use crate::table::pattern::TextPattern;
+use crate::table::table::{self, ColumnSpec, RenderOptions, Row, Table, EXPIRES_COLUMN};
+use crate::table::value::{self, ColumnType, NULL_TEXT};
+use crate::tokens;
+use crate::views::{self, View};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, MatchedPath, Path, Query, Request, State};
+use axum::http::{header, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use base64ct::{Base64, Encoding};
+use blake2::{Blake2b512, Digest};
+use log::info;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Address the HTTP API binds to when `RUSTDB_HTTP` is not set.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// Most bytes of a `PUT /views/{view}` body, which is read before the request is authenticated;
+/// a view definition is a table name and a condition.
+const VIEW_BODY_LIMIT: usize = 64 * 1024;
+
+type SharedDb = Arc<RwLock<Database>>;
+
+/// Wraps database errors so they map to HTTP status codes.
+struct ApiError(DatabaseError);
+
+impl From<DatabaseError> for ApiError {
+    fn from(err: DatabaseError) -> Self {
+        ApiError(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0 {
+            DatabaseError::TableDoesNotExist(_)
+            | DatabaseError::RowDoesNotExist(_, _)
+            | DatabaseError::RowNotFound(_, _)
+            | DatabaseError::ColumnDoesNotExist(_, _)
+            | DatabaseError::ViewDoesNotExist(_) => StatusCode::NOT_FOUND,
+            DatabaseError::TableAlreadyExists(_)
+            | DatabaseError::ViewAlreadyExists(_)
+            | DatabaseError::ColumnAlreadyExists(_, _)
+            | DatabaseError::RowAlreadyExists(_, _)
+            | DatabaseError::RowNotDeleted(_, _) => StatusCode::CONFLICT,
+            DatabaseError::InvalidCondition(_)
+            | DatabaseError::InvalidValue(_, _, _)
+            | DatabaseError::UnknownColumnType(_)
+            | DatabaseError::NoPrimaryKey(_)
+            | DatabaseError::InvalidTtl(_)
+            | DatabaseError::InvalidCursor(_)
+            | DatabaseError::InvalidPageSize(_)
+            | DatabaseError::InvalidFormat(_)
+            | DatabaseError::InvalidIncrement(_, _)
+            | DatabaseError::RowConversionError(_, _) => StatusCode::BAD_REQUEST,
+            DatabaseError::ConstraintViolation(_) => StatusCode::CONFLICT,
+            DatabaseError::AuthenticationRequired | DatabaseError::AuthenticationFailed | DatabaseError::InvalidToken => StatusCode::UNAUTHORIZED,
+            DatabaseError::SystemTable(_) | DatabaseError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            DatabaseError::RateLimited(_, _) | DatabaseError::TooManyConnections(_, _) => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = Json(json!({ "status": "error", "message": self.0.to_string() }));
+        if let DatabaseError::RateLimited(_, wait) = self.0 {
+            return (status, [(header::RETRY_AFTER, wait.div_ceil(1000).to_string())], body).into_response();
+        }
+        if status == StatusCode::UNAUTHORIZED {
+            return (status, [(header::WWW_AUTHENTICATE, "Basic realm=\"rustdb\"")], body).into_response();
+        }
+        (status, body).into_response()
+    }
+}
+
+type ApiResult = std::result::Result<Json<Value>, ApiError>;
+
+fn ok(data: Value) -> ApiResult {
+    Ok(Json(json!({ "status": "ok", "data": data })))
+}
+
+/// Answer a query in its `format` parameter: JSON as `ok` does unless `format=csv` or `format=table`
+/// asks for its rows as CSV (`text/csv`) or a grid (`text/plain`).
+fn respond(data: Value, params: &HashMap<String, String>) -> std::result::Result<Response, ApiError> {
+    let format = match params.get("format") {
+        Some(name) => OutputFormat::parse(name).ok_or_else(|| DatabaseError::InvalidFormat(name.clone()))?,
+        None => OutputFormat::Json,
+    };
+    let content_type = match format {
+        OutputFormat::Csv => "text/csv; charset=utf-8",
+        _ => "text/plain; charset=utf-8",
+    };
+    Ok(match format::render_data(&data, format, &RenderOptions::default()) {
+        Some(text) => ([(header::CONTENT_TYPE, content_type)], text + "\n").into_response(),
+        None => ok(data)?.into_response(),
+    })
+}
+
+fn row_json(table: &Table, row_id: &str, data: &Row) -> Value {
+    json!({ "row_id": row_id, "data": table.row_to_json(data) })
+}
+
+/// Build the REST router:
+/// - `GET /tables` lists tables
+/// - `PUT /tables/{t}` creates a table, optionally with `{"columns": ["id:int*", "name!", "age:int=0", ...]}`;
+///   `DELETE /tables/{t}` drops it
+/// - `GET /tables/{t}?where=age<10` (or `where=age IS NULL`) returns all (or matching) rows; with `limit=100`
+///   it returns `{"rows": [...], "next": cursor}` instead, and `cursor=` fetches the following page
+/// - `PUT /tables/{t}/columns/{c}?type=int&not_null=true&default=0` adds a column (text unless `type` is given;
+///   `primary_key=true` makes it the primary key); `PATCH ...?type=float` converts it; `DELETE` drops it
+/// - `GET /tables/{t}/stats` reports the row count, per-column non-NULL counts and cardinality, and stored size
+/// - `GET /tables/{t}/columns/{c}/distinct` lists the column's unique values (`?counts=true` with row counts)
+/// - `POST|GET|DELETE /tables/{t}/rows/{id}` upserts (`?ttl=60` expires it after 60 seconds), fetches or deletes
+///   a row (`?soft=true` only marks it deleted); `POST /tables/{t}/rows/{id}/restore` undoes a soft delete
+/// - `PATCH /tables/{t}/rows/{id}` sets the columns in the body of an existing row, all or none
+/// - `POST /tables/{t}/rows/{id}/increment/{c}?by=-2` adds to a numeric cell (1 without `by`)
+/// - `POST /tables/{t}/rows/{id}/cas/{c}` with `{"expected": ..., "value": ...}` sets the cell only if it
+///   currently holds `expected` (missing means NULL) and returns whether it did
+/// - `DELETE /tables/{t}/rows?where=age<10` deletes the matching rows (all of them, truncating, without `where`;
+///   the soft-deleted ones with `deleted=true`)
+/// - `GET /tables/{t}/keys/{key}` fetches the row with that primary key
+/// - `columns=a,b` on the `GET`s of rows returns only those columns, and `format=csv` (or `format=table`)
+///   on `GET /tables/{t}` and `GET /views/{v}` returns the rows as CSV (or a text grid) instead of JSON
+/// - once user accounts exist (see `auth::required`), every request must log in with Basic
+///   authentication (`curl -u alice:secret ...`) or an API token (`Authorization: Bearer rdb_...`),
+///   and is answered `401 Unauthorized` otherwise, or `403 Forbidden` if the account's roles lack
+///   the privilege (see `needs`)
+/// - a client over its `quotas` is answered `429 Too Many Requests`, with `Retry-After` when it
+///   sent requests too fast
+/// - `GET /status` reports uptime, table and row counts, WAL backlog, last checkpoint and disk usage;
+///   it needs no login and no quota, so load balancers can poll it, and answers `503 Service
+///   Unavailable` while the WalEngine is stalled or failing
+/// - `GET /views` lists views; `PUT /views/{v}` with `{"table": "users", "where": "age>=18", "columns": ["name"]}`
+///   defines one (`where` and `columns` are optional), `GET /views/{v}?where=...` runs it and `DELETE` drops it
+pub fn router(db: SharedDb, quotas: Arc<Quotas>) -> Router {
+    Router::new()
+        .route("/tables", get(list_tables))
+        .route("/tables/{table}", put(create_table).get(query_table).delete(drop_table))
+        .route("/tables/{table}/stats", get(table_stats))
+        .route("/tables/{table}/columns/{column}", put(add_column).patch(alter_column).delete(drop_column))
+        .route("/tables/{table}/columns/{column}/distinct", get(distinct))
+        .route("/tables/{table}/rows", delete(delete_rows))
+        .route("/tables/{table}/rows/{row_id}", post(insert_row).patch(update_row).get(get_row).delete(delete_row))
+        .route("/tables/{table}/rows/{row_id}/restore", post(restore_row))
+        .route("/tables/{table}/rows/{row_id}/increment/{column}", post(increment))
+        .route("/tables/{table}/rows/{row_id}/cas/{column}", post(compare_and_swap))
+        .route("/tables/{table}/keys/{key}", get(get_row_by_key))
+        .route("/views", get(list_views))
+        .route("/views/{view}", put(create_view).get(query_view).delete(drop_view))
+        .route_layer(middleware::from_fn_with_state(Logins::new(Arc::clone(&db), quotas), authenticate))
+        .route("/status", get(status))
+        .with_state(db)
+}
+
+/// Credentials the HTTP API has verified: for each `Authorization` header (by its BLAKE2 hash, so
+/// no password is kept), the password hash of the account it was checked against. argon2 is slow
+/// on purpose, so a client sending the same credentials with every request pays for it once, and
+/// again only after the account's password changes. Requests are held to `quotas` as well.
+#[derive(Clone)]
+struct Logins {
+    db: SharedDb,
+    verified: Arc<Mutex<HashMap<String, String>>>,
+    quotas: Arc<Quotas>,
+}
+
+impl Logins {
+    fn new(db: SharedDb, quotas: Arc<Quotas>) -> Self {
+        Logins { db, verified: Arc::default(), quotas }
+    }
+
+    /// Let a request through if no account exists, or if `authorization` holds Basic credentials
+    /// of one, or a Bearer API token (see `tokens`), whose roles grant what the request `needs`.
+    /// Returns the name of the token, which the request's rate is counted against.
+    fn check(&self, authorization: Option<&str>, needs: &Needs) -> Result<Option<String>, DatabaseError> {
+        let db = self.db.read().unwrap();
+        if !auth::required(&db) {
+            return Ok(None);
+        }
+        let authorization = authorization.ok_or(DatabaseError::AuthenticationRequired)?;
+        let mut token = None;
+        let (name, roles) = match bearer_token(authorization) {
+            Some(secret) => {
+                let name = tokens::authenticate(&db, secret)?;
+                let user = tokens::user(&db, &name).ok_or(DatabaseError::InvalidToken)?;
+                let roles = tokens::roles(&db, &name)?;
+                token = Some(name);
+                (user, roles)
+            }
+            None => {
+                let (name, password) = basic_credentials(authorization).ok_or(DatabaseError::AuthenticationRequired)?;
+                let stored = auth::stored_hash(&db, &name).ok_or(DatabaseError::AuthenticationFailed)?;
+                let key = format!("{:x}", Blake2b512::digest(authorization.as_bytes()));
+                if self.verified.lock().unwrap().get(&key) != Some(&stored) {
+                    auth::check_password(&db, &name, &password)?;
+                    self.verified.lock().unwrap().insert(key, stored);
+                }
+                let roles = auth::roles(&db, &name);
+                (name, roles)
+            }
+        };
+        privileges::check(&db, &name, &roles, needs)?;
+        // Row policies are not applied over HTTP, so a limited account may not use the table here.
+        let limits = policies::limits(&db, &name, &roles)?;
+        if let Needs::Privileges(used) = needs {
+            used.iter()
+                .filter(|(privilege, _)| *privilege != Privilege::Ddl)
+                .try_for_each(|(_, table)| policies::check_unlimited(&db, &limits, table))?;
+        }
+        Ok(token)
+    }
+}
+
+/// The token of a `Bearer <token>` header.
+fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// The user name and password of a `Basic <base64 of name:password>` header.
+fn basic_credentials(authorization: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = authorization.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(Base64::decode_vec(encoded.trim()).ok()?).ok()?;
+    let (name, password) = decoded.split_once(':')?;
+    Some((name.to_string(), password.to_string()))
+}
+
+/// What a request needs, by its route and method: reading a table or view takes SELECT on it,
+/// and the rest what the matching command takes (see `privileges::needs`). Defining a view also
+/// reads the table in the body, `view_table`.
+fn needs(method: &Method, route: &str, params: &HashMap<String, String>, view_table: Option<String>) -> Needs {
+    let Some(name) = params.get("table").or(params.get("view")) else {
+        return Needs::Nothing;
+    };
+    let on = |privilege: Privilege| Needs::Privileges(vec![(privilege, name.clone())]);
+    match (method.clone(), route) {
+        (Method::GET, _) => on(Privilege::Select),
+        (_, "/tables/{table}" | "/tables/{table}/columns/{column}") | (Method::DELETE, "/views/{view}") => on(Privilege::Ddl),
+        (Method::PUT, "/views/{view}") => {
+            Needs::Privileges([(Privilege::Ddl, name.clone())].into_iter().chain(view_table.map(|table| (Privilege::Select, table))).collect())
+        }
+        (Method::DELETE, _) => on(Privilege::Delete),
+        (Method::POST, "/tables/{table}/rows/{row_id}") => Needs::Privileges(vec![(Privilege::Insert, name.clone()), (Privilege::Update, name.clone())]),
+        _ => on(Privilege::Update),
+    }
+}
+
+/// Check a request's credentials and privileges (see `Logins::check`) before it reaches its
+/// handler, and keep every request away from the tables the database keeps for itself. A request
+/// counts against the quotas (see `Quotas`) of its IP address, and its rate against those of its
+/// API token if it has one.
+async fn authenticate(
+    State(logins): State<Logins>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    route: MatchedPath,
+    params: Option<Path<HashMap<String, String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let quotas = Arc::clone(&logins.quotas);
+    let slot = match quotas.connect(peer.ip()) {
+        Ok(slot) => slot,
+        Err(e) => return ApiError(e).into_response(),
+    };
+    let params = params.map(|Path(params)| params).unwrap_or_default();
+    let (request, view_table) = match (request.method(), route.as_str()) {
+        // The table a view reads is in the body, which the handler still needs afterwards.
+        (&Method::PUT, "/views/{view}") => {
+            let (parts, body) = request.into_parts();
+            let Ok(body) = axum::body::to_bytes(body, VIEW_BODY_LIMIT).await else {
+                return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+            };
+            let table = serde_json::from_slice::<Value>(&body).ok().and_then(|body| body["table"].as_str().map(str::to_string));
+            (Request::from_parts(parts, Body::from(body)), table)
+        }
+        _ => (request, None),
+    };
+    if let Some(name) = params.values().chain(view_table.as_ref()).find(|name| auth::is_system_table(name)) {
+        return ApiError(DatabaseError::SystemTable(name.clone())).into_response();
+    }
+    let needs = needs(request.method(), route.as_str(), &params, view_table);
+    let authorization = request.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()).map(str::to_string);
+    // Before the password is verified, so guessing it is held to the rate too.
+    if authorization.as_deref().and_then(bearer_token).is_none() {
+        if let Err(e) = quotas.check_rate(&peer.ip().to_string()) {
+            return ApiError(e).into_response();
+        }
+    }
+    // Verifying a password takes a while; keep it off the async workers.
+    let checked = tokio::task::spawn_blocking(move || logins.check(authorization.as_deref(), &needs)).await;
+    let response = match checked {
+        Ok(Ok(token)) => match token.map_or(Ok(()), |token| quotas.check_rate(&format!("token '{}'", token))) {
+            Ok(()) => next.run(request).await,
+            Err(e) => ApiError(e).into_response(),
+        },
+        Ok(Err(e)) => ApiError(e).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    drop(slot);
+    response
+}
+
+/// Serve the REST API on `addr` until the process exits, holding clients to `quotas`.
+pub async fn serve(addr: &str, db: SharedDb, quotas: Arc<Quotas>) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!(addr:% = listener.local_addr()?; "RustDB HTTP API listening");
+    axum::serve(listener, router(db, quotas).into_make_service_with_connect_info::<SocketAddr>()).await
+}
+
+async fn list_tables(State(db): State<SharedDb>) -> ApiResult {
+    let db = db.read().unwrap();
+    let mut names: Vec<&String> = db.tables.keys().collect();
+    names.sort();
+    ok(json!(names))
+}
+
+async fn create_table(
+    State(db): State<SharedDb>,
+    Path(table): Path<String>,
+    body: Option<Json<Value>>,
+) -> ApiResult {
+    let mut db = db.write().unwrap();
+    db.create_table(&table)?;
+    let columns = body
+        .as_ref()
+        .and_then(|Json(body)| body.get("columns"))
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    for declaration in columns.iter().filter_map(Value::as_str) {
+        let (column, spec) = ColumnSpec::parse_declaration(declaration)
+            .ok_or_else(|| DatabaseError::UnknownColumnType(declaration.to_string()))?;
+        db.add_typed_column(&table, column, spec)?;
+    }
+    ok(json!(table))
+}
+
+async fn drop_table(State(db): State<SharedDb>, Path(table): Path<String>) -> ApiResult {
+    let mut db = db.write().unwrap();
+    ok(json!(db.drop_table(&table)?))
+}
+
+async fn add_column(
+    State(db): State<SharedDb>,
+    Path((table, column)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let column_type = match params.get("type") {
+        Some(name) => ColumnType::parse(name).ok_or_else(|| DatabaseError::UnknownColumnType(name.clone()))?,
+        None => ColumnType::Text,
+    };
+    let spec = ColumnSpec {
+        column_type,
+        not_null: params.get("not_null").is_some_and(|v| v == "true"),
+        default: params.get("default").cloned(),
+        primary_key: params.get("primary_key").is_some_and(|v| v == "true"),
+    };
+    let mut db = db.write().unwrap();
+    ok(json!(db.add_typed_column(&table, &column, spec)?))
+}
+
+async fn alter_column(
+    State(db): State<SharedDb>,
+    Path((table, column)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let name = params.get("type").ok_or_else(|| DatabaseError::UnknownColumnType(String::new()))?;
+    let column_type = ColumnType::parse(name).ok_or_else(|| DatabaseError::UnknownColumnType(name.clone()))?;
+    let mut db = db.write().unwrap();
+    let failed = db.alter_column_type(&table, &column, column_type)?;
+    let failed: Vec<Value> = failed.into_iter().map(|(row_id, value)| json!({ "row_id": row_id, "value": value })).collect();
+    ok(json!({ "column": column, "type": column_type.name(), "unconverted": failed }))
+}
+
+async fn status(State(db): State<SharedDb>) -> std::result::Result<Response, ApiError> {
+    let status = db.read().unwrap().status()?;
+    let code = if status.is_healthy() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok((code, ok(status.to_json())?).into_response())
+}
+
+async fn table_stats(State(db): State<SharedDb>, Path(table): Path<String>) -> ApiResult {
+    let mut db = db.write().unwrap();
+    db.ensure_table_loaded(&table)?;
+    ok(db.stats(&table)?.to_json())
+}
+
+async fn distinct(
+    State(db): State<SharedDb>,
+    Path((table, column)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let mut db = db.write().unwrap();
+    db.ensure_table_loaded(&table)?;
+    let values = db.distinct(&table, &column)?;
+    if params.get("counts").is_some_and(|v| v == "true") {
+        return ok(Value::Array(
+            values.iter().map(|(value, count)| json!({ "value": value.to_json(), "count": count })).collect(),
+        ));
+    }
+    ok(Value::Array(values.iter().map(|(value, _)| value.to_json()).collect()))
+}
+
+async fn drop_column(State(db): State<SharedDb>, Path((table, column)): Path<(String, String)>) -> ApiResult {
+    let mut db = db.write().unwrap();
+    ok(json!(db.drop_column(&table, &column)?))
+}
+
+async fn insert_row(
+    State(db): State<SharedDb>,
+    Path((table, row_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(body): Json<Map<String, Value>>,
+) -> ApiResult {
+    let mut data = table::json_to_text(body);
+    let mut db = db.write().unwrap();
+    if let Some(ttl) = params.get("ttl") {
+        let ttl: u64 = ttl.parse().map_err(|_| DatabaseError::InvalidTtl(ttl.clone()))?;
+        db.enable_expiry(&table)?;
+        data.insert(EXPIRES_COLUMN.to_string(), value::now().saturating_add_unsigned(ttl).to_string());
+    }
+    let row = db.upsert_row(&table, &row_id, data)?;
+    let stored = db.get_table(&table)?;
+    ok(row_json(&stored, &row_id, &row))
+}
+
+async fn update_row(
+    State(db): State<SharedDb>,
+    Path((table, row_id)): Path<(String, String)>,
+    Json(body): Json<Map<String, Value>>,
+) -> ApiResult {
+    let mut db = db.write().unwrap();
+    let row = db.update_row_multi(&table, &row_id, table::json_to_text(body))?;
+    let stored = db.get_table(&table)?;
+    ok(row_json(&stored, &row_id, &row))
+}
+
+/// Cells of a request body in their textual form, parsed later with the column's type; `null` clears a cell.
+async fn increment(
+    State(db): State<SharedDb>,
+    Path((table, row_id, column)): Path<(String, String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let delta = params.get("by").map_or("1", String::as_str);
+    let mut db = db.write().unwrap();
+    let row = db.increment(&table, &row_id, &column, delta)?;
+    let stored = db.get_table(&table)?;
+    ok(row_json(&stored, &row_id, &row))
+}
+
+async fn compare_and_swap(
+    State(db): State<SharedDb>,
+    Path((table, row_id, column)): Path<(String, String, String)>,
+    Json(body): Json<Map<String, Value>>,
+) -> ApiResult {
+    let mut cells = table::json_to_text(body);
+    let mut take = |name: &str| cells.remove(name).unwrap_or_else(|| NULL_TEXT.to_string());
+    let (value, expected) = (take("value"), take("expected"));
+    let mut db = db.write().unwrap();
+    ok(json!(db.update_row_if(&table, &row_id, &column, &value, &expected)?))
+}
+
+async fn get_row(
+    State(db): State<SharedDb>,
+    Path((table, row_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let mut db = db.write().unwrap();
+    let row = db.get_row(&table, &row_id)?;
+    let shape = shape(&db, &table, &params)?;
+    ok(row_json(&shape, &row_id, &row))
+}
+
+async fn get_row_by_key(
+    State(db): State<SharedDb>,
+    Path((table, key)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let mut db = db.write().unwrap();
+    db.ensure_table_loaded(&table)?;
+    let (row_id, row) = db.get_row_by_key(&table, &key)?;
+    let shape = shape(&db, &table, &params)?;
+    ok(row_json(&shape, &row_id, &row))
+}
+
+/// The table to render rows through, without its rows: with `columns=a,b`, only those columns.
+fn shape(db: &Database, table: &str, params: &HashMap<String, String>) -> Result<Table, DatabaseError> {
+    match params.get("columns") {
+        Some(columns) => db.projection(table, &columns.split(',').collect::<Vec<_>>()),
+        None => {
+            let table = db.get_table(table)?;
+            Ok(table.projection(&table.columns.iter().map(String::as_str).collect::<Vec<_>>()))
+        }
+    }
+}
+
+async fn delete_row(
+    State(db): State<SharedDb>,
+    Path((table, row_id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let mut db = db.write().unwrap();
+    if params.get("soft").is_some_and(|v| v == "true") {
+        return ok(json!(db.soft_delete_row(&table, &row_id)?));
+    }
+    ok(json!(db.delete_row(&table, &row_id)?))
+}
+
+async fn restore_row(State(db): State<SharedDb>, Path((table, row_id)): Path<(String, String)>) -> ApiResult {
+    let mut db = db.write().unwrap();
+    let row = db.restore_row(&table, &row_id)?;
+    let stored = db.get_table(&table)?;
+    ok(row_json(&stored, &row_id, &row))
+}
+
+async fn delete_rows(
+    State(db): State<SharedDb>,
+    Path(table): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> ApiResult {
+    let mut db = db.write().unwrap();
+    if params.get("deleted").is_some_and(|v| v == "true") {
+        return ok(json!(db.purge_deleted(&table)?));
+    }
+    match params.get("where") {
+        Some(expr) => {
+            let condition = parse_where(expr).ok_or_else(|| DatabaseError::InvalidCondition(expr.to_string()))?;
+            ok(json!(db.delete_rows_where(&table, &condition)?))
+        }
+        None => ok(json!(db.truncate_table(&table)?)),
+    }
+}
+
+async fn query_table(
+    State(db): State<SharedDb>,
+    Path(table): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> std::result::Result<Response, ApiError> {
+    let mut db = db.write().unwrap();
+    db.ensure_table_loaded(&table)?;
+    if let Some(limit) = params.get("limit") {
+        let limit = limit.parse().ok().filter(|&limit| limit > 0)
+            .ok_or_else(|| DatabaseError::InvalidPageSize(limit.clone()))?;
+        let condition = params.get("where").map(|expr| {
+            parse_where(expr).ok_or_else(|| DatabaseError::InvalidCondition(expr.to_string()))
+        }).transpose()?;
+        let cursor = params.get("cursor").map(String::as_str);
+        let page = db.page_rows(&table, limit, cursor, condition.as_deref())?;
+        let table = shape(&db, &table, &params)?;
+        let rows: Vec<Value> = page.rows.iter().map(|(id, data)| row_json(&table, id, data)).collect();
+        return respond(json!({ "rows": rows, "next": page.next }), &params);
+    }
+    let rows = match params.get("where") {
+        Some(expr) => {
+            let condition = parse_where(expr)
+                .ok_or_else(|| DatabaseError::InvalidCondition(expr.to_string()))?;
+            db.search_rows_by_condition_in_table(&table, &condition)?
+        }
+        None => db
+            .get_table(&table)?
+            .live_rows()
+            .map(|(id, data)| (id.clone(), data.clone()))
+            .collect(),
+    };
+    let table = shape(&db, &table, &params)?;
+    respond(Value::Array(rows.iter().map(|(id, data)| row_json(&table, id, data)).collect()), &params)
+}
+
+async fn list_views(State(db): State<SharedDb>) -> ApiResult {
+    let mut db = db.write().unwrap();
+    ok(Value::Array(views::list(&mut db)?.iter().map(View::to_json).collect()))
+}
+
+async fn create_view(State(db): State<SharedDb>, Path(name): Path<String>, Json(body): Json<Value>) -> ApiResult {
+    let table = body["table"].as_str().ok_or_else(|| DatabaseError::TableDoesNotExist(String::new()))?;
+    let condition = body["where"]
+        .as_str()
+        .map(|expr| parse_where(expr).ok_or_else(|| DatabaseError::InvalidCondition(expr.to_string())))
+        .transpose()?;
+    let columns = body["columns"].as_array().map(|columns| columns.iter().filter_map(Value::as_str).map(str::to_string).collect());
+    let view = View { name, table: table.to_string(), condition, columns };
+    let mut db = db.write().unwrap();
+    views::create(&mut db, &view)?;
+    ok(view.to_json())
+}
+
+async fn query_view(
+    State(db): State<SharedDb>,
+    Path(name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> std::result::Result<Response, ApiError> {
+    let condition = params
+        .get("where")
+        .map(|expr| parse_where(expr).ok_or_else(|| DatabaseError::InvalidCondition(expr.to_string())))
+        .transpose()?;
+    let mut db = db.write().unwrap();
+    views::load(&mut db)?;
+    let view = views::get(&db, &name).ok_or_else(|| DatabaseError::ViewDoesNotExist(name.clone()))?;
+    db.ensure_table_loaded(&view.table)?;
+    let table = views::query(&db, &view, condition.as_deref())?;
+    respond(Value::Array(table.rows.iter().map(|(id, data)| row_json(&table, id, data)).collect()), &params)
+}
+
+async fn drop_view(State(db): State<SharedDb>, Path(name): Path<String>) -> ApiResult {
+    let mut db = db.write().unwrap();
+    views::drop(&mut db, &name)?;
+    ok(json!(name))
+}
+
+/// Turn a compact filter such as `age<10`, `name==Alice` or `name~^A` into the
+/// `"column operator value"` form understood by `search_rows_by_condition_in_table`.
+fn parse_where(expr: &str) -> Option<String> {
+    let upper = expr.to_uppercase();
+    if upper.ends_with(" IS NULL") || upper.ends_with(" IS NOT NULL") {
+        return Some(expr.trim().to_string());
+    }
+    // Word operators need spaces around them: `name LIKE a%`, `name ICONTAINS bob`.
+    if let [_, op, _] = expr.split_whitespace().collect::<Vec<_>>()[..] {
+        if TextPattern::parse(op, "").is_some() {
+            return Some(expr.trim().to_string());
+        }
+    }
+    // The operator is the first one in the expression, so a pattern like `name~a=b` keeps its `=`;
+    // of the operators starting there the longest wins, so `>=` is not read as `>` nor `!=` as
+    // `=` after a column `a!`.
+    let (col, op, val) = ["~*", "==", "!=", ">=", "<=", "~", ">", "<", "="]
+        .into_iter()
+        .filter_map(|op| expr.split_once(op).map(|(col, val)| (col, op, val)))
+        .min_by_key(|(col, op, _)| (col.len(), std::cmp::Reverse(op.len())))?;
+    let (col, val) = (col.trim(), val.trim());
+    if col.is_empty() || val.is_empty() {
+        return None;
+    }
+    let op = if op == "=" { "==" } else { op };
+    Some(format!("{} {} {}", col, op, val))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn where_reads_the_longest_operator() {
+        assert_eq!(parse_where("a!=b").as_deref(), Some("a != b"));
+        assert_eq!(parse_where("age<=10").as_deref(), Some("age <= 10"));
+        assert_eq!(parse_where("age>=10").as_deref(), Some("age >= 10"));
+        assert_eq!(parse_where("name=Ada").as_deref(), Some("name == Ada"));
+        assert_eq!(parse_where("name~a=b").as_deref(), Some("name ~ a=b"));
+        assert_eq!(parse_where("=b"), None);
+    }
+}
@@ -0,0 +1,27 @@
+//! The RustDB database engine: `commands::db::Database` and the tables, storage engines, WAL and
+//! server around it. The `testing` binary is the prompt, script runner and server built on it;
+//! applications can open a `Database` of their own and read and write rows as their own structs
+//! (see `Database::insert_struct` and `Database::get_row_as`).
+
+pub mod commands;
+pub mod format;
+pub mod http;
+pub mod quotas;
+pub mod repl;
+pub mod replication;
+pub mod server;
+pub mod session;
+pub mod sharding;
+pub mod storage;
+pub mod table;
+
+mod auth;
+mod bench;
+mod completion;
+mod dot_commands;
+mod migrations;
+mod policies;
+mod privileges;
+mod statistics;
+mod tokens;
+mod views;
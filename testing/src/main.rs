@@ -1,110 +1,509 @@
-//// filepath: c:\Users\srija\Documents\GitHub\Rust_DB\testing\src\main.rs
-#[warn(unused_imports)]
 use std::fs;
-use env_logger;
-pub mod table;
+use testing::{commands, format, http, quotas, repl, replication, server, session, sharding, storage, table};
+use commands::{db, executor, sweeper};
+use commands::backups::BackupSchedule;
+use commands::walengine::WalEngineConfig;
 
-mod commands;
-const FOLDER_PATH: &str = "./src/commands";
-use commands::{command1, command2, db, walengine};
 
-
-use std::sync::{Arc, Mutex};
+use std::fmt::Write as _;
+use std::io::{IsTerminal, Write};
+use std::net::ToSocketAddrs;
+use std::sync::{Arc, LazyLock, RwLock};
+use table::rows::MAX_ROW_SHARDS;
+use table::table::RenderOptions;
 use std::time::Duration;
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
-fn get_command_names() -> Vec<String> {
-    let folder_path = FOLDER_PATH;
-    let mut files = vec![];
-    if let Ok(entries) = fs::read_dir(folder_path) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.extension() == Some(std::ffi::OsStr::new("rs")) &&
-               path.file_name() != Some(std::ffi::OsStr::new("mod.rs")) {
-                if let Some(file_name) = path.file_name().and_then(|f| f.to_str()) {
-                    println!("{:?}", file_name);
-                    files.push(file_name.split('.').next().unwrap().to_string());
+/// Run only a TCP server that routes commands across the shards in `spec`, until Ctrl-C, which
+/// writes out the in-process shards first (see `ShardRouter::shutdown`). Nodes check the logins
+/// passed on to them, but in-process shards have no accounts, so a router with any only listens
+/// on a loopback address.
+fn run_router(spec: &str) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start tokio runtime");
+    let addr = std::env::var("RUSTDB_LISTEN").unwrap_or_else(|_| server::DEFAULT_ADDR.to_string());
+    let loopback = addr.to_socket_addrs().is_ok_and(|mut addrs| addrs.all(|addr| addr.ip().is_loopback()));
+    if spec.split(',').any(|entry| entry.trim() == "local") && !loopback {
+        eprintln!("RUSTDB_SHARDS: 'local' shards have no accounts, so RUSTDB_LISTEN must be a loopback address, got '{}'", addr);
+        std::process::exit(2);
+    }
+    runtime.block_on(async {
+        let router = match sharding::ShardRouter::from_spec(spec, wal_engine_config()) {
+            Ok(router) => Arc::new(router),
+            Err(e) => return eprintln!("Failed to set up shards: {}", e),
+        };
+        match server::Server::bind_router(&addr, Arc::clone(&router)).await {
+            Ok(server) => {
+                tokio::select! {
+                    _ = server.with_quotas(quotas()).run() => {}
+                    _ = tokio::signal::ctrl_c() => {}
                 }
             }
+            Err(e) => eprintln!("Failed to start server on {}: {}", addr, e),
         }
-    } else {
-        eprintln!("Error reading directory");
+        for (name, e) in router.shutdown().await {
+            eprintln!("Failed to write out shard '{}': {}", name, e);
+        }
+        println!("Shutting down.");
+    });
+}
+
+/// Run a script of commands, one per line as typed at the prompt, in a single session on `db`:
+/// `BEGIN`/`COMMIT`, `CREATE DATABASE` and `USE` work as over TCP. Blank lines and `--` comments
+/// are skipped and a trailing `;` is ignored. Each response is printed as a JSON line, or as a
+/// command's trailing `FORMAT csv|table` asks; the script stops at the first command that fails
+/// (or at `EXIT`). Every database is written out before returning, as on EXIT at the prompt. Returns the process exit code: 0 if every command succeeded, 1 otherwise.
+/// `unlocked` logs the script in as an admin, as the database was opened with its passphrase.
+fn run_script(path: &str, db: session::SharedDb, unlocked: bool) -> i32 {
+    let script = match fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("Failed to read script '{}': {}", path, e);
+            return 1;
+        }
+    };
+    // `CREATE DATABASE` starts background tasks, which need a runtime.
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start tokio runtime");
+    let _guard = runtime.enter();
+    let catalog = match session::Catalog::new(db, wal_engine_config()) {
+        Ok(catalog) => Arc::new(catalog),
+        Err(e) => {
+            eprintln!("Cannot open the database: {}", e);
+            return 2;
+        }
+    };
+    let mut session = session::Session::new(Arc::clone(&catalog));
+    if unlocked {
+        session.log_in_unlocked();
+    }
+
+    let mut code = 0;
+    let lines = script.lines().enumerate().map(|(i, line)| (i + 1, line.trim().trim_end_matches(';').trim_end()));
+    for (number, line) in lines.filter(|(_, line)| !line.is_empty() && !line.starts_with("--")) {
+        let (command, format) = format::split_flag(line);
+        let response = session.handle(command);
+        if let executor::Response::Exit = response {
+            break;
+        }
+        let format = format.unwrap_or(format::OutputFormat::Json);
+        println!("{}", format::render(&response, format, &RenderOptions::default()));
+        if let executor::Response::Error(e) = response {
+            eprintln!("{}:{}: '{}' failed: {}", path, number, line, e);
+            code = 1;
+            break;
+        }
+    }
+    for (name, e) in runtime.block_on(catalog.shutdown()) {
+        eprintln!("Failed to write out database '{}': {}", name, e);
+        code = 1;
+    }
+    code
+}
+
+/// Stop the WAL engines and write out every database (see `Catalog::shutdown`), returning the
+/// process exit code: 0 if everything was written, 1 otherwise.
+async fn shut_down(catalog: &session::Catalog) -> i32 {
+    let failed = catalog.shutdown().await;
+    for (name, e) in &failed {
+        eprintln!("Failed to write out database '{}': {}", name, e);
+    }
+    println!("Shutting down.");
+    i32::from(!failed.is_empty())
+}
+
+/// Check the table files and WAL (see `Database::verify`), printing each problem as a JSON line,
+/// and repair what can be repaired if `repair` is set. Returns the process exit code: 0 if
+/// nothing is left wrong, 1 otherwise.
+fn verify_files(db: &mut db::Database, repair: bool) -> i32 {
+    let issues = match db.verify(repair) {
+        Ok(issues) => issues,
+        Err(e) => {
+            eprintln!("Failed to verify: {}", e);
+            return 1;
+        }
+    };
+    for issue in &issues {
+        println!("{}", issue.to_json());
+    }
+    let left = issues.iter().filter(|issue| issue.repaired.is_none()).count();
+    println!("{} problems found, {} repaired.", issues.len(), issues.len() - left);
+    i32::from(left > 0)
+}
+
+/// The scheduled backups of the `main` database, if RUSTDB_BACKUP_DIR=<dir> asks for them: a full
+/// backup every RUSTDB_BACKUP_EVERY (`24h` by default), an incremental one every
+/// RUSTDB_BACKUP_INCREMENTAL (e.g. `1h`; none by default) and the last RUSTDB_BACKUP_KEEP full
+/// backups kept. Exits on a setting it cannot read rather than back up other than asked.
+fn backup_schedule() -> Option<BackupSchedule> {
+    let dir = std::env::var("RUSTDB_BACKUP_DIR").ok()?;
+    let setting = |name: &str| std::env::var(name).ok();
+    let invalid = |name: &str, value: &str| -> ! {
+        eprintln!("Invalid {}='{}': use a number of seconds or e.g. 30m, 1h, 1d.", name, value);
+        std::process::exit(2);
+    };
+    let interval = |name: &str| {
+        setting(name).map(|value| BackupSchedule::parse_interval(&value).unwrap_or_else(|| invalid(name, &value)))
+    };
+    let full_every = interval("RUSTDB_BACKUP_EVERY").unwrap_or(Duration::from_secs(86_400));
+    let mut schedule = BackupSchedule::new(&dir, full_every);
+    if let Some(every) = interval("RUSTDB_BACKUP_INCREMENTAL") {
+        schedule = schedule.with_incrementals(every);
+    }
+    if let Some(keep) = setting("RUSTDB_BACKUP_KEEP") {
+        schedule = schedule.keeping(keep.parse().unwrap_or_else(|_| invalid("RUSTDB_BACKUP_KEEP", &keep)));
+    }
+    Some(schedule)
+}
+
+/// When the WAL engines archive the WAL (see `WalEngineConfig`): every RUSTDB_CHECKPOINT_EVERY
+/// (`10s` by default), and as soon as more than RUSTDB_CHECKPOINT_ENTRIES entries or
+/// RUSTDB_CHECKPOINT_BYTES bytes are waiting, if set. Exits on a setting it cannot read.
+fn wal_engine_config() -> WalEngineConfig {
+    let setting = |name: &str| std::env::var(name).ok();
+    let invalid = |name: &str, value: &str, expected: &str| -> ! {
+        eprintln!("Invalid {}='{}': use {}.", name, value, expected);
+        std::process::exit(2);
+    };
+    let limit = |name: &str| {
+        setting(name).map(|value| value.parse().ok().filter(|n| *n > 0).unwrap_or_else(|| invalid(name, &value, "a positive number")))
+    };
+    let mut config = WalEngineConfig::default();
+    if let Some(every) = setting("RUSTDB_CHECKPOINT_EVERY") {
+        config.interval = BackupSchedule::parse_interval(&every)
+            .unwrap_or_else(|| invalid("RUSTDB_CHECKPOINT_EVERY", &every, "a number of seconds or e.g. 30s, 5m"));
+    }
+    config.max_entries = limit("RUSTDB_CHECKPOINT_ENTRIES");
+    config.max_bytes = limit("RUSTDB_CHECKPOINT_BYTES");
+    config
+}
+
+/// The quotas of TCP and HTTP clients (see `quotas::Quotas`): RUSTDB_RATE_LIMIT=<n> commands a
+/// second per client and RUSTDB_MAX_CONNECTIONS=<n> connections per IP address. Exits on a
+/// setting it cannot read.
+fn quotas() -> Arc<quotas::Quotas> {
+    match quotas::Quotas::from_env() {
+        Ok(quotas) => Arc::new(quotas),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// The key to encrypt stored data with (see `storage::encryption`): RUSTDB_ENCRYPTION_KEY, or the
+/// contents of the file RUSTDB_ENCRYPTION_KEY_FILE names, as 64 hex digits. Exits on a key it
+/// cannot read rather than write plaintext.
+fn encryption_key() -> Option<[u8; 32]> {
+    let (name, key) = match std::env::var("RUSTDB_ENCRYPTION_KEY") {
+        Ok(key) => ("RUSTDB_ENCRYPTION_KEY", key),
+        Err(_) => {
+            let path = std::env::var("RUSTDB_ENCRYPTION_KEY_FILE").ok()?;
+            let key = fs::read_to_string(&path).unwrap_or_else(|e| {
+                eprintln!("Cannot read RUSTDB_ENCRYPTION_KEY_FILE '{}': {}", path, e);
+                std::process::exit(2);
+            });
+            ("RUSTDB_ENCRYPTION_KEY_FILE", key)
+        }
+    };
+    let key = storage::encryption::parse_key(&key);
+    if key.is_none() {
+        eprintln!("Invalid {}: the key must be 64 hex digits (32 bytes).", name);
+        std::process::exit(2);
+    }
+    key
+}
+
+/// The passphrase to unlock the keyring with (see `storage::keyring`): RUSTDB_PASSPHRASE, or the
+/// first line of the file RUSTDB_PASSPHRASE_FILE names.
+fn passphrase() -> Option<String> {
+    if let Ok(passphrase) = std::env::var("RUSTDB_PASSPHRASE") {
+        return Some(passphrase);
+    }
+    let path = std::env::var("RUSTDB_PASSPHRASE_FILE").ok()?;
+    match fs::read_to_string(&path) {
+        Ok(text) => Some(text.lines().next().unwrap_or_default().to_string()),
+        Err(e) => {
+            eprintln!("Cannot read RUSTDB_PASSPHRASE_FILE '{}': {}", path, e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Log to standard error as env_logger does, at the levels RUST_LOG asks for (only errors by
+/// default), with a record's structured fields after its message: `Row written and logged to WAL
+/// table=users row_id=1`.
+fn init_logging() {
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let mut fields = Fields(String::new());
+            let _ = record.key_values().visit(&mut fields);
+            writeln!(buf, "[{} {} {}] {}{}", buf.timestamp(), record.level(), record.target(), record.args(), fields.0)
+        })
+        .init();
+}
+
+/// The `key=value` fields of a log record, each after a space.
+struct Fields(String);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+        write!(self.0, " {}={}", key, value).map_err(|_| log::kv::Error::msg("cannot format field"))
     }
-    println!("{:?}", files);
-    files
 }
 
 fn main() {
-    env_logger::init();
+    LazyLock::force(&db::STARTED);
+    init_logging();
 
-    // Initialize the database wrapped in Arc<Mutex<>>
-    let db = Arc::new(Mutex::new(db::Database::new()));
-    let running = Arc::new(AtomicBool::new(true));
+    // RUSTDB_ENCRYPTION_KEY=<64 hex digits> (or RUSTDB_ENCRYPTION_KEY_FILE=<path>) encrypts table
+    // files, WAL and SSTables at rest; it must be installed before any file is opened.
+    // RUSTDB_PASSPHRASE (or RUSTDB_PASSPHRASE_FILE) unlocks the keys in `keyring.json` instead,
+    // creating it the first time, and logs the prompt or script in as an admin.
+    let key = encryption_key();
+    let unlocked = match passphrase() {
+        Some(passphrase) => {
+            if let Err(e) = storage::keyring::unlock(&passphrase, key) {
+                eprintln!("Cannot open the database: {}", e);
+                std::process::exit(2);
+            }
+            true
+        }
+        None if storage::keyring::exists() => {
+            eprintln!("The database is protected by a passphrase: set RUSTDB_PASSPHRASE or RUSTDB_PASSPHRASE_FILE.");
+            std::process::exit(2);
+        }
+        None => {
+            if let Some(key) = key {
+                storage::encryption::install(&[key]);
+            }
+            false
+        }
+    };
 
-    // Load the WAL at startup
-    {
-        let mut db_lock = db.lock().unwrap();
-        if let Err(e) = db_lock.load_wal() {
-            eprintln!("Failed to load WAL: {}", e);
+    // RUSTDB_SHARDS=<addr>,<addr>,local,... turns this process into a shard router.
+    if let Ok(spec) = std::env::var("RUSTDB_SHARDS") {
+        run_router(&spec);
+        return;
+    }
+
+    // Initialize the database wrapped in Arc<RwLock<>>.
+    // RUSTDB_STORAGE=lsm keeps tables in the LSM engine under ./lsm_data instead of CSV files.
+    // RUSTDB_KEEP_GENERATIONS=<n> keeps the n previous versions of every CSV table file.
+    let generations = std::env::var("RUSTDB_KEEP_GENERATIONS").ok().and_then(|n| n.parse().ok()).unwrap_or(0);
+    let mut database = match std::env::var("RUSTDB_STORAGE").as_deref() {
+        Ok("lsm") => db::Database::with_storage(Box::new(storage::lsm::LsmStorage::new("lsm_data"))),
+        _ => db::Database::with_storage(Box::new(storage::csv::CsvStorage::new().keep_generations(generations))),
+    };
+    // RUSTDB_REDACT=users.email,*.ssn keeps those columns' values out of logs and the WAL listing.
+    if let Ok(spec) = std::env::var("RUSTDB_REDACT") {
+        match commands::redaction::Redaction::parse(&spec) {
+            Ok(redaction) => database.redaction = redaction,
+            Err(e) => {
+                eprintln!("RUSTDB_REDACT: {}", e);
+                std::process::exit(2);
+            }
         }
-        if let Err(e) = db_lock.flush_wal() {
-            eprintln!("Failed to flush WAL: {}", e);
+    }
+    // RUSTDB_PARALLEL_SCAN=<rows> splits scans of tables with at least that many rows across every
+    // core (50000 by default); RUSTDB_PARALLEL_SCAN=off keeps every scan on one thread.
+    match std::env::var("RUSTDB_PARALLEL_SCAN").as_deref() {
+        Ok("off") => database.parallel_scan = None,
+        Ok(rows) => match rows.parse() {
+            Ok(rows) => database.parallel_scan = Some(rows),
+            Err(_) => {
+                eprintln!("RUSTDB_PARALLEL_SCAN: expected a number of rows or 'off', got '{}'", rows);
+                std::process::exit(2);
+            }
+        },
+        Err(_) => {}
+    }
+    // RUSTDB_GROUP_COMMIT=<ms> writes what was logged to the WAL file, with one fsync, that often
+    // (every 10ms by default); RUSTDB_GROUP_COMMIT=off leaves it in memory until it is archived.
+    match std::env::var("RUSTDB_GROUP_COMMIT").as_deref() {
+        Ok("off") => database.group_commit = None,
+        Ok(millis) => match millis.parse() {
+            Ok(millis) if millis > 0 => database.group_commit = Some(Duration::from_millis(millis)),
+            _ => {
+                eprintln!("RUSTDB_GROUP_COMMIT: expected a number of milliseconds or 'off', got '{}'", millis);
+                std::process::exit(2);
+            }
+        },
+        Err(_) => {}
+    }
+    // RUSTDB_TABLE_CACHE=<n> keeps at most n tables in memory, evicting the least recently used.
+    if let Ok(tables) = std::env::var("RUSTDB_TABLE_CACHE") {
+        match tables.parse() {
+            Ok(tables) if tables > 0 => database.table_cache = Some(tables),
+            _ => {
+                eprintln!("RUSTDB_TABLE_CACHE: expected a number of tables, got '{}'", tables);
+                std::process::exit(2);
+            }
+        }
+    }
+    // RUSTDB_MEMORY_LIMIT_MB=<n> spills the least recently used tables to disk once the tables in
+    // memory take more than n megabytes.
+    if let Ok(megabytes) = std::env::var("RUSTDB_MEMORY_LIMIT_MB") {
+        match megabytes.parse::<usize>() {
+            Ok(megabytes) if megabytes > 0 => database.memory_limit = Some(megabytes << 20),
+            _ => {
+                eprintln!("RUSTDB_MEMORY_LIMIT_MB: expected a number of megabytes, got '{}'", megabytes);
+                std::process::exit(2);
+            }
+        }
+    }
+    // RUSTDB_ROW_SHARDS=orders:8,events:4 splits the rows of those tables across that many maps in
+    // memory, so that point writes to them go on side by side (as ALTER TABLE <table> SHARDS <n>).
+    if let Ok(spec) = std::env::var("RUSTDB_ROW_SHARDS") {
+        for item in spec.split(',').filter(|item| !item.is_empty()) {
+            match item.split_once(':').map(|(table, shards)| (table, shards.parse::<usize>())) {
+                Some((table, Ok(shards))) if (1..=MAX_ROW_SHARDS).contains(&shards) => database.tables.set_row_shards(table, shards),
+                _ => {
+                    eprintln!("RUSTDB_ROW_SHARDS: expected <table>:<shards> with 1 to {} shards, got '{}'", MAX_ROW_SHARDS, item);
+                    std::process::exit(2);
+                }
+            }
         }
     }
+    // `verify [--repair]` checks the files before anything reads them, and does not serve.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match &args[..] {
+        [mode] if mode == "verify" => std::process::exit(verify_files(&mut database, false)),
+        [mode, repair] if mode == "verify" && repair == "--repair" => std::process::exit(verify_files(&mut database, true)),
+        _ => {}
+    }
 
-    // Start the WAL engine to persist/replay WAL periodically
-    let wal_engine = walengine::WalEngine::new(Arc::clone(&db), Duration::from_secs(10));
-    thread::spawn(move || wal_engine.start());
+    let db = Arc::new(RwLock::new(database));
+    // Long imports and exports draw a progress bar at a terminal, and are logged otherwise.
+    db.write().unwrap().progress = Some(if std::io::stderr().is_terminal() {
+        Arc::new(repl::draw_progress)
+    } else {
+        Arc::new(|progress: &storage::progress::Progress| log::info!("Progress: {}", progress.to_json()))
+    });
 
-    // Simulate database operations
-    {
-        let mut db_lock = db.lock().unwrap();
-        // db_lock.create_table("users").unwrap();
-        // db_lock.flush_wal().unwrap();
-    
-        // db_lock.add_column("users", "name").unwrap();
-        // db_lock.add_column("users", "age").unwrap();
-        // db_lock.add_column("users", "email").unwrap();
-        
-        let mut row_data = std::collections::HashMap::new();
-        row_data.insert("name".to_string(), "yes".to_string());
-        row_data.insert("age".to_string(), "100".to_string());
-        row_data.insert("email".to_string(), "xyz@.com".to_string());
-        // db_lock.insert_row("users", "1", row_data).unwrap();
-        
-        // db_lock.save_table("users", "users.csv").unwrap();
+    // Replay the WAL at startup. Serving without it would answer from tables missing the writes
+    // it holds, and the next WAL cycle would throw them away, so refuse to start instead.
+    if let Err(e) = db.write().unwrap().load_wal() {
+        eprintln!("Cannot replay the WAL, so not starting: {}", e);
+        std::process::exit(2);
+    }
 
-        db_lock.update_row("users", "4", "age", "10").unwrap();
-        db_lock.update_row("users", "2", "email", "y@.com").unwrap();
+    // `run <script>` (or `--file <script>`) executes the script and exits instead of serving.
+    match &args[..] {
+        [] => {}
+        [mode, path] if mode == "run" || mode == "--file" => std::process::exit(run_script(path, db, unlocked)),
+        _ => {
+            eprintln!("Usage: testing [run <script> | --file <script> | verify [--repair]]");
+            std::process::exit(2);
+        }
+    }
+
+    // One tokio runtime drives the WAL engine, the TCP server and the HTTP API.
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start tokio runtime");
 
+    // RUSTDB_REPLICA_OF=<primary replication address> runs this process as a read-only replica,
+    // logging in with RUSTDB_REPLICA_USER and RUSTDB_REPLICA_PASSWORD, or RUSTDB_REPLICA_TOKEN;
+    // otherwise it is a primary, accepting replicas on RUSTDB_REPLICATION if that is set.
+    let replica_of = std::env::var("RUSTDB_REPLICA_OF").ok();
+    let mut replica_status = None;
+    let shipping_metrics = Arc::new(replication::ShippingMetrics::default());
+    match &replica_of {
+        Some(primary) => {
+            let mut replica = replication::Replica::new(primary, Arc::clone(&db));
+            let user = std::env::var("RUSTDB_REPLICA_USER").ok();
+            let password = std::env::var("RUSTDB_REPLICA_PASSWORD").ok();
+            match (user, password, std::env::var("RUSTDB_REPLICA_TOKEN").ok()) {
+                (Some(user), Some(password), None) => {
+                    replica = replica.with_credentials(replication::Credentials::Password { user, password });
+                }
+                (None, None, Some(token)) => replica = replica.with_credentials(replication::Credentials::Token(token)),
+                (None, None, None) => {}
+                _ => {
+                    eprintln!("Set RUSTDB_REPLICA_USER and RUSTDB_REPLICA_PASSWORD, or RUSTDB_REPLICA_TOKEN, not both");
+                    std::process::exit(2);
+                }
+            }
+            replica_status = Some(replica.status());
+            runtime.spawn(replica.run());
+        }
+        None => {
+            // Only the primary expires rows; replicas apply its logged deletions.
+            runtime.spawn(sweeper::Sweeper::new(Arc::clone(&db), Duration::from_secs(1)).run());
 
-        match db_lock.get_row("users", "1") {
-            Ok(row) => println!("Row: {:?}", row),
-            Err(e) => eprintln!("Error: {}", e),
+            if let Ok(replication_addr) = std::env::var("RUSTDB_REPLICATION") {
+                let replication_db = Arc::clone(&db);
+                let metrics = Arc::clone(&shipping_metrics);
+                runtime.spawn(async move {
+                    match replication::Primary::bind(&replication_addr, replication_db, metrics).await {
+                        Ok(primary) => primary.run().await,
+                        Err(e) => eprintln!("Failed to start replication on {}: {}", replication_addr, e),
+                    }
+                });
+            }
         }
+    }
 
-        match db_lock.get_table("users") {
-            Ok(table) => println!("Table: {}", table),
-            Err(e) => eprintln!("Error: {}", e),
+    // Serve the same commands as the REPL over TCP (RUSTDB_LISTEN overrides the address).
+    let addr = std::env::var("RUSTDB_LISTEN").unwrap_or_else(|_| server::DEFAULT_ADDR.to_string());
+    // Each connection gets its own session; `main` is the database above.
+    let mut catalog = match session::Catalog::new(Arc::clone(&db), wal_engine_config()) {
+        Ok(catalog) => catalog.with_generations(generations),
+        Err(e) => {
+            eprintln!("Cannot open the database: {}", e);
+            std::process::exit(2);
         }
+    };
+    match replica_status {
+        Some(status) => catalog = catalog.with_replica(status),
+        None => catalog = catalog.with_shipping_metrics(shipping_metrics),
+    }
+    // Start the WAL engine to persist the WAL periodically
+    let _guard = runtime.enter();
+    catalog.start_wal_engine(Arc::clone(&db), backup_schedule());
+    let catalog = Arc::new(catalog);
 
-        match db_lock.search_rows_by_condition_in_table("users", "age < 10") {
-            Ok(rows) => println!("Rows: {:?}", rows),
-            Err(e) => eprintln!("Error: {}", e),
+    // Ctrl-C gets the same final flush as EXIT instead of losing what is only in memory.
+    let signal_catalog = Arc::clone(&catalog);
+    runtime.spawn(async move {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            return eprintln!("Failed to listen for Ctrl-C: {}", e);
         }
+        println!("Interrupted.");
+        std::process::exit(shut_down(&signal_catalog).await);
+    });
 
-        match db_lock.find_rows_by_value_in_table("users", "age", "5", false) {
-            Ok(rows) => println!("Rows: {:?}", rows),
-            Err(e) => eprintln!("Error: {}", e),
+    // RUSTDB_RATE_LIMIT and RUSTDB_MAX_CONNECTIONS hold clients to quotas over TCP and HTTP together.
+    let quotas = quotas();
+    let server_catalog = Arc::clone(&catalog);
+    let server_quotas = Arc::clone(&quotas);
+    runtime.spawn(async move {
+        match server::Server::bind(&addr, server_catalog).await {
+            Ok(server) => server.with_quotas(server_quotas).run().await,
+            Err(e) => eprintln!("Failed to start server on {}: {}", addr, e),
         }
-        // Optionally, perform a manual commit here if needed:
-        // db_lock.flush_wal().unwrap();
-        db_lock.commit_wal().unwrap();
+    });
+
+    // Expose the database over HTTP as well (RUSTDB_HTTP overrides the address).
+    // The HTTP API has no read-only mode, so replicas only serve reads over TCP.
+    if replica_of.is_none() {
+        let http_addr = std::env::var("RUSTDB_HTTP").unwrap_or_else(|_| http::DEFAULT_ADDR.to_string());
+        let http_db = Arc::clone(&db);
+        runtime.spawn(async move {
+            if let Err(e) = http::serve(&http_addr, http_db, quotas).await {
+                eprintln!("Failed to start HTTP API on {}: {}", http_addr, e);
+            }
+        });
     }
 
-    // Run for a finite duration then exit.
-    thread::sleep(Duration::from_secs(60));
-    running.store(false, Ordering::SeqCst);
-    println!("Shutting down.");
-}
\ No newline at end of file
+    // The prompt runs the same commands as a TCP connection, in its own session.
+    let mut session = session::Session::new(Arc::clone(&catalog));
+    if unlocked {
+        session.log_in_unlocked();
+    }
+    if repl::run(session) {
+        std::process::exit(runtime.block_on(shut_down(&catalog)));
+    }
+    // Without a terminal (e.g. running as a service) keep serving until the process is stopped.
+    println!("Standard input closed; serving until stopped.");
+    loop {
+        thread::park();
+    }
+}
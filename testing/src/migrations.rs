@@ -0,0 +1,181 @@
+use crate::commands::db::{Database, DatabaseError, Result};
+use crate::commands::executor::{self, Response};
+use crate::table::table::ColumnSpec;
+use crate::table::value::ColumnType;
+use log::info;
+use std::collections::HashMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory `MIGRATE` reads migration scripts from.
+pub const DEFAULT_DIR: &str = "migrations";
+
+/// Table recording which migrations have been applied: one row per version (row_id), with the
+/// migration's name and when it was applied.
+pub const VERSION_TABLE: &str = "__schema_version";
+
+/// **Migration scripts**
+/// A file `<version>_<name>.migration` in the migrations directory, e.g. `0002_add_email.migration`:
+/// ```text
+/// -- up
+/// ADD COLUMN users email text NOT NULL DEFAULT none
+/// -- down
+/// DROP COLUMN users email
+/// ```
+/// Every line of a section is one command, as typed at the prompt (`CREATE TABLE`, `ADD COLUMN`,
+/// `ALTER COLUMN`, `RENAME COLUMN`, ...). Blank lines and other `--` lines are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub version: u64,
+    pub name: String,
+    pub up: Vec<String>,
+    pub down: Vec<String>,
+}
+
+impl Migration {
+    /// Parse a script; `file_name` supplies the version and name.
+    pub fn parse(file_name: &str, script: &str) -> Result<Migration> {
+        let invalid = |reason: &str| DatabaseError::MigrationError(format!("{}: {}", file_name, reason));
+        let stem = file_name.strip_suffix(".migration").unwrap_or(file_name);
+        let (version, name) = stem.split_once('_').unwrap_or((stem, ""));
+        let version = version.parse().map_err(|_| invalid("the file name must start with a version number"))?;
+
+        let mut migration = Migration { version, name: name.to_string(), up: Vec::new(), down: Vec::new() };
+        let mut section = None;
+        for line in script.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line.strip_prefix("--").map(|marker| marker.trim().to_lowercase()) {
+                Some(marker) if marker == "up" => section = Some(&mut migration.up),
+                Some(marker) if marker == "down" => section = Some(&mut migration.down),
+                Some(_) => {}
+                None => section
+                    .as_mut()
+                    .ok_or_else(|| invalid("commands must follow '-- up' or '-- down'"))?
+                    .push(line.to_string()),
+            }
+        }
+        Ok(migration)
+    }
+}
+
+/// Every migration script in `dir`, by version. Fails on two scripts with the same version.
+pub fn load_dir(dir: &str) -> Result<Vec<Migration>> {
+    let entries = fs::read_dir(dir).map_err(|e| DatabaseError::MigrationError(format!("{}: {}", dir, e)))?;
+    let mut migrations: Vec<Migration> = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let Some(file_name) = path.file_name().and_then(|f| f.to_str()).filter(|f| f.ends_with(".migration")) else {
+            continue;
+        };
+        let script = fs::read_to_string(&path)
+            .map_err(|e| DatabaseError::MigrationError(format!("{}: {}", file_name, e)))?;
+        migrations.push(Migration::parse(file_name, &script)?);
+    }
+    migrations.sort_by_key(|m| m.version);
+    if let Some(pair) = migrations.windows(2).find(|pair| pair[0].version == pair[1].version) {
+        return Err(DatabaseError::MigrationError(format!("two migrations have version {}", pair[0].version)));
+    }
+    Ok(migrations)
+}
+
+/// Applied versions with their names, from the version table (empty if it does not exist yet).
+pub fn applied(db: &mut Database) -> Result<Vec<(u64, String)>> {
+    if db.ensure_table_loaded(VERSION_TABLE).is_err() {
+        return Ok(Vec::new());
+    }
+    let table = db.get_table(VERSION_TABLE)?;
+    let mut versions: Vec<(u64, String)> = table
+        .rows
+        .iter()
+        .filter_map(|(row_id, row)| {
+            let name = row.get("name").map(|name| name.to_string()).unwrap_or_default();
+            Some((row_id.parse().ok()?, name))
+        })
+        .collect();
+    versions.sort();
+    Ok(versions)
+}
+
+/// The newest applied version, 0 if none.
+pub fn current_version(db: &mut Database) -> Result<u64> {
+    Ok(applied(db)?.last().map_or(0, |(version, _)| *version))
+}
+
+/// Apply every migration newer than the current version, up to `target` (all if `None`), oldest
+/// first. Returns the versions applied. Stops at the first migration that fails; see `run_steps`.
+pub fn migrate_up(db: &mut Database, migrations: &[Migration], target: Option<u64>) -> Result<Vec<u64>> {
+    let current = current_version(db)?;
+    let mut done = Vec::new();
+    for migration in migrations
+        .iter()
+        .filter(|m| m.version > current && target.is_none_or(|target| m.version <= target))
+    {
+        run_steps(db, migration.version, &migration.up)?;
+        record_version(db, migration)?;
+        info!(version = migration.version, name = migration.name; "Migrated up");
+        done.push(migration.version);
+    }
+    Ok(done)
+}
+
+/// Revert every applied migration newer than `target`, newest first. Returns the versions reverted.
+/// Fails before changing anything if an applied version has no script.
+pub fn migrate_down(db: &mut Database, migrations: &[Migration], target: u64) -> Result<Vec<u64>> {
+    let by_version: HashMap<u64, &Migration> = migrations.iter().map(|m| (m.version, m)).collect();
+    let to_revert: Vec<&Migration> = applied(db)?
+        .into_iter()
+        .rev()
+        .filter(|(version, _)| *version > target)
+        .map(|(version, _)| {
+            by_version.get(&version).copied().ok_or_else(|| {
+                DatabaseError::MigrationError(format!("no script for applied version {}", version))
+            })
+        })
+        .collect::<Result<_>>()?;
+    let mut done = Vec::new();
+    for migration in to_revert {
+        run_steps(db, migration.version, &migration.down)?;
+        db.delete_row(VERSION_TABLE, &migration.version.to_string())?;
+        info!(version = migration.version, name = migration.name; "Migrated down");
+        done.push(migration.version);
+    }
+    Ok(done)
+}
+
+/// Run one migration's commands. If one fails, every table is restored in memory and in storage
+/// and the WAL is cut back, so a half-applied migration is never left behind.
+fn run_steps(db: &mut Database, version: u64, steps: &[String]) -> Result<()> {
+    // Tables a command drops or renames must be in the snapshot even if they were never loaded.
+    db.load_all_tables()?;
+    let tables = db.tables.clone();
+    let wal_len = db.wal.get_mut().len();
+    for step in steps {
+        if let Response::Error(e) = executor::execute(db, step) {
+            db.tables = tables;
+            db.wal.get_mut().truncate(wal_len);
+            for name in db.storage.get_mut().table_names()?.iter().filter(|name| !db.tables.contains_key(name)) {
+                db.storage.get_mut().drop_table(name)?;
+            }
+            let names: Vec<String> = db.tables.keys().cloned().collect();
+            for name in names {
+                db.persist_table(&name)?;
+            }
+            return Err(DatabaseError::MigrationError(format!("version {}: '{}' failed: {}", version, step, e)));
+        }
+    }
+    Ok(())
+}
+
+fn record_version(db: &mut Database, migration: &Migration) -> Result<()> {
+    if db.ensure_table_loaded(VERSION_TABLE).is_err() {
+        db.create_table(VERSION_TABLE)?;
+        db.add_column(VERSION_TABLE, "name", None)?;
+        let applied_at = ColumnSpec { column_type: ColumnType::Timestamp, ..ColumnSpec::default() };
+        db.add_typed_column(VERSION_TABLE, "applied_at", applied_at)?;
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let data = HashMap::from([
+        ("name".to_string(), migration.name.clone()),
+        ("applied_at".to_string(), now.to_string()),
+    ]);
+    db.insert_row(VERSION_TABLE, &migration.version.to_string(), data)?;
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use crate::auth::{self, ADMIN_ROLE};
+use crate::commands::db::{Database, DatabaseError, Result, RowMatcher};
+use crate::commands::executor::{self, Response};
+use crate::privileges::{self, Needs, Privilege, ROLE_TABLE};
+use crate::storage::StorageEngine;
+use crate::table::table::{ColumnSpec, Table};
+use crate::table::tables::TableRef;
+use crate::table::value::Value;
+use crate::views::{self, VIEW_TABLE};
+use log::info;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Column of the role table holding a role's row policies, as a JSON object from table name to
+/// condition, e.g. `{"orders":"tenant_id == $tenant"}`. Added with the first policy.
+const POLICY_COLUMN: &str = "policies";
+
+/// The conditions an account's rows are limited to, by table; see `limits`.
+pub type Limits = BTreeMap<String, Vec<String>>;
+
+/// **Row-level security**
+/// A row policy limits the members of a role to the rows of a table that match a condition, as in
+/// SEARCH: `CREATE POLICY ON orders FOR clerk WHERE tenant_id == $tenant`. In the value, `$user`
+/// stands for the account's name and any other `$<name>` for the attribute an admin set on the
+/// account (`ALTER USER bob SET tenant 42`). Reads of the table only see those rows, and writes
+/// may only change rows that match both before and after (see `execute`). An account whose roles
+/// hold several policies on a table is held to all of them; admins to none.
+pub fn create(db: &mut Database, role: &str, table: &str, condition: &str) -> Result<()> {
+    privileges::stored_role(db, role)?;
+    let lower = condition.to_lowercase();
+    let valid = match lower.split_whitespace().collect::<Vec<_>>()[..] {
+        [_, "is", "null"] | [_, "is", "not", "null"] => true,
+        [_, operator, _] => operator != "is",
+        _ => false,
+    };
+    if !valid {
+        return Err(DatabaseError::InvalidPolicy(condition.to_string()));
+    }
+    let mut policies = policies(db, role);
+    if policies.contains_key(table) {
+        return Err(DatabaseError::PolicyAlreadyExists(table.to_string(), role.to_string()));
+    }
+    policies.insert(table.to_string(), condition.to_string());
+    store(db, role, &policies)?;
+    info!(table, role, condition; "Row policy created");
+    Ok(())
+}
+
+/// Remove the row policy of `role` on `table`, so its members see every row again.
+pub fn drop(db: &mut Database, role: &str, table: &str) -> Result<()> {
+    privileges::stored_role(db, role)?;
+    let mut policies = policies(db, role);
+    if policies.remove(table).is_none() {
+        return Err(DatabaseError::PolicyDoesNotExist(table.to_string(), role.to_string()));
+    }
+    store(db, role, &policies)?;
+    info!(table, role; "Row policy dropped");
+    Ok(())
+}
+
+/// The row policies of a stored role: its condition on each table.
+pub fn policies(db: &Database, role: &str) -> BTreeMap<String, String> {
+    let Some(Value::Text(text)) = db.tables.get(ROLE_TABLE).and_then(|table| table.rows.get(role)?.get(POLICY_COLUMN).cloned()) else {
+        return BTreeMap::new();
+    };
+    serde_json::from_str(&text).unwrap_or_default()
+}
+
+fn store(db: &mut Database, role: &str, policies: &BTreeMap<String, String>) -> Result<()> {
+    if !db.get_table(ROLE_TABLE)?.columns.contains(POLICY_COLUMN) {
+        db.add_typed_column(ROLE_TABLE, POLICY_COLUMN, ColumnSpec::default())?;
+    }
+    db.update_row(ROLE_TABLE, role, POLICY_COLUMN, &json!(policies).to_string())?;
+    db.persist_table(ROLE_TABLE)
+}
+
+/// The row policies account `user`, acting with `roles`, is held to, with the variables in their
+/// values replaced by the account's. Fails if one uses an attribute the account does not have,
+/// rather than guess which rows it was meant to see.
+pub fn limits(db: &Database, user: &str, roles: &[String]) -> Result<Limits> {
+    let mut limits = Limits::new();
+    if roles.iter().any(|role| role == ADMIN_ROLE) {
+        return Ok(limits);
+    }
+    let attributes = auth::attributes(db, user);
+    for role in roles {
+        for (table, condition) in policies(db, role) {
+            let words = condition.split_whitespace().map(|word| match word.strip_prefix('$') {
+                None => Ok(word),
+                Some("user") => Ok(user),
+                Some(name) => attributes.get(name).map(String::as_str).ok_or_else(|| {
+                    DatabaseError::PermissionDenied(format!("the row policy on '{}' uses ${}, which '{}' has no value for", table, name, user))
+                }),
+            });
+            let condition = words.collect::<Result<Vec<&str>>>()?.join(" ");
+            limits.entry(table).or_default().push(condition);
+        }
+    }
+    Ok(limits)
+}
+
+/// Run a command line for an account held to `limits`. A command that uses no limited table, or
+/// only changes its schema (DDL), runs as usual. Reads run on a copy of the tables they use that
+/// holds only the rows the account may see, so every read command, view and export is limited
+/// alike. Writes are tried on a copy of the tables they change first and refused if they would
+/// change a row the account may not see, or one it would no longer see, so only writes within the
+/// policies reach the database; `DELETE ... WHERE` deletes only the rows it may see.
+pub fn execute(db: &mut Database, line: &str, limits: &Limits) -> Response {
+    match limited(db, line, limits) {
+        Ok(Some(response)) => response,
+        Ok(None) => executor::execute(db, line),
+        Err(e) => Response::Error(e.to_string()),
+    }
+}
+
+fn limited(db: &mut Database, line: &str, limits: &Limits) -> Result<Option<Response>> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let Needs::Privileges(used) = privileges::needs(&parts) else {
+        return Ok(None);
+    };
+    if limits.is_empty() || used.is_empty() {
+        return Ok(None);
+    }
+    views::load(db)?;
+    let used: Vec<(Privilege, String)> = used.into_iter().map(|(privilege, name)| (privilege, table_of(db, &name))).collect();
+    let written: BTreeSet<&String> = used
+        .iter()
+        .filter(|(privilege, table)| !matches!(privilege, Privilege::Select | Privilege::Ddl) && limits.contains_key(table))
+        .map(|(_, table)| table)
+        .collect();
+    if !written.is_empty() {
+        return write(db, line, &written, limits).map(Some);
+    }
+    if !used.iter().any(|(privilege, table)| *privilege == Privilege::Select && limits.contains_key(table)) {
+        return Ok(None);
+    }
+    let keyword = parts[0].to_lowercase();
+    match keyword.as_str() {
+        // A view stores its definition only; reading it is limited like reading its table.
+        "create" => Ok(None),
+        "save" | "export" => read(db, line, &used, limits).map(Some),
+        _ if executor::is_read_only(line) => read(db, line, &used, limits).map(Some),
+        _ => Err(DatabaseError::PermissionDenied(format!("{} would use every row of a table with a row policy", keyword.to_uppercase()))),
+    }
+}
+
+/// Fail if `limits` hold to some rows of `name` (a table, or a view of one), for the commands
+/// that stream every row of it without going through `execute` (SUBSCRIBE, EXPORT over TCP).
+pub fn check_unlimited(db: &Database, limits: &Limits, name: &str) -> Result<()> {
+    let table = table_of(db, name);
+    match limits.contains_key(&table) {
+        true => Err(DatabaseError::PermissionDenied(format!("a row policy limits '{}' to some of its rows", table))),
+        false => Ok(()),
+    }
+}
+
+/// The table a name stands for: the one a view reads, or the name itself.
+fn table_of(db: &Database, name: &str) -> String {
+    views::get(db, name).map_or_else(|| name.to_string(), |view| view.table)
+}
+
+/// Run a read on a detached database holding the tables it uses, limited ones with only the
+/// rows the account may see, and the view definitions.
+fn read(db: &mut Database, line: &str, used: &[(Privilege, String)], limits: &Limits) -> Result<Response> {
+    let mut visible = detached(db);
+    for (_, table) in used {
+        db.ensure_table_loaded(table)?;
+        let copy = match limits.get(table) {
+            Some(conditions) => TableRef::new(visible_rows(db, table, conditions)?),
+            None => db.get_table(table)?,
+        };
+        visible.tables.insert(table.clone(), copy);
+    }
+    Ok(executor::execute(&mut visible, line))
+}
+
+/// A database on `Detached` storage holding only the view definitions of `db`, for `read` and
+/// `write` to run a command on.
+fn detached(db: &Database) -> Database {
+    let mut detached = Database::with_storage(Box::new(Detached));
+    detached.progress = db.progress.clone();
+    if let Some(views) = db.tables.get(VIEW_TABLE) {
+        detached.tables.insert(VIEW_TABLE.to_string(), views.clone());
+    }
+    detached
+}
+
+/// A copy of `table` without the rows that fail any of `conditions`.
+fn visible_rows(db: &Database, table: &str, conditions: &[String]) -> Result<Table> {
+    let matches = matchers(db, table, conditions)?;
+    let mut copy = (*db.get_table(table)?).clone();
+    let hidden: Vec<String> = copy.rows.iter().filter(|(_, row)| !matches.iter().all(|m| m(row))).map(|(row_id, _)| row_id.clone()).collect();
+    for row_id in hidden {
+        copy.delete_row(&row_id);
+    }
+    Ok(copy)
+}
+
+fn matchers(db: &Database, table: &str, conditions: &[String]) -> Result<Vec<RowMatcher>> {
+    conditions.iter().map(|condition| db.row_matcher(table, condition)).collect()
+}
+
+/// Run a write to the limited tables `written`, unless trying it on a detached copy of them (see
+/// `read`) shows it would change a row outside the account's policies: then it fails before
+/// anything is logged, persisted or told to hooks. A row the account may not see does not exist
+/// as far as single-row commands are concerned.
+fn write(db: &mut Database, line: &str, written: &BTreeSet<&String>, limits: &Limits) -> Result<Response> {
+    for table in written {
+        db.ensure_table_loaded(table)?;
+    }
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let keyword = parts[0].to_lowercase();
+    let single_row = matches!(keyword.as_str(), "update" | "increment" | "decrement" | "expire" | "delete" | "restore" | "upsert" | "replace");
+    if let (true, Some(&table), Some(&row_id)) = (single_row, parts.get(1), parts.get(2)) {
+        if let Some(conditions) = limits.get(table) {
+            let matches = matchers(db, table, conditions)?;
+            let stored = db.get_table(table)?;
+            if stored.get_row(row_id).is_some_and(|row| !matches.iter().all(|m| m(row))) {
+                return Err(DatabaseError::RowDoesNotExist(row_id.to_string(), table.to_string()));
+            }
+        }
+    }
+    if let [_, table, keyword, condition @ ..] = &parts[..] {
+        let conditions = limits.get(*table).filter(|_| parts[0].eq_ignore_ascii_case("delete") && keyword.eq_ignore_ascii_case("where"));
+        if let Some(conditions) = conditions.filter(|_| (3..=4).contains(&condition.len())) {
+            let mut matches = matchers(db, table, conditions)?;
+            matches.push(db.row_matcher(table, &condition.join(" "))?);
+            let rows: Vec<String> = db.get_table(table)?.live_rows().filter(|(_, row)| matches.iter().all(|m| m(row))).map(|(row_id, _)| row_id.clone()).collect();
+            for row_id in &rows {
+                db.delete_row(table, row_id)?;
+            }
+            return Ok(Response::Ok(json!(rows.len())));
+        }
+    }
+
+    // The copy shares the tables' rows until the write changes them, so it costs what the write does.
+    let mut trial = detached(db);
+    for table in written {
+        trial.tables.set_row_shards(table, db.tables.row_shards(table));
+        trial.tables.insert((*table).clone(), db.get_table(table)?);
+    }
+    if let Response::Error(e) = executor::execute(&mut trial, line) {
+        return Ok(Response::Error(e));
+    }
+    for table in written {
+        let matches = matchers(db, table, &limits[*table])?;
+        let (old, new) = (db.get_table(table)?, trial.get_table(table)?);
+        let changed = old.rows.keys().chain(new.rows.keys()).find(|row_id| {
+            let (was, is) = (old.rows.get(row_id), new.rows.get(row_id));
+            was != is && [was, is].into_iter().flatten().any(|row| !matches.iter().all(|m| m(row)))
+        });
+        if let Some(row_id) = changed {
+            return Err(DatabaseError::PermissionDenied(format!("row '{}' of '{}' is outside the row policy", row_id, table)));
+        }
+    }
+    Ok(executor::execute(db, line))
+}
+
+/// Storage for the copies `read` runs on: it holds nothing and keeps nothing, so a read can never
+/// load, or write back, rows the account may not see.
+struct Detached;
+
+impl StorageEngine for Detached {
+    fn name(&self) -> &str {
+        "detached"
+    }
+
+    fn load_table(&self, _table_name: &str) -> Result<Option<Table>> {
+        Ok(None)
+    }
+
+    fn table_names(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn save_table(&mut self, _table_name: &str, _table: &Table) -> Result<()> {
+        Ok(())
+    }
+
+    fn rename_table(&mut self, _old_name: &str, _new_name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn drop_table(&mut self, _table_name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn stored_size(&self, _table_name: &str) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,305 @@
+use crate::auth::{self, ADMIN_ROLE};
+use crate::commands::db::{Database, DatabaseError, Result};
+use crate::policies;
+use crate::replication::ReadConsistency;
+use crate::table::table::ColumnSpec;
+use crate::table::value::Value;
+use log::info;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Table holding the roles: one row per role name (row_id), with its grants as a JSON object
+/// from table name (or `*`, every table) to privilege names, e.g. `{"users":["select","insert"]}`.
+/// Like the user table it lives in `main` and sessions cannot use it directly.
+pub const ROLE_TABLE: &str = "__roles";
+
+/// Grants on this table name cover every table.
+pub const ALL_TABLES: &str = "*";
+
+/// What a role may be granted on a table. `Ddl` covers creating, altering, renaming and dropping
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Ddl,
+}
+
+impl Privilege {
+    pub const ALL: [Privilege; 5] = [Privilege::Select, Privilege::Insert, Privilege::Update, Privilege::Delete, Privilege::Ddl];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Privilege::Select => "select",
+            Privilege::Insert => "insert",
+            Privilege::Update => "update",
+            Privilege::Delete => "delete",
+            Privilege::Ddl => "ddl",
+        }
+    }
+
+    /// Parse a privilege name in any case.
+    pub fn parse(word: &str) -> Option<Privilege> {
+        Privilege::ALL.into_iter().find(|privilege| word.eq_ignore_ascii_case(privilege.name()))
+    }
+
+    /// Parse `SELECT,INSERT` or `ALL`.
+    pub fn parse_list(list: &str) -> Result<Vec<Privilege>> {
+        if list.eq_ignore_ascii_case("all") {
+            return Ok(Privilege::ALL.to_vec());
+        }
+        list.split(',').map(|word| Privilege::parse(word).ok_or_else(|| DatabaseError::InvalidPrivilege(word.to_string()))).collect()
+    }
+}
+
+/// What running a command takes, beyond being logged in.
+#[derive(Debug, PartialEq)]
+pub enum Needs {
+    Nothing,
+    /// Each privilege on its table
+    Privileges(Vec<(Privilege, String)>),
+    /// Being this account, or an admin (e.g. changing a password)
+    Account(String),
+    /// The admin role: managing accounts, and commands that work on the whole database
+    Admin,
+}
+
+/// What the command made of `parts` (a command line, or a dot-command, split on whitespace)
+/// needs. Unknown commands need nothing, since they fail anyway.
+pub fn needs(parts: &[&str]) -> Needs {
+    let Some(keyword) = parts.first().map(|word| word.to_lowercase()) else {
+        return Needs::Nothing;
+    };
+    let kind = parts.get(1).map(|word| word.to_lowercase()).unwrap_or_default();
+    let on = |privileges: &[Privilege], table: Option<&&str>| match table {
+        Some(table) => Needs::Privileges(privileges.iter().map(|privilege| (*privilege, table.to_string())).collect()),
+        None => Needs::Nothing,
+    };
+    use Privilege::*;
+    match (keyword.as_str(), kind.as_str()) {
+        ("read", _) => match ReadConsistency::parse(&parts[1..]) {
+            Some((_, used)) => needs(&parts[1 + used..]),
+            None => Needs::Nothing,
+        },
+        ("get" | "lookup" | "search" | "distinct" | "stats" | "page" | "print" | "subscribe", _) => on(&[Select], parts.get(1)),
+        ("explain", _) => on(&[Select], parts.get(3)),
+        ("analyze", _) => on(&[Select], parts.get(1)),
+        // Commands that read or write a file of the server's can reach any path it can, so they
+        // are for admins. A streamed `EXPORT t [WHERE ...]` writes no file.
+        ("save" | "export", _) if parts.get(2).is_some_and(|word| !word.eq_ignore_ascii_case("where")) => Needs::Admin,
+        ("load" | ".import" | "merge", _) | ("restore", "table") => Needs::Admin,
+        // `EXPORT a,b` reads every table it names.
+        ("save" | "export", _) => match parts.get(1) {
+            Some(names) => Needs::Privileges(names.split(',').map(|name| (Select, name.to_string())).collect()),
+            None => Needs::Nothing,
+        },
+        ("insert", _) => on(&[Insert], parts.get(1)),
+        ("update" | "increment" | "decrement" | "expire", _) => on(&[Update], parts.get(1)),
+        ("upsert" | "replace", _) => on(&[Insert, Update], parts.get(1)),
+        ("delete" | "purge", _) => on(&[Delete], parts.get(1)),
+        ("truncate", "table") => on(&[Delete], parts.get(2)),
+        ("create" | "drop", "table" | "column" | "view") | ("rename" | "add" | "alter", "table" | "column") => {
+            let mut needs = vec![(Ddl, parts.get(2).map_or_else(String::new, |name| name.to_string()))];
+            // `RENAME TABLE a b` takes the new name too; `CREATE VIEW v AS t ...` reads `t`.
+            match (keyword.as_str(), kind.as_str()) {
+                ("rename", "table") => needs.extend(parts.get(3).map(|name| (Ddl, name.to_string()))),
+                ("create", "view") => needs.extend(parts.get(4).map(|table| (Select, table.to_string()))),
+                _ => {}
+            }
+            Needs::Privileges(needs)
+        }
+        ("copy", "table") => Needs::Privileges(
+            parts.get(2).map(|table| (Select, table.to_string())).into_iter().chain(parts.get(3).map(|name| (Ddl, name.to_string()))).collect(),
+        ),
+        ("restore", "database") => Needs::Admin,
+        ("restore", _) => on(&[Update], parts.get(1)),
+        // `ALTER USER <name> SET <attribute> <value>` changes what row policies let it see.
+        ("alter", "user") if parts.get(3).is_some_and(|word| word.eq_ignore_ascii_case("set")) => Needs::Admin,
+        ("alter", "user") => parts.get(2).map_or(Needs::Nothing, |name| Needs::Account(name.to_string())),
+        // `CREATE TOKEN <name> FOR <user>`; a token may be revoked by its account, which the
+        // command checks as only it knows whose the token is.
+        ("create", "token") => parts.get(4).map_or(Needs::Nothing, |name| Needs::Account(name.to_string())),
+        ("revoke", "token") => Needs::Nothing,
+        ("tokens", _) => parts.get(1).map_or(Needs::Nothing, |name| Needs::Account(name.to_string())),
+        ("grants", _) => parts.get(1).map_or(Needs::Nothing, |name| Needs::Account(name.to_string())),
+        ("create" | "drop", "user" | "role" | "database" | "policy")
+        | ("copy", "database")
+        | ("users" | "roles" | "grant" | "revoke" | "import" | "dump" | "verify" | "wal" | "migrate" | "backup" | "rotate" | "bench" | ".dump", _) => Needs::Admin,
+        _ => Needs::Nothing,
+    }
+}
+
+/// Fail unless account `user`, acting with `roles` (its own, or a token's; see `tokens::roles`),
+/// has what `needs` asks for. Admins have everything; others have the grants of their roles.
+pub fn check(db: &Database, user: &str, roles: &[String], needs: &Needs) -> Result<()> {
+    if roles.iter().any(|role| role == ADMIN_ROLE) {
+        return Ok(());
+    }
+    let denied = |reason: String| Err(DatabaseError::PermissionDenied(reason));
+    match needs {
+        Needs::Nothing => Ok(()),
+        Needs::Account(name) if name == user => Ok(()),
+        Needs::Account(_) | Needs::Admin => denied(format!("'{}' needs the {} role", user, ADMIN_ROLE)),
+        Needs::Privileges(privileges) => {
+            let grants: Vec<BTreeMap<String, BTreeSet<Privilege>>> = roles.iter().map(|role| grants(db, role)).collect();
+            for (privilege, table) in privileges {
+                let granted = grants.iter().any(|grants| {
+                    [table.as_str(), ALL_TABLES].iter().any(|name| grants.get(*name).is_some_and(|held| held.contains(privilege)))
+                });
+                if !granted {
+                    return denied(format!("'{}' has no {} privilege on '{}'", user, privilege.name().to_uppercase(), table));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The role names, with `admin` first.
+fn role_names(db: &Database) -> Vec<String> {
+    let stored = db.tables.get(ROLE_TABLE).map(|table| table.rows.keys().cloned().collect::<Vec<_>>()).unwrap_or_default();
+    std::iter::once(ADMIN_ROLE.to_string()).chain(stored).collect()
+}
+
+/// Every role with its grants, row policies and the accounts holding it.
+pub fn roles(db: &mut Database) -> Result<serde_json::Value> {
+    load(db)?;
+    let users = auth::users(db)?;
+    Ok(role_names(db)
+        .into_iter()
+        .map(|role| {
+            let members: Vec<&String> = users.iter().filter(|user| auth::roles(db, user).contains(&role)).collect();
+            let grants = if role == ADMIN_ROLE { json!({ ALL_TABLES: ["all"] }) } else { grants_json(&grants(db, &role)) };
+            json!({ "role": role, "grants": grants, "policies": policies::policies(db, &role), "members": members })
+        })
+        .collect())
+}
+
+/// What account `user` may do: its roles, what they grant on each table, the row policies it is
+/// held to and the attributes they use.
+pub fn grants_of(db: &mut Database, user: &str) -> Result<serde_json::Value> {
+    load(db)?;
+    if auth::stored_hash(db, user).is_none() {
+        return Err(DatabaseError::UserDoesNotExist(user.to_string()));
+    }
+    let roles = auth::roles(db, user);
+    let mut privileges: BTreeMap<String, BTreeSet<Privilege>> = BTreeMap::new();
+    for role in &roles {
+        if role == ADMIN_ROLE {
+            privileges.insert(ALL_TABLES.to_string(), Privilege::ALL.into_iter().collect());
+        }
+        for (table, held) in grants(db, role) {
+            privileges.entry(table).or_default().extend(held);
+        }
+    }
+    let policies = policies::limits(db, user, &roles).unwrap_or_default();
+    let attributes = auth::attributes(db, user);
+    Ok(json!({ "user": user, "roles": roles, "privileges": grants_json(&privileges), "policies": policies, "attributes": attributes }))
+}
+
+/// Create a role with no grants. Names follow the rules for user names.
+pub fn create_role(db: &mut Database, role: &str) -> Result<()> {
+    load(db)?;
+    if role.is_empty() || !role.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(DatabaseError::InvalidRoleName(role.to_string()));
+    }
+    if role_names(db).iter().any(|name| name == role) {
+        return Err(DatabaseError::RoleAlreadyExists(role.to_string()));
+    }
+    if !db.check_table(ROLE_TABLE) {
+        db.create_table(ROLE_TABLE)?;
+        db.add_typed_column(ROLE_TABLE, "grants", ColumnSpec { not_null: true, ..ColumnSpec::default() })?;
+    }
+    db.insert_row(ROLE_TABLE, role, HashMap::from([("grants".to_string(), "{}".to_string())]))?;
+    db.persist_table(ROLE_TABLE)?;
+    info!(role; "Role created");
+    Ok(())
+}
+
+/// Drop a role, taking it away from every account that holds it.
+pub fn drop_role(db: &mut Database, role: &str) -> Result<()> {
+    stored_role(db, role)?;
+    for user in auth::users(db)? {
+        if auth::roles(db, &user).iter().any(|held| held == role) {
+            auth::set_role(db, &user, role, false)?;
+        }
+    }
+    db.delete_row(ROLE_TABLE, role)?;
+    db.persist_table(ROLE_TABLE)?;
+    info!(role; "Role dropped");
+    Ok(())
+}
+
+/// `GRANT <privileges> ON <table> TO <role>` when `granted`, else `REVOKE ... FROM <role>`.
+pub fn set_grants(db: &mut Database, role: &str, privileges: &[Privilege], table: &str, granted: bool) -> Result<()> {
+    stored_role(db, role)?;
+    let mut grants = grants(db, role);
+    let held = grants.entry(table.to_string()).or_default();
+    for privilege in privileges {
+        if granted {
+            held.insert(*privilege);
+        } else {
+            held.remove(privilege);
+        }
+    }
+    grants.retain(|_, held| !held.is_empty());
+    db.update_row(ROLE_TABLE, role, "grants", &grants_json(&grants).to_string())?;
+    db.persist_table(ROLE_TABLE)?;
+    let names: Vec<&str> = privileges.iter().map(|privilege| privilege.name()).collect();
+    match granted {
+        true => info!(privileges = names.join(","), table, role; "Privileges granted"),
+        false => info!(privileges = names.join(","), table, role; "Privileges revoked"),
+    }
+    Ok(())
+}
+
+/// `GRANT ROLE <role> TO <user>` when `granted`, else `REVOKE ROLE <role> FROM <user>`.
+pub fn set_member(db: &mut Database, role: &str, user: &str, granted: bool) -> Result<()> {
+    load(db)?;
+    if !role_names(db).iter().any(|name| name == role) {
+        return Err(DatabaseError::RoleDoesNotExist(role.to_string()));
+    }
+    auth::set_role(db, user, role, granted)
+}
+
+/// Load the role table if it has been stored, so `check` sees every grant.
+pub fn load(db: &mut Database) -> Result<()> {
+    if !db.check_table(ROLE_TABLE) && db.storage.get_mut().table_names()?.iter().any(|name| name == ROLE_TABLE) {
+        db.ensure_table_loaded(ROLE_TABLE)?;
+    }
+    Ok(())
+}
+
+/// Fail unless `role` is a role that grants can be changed on: it exists and is not `admin`,
+/// which always holds everything.
+pub fn stored_role(db: &mut Database, role: &str) -> Result<()> {
+    load(db)?;
+    if role == ADMIN_ROLE {
+        return Err(DatabaseError::BuiltinRole(role.to_string()));
+    }
+    match db.tables.get(ROLE_TABLE).is_some_and(|table| table.rows.contains_key(role)) {
+        true => Ok(()),
+        false => Err(DatabaseError::RoleDoesNotExist(role.to_string())),
+    }
+}
+
+/// The grants of a stored role, by table.
+fn grants(db: &Database, role: &str) -> BTreeMap<String, BTreeSet<Privilege>> {
+    let Some(Value::Text(text)) = db.tables.get(ROLE_TABLE).and_then(|table| table.rows.get(role)?.get("grants").cloned()) else {
+        return BTreeMap::new();
+    };
+    let stored: BTreeMap<String, Vec<String>> = serde_json::from_str(&text).unwrap_or_default();
+    stored
+        .into_iter()
+        .map(|(table, names)| (table, names.iter().filter_map(|name| Privilege::parse(name)).collect()))
+        .collect()
+}
+
+fn grants_json(grants: &BTreeMap<String, BTreeSet<Privilege>>) -> serde_json::Value {
+    json!(grants
+        .iter()
+        .map(|(table, held)| (table.clone(), held.iter().map(|privilege| privilege.name()).collect::<Vec<_>>()))
+        .collect::<BTreeMap<_, _>>())
+}
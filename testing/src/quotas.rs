@@ -0,0 +1,119 @@
+use crate::commands::db::{DatabaseError, Result};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Buckets kept before the full ones (clients idle for a second or more) are dropped.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// **Client quotas**
+/// Limits shared by the TCP server and the HTTP API, so one client cannot keep the others from
+/// the database lock: at most `rate` commands (or HTTP requests) a second per client, and at most
+/// `max_connections` TCP connections (or HTTP requests in flight) at a time per IP address. A
+/// client is the API token it logged in with (see `tokens`), or else its IP address. Over the
+/// limit a command fails with `RateLimited`, which says how long to back off, and a connection
+/// with `TooManyConnections`; neither waits for the lock.
+#[derive(Default)]
+pub struct Quotas {
+    rate: Option<u32>,
+    max_connections: Option<usize>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    connections: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/// Token bucket of one client: it holds up to `rate` commands, refilled at `rate` a second, so a
+/// client may burst for a second before it is held to the rate.
+struct Bucket {
+    available: f64,
+    refilled: Instant,
+}
+
+impl Quotas {
+    /// Quotas from RUSTDB_RATE_LIMIT (commands a second per client) and RUSTDB_MAX_CONNECTIONS
+    /// (connections at a time per IP address); an unset one does not limit.
+    pub fn from_env() -> std::result::Result<Self, String> {
+        let setting = |name: &str| match std::env::var(name) {
+            Ok(value) => value.parse::<u32>().map(Some).map_err(|_| format!("Invalid {} '{}': use a positive number.", name, value)),
+            Err(_) => Ok(None),
+        };
+        let rate = setting("RUSTDB_RATE_LIMIT")?.filter(|rate| *rate > 0);
+        let max_connections = setting("RUSTDB_MAX_CONNECTIONS")?.map(|max| max as usize).filter(|max| *max > 0);
+        Ok(Quotas { rate, max_connections, ..Quotas::default() })
+    }
+
+    /// Take one command from the bucket of `client`, or fail with how long until it may send the next.
+    pub fn check_rate(&self, client: &str) -> Result<()> {
+        let Some(rate) = self.rate.map(f64::from) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| now.duration_since(bucket.refilled).as_secs_f64() < 1.0);
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket { available: rate, refilled: now });
+        bucket.available = (bucket.available + now.duration_since(bucket.refilled).as_secs_f64() * rate).min(rate);
+        bucket.refilled = now;
+        if bucket.available < 1.0 {
+            let wait = ((1.0 - bucket.available) / rate * 1000.0).ceil() as u64;
+            return Err(DatabaseError::RateLimited(client.to_string(), wait));
+        }
+        bucket.available -= 1.0;
+        Ok(())
+    }
+
+    /// Count a connection from `ip` until the returned slot is dropped, or fail if it already has
+    /// as many as it may.
+    pub fn connect(self: &Arc<Self>, ip: IpAddr) -> Result<ConnectionSlot> {
+        let mut connections = self.connections.lock().unwrap();
+        let open = connections.entry(ip).or_insert(0);
+        if let Some(max) = self.max_connections.filter(|max| *open >= *max) {
+            return Err(DatabaseError::TooManyConnections(ip.to_string(), max));
+        }
+        *open += 1;
+        Ok(ConnectionSlot { quotas: Arc::clone(self), ip })
+    }
+}
+
+/// A connection counted against its IP address's quota (see `Quotas::connect`).
+pub struct ConnectionSlot {
+    quotas: Arc<Quotas>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut connections = self.quotas.connections.lock().unwrap();
+        if let Some(open) = connections.get_mut(&self.ip) {
+            *open -= 1;
+            if *open == 0 {
+                connections.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_client_over_its_rate_is_told_to_back_off_without_holding_up_others() {
+        let quotas = Quotas { rate: Some(2), ..Quotas::default() };
+        assert!(quotas.check_rate("a").is_ok() && quotas.check_rate("a").is_ok());
+        assert!(matches!(quotas.check_rate("a"), Err(DatabaseError::RateLimited(client, wait)) if client == "a" && wait > 0));
+        assert!(quotas.check_rate("b").is_ok());
+    }
+
+    #[test]
+    fn a_connection_slot_is_given_back_when_dropped() {
+        let quotas = Arc::new(Quotas { max_connections: Some(1), ..Quotas::default() });
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let slot = quotas.connect(ip).unwrap();
+        assert!(matches!(quotas.connect(ip), Err(DatabaseError::TooManyConnections(_, 1))));
+        assert!(quotas.connect("127.0.0.2".parse().unwrap()).is_ok());
+        drop(slot);
+        assert!(quotas.connect(ip).is_ok());
+    }
+}
@@ -0,0 +1,238 @@
+use crate::commands::executor::Response;
+use crate::completion::Completion;
+use crate::dot_commands;
+use crate::format::{self, OutputFormat};
+use crate::session::Session;
+use crate::storage::progress::Progress;
+use crate::table::table::RenderOptions;
+use log::error;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{CompletionType, Config, Editor};
+
+/// Where the prompt keeps its command history between runs.
+const HISTORY_FILE: &str = ".rustdb_history";
+
+/// Widest a column is drawn at the prompt until `\width` changes it.
+const DEFAULT_WIDTH: usize = 30;
+/// Most rows drawn for one response at the prompt until `\rows` changes it.
+const DEFAULT_ROWS: usize = 50;
+
+/// The backslash commands, for the message about an unknown one.
+const SETTINGS: &str = "\\width <n|off>, \\rows <n|off> and \\format json|csv|table";
+
+/// Prompt settings, changed with backslash commands that never reach the database.
+struct Settings {
+    /// `\width <n|off>`: values wider than this are cut short with '…'
+    width: Option<usize>,
+    /// `\rows <n|off>`: grids show at most this many rows; page with PRINT's LIMIT and OFFSET
+    rows: Option<usize>,
+    /// `\format json|csv|table`: how responses with rows are shown
+    format: OutputFormat,
+}
+
+impl Settings {
+    /// Apply a `\<setting> <value>` line, returning what to tell the user.
+    fn apply(&mut self, line: &str) -> String {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts[0] == "\\format" {
+            if let Some([value]) = parts.get(1..) {
+                match OutputFormat::parse(value) {
+                    Some(format) => self.format = format,
+                    None => return format!("'{}' is not json, csv or table.", value),
+                }
+            }
+            return format!("format is {}.", self.format.name());
+        }
+        let (name, setting) = match parts[0] {
+            "\\width" => ("width", &mut self.width),
+            "\\rows" => ("rows", &mut self.rows),
+            _ => return format!("Unknown setting '{}'. Settings are {}.", parts[0], SETTINGS),
+        };
+        match parts.get(1..) {
+            Some([value]) if value.eq_ignore_ascii_case("off") => *setting = None,
+            Some([value]) => match value.parse::<usize>() {
+                Ok(n) if n > 0 => *setting = Some(n),
+                _ => return format!("'{}' is not a positive number or 'off'.", value),
+            },
+            _ => {}
+        }
+        match setting {
+            Some(n) => format!("{} is {}.", name, n),
+            None => format!("{} is off.", name),
+        }
+    }
+
+    /// `response` as the prompt shows it: in `format` if the command asked for one, else in the
+    /// `\format` setting, with grids laid out by `\width` and `\rows`.
+    fn render(&self, response: &Response, format: Option<OutputFormat>) -> String {
+        let options = RenderOptions { max_width: self.width, limit: self.rows, offset: 0 };
+        format::render(response, format.unwrap_or(self.format), &options)
+    }
+}
+
+/// Cells of the progress bar.
+const BAR_WIDTH: usize = 30;
+
+/// Draw a long import or export's progress on standard error, on one line redrawn in place:
+/// `import people.csv [########······] 57% 57000/100000 rows, 2.1 MB, 3s left`.
+pub fn draw_progress(progress: &Progress) {
+    let mut line = format!("{} ", progress.operation);
+    if let (Some(fraction), Some(total)) = (progress.fraction(), progress.total_rows) {
+        let filled = ((fraction * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+        line.push_str(&format!(
+            "[{}{}] {:>3}% {}/{} rows",
+            "#".repeat(filled),
+            "·".repeat(BAR_WIDTH - filled),
+            (fraction * 100.0) as usize,
+            progress.rows,
+            total
+        ));
+    } else {
+        line.push_str(&format!("{} rows", progress.rows));
+    }
+    line.push_str(&format!(", {:.1} MB", progress.bytes as f64 / 1_000_000.0));
+    match progress.eta() {
+        _ if progress.done => line.push_str(&format!(", done in {:.1}s\n", progress.elapsed.as_secs_f64())),
+        Some(eta) => line.push_str(&format!(", {}s left", eta.as_secs())),
+        None => {}
+    }
+    // `\x1b[K` clears what is left of a longer earlier line.
+    eprint!("\r{}\x1b[K", line);
+}
+
+/// Commands that run as soon as they are typed, without a `;`.
+const UNTERMINATED: &[&str] = &["exit", "quit", "help"];
+
+/// Whether `line` is a whole statement on its own: a `\` setting, a dot-command or one of
+/// `UNTERMINATED`.
+fn runs_alone(line: &str) -> bool {
+    line.starts_with(['\\', '.']) || UNTERMINATED.iter().any(|command| line.eq_ignore_ascii_case(command))
+}
+
+/// Whether `statement` carries a password (`LOGIN`, `CREATE USER` or `ALTER USER`), passphrase
+/// (`ROTATE PASSPHRASE`) or API token (`TOKEN`), which must not be written to the history file.
+fn holds_password(statement: &str) -> bool {
+    let words: Vec<String> = statement.split_whitespace().take(2).map(str::to_lowercase).collect();
+    matches!(words.iter().map(String::as_str).collect::<Vec<_>>()[..], ["login" | "token", ..] | ["create" | "alter", "user"] | ["rotate", "passphrase"])
+}
+
+/// Read statements from standard input and print each response, rows in the `\format` setting (a
+/// grid at first) or a statement's trailing `FORMAT json|csv|table`, the rest as JSON lines.
+/// A statement ends with `;` and may span lines, which are joined with spaces; the prompt turns
+/// into `...>` until it is complete. Lines are edited with rustyline: arrow keys walk the history
+/// (one entry per statement), Ctrl-R searches it and Tab completes commands, tables and columns
+/// (see `Completion`). Ctrl-C drops the statement being typed. Returns true on EXIT, false once
+/// the input ends; a statement left without its `;` then still runs.
+pub fn run(mut session: Session) -> bool {
+    // Tab completes as far as the candidates agree, and a second Tab lists them.
+    let config = Config::builder().completion_type(CompletionType::List).build();
+    let mut editor = match Editor::<Completion, DefaultHistory>::with_config(config) {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Failed to start the prompt: {}", e);
+            return false;
+        }
+    };
+    editor.set_helper(Some(Completion::new(session.db())));
+    // The first run has no history yet.
+    let _ = editor.load_history(HISTORY_FILE);
+    let mut settings = Settings { width: Some(DEFAULT_WIDTH), rows: Some(DEFAULT_ROWS), format: OutputFormat::Table };
+    println!("Welcome to RustDB! Type 'help' for a list of commands; end each command with ';'.");
+    let mut statement = String::new();
+    let exited = loop {
+        let prompt = if statement.is_empty() { "> " } else { "...> " };
+        let (line, at_end) = match editor.readline(prompt) {
+            Ok(line) => (line, false),
+            Err(ReadlineError::Interrupted) => {
+                statement.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) if !statement.is_empty() => (String::new(), true),
+            Err(ReadlineError::Eof) => break false,
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                break false;
+            }
+        };
+        let line = line.trim();
+        if statement.is_empty() && runs_alone(line) {
+            statement.push_str(line);
+        } else {
+            if !line.is_empty() {
+                if !statement.is_empty() {
+                    statement.push(' ');
+                }
+                statement.push_str(line);
+            }
+            if statement.is_empty() || !(at_end || statement.ends_with(';')) {
+                continue;
+            }
+        }
+        if !holds_password(&statement) {
+            let _ = editor.add_history_entry(statement.as_str());
+        }
+        let text = std::mem::take(&mut statement);
+        let text = text.trim_end_matches(';').trim_end();
+        if text.is_empty() {
+            continue;
+        }
+        if text.starts_with('\\') {
+            println!("{}", settings.apply(text));
+            continue;
+        }
+        if text.starts_with('.') {
+            if let Err(e) = session.check_access(&text.split_whitespace().collect::<Vec<_>>()) {
+                println!("{}", Response::Error(e.to_string()).to_line());
+                continue;
+            }
+            match dot_commands::execute(&mut session.db().write().unwrap(), text) {
+                Ok(output) => println!("{}", output),
+                Err(e) => println!("{}", Response::Error(e.to_string()).to_line()),
+            }
+            continue;
+        }
+        let (command, format) = format::split_flag(text);
+        let response = session.handle(command);
+        // `USE` may have switched databases.
+        if let Some(completion) = editor.helper_mut() {
+            completion.db = session.db();
+        }
+        match response {
+            Response::Exit => break true,
+            response => println!("{}", settings.render(&response, format)),
+        }
+        if at_end {
+            break false;
+        }
+    };
+    if let Err(e) = editor.save_history(HISTORY_FILE) {
+        error!("Failed to save the command history: {}", e);
+    }
+    exited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_with_secrets_stay_out_of_the_history() {
+        for statement in ["LOGIN ann secret;", "token abc", "CREATE USER ann PASSWORD x;", "alter user ann password y", "ROTATE PASSPHRASE z"] {
+            assert!(holds_password(statement), "'{}' would be saved", statement);
+        }
+        for statement in ["CREATE TABLE users;", "ALTER COLUMN users name TYPE text;", "LOGOUT;"] {
+            assert!(!holds_password(statement), "'{}' would not be saved", statement);
+        }
+    }
+
+    #[test]
+    fn only_settings_dot_commands_and_a_few_words_need_no_semicolon() {
+        for line in ["\\format csv", ".tables", "exit", "QUIT", "help"] {
+            assert!(runs_alone(line), "'{}'", line);
+        }
+        for line in ["PRINT users", "exit now", "HELP ME"] {
+            assert!(!runs_alone(line), "'{}'", line);
+        }
+    }
+}
@@ -0,0 +1,514 @@
+use crate::commands::db::{self, Database, DatabaseError};
+use crate::privileges::{self, Needs};
+use crate::session::SharedDb;
+use crate::{auth, tokens};
+use crate::storage::encryption;
+use crate::table::table::{self, Table};
+use log::{error, info};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How often the primary checks the WAL archive for newly committed entries.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often the primary tells an idle replica which LSN it has committed up to.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a replica waits before reconnecting to the primary.
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// **Replication protocol** (JSON lines over TCP)
+/// Every committed WAL entry has a log sequence number (LSN): its line number in the WAL archive.
+/// 0. Once the primary has accounts (see `auth::required`), the replica first sends `LOGIN <user>
+///    <password>` or `TOKEN <token>` of an admin: it is sent every table, accounts included.
+///    Anything else is answered with `{"type":"error",...}` and the connection is closed.
+/// 1. The replica sends `REPLICATE <lsn>`, the LSN of the last entry it applied (0 if none).
+/// 2. If the primary cannot resume from there, it sends
+///    `{"type":"snapshot","lsn":L,"tables":{"<table>":{"columns":[...],"rows":{"<id>":{...}}}}}`.
+/// 3. It then streams `{"type":"entry","lsn":N,"op":"insert_row:..."}` for every entry after
+///    that LSN, as soon as the WalEngine archives it.
+/// 4. While there is nothing new it sends `{"type":"heartbeat","lsn":N}` every second, so the
+///    replica knows when it has caught up.
+/// 5. The replica answers entries and heartbeats with `ACK <lsn>`, which feeds `ShippingMetrics`.
+pub struct Primary {
+    db: SharedDb,
+    listener: TcpListener,
+    metrics: Arc<ShippingMetrics>,
+}
+
+impl Primary {
+    pub async fn bind(addr: &str, db: SharedDb, metrics: Arc<ShippingMetrics>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(addr:% = listener.local_addr()?; "Replication listening");
+        Ok(Primary { db, listener, metrics })
+    }
+
+    /// The address replicas connect to, e.g. to find the port after binding port 0.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept replicas forever, streaming to each one on its own task.
+    pub async fn run(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, peer)) => {
+                    let db = SharedDb::clone(&self.db);
+                    let metrics = Arc::clone(&self.metrics);
+                    tokio::spawn(async move {
+                        info!("Replica {} connected.", peer);
+                        metrics.connected(peer);
+                        if let Err(e) = stream_to_replica(stream, peer, db, &metrics).await {
+                            error!("Replica {} error: {}", peer, e);
+                        }
+                        metrics.disconnected(peer);
+                        info!("Replica {} disconnected.", peer);
+                    });
+                }
+                Err(e) => error!("Failed to accept replica: {}", e),
+            }
+        }
+    }
+}
+
+async fn send(writer: &mut (impl AsyncWriteExt + Unpin), message: &Value) -> std::io::Result<()> {
+    writer.write_all(format!("{}\n", message).as_bytes()).await
+}
+
+async fn stream_to_replica(
+    stream: TcpStream,
+    peer: SocketAddr,
+    db: SharedDb,
+    metrics: &Arc<ShippingMetrics>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut request = String::new();
+    reader.read_line(&mut request).await?;
+    if is_login(&request) {
+        let (login_db, login) = (SharedDb::clone(&db), request.clone());
+        let checked = tokio::task::spawn_blocking(move || check_login(&login_db, &login)).await.map_err(std::io::Error::other)?;
+        if let Err(e) = checked {
+            error!("Replica {} refused: {}", peer, e);
+            return send(&mut writer, &json!({ "type": "error", "message": e.to_string() })).await;
+        }
+        request.clear();
+        reader.read_line(&mut request).await?;
+    } else if auth::required(&db.read().unwrap()) {
+        error!("Replica {} refused: {}", peer, DatabaseError::AuthenticationRequired);
+        let message = json!({ "type": "error", "message": DatabaseError::AuthenticationRequired.to_string() });
+        return send(&mut writer, &message).await;
+    }
+    let requested: u64 = match request.split_whitespace().collect::<Vec<_>>()[..] {
+        [cmd, lsn] if cmd.eq_ignore_ascii_case("replicate") => lsn.parse().unwrap_or(0),
+        _ => {
+            let message = json!({ "type": "error", "message": "expected 'REPLICATE <lsn>'" });
+            return send(&mut writer, &message).await;
+        }
+    };
+
+    let (current, archive_file) = {
+        let db = db.read().unwrap();
+        (db.current_lsn(), db.wal_archive_file.clone())
+    };
+    // A replica that is ahead of us has applied history we no longer have: start it over.
+    let mut lsn = requested;
+    if requested == 0 || requested > current {
+        let db = SharedDb::clone(&db);
+        let (snapshot_lsn, snapshot) = tokio::task::spawn_blocking(move || snapshot(&db))
+            .await
+            .map_err(std::io::Error::other)?
+            .map_err(std::io::Error::other)?;
+        send(&mut writer, &snapshot).await?;
+        lsn = snapshot_lsn;
+        metrics.update(peer, |lag| {
+            lag.snapshots_sent += 1;
+            lag.sent_lsn = lsn;
+        });
+        info!("Sent snapshot at LSN {}.", lsn);
+    }
+
+    // Acknowledgements arrive on the read half while entries go out on the write half.
+    let ack_metrics = Arc::clone(metrics);
+    let ack_db = SharedDb::clone(&db);
+    let acks = tokio::spawn(async move {
+        let mut lines = reader.lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let Some(acked) = line.strip_prefix("ACK ").and_then(|n| n.trim().parse::<u64>().ok()) else {
+                continue;
+            };
+            let committed = ack_db.read().unwrap().wal_lsn;
+            ack_metrics.update(peer, |lag| {
+                lag.acked_lsn = acked;
+                if acked >= committed {
+                    lag.caught_up_at = Instant::now();
+                }
+            });
+        }
+    });
+    let result = ship_archive(&archive_file, lsn, &mut writer, peer, metrics).await;
+    acks.abort();
+    result
+}
+
+fn is_login(line: &str) -> bool {
+    line.split_whitespace().next().is_some_and(|word| word.eq_ignore_ascii_case("login") || word.eq_ignore_ascii_case("token"))
+}
+
+/// Check a replica's `LOGIN <user> <password>` or `TOKEN <token>` line: with accounts, it must be
+/// an admin's. Without any, every replica is let in, as every session is.
+fn check_login(db: &SharedDb, line: &str) -> Result<(), DatabaseError> {
+    let mut db = db.write().unwrap();
+    auth::load(&mut db)?;
+    tokens::load(&mut db)?;
+    if !auth::required(&db) {
+        return Ok(());
+    }
+    let (user, roles) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [login, name, password] if login.eq_ignore_ascii_case("login") => {
+            auth::check_password(&db, name, password)?;
+            (name.to_string(), auth::roles(&db, name))
+        }
+        [token, secret] if token.eq_ignore_ascii_case("token") => {
+            let name = tokens::authenticate(&db, secret)?;
+            let user = tokens::user(&db, &name).ok_or(DatabaseError::InvalidToken)?;
+            (user, tokens::roles(&db, &name)?)
+        }
+        _ => return Err(DatabaseError::AuthenticationRequired),
+    };
+    privileges::check(&db, &user, &roles, &Needs::Admin)
+}
+
+/// Tail the archive: skip what the replica has, then forward complete lines as they appear.
+async fn ship_archive(
+    archive_file: &str,
+    lsn: u64,
+    writer: &mut (impl AsyncWriteExt + Unpin),
+    peer: SocketAddr,
+    metrics: &ShippingMetrics,
+) -> std::io::Result<()> {
+    let file = loop {
+        match tokio::fs::File::open(archive_file).await {
+            Ok(file) => break file,
+            Err(_) => tokio::time::sleep(POLL_INTERVAL).await,
+        }
+    };
+    let mut archive = BufReader::new(file);
+    let mut line = String::new();
+    let mut read: u64 = 0;
+    let mut last_sent = Instant::now();
+    loop {
+        if archive.read_line(&mut line).await? == 0 || !line.ends_with('\n') {
+            if read >= lsn && last_sent.elapsed() >= HEARTBEAT_INTERVAL {
+                send(writer, &json!({ "type": "heartbeat", "lsn": read })).await?;
+                last_sent = Instant::now();
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+            continue;
+        }
+        read += 1;
+        if read > lsn {
+            let op = encryption::open(line.trim_end()).map_err(std::io::Error::other)?;
+            let message = json!({ "type": "entry", "lsn": read, "op": op });
+            send(writer, &message).await?;
+            metrics.update(peer, |lag| {
+                lag.sent_lsn = read;
+                lag.entries_sent += 1;
+                lag.bytes_sent += line.len() as u64;
+            });
+            last_sent = Instant::now();
+        }
+        line.clear();
+    }
+}
+
+/// Load every stored table and encode the full database with the LSN it reflects. The database
+/// is only locked to take its tables as they are (see `Tables::snapshot`); encoding them, the
+/// slow part, goes on while other sessions write.
+fn snapshot(db: &SharedDb) -> Result<(u64, Value), DatabaseError> {
+    let (lsn, tables) = {
+        let mut db = db.write().unwrap();
+        db.load_all_tables()?;
+        (db.current_lsn(), db.tables.snapshot())
+    };
+    let tables: Map<String, Value> = tables
+        .into_iter()
+        .map(|(name, table)| {
+            let rows: Map<String, Value> = table
+                .rows
+                .iter()
+                .map(|(row_id, row)| (row_id.clone(), json!(table::row_to_text(row))))
+                .collect();
+            (name, json!({ "columns": table.column_declarations(), "rows": rows }))
+        })
+        .collect();
+    Ok((lsn, json!({ "type": "snapshot", "lsn": lsn, "tables": tables })))
+}
+
+/// **Replica**
+/// Follows a primary, applying its WAL entries through the normal write path so the replica
+/// logs and persists them like local writes. After a dropped connection it reconnects and resumes
+/// from the last applied LSN; after a restart it starts over from a fresh snapshot.
+pub struct Replica {
+    primary: String,
+    db: SharedDb,
+    status: Arc<ReplicaStatus>,
+    credentials: Option<Credentials>,
+}
+
+/// How a replica logs in to a primary that has accounts: an admin's password or API token.
+#[derive(Clone)]
+pub enum Credentials {
+    Password { user: String, password: String },
+    Token(String),
+}
+
+impl Replica {
+    pub fn new(primary: &str, db: SharedDb) -> Self {
+        Replica { primary: primary.to_string(), db, status: Arc::default(), credentials: None }
+    }
+
+    /// Log in to the primary with `credentials` before asking for its WAL.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Freshness of this replica, for sessions that decide whether it may serve a read.
+    pub fn status(&self) -> Arc<ReplicaStatus> {
+        Arc::clone(&self.status)
+    }
+
+    /// Follow the primary forever.
+    pub async fn run(mut self) {
+        loop {
+            if let Err(e) = self.follow().await {
+                error!("Replication from {} failed: {}", self.primary, e);
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+
+    async fn follow(&mut self) -> std::io::Result<()> {
+        let stream = TcpStream::connect(&self.primary).await?;
+        let applied = self.status.applied_lsn();
+        info!(primary = self.primary, lsn = applied; "Replicating from primary");
+        let (reader, mut writer) = stream.into_split();
+        match &self.credentials {
+            Some(Credentials::Password { user, password }) => writer.write_all(format!("LOGIN {} {}\n", user, password).as_bytes()).await?,
+            Some(Credentials::Token(token)) => writer.write_all(format!("TOKEN {}\n", token).as_bytes()).await?,
+            None => {}
+        }
+        writer.write_all(format!("REPLICATE {}\n", applied).as_bytes()).await?;
+
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let message: Value = serde_json::from_str(&line).map_err(std::io::Error::other)?;
+            let lsn = message["lsn"].as_u64().unwrap_or_default();
+            if message["type"] == "error" {
+                return Err(std::io::Error::other(message["message"].as_str().unwrap_or_default().to_string()));
+            }
+            if message["type"] == "heartbeat" {
+                if lsn == self.status.applied_lsn() {
+                    *self.status.caught_up_at.lock().unwrap() = Some(Instant::now());
+                }
+                writer.write_all(format!("ACK {}\n", self.status.applied_lsn()).as_bytes()).await?;
+                continue;
+            }
+            let db = SharedDb::clone(&self.db);
+            let applied = tokio::task::spawn_blocking(move || {
+                let mut db = db.write().unwrap();
+                match message["type"].as_str() {
+                    Some("snapshot") => restore_snapshot(&mut db, &message["tables"]),
+                    Some("entry") => db.apply_wal_entry(message["op"].as_str().unwrap_or_default()),
+                    _ => Err(DatabaseError::InvalidWalEntry(line)),
+                }
+            })
+            .await
+            .map_err(std::io::Error::other)?;
+            if let Err(e) = applied {
+                // Stop here so the entry is requested again instead of being skipped.
+                return Err(std::io::Error::other(e));
+            }
+            self.status.applied_lsn.store(lsn, Ordering::SeqCst);
+            writer.write_all(format!("ACK {}\n", lsn).as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// How far one connected replica is behind, as seen by the primary.
+#[derive(Debug, Clone)]
+pub struct ReplicaLag {
+    pub connected_at: Instant,
+    pub sent_lsn: u64,
+    pub acked_lsn: u64,
+    pub entries_sent: u64,
+    pub bytes_sent: u64,
+    pub snapshots_sent: u64,
+    // Last time the replica acknowledged everything committed so far.
+    pub caught_up_at: Instant,
+}
+
+/// WAL shipping statistics for every replica connected to this primary.
+#[derive(Debug, Default)]
+pub struct ShippingMetrics {
+    replicas: Mutex<HashMap<SocketAddr, ReplicaLag>>,
+}
+
+impl ShippingMetrics {
+    fn connected(&self, peer: SocketAddr) {
+        let now = Instant::now();
+        let lag = ReplicaLag {
+            connected_at: now,
+            sent_lsn: 0,
+            acked_lsn: 0,
+            entries_sent: 0,
+            bytes_sent: 0,
+            snapshots_sent: 0,
+            caught_up_at: now,
+        };
+        self.replicas.lock().unwrap().insert(peer, lag);
+    }
+
+    fn disconnected(&self, peer: SocketAddr) {
+        self.replicas.lock().unwrap().remove(&peer);
+    }
+
+    fn update(&self, peer: SocketAddr, change: impl FnOnce(&mut ReplicaLag)) {
+        if let Some(lag) = self.replicas.lock().unwrap().get_mut(&peer) {
+            change(lag);
+        }
+    }
+
+    /// Per-replica lag against `committed_lsn`, the last LSN handed to the WAL archive.
+    /// `seconds_behind` is how long ago the replica last had everything committed (0 if it has now).
+    pub fn report(&self, committed_lsn: u64) -> Value {
+        let replicas = self.replicas.lock().unwrap();
+        let mut report: Vec<Value> = replicas
+            .iter()
+            .map(|(peer, lag)| {
+                let lsn_lag = committed_lsn.saturating_sub(lag.acked_lsn);
+                let seconds_behind = if lsn_lag == 0 { 0.0 } else { lag.caught_up_at.elapsed().as_secs_f64() };
+                json!({
+                    "replica": peer.to_string(),
+                    "connected_secs": lag.connected_at.elapsed().as_secs(),
+                    "sent_lsn": lag.sent_lsn,
+                    "acked_lsn": lag.acked_lsn,
+                    "lsn_lag": lsn_lag,
+                    "seconds_behind": seconds_behind,
+                    "entries_sent": lag.entries_sent,
+                    "bytes_sent": lag.bytes_sent,
+                    "snapshots_sent": lag.snapshots_sent,
+                })
+            })
+            .collect();
+        report.sort_by(|a, b| a["replica"].as_str().cmp(&b["replica"].as_str()));
+        Value::Array(report)
+    }
+}
+
+/// What a replica knows about its own freshness, shared with the sessions serving its reads.
+#[derive(Debug, Default)]
+pub struct ReplicaStatus {
+    applied_lsn: AtomicU64,
+    // Last time the replica had applied everything the primary had committed.
+    caught_up_at: Mutex<Option<Instant>>,
+}
+
+impl ReplicaStatus {
+    pub fn applied_lsn(&self) -> u64 {
+        self.applied_lsn.load(Ordering::SeqCst)
+    }
+
+    /// How far behind the primary's committed WAL this replica may be, or `None` if it never caught up.
+    pub fn staleness(&self) -> Option<Duration> {
+        self.caught_up_at.lock().unwrap().map(|at| at.elapsed())
+    }
+
+    /// Whether this replica may serve a read with the given consistency; `Err` explains why not.
+    pub fn check(&self, consistency: &ReadConsistency) -> Result<(), String> {
+        match *consistency {
+            ReadConsistency::Any => Ok(()),
+            ReadConsistency::Leader => Err("this node is a replica".to_string()),
+            ReadConsistency::BoundedStaleness(bound) => match self.staleness() {
+                Some(staleness) if staleness <= bound => Ok(()),
+                Some(staleness) => Err(format!("replica is {}ms behind", staleness.as_millis())),
+                None => Err("replica has not caught up yet".to_string()),
+            },
+            ReadConsistency::AtLeast(lsn) if self.applied_lsn() >= lsn => Ok(()),
+            ReadConsistency::AtLeast(lsn) => {
+                Err(format!("replica is at LSN {}, read needs {}", self.applied_lsn(), lsn))
+            }
+        }
+    }
+}
+
+/// Freshness a read asks for, given as `READ <mode> <command>`:
+/// - `LEADER`: only the primary may answer.
+/// - `ANY`: any node, however far behind.
+/// - `STALE <ms>`: a replica that was fully caught up at most `ms` milliseconds ago.
+/// - `AFTER <lsn>`: a node that has applied `lsn` (read-your-writes, with the LSN from `LSN`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadConsistency {
+    Leader,
+    Any,
+    BoundedStaleness(Duration),
+    AtLeast(u64),
+}
+
+impl ReadConsistency {
+    /// Parse the mode at the start of `parts`, returning it with the number of tokens it used.
+    pub fn parse(parts: &[&str]) -> Option<(Self, usize)> {
+        let mode = parts.first()?.to_uppercase();
+        let arg = || parts.get(1)?.parse::<u64>().ok();
+        match mode.as_str() {
+            "LEADER" => Some((ReadConsistency::Leader, 1)),
+            "ANY" => Some((ReadConsistency::Any, 1)),
+            "STALE" => Some((ReadConsistency::BoundedStaleness(Duration::from_millis(arg()?)), 2)),
+            "AFTER" => Some((ReadConsistency::AtLeast(arg()?), 2)),
+            _ => None,
+        }
+    }
+}
+
+/// Replace the replica's tables with the snapshot and persist them. Tables the snapshot does not
+/// hold are dropped, from storage too, as `Database::restore` does. The snapshot is read in full
+/// before anything changes, so a damaged one leaves the replica as it was.
+fn restore_snapshot(db: &mut Database, tables: &Value) -> Result<(), DatabaseError> {
+    let invalid = || DatabaseError::InvalidWalEntry("snapshot".to_string());
+    let tables = tables.as_object().ok_or_else(invalid)?;
+    let mut restored = Vec::with_capacity(tables.len());
+    for (name, contents) in tables {
+        db::check_table_name(name)?;
+        let mut table = Table::new();
+        for column in contents["columns"].as_array().ok_or_else(invalid)? {
+            table.declare_column(column.as_str().ok_or_else(invalid)?)?;
+        }
+        let rows: HashMap<String, HashMap<String, String>> =
+            serde_json::from_value(contents["rows"].clone()).map_err(|_| invalid())?;
+        for (row_id, data) in rows {
+            table.insert_row(&row_id, data)?;
+        }
+        restored.push((name.clone(), table));
+    }
+    let mut stale: Vec<String> = db.storage.get_mut().table_names()?;
+    stale.extend(db.tables.keys().cloned());
+    stale.retain(|name| !tables.contains_key(name));
+    for name in &stale {
+        db.storage.get_mut().drop_table(name)?;
+    }
+    db.tables.clear();
+    for (name, table) in restored {
+        db.tables.insert(name.clone(), table);
+        db.persist_table(&name)?;
+    }
+    info!(tables = tables.len(); "Restored snapshot");
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use crate::commands::changes::ChangeEvent;
+use crate::commands::db::DatabaseError;
+use crate::commands::executor::{self, Response};
+use crate::format::{self, OutputFormat};
+use crate::quotas::Quotas;
+use crate::session::{Catalog, Session};
+use crate::sharding::ShardRouter;
+use crate::storage::json;
+use crate::table::table::RenderOptions;
+use log::{error, info};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// Address the server binds to when `RUSTDB_LISTEN` is not set.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+/// **TCP server**
+/// Clients send one command per line using the REPL syntax (e.g. `GET users 1`) and receive one
+/// JSON line per command: `{"status":"ok","data":...}` or `{"status":"error","message":"..."}`.
+/// Every client gets its own tokio task and `Session` (current database, open transaction),
+/// so idle connections cost no OS thread. A server bound with `bind_router` forwards commands
+/// to a `ShardRouter` instead. `with_quotas` limits how many connections and commands a second
+/// each client gets (see `Quotas`).
+pub struct Server {
+    backend: Backend,
+    listener: TcpListener,
+    quotas: Arc<Quotas>,
+}
+
+/// What the server runs client commands against.
+enum Backend {
+    Sessions(Arc<Catalog>),
+    Shards(Arc<ShardRouter>),
+}
+
+/// Per-connection handler for one command line.
+enum Handler {
+    Session(Session),
+    Router(ShardRouter),
+}
+
+impl Handler {
+    fn handle(&mut self, line: &str) -> Response {
+        match self {
+            Handler::Session(session) => session.handle(line),
+            Handler::Router(router) => router.execute(line),
+        }
+    }
+}
+
+impl Server {
+    pub async fn bind(addr: &str, catalog: Arc<Catalog>) -> std::io::Result<Self> {
+        Self::bind_backend(addr, Backend::Sessions(catalog)).await
+    }
+
+    /// Serve a sharded database: every command goes through `router`.
+    pub async fn bind_router(addr: &str, router: Arc<ShardRouter>) -> std::io::Result<Self> {
+        Self::bind_backend(addr, Backend::Shards(router)).await
+    }
+
+    async fn bind_backend(addr: &str, backend: Backend) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(addr:% = listener.local_addr()?; "RustDB server listening");
+        Ok(Server { backend, listener, quotas: Arc::default() })
+    }
+
+    /// The address clients connect to, e.g. to find the port after binding port 0.
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Hold every client to `quotas`.
+    pub fn with_quotas(mut self, quotas: Arc<Quotas>) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Accept clients forever, handling each one on its own task.
+    pub async fn run(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((mut stream, peer)) => {
+                    let slot = match self.quotas.connect(peer.ip()) {
+                        Ok(slot) => slot,
+                        Err(e) => {
+                            info!("Client {} refused: {}", peer, e);
+                            tokio::spawn(async move {
+                                let _ = stream.write_all(format!("{}\n", Response::Error(e.to_string()).to_line()).as_bytes()).await;
+                            });
+                            continue;
+                        }
+                    };
+                    let handler = match &self.backend {
+                        Backend::Sessions(catalog) => Handler::Session(Session::new(Arc::clone(catalog))),
+                        Backend::Shards(router) => Handler::Router(router.session()),
+                    };
+                    let quotas = Arc::clone(&self.quotas);
+                    tokio::spawn(async move {
+                        info!("Client {} connected.", peer);
+                        if let Err(e) = handle_client(stream, peer, handler, &quotas).await {
+                            error!("Client {} error: {}", peer, e);
+                        }
+                        drop(slot);
+                        info!("Client {} disconnected.", peer);
+                    });
+                }
+                Err(e) => error!("Failed to accept connection: {}", e),
+            }
+        }
+    }
+}
+
+async fn handle_client(stream: TcpStream, peer: SocketAddr, mut handler: Handler, quotas: &Quotas) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Checked before the command goes near the database lock, so a client over its rate
+        // only waits on itself.
+        let client = match &handler {
+            Handler::Session(session) => session.token().map(|token| format!("token '{}'", token)),
+            Handler::Router(_) => None,
+        };
+        if let Err(e) = quotas.check_rate(&client.unwrap_or_else(|| peer.ip().to_string())) {
+            writer.write_all(format!("{}\n", Response::Error(e.to_string()).to_line()).as_bytes()).await?;
+            continue;
+        }
+        let streamed = subscribe_target(&line).map(str::to_string).or_else(|| export_request(&line).map(|(name, _)| name));
+        if let (Handler::Session(session), Some(name)) = (&handler, streamed) {
+            // Streamed commands never reach `Session::handle`, so they are checked here. They send
+            // every row, so row policies cannot be applied to them.
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if let Err(e) = session.check_access(&parts).and_then(|()| session.check_unlimited(&name)) {
+                writer.write_all(format!("{}\n", Response::Error(e.to_string()).to_line()).as_bytes()).await?;
+                continue;
+            }
+        }
+        if let (Handler::Session(session), Some(table)) = (&handler, subscribe_target(&line)) {
+            let changes = session.subscribe_changes(table);
+            let ack = Response::Ok(json!({ "subscribed": table }));
+            writer.write_all(format!("{}\n", ack.to_line()).as_bytes()).await?;
+            return stream_changes(changes, lines, writer).await;
+        }
+        if let (Handler::Session(session), Some((name, condition))) = (&handler, export_request(&line)) {
+            stream_export(session, name, condition, &mut writer).await?;
+            continue;
+        }
+        // Commands take the database lock and may touch storage, so keep them off the async workers.
+        let (returned, response) = tokio::task::spawn_blocking(move || {
+            let (command, format) = format::split_flag(&line);
+            let response = with_format(handler.handle(command), format);
+            (handler, response)
+        })
+        .await
+        .map_err(std::io::Error::other)?;
+        handler = returned;
+        writer.write_all(format!("{}\n", response.to_line()).as_bytes()).await?;
+        if let Response::Exit = response {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// A command's trailing `FORMAT csv|table` keeps the JSON line, with rows as CSV or grid text
+/// in its `data`.
+fn with_format(response: Response, format: Option<OutputFormat>) -> Response {
+    match (response, format) {
+        (Response::Ok(data), Some(format)) => match format::render_data(&data, format, &RenderOptions::default()) {
+            Some(text) => Response::Ok(json!(text)),
+            None => Response::Ok(data),
+        },
+        (response, _) => response,
+    }
+}
+
+/// The table of a `SUBSCRIBE <table>` line.
+fn subscribe_target(line: &str) -> Option<&str> {
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [keyword, table] if keyword.eq_ignore_ascii_case("subscribe") => Some(table),
+        _ => None,
+    }
+}
+
+/// The table or view, and condition, of an `EXPORT <name> [WHERE <condition>]` line: an export
+/// without a file, streamed to the connection.
+fn export_request(line: &str) -> Option<(String, Option<String>)> {
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [keyword, name] if keyword.eq_ignore_ascii_case("export") => Some((name.to_string(), None)),
+        [keyword, name, filter, ref condition @ ..]
+            if keyword.eq_ignore_ascii_case("export") && filter.eq_ignore_ascii_case("where") && (3..=4).contains(&condition.len()) =>
+        {
+            Some((name.to_string(), Some(condition.join(" "))))
+        }
+        _ => None,
+    }
+}
+
+/// Send the rows of an export as JSON Lines, the `{"columns": [...]}` line first, then one JSON
+/// line with the number of rows (or the error). The export runs on a blocking thread and hands
+/// over what it writes a chunk at a time; the bounded channel holds it back while the client is
+/// slow to read, so a large table is never buffered whole.
+async fn stream_export(
+    session: &Session,
+    name: String,
+    condition: Option<String>,
+    writer: &mut OwnedWriteHalf,
+) -> std::io::Result<()> {
+    let (sender, mut chunks) = mpsc::channel(16);
+    let db = session.db();
+    let export = tokio::task::spawn_blocking(move || -> Result<usize, DatabaseError> {
+        // Loading takes the exclusive lock; the rows are written under the shared one.
+        executor::load_source(&mut db.write().unwrap(), &name)?;
+        let writer = std::io::BufWriter::new(ChunkSender(sender));
+        db.read().unwrap().export_rows(&name, condition.as_deref(), writer, json::Layout::Lines, "connection")
+    });
+    while let Some(chunk) = chunks.recv().await {
+        writer.write_all(&chunk).await?;
+    }
+    let response = match export.await.map_err(std::io::Error::other)? {
+        Ok(rows) => Response::Ok(json!({ "exported": rows })),
+        Err(e) => Response::Error(e.to_string()),
+    };
+    writer.write_all(format!("{}\n", response.to_line()).as_bytes()).await
+}
+
+/// The write side of an export's channel; fails once the connection is gone.
+struct ChunkSender(mpsc::Sender<Vec<u8>>);
+
+impl std::io::Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.blocking_send(buf.to_vec()).map_err(|_| std::io::ErrorKind::BrokenPipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Send one JSON line per committed change until the client disconnects or sends `EXIT`.
+async fn stream_changes(
+    mut events: UnboundedReceiver<ChangeEvent>,
+    mut lines: tokio::io::Lines<BufReader<tokio::net::tcp::OwnedReadHalf>>,
+    mut writer: OwnedWriteHalf,
+) -> std::io::Result<()> {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => {
+                    let line = Response::Ok(event.to_json()).to_line();
+                    writer.write_all(format!("{}\n", line).as_bytes()).await?;
+                }
+                None => return Ok(()),
+            },
+            line = lines.next_line() => match line?.map(|l| l.trim().to_lowercase()).as_deref() {
+                None => return Ok(()),
+                Some("exit" | "quit") => {
+                    writer.write_all(format!("{}\n", Response::Exit.to_line()).as_bytes()).await?;
+                    return Ok(());
+                }
+                Some(_) => {
+                    let error = Response::Error("Subscribed: only EXIT is accepted.".to_string());
+                    writer.write_all(format!("{}\n", error.to_line()).as_bytes()).await?;
+                }
+            },
+        }
+    }
+}
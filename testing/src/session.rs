@@ -0,0 +1,655 @@
+use crate::auth;
+use crate::commands::backups::BackupSchedule;
+use crate::commands::changes::ChangeEvent;
+use crate::commands::db::{Database, DatabaseError};
+use crate::commands::executor::{self, Response};
+use crate::commands::sweeper::Sweeper;
+use crate::commands::walengine::{WalEngine, WalEngineConfig, WalEngineHandle};
+use crate::policies::{self, Limits};
+use crate::privileges::{self, Needs, Privilege};
+use crate::replication::{ReadConsistency, ReplicaStatus, ShippingMetrics};
+use crate::storage::{backup, keyring};
+use crate::storage::csv::CsvStorage;
+use crate::statistics;
+use crate::tokens;
+use log::{error, info};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Name of the database every session starts in.
+pub const DEFAULT_DATABASE: &str = "main";
+
+/// Directory holding the files of databases created with `CREATE DATABASE`.
+const DATABASES_DIR: &str = "databases";
+
+/// How often databases created with `CREATE DATABASE` delete their expired rows.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+pub type SharedDb = Arc<RwLock<Database>>;
+
+/// All databases served by this process, by name.
+/// Each database has its own lock, so sessions working in different databases never block each other.
+pub struct Catalog {
+    databases: RwLock<HashMap<String, SharedDb>>,
+    wal_config: WalEngineConfig,
+    // Previous versions of each table file the databases created later keep.
+    generations: usize,
+    // Set on replicas: sessions reject writes and check reads against this status.
+    replica: Option<Arc<ReplicaStatus>>,
+    // Set on primaries: what `REPLICATION` reports about connected replicas.
+    shipping: Option<Arc<ShippingMetrics>>,
+    // The WalEngines started by `start_wal_engine`, which `shutdown` stops.
+    wal_engines: Mutex<Vec<WalEngineHandle>>,
+}
+
+impl Catalog {
+    /// Create a catalog whose default database is `main`.
+    /// Databases created later get their own WalEngine task cycling as `wal_config` says and a Sweeper,
+    /// so `create_database` must be called from within the tokio runtime.
+    /// Fails if the user accounts, roles or tokens are stored but cannot be loaded, since sessions
+    /// would otherwise be let in without them.
+    pub fn new(main: SharedDb, wal_config: WalEngineConfig) -> Result<Self, DatabaseError> {
+        {
+            let mut db = main.write().unwrap();
+            auth::load(&mut db).and_then(|()| privileges::load(&mut db)).and_then(|()| tokens::load(&mut db))?;
+            if let Err(e) = statistics::load(&mut db) {
+                error!("Failed to load the table statistics: {}", e);
+            }
+        }
+        let mut databases = HashMap::new();
+        databases.insert(DEFAULT_DATABASE.to_string(), main);
+        Ok(Catalog {
+            databases: RwLock::new(databases),
+            wal_config,
+            generations: 0,
+            replica: None,
+            shipping: None,
+            wal_engines: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Serve a replica: sessions reject every write, since the data only changes through
+    /// replication, and `READ` checks its freshness against `status`.
+    pub fn with_replica(mut self, status: Arc<ReplicaStatus>) -> Self {
+        self.replica = Some(status);
+        self
+    }
+
+    /// Keep `generations` previous versions of every table file in the databases created later
+    /// (see `CsvStorage::keep_generations`).
+    pub fn with_generations(mut self, generations: usize) -> Self {
+        self.generations = generations;
+        self
+    }
+
+    /// Serve a primary whose WAL shipping to replicas is tracked in `metrics`.
+    pub fn with_shipping_metrics(mut self, metrics: Arc<ShippingMetrics>) -> Self {
+        self.shipping = Some(metrics);
+        self
+    }
+
+    /// `REPLICATION`: this node's role and how far behind its replicas (or itself) are.
+    /// Lag is measured on the `main` database, the only one that is replicated.
+    pub fn replication_status(&self) -> serde_json::Value {
+        if let Some(status) = &self.replica {
+            let seconds_behind = status.staleness().map(|s| s.as_secs_f64());
+            return json!({
+                "role": "replica",
+                "applied_lsn": status.applied_lsn(),
+                "seconds_behind": seconds_behind,
+            });
+        }
+        let committed_lsn = self.get(DEFAULT_DATABASE).map(|db| db.read().unwrap().wal_lsn).unwrap_or_default();
+        let replicas = self.shipping.as_ref().map(|m| m.report(committed_lsn)).unwrap_or_else(|| json!([]));
+        json!({ "role": "primary", "committed_lsn": committed_lsn, "replicas": replicas })
+    }
+
+    pub fn get(&self, name: &str) -> Option<SharedDb> {
+        self.databases.read().unwrap().get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.databases.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Create a database stored under `databases/<name>/`, replaying its WAL if it existed before.
+    pub fn create_database(&self, name: &str) -> Result<SharedDb, DatabaseError> {
+        self.open_database(name, &format!("{}/{}", DATABASES_DIR, name))
+    }
+
+    /// Register a database `name` stored in `dir`, replaying its WAL if it existed before.
+    pub fn open_database(&self, name: &str, dir: &str) -> Result<SharedDb, DatabaseError> {
+        self.check_new_name(name)?;
+        let mut databases = self.databases.write().unwrap();
+        if databases.contains_key(name) {
+            return Err(DatabaseError::DatabaseAlreadyExists(name.to_string()));
+        }
+
+        let mut database = Database::with_storage(Box::new(CsvStorage::in_dir(dir)?.keep_generations(self.generations)));
+        if let Some(main) = databases.get(DEFAULT_DATABASE) {
+            let main = main.read().unwrap();
+            database.redaction = main.redaction.clone();
+            database.parallel_scan = main.parallel_scan;
+            database.group_commit = main.group_commit;
+            database.table_cache = main.table_cache;
+            database.memory_limit = main.memory_limit;
+            for (table, shards) in main.tables.sharded() {
+                database.tables.set_row_shards(table, shards);
+            }
+        }
+        database.wal_file = format!("{}/wal.log", dir);
+        database.wal_archive_file = format!("{}/wal_archive.log", dir);
+        database.load_wal()?;
+        statistics::load(&mut database)?;
+
+        let db = Arc::new(RwLock::new(database));
+        self.start_wal_engine(Arc::clone(&db), None);
+        Sweeper::new(Arc::clone(&db), EXPIRY_INTERVAL).start();
+        databases.insert(name.to_string(), Arc::clone(&db));
+        info!(database = name, dir; "Database created");
+        Ok(db)
+    }
+
+    /// Fail unless `name` can name a new database: letters, digits, `_` and `-`, and not taken.
+    pub fn check_new_name(&self, name: &str) -> Result<(), DatabaseError> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(DatabaseError::InvalidDatabaseName(name.to_string()));
+        }
+        if self.databases.read().unwrap().contains_key(name) {
+            return Err(DatabaseError::DatabaseAlreadyExists(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Start a WalEngine for `db` that `shutdown` stops, taking `backups` if given. Must be called
+    /// from within the tokio runtime.
+    pub fn start_wal_engine(&self, db: SharedDb, backups: Option<BackupSchedule>) {
+        let mut wal_engine = WalEngine::new(db, self.wal_config);
+        if let Some(schedule) = backups {
+            wal_engine = wal_engine.with_backups(schedule);
+        }
+        self.wal_engines.lock().unwrap().push(wal_engine.start());
+    }
+
+    /// Final flush before the process exits: stop the WalEngines after their last cycle, then
+    /// persist every database's dirty tables and archive what is left of its WAL. Returns the
+    /// databases that could not be written out, with why.
+    pub async fn shutdown(&self) -> Vec<(String, DatabaseError)> {
+        let wal_engines = std::mem::take(&mut *self.wal_engines.lock().unwrap());
+        for wal_engine in &wal_engines {
+            wal_engine.stop();
+        }
+        for wal_engine in wal_engines {
+            wal_engine.join().await;
+        }
+        let mut failed = Vec::new();
+        for name in self.names() {
+            let Some(db) = self.get(&name) else { continue };
+            let mut db = db.write().unwrap();
+            if let Err(e) = db.checkpoint().and_then(|_| db.commit_wal()) {
+                failed.push((name, e));
+            }
+        }
+        failed
+    }
+}
+
+/// **Per-connection session**
+/// Tracks the client's current database (`USE <db>`) and an optional transaction
+/// (`BEGIN` ... `COMMIT`/`ROLLBACK`). Read-only commands run under a shared lock, so many
+/// sessions can read the same database at once; writes take the database's exclusive lock.
+pub struct Session {
+    catalog: Arc<Catalog>,
+    database: String,
+    db: SharedDb,
+    // Write commands queued since BEGIN; `None` outside a transaction.
+    transaction: Option<Vec<String>>,
+    // The account logged in with `LOGIN` or `TOKEN`, if any.
+    user: Option<String>,
+    // The token the session logged in with, which limits its roles (see `tokens::roles`).
+    token: Option<String>,
+}
+
+impl Session {
+    pub fn new(catalog: Arc<Catalog>) -> Self {
+        let db = catalog.get(DEFAULT_DATABASE).expect("default database is always registered");
+        Session {
+            catalog,
+            database: DEFAULT_DATABASE.to_string(),
+            db,
+            transaction: None,
+            user: None,
+            token: None,
+        }
+    }
+
+    /// Handle one command line: session commands first, everything else goes to the executor.
+    pub fn handle(&mut self, line: &str) -> Response {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let keyword = parts.first().map(|p| p.to_lowercase()).unwrap_or_default();
+        let session_command = matches!(
+            keyword.as_str(),
+            "use" | "databases" | "begin" | "commit" | "rollback" | "lsn" | "read" | "replication" | "backup"
+                | "login" | "token" | "tokens" | "logout" | "whoami" | "users" | "roles" | "grants" | "rotate" | "exit" | "quit"
+        ) || (keyword == "copy" && parts.get(1).is_some_and(|part| part.eq_ignore_ascii_case("database")));
+        if !matches!(keyword.as_str(), "login" | "token" | "help" | "exit" | "quit") {
+            if let Err(e) = self.check_access(&parts) {
+                return Response::Error(e.to_string());
+            }
+        }
+        let user_command = |verb: &str| keyword == verb && parts.get(1).is_some_and(|part| part.eq_ignore_ascii_case("user"));
+        let role_command = |verb: &str| keyword == verb && parts.get(1).is_some_and(|part| part.eq_ignore_ascii_case("role"));
+        let with_password = parts.len() == 5 && parts[3].eq_ignore_ascii_case("password");
+        if self.catalog.replica.is_some() && !session_command && !executor::is_read_only(line) {
+            return Response::Error(DatabaseError::ReadOnlyReplica.to_string());
+        }
+        let result = match (keyword.as_str(), parts.len()) {
+            ("use", 2) => self.use_database(parts[1]),
+            ("create", 3) if parts[1].eq_ignore_ascii_case("database") => {
+                self.catalog.create_database(parts[2]).map(|_| json!(parts[2]))
+            }
+            ("databases", 1) => Ok(json!(self.catalog.names())),
+            ("login", 3) => self.login(parts[1], parts[2]),
+            ("token", 2) => self.log_in_with_token(parts[1]),
+            ("logout", 1) => {
+                self.token = None;
+                Ok(json!(self.user.take()))
+            }
+            ("whoami", 1) => Ok(json!(self.user)),
+            ("users", 1) => auth::users(&mut self.main_db().write().unwrap()).map(|users| json!(users)),
+            _ if user_command("create") && with_password => self.create_user(parts[2], parts[4]),
+            _ if user_command("alter") && with_password => {
+                auth::set_password(&mut self.main_db().write().unwrap(), parts[2], parts[4]).map(|()| json!(parts[2]))
+            }
+            ("alter", 6) if user_command("alter") && parts[3].eq_ignore_ascii_case("set") => {
+                let value = Some(parts[5]).filter(|value| !value.eq_ignore_ascii_case("null"));
+                auth::set_attribute(&mut self.main_db().write().unwrap(), parts[2], parts[4], value).map(|()| json!({ "user": parts[2], parts[4]: value }))
+            }
+            ("drop", 3) if user_command("drop") => auth::drop_user(&mut self.main_db().write().unwrap(), parts[2]).map(|()| json!(parts[2])),
+            ("roles", 1) => privileges::roles(&mut self.main_db().write().unwrap()),
+            ("grants", 1 | 2) => match parts.get(1).copied().or(self.user.as_deref()) {
+                Some(user) => privileges::grants_of(&mut self.main_db().write().unwrap(), user),
+                None => Ok(json!(null)),
+            },
+            ("create", 5 | 7) if is_token(&parts) => self.create_token(&parts),
+            ("revoke", 3) if parts[1].eq_ignore_ascii_case("token") => {
+                tokens::revoke(&mut self.main_db().write().unwrap(), parts[2], self.user.as_deref()).map(|()| json!(parts[2]))
+            }
+            ("tokens", 1 | 2) => {
+                let user = parts.get(1).copied().or(self.user.as_deref().filter(|user| !self.is_admin(user)));
+                tokens::list(&mut self.main_db().write().unwrap(), user)
+            }
+            ("create", 3) if role_command("create") => privileges::create_role(&mut self.main_db().write().unwrap(), parts[2]).map(|()| json!(parts[2])),
+            ("drop", 3) if role_command("drop") => privileges::drop_role(&mut self.main_db().write().unwrap(), parts[2]).map(|()| json!(parts[2])),
+            ("grant", 5) if role_command("grant") && parts[3].eq_ignore_ascii_case("to") => {
+                privileges::set_member(&mut self.main_db().write().unwrap(), parts[2], parts[4], true).map(|()| json!(parts[4]))
+            }
+            ("revoke", 5) if role_command("revoke") && parts[3].eq_ignore_ascii_case("from") => {
+                privileges::set_member(&mut self.main_db().write().unwrap(), parts[2], parts[4], false).map(|()| json!(parts[4]))
+            }
+            ("create", 10 | 11) if is_policy(&parts) => {
+                let condition = parts[7..].join(" ");
+                policies::create(&mut self.main_db().write().unwrap(), parts[5], parts[3], &condition).map(|()| json!(condition))
+            }
+            ("drop", 6) if is_policy(&parts) => {
+                policies::drop(&mut self.main_db().write().unwrap(), parts[5], parts[3]).map(|()| json!({ "role": parts[5], "table": parts[3] }))
+            }
+            ("grant", 6) if parts[2].eq_ignore_ascii_case("on") && parts[4].eq_ignore_ascii_case("to") => self.set_grants(&parts, true),
+            ("revoke", 6) if parts[2].eq_ignore_ascii_case("on") && parts[4].eq_ignore_ascii_case("from") => self.set_grants(&parts, false),
+            ("rename" | "copy", 2..) | ("drop", 3) if self.transaction.is_some() && parts[1].eq_ignore_ascii_case("table") => {
+                Err(DatabaseError::TransactionError(format!("cannot {} a table inside a transaction", keyword)))
+            }
+            ("begin", 1) => self.begin(),
+            ("commit", 1) => self.commit(),
+            ("rollback", 1) => self.rollback(),
+            ("lsn", 1) => Ok(json!(self.lsn())),
+            ("replication", 1) => Ok(self.catalog.replication_status()),
+            ("copy", 3) if parts[1].eq_ignore_ascii_case("database") => self.copy_database(parts[2], None),
+            ("copy", 5) if parts[1].eq_ignore_ascii_case("database") && parts[3].eq_ignore_ascii_case("as") => {
+                self.copy_database(parts[2], Some(parts[4]))
+            }
+            ("rotate", 2) if parts[1].eq_ignore_ascii_case("key") => self.rotate_key(),
+            ("rotate", 3) if parts[1].eq_ignore_ascii_case("passphrase") => keyring::change_passphrase(parts[2]).map(|()| json!("passphrase changed")),
+            ("backup", 2) => Database::backup(&self.db, parts[1]),
+            ("backup", 3) if parts[1].eq_ignore_ascii_case("verify") => backup::verify(parts[2]),
+            ("read", _) => return self.read_with_consistency(&parts[1..]),
+            _ => return self.execute(line),
+        };
+        match result {
+            Ok(data) => Response::Ok(data),
+            Err(e) => Response::Error(e.to_string()),
+        }
+    }
+
+    /// Fail unless the session may run a command made of `parts`: once accounts exist (see
+    /// `auth::required`) it must be logged in and its roles must grant what the command needs
+    /// (see `privileges::needs`), and no command may name a table the database keeps for itself.
+    pub fn check_access(&self, parts: &[&str]) -> Result<(), DatabaseError> {
+        let main = self.main_db();
+        let main = main.read().unwrap();
+        let user = match (&self.user, auth::required(&main)) {
+            (_, false) => None,
+            (Some(user), true) => Some(user),
+            (None, true) => return Err(DatabaseError::AuthenticationRequired),
+        };
+        // `SAVE a,b book.xlsx` names several tables in one word.
+        if let Some(table) = parts.iter().flat_map(|part| part.split(',')).find(|name| auth::is_system_table(name)) {
+            return Err(DatabaseError::SystemTable(table.to_string()));
+        }
+        let Some(user) = user else {
+            return Ok(());
+        };
+        let roles = self.roles(&main, user)?;
+        let needs = privileges::needs(parts);
+        // Otherwise a token limited to some roles could set the password and log in with them all.
+        if self.token.is_some() && matches!(needs, Needs::Account(_)) && !roles.iter().any(|role| role == auth::ADMIN_ROLE) {
+            return Err(DatabaseError::PermissionDenied(format!("a token cannot manage the account '{}'", user)));
+        }
+        privileges::check(&main, user, &roles, &needs)
+    }
+
+    /// The roles the session acts with: its account's, limited to those of the token it logged in
+    /// with. Fails once that token is revoked.
+    fn roles(&self, main: &Database, user: &str) -> Result<Vec<String>, DatabaseError> {
+        match &self.token {
+            Some(token) => tokens::roles(main, token),
+            None => Ok(auth::roles(main, user)),
+        }
+    }
+
+    fn is_admin(&self, user: &str) -> bool {
+        let main = self.main_db();
+        let main = main.read().unwrap();
+        self.roles(&main, user).is_ok_and(|roles| roles.iter().any(|role| role == auth::ADMIN_ROLE))
+    }
+
+    /// `LOGIN <user> <password>`: run the session's commands as that account from now on.
+    fn login(&mut self, name: &str, password: &str) -> Result<serde_json::Value, DatabaseError> {
+        auth::check_password(&self.main_db().read().unwrap(), name, password)?;
+        self.user = Some(name.to_string());
+        self.token = None;
+        Ok(json!({ "user": name }))
+    }
+
+    /// `TOKEN <token>`: run the session's commands as the account of an API token from now on, with
+    /// the token's roles (see `tokens`).
+    fn log_in_with_token(&mut self, token: &str) -> Result<serde_json::Value, DatabaseError> {
+        let main = self.main_db();
+        let mut main = main.write().unwrap();
+        tokens::load(&mut main)?;
+        let name = tokens::authenticate(&main, token)?;
+        let user = tokens::user(&main, &name).ok_or(DatabaseError::InvalidToken)?;
+        self.user = Some(user.clone());
+        self.token = Some(name.clone());
+        Ok(json!({ "user": user, "token": name }))
+    }
+
+    /// `CREATE TOKEN <name> FOR <user> [ROLES <role,...>]`. The token is in the response only.
+    fn create_token(&self, parts: &[&str]) -> Result<serde_json::Value, DatabaseError> {
+        let roles: Option<Vec<&str>> = parts.get(6).map(|roles| roles.split(',').collect());
+        let token = tokens::create(&mut self.main_db().write().unwrap(), parts[2], parts[4], roles.as_deref())?;
+        Ok(json!({ "token": parts[2], "user": parts[4], "roles": roles, "secret": token }))
+    }
+
+    /// Log in as the first admin account, by name, without a password: for the session at the
+    /// prompt or running a script when the database was opened with its passphrase (see
+    /// `storage::keyring`), which unlocks every file anyway. No one is logged in while there are
+    /// no accounts.
+    pub fn log_in_unlocked(&mut self) {
+        let main = self.main_db();
+        let mut main = main.write().unwrap();
+        let admin = auth::users(&mut main).map(|users| users.into_iter().find(|user| auth::roles(&main, user).iter().any(|role| role == auth::ADMIN_ROLE)));
+        match admin {
+            Ok(Some(user)) => {
+                info!(user; "Unlocked with the passphrase; logged in");
+                self.user = Some(user);
+            }
+            Ok(None) => {}
+            Err(e) => error!("Failed to log in with the passphrase: {}", e),
+        }
+    }
+
+    /// `CREATE USER <name> PASSWORD <password>`. Creating the first account turns authentication
+    /// on, so the session that does so is logged in as it rather than locked out.
+    fn create_user(&mut self, name: &str, password: &str) -> Result<serde_json::Value, DatabaseError> {
+        auth::create_user(&mut self.main_db().write().unwrap(), name, password)?;
+        if self.user.is_none() {
+            self.user = Some(name.to_string());
+        }
+        Ok(json!(name))
+    }
+
+    /// `GRANT <privileges> ON <table> TO <role>` or `REVOKE <privileges> ON <table> FROM <role>`.
+    fn set_grants(&self, parts: &[&str], granted: bool) -> Result<serde_json::Value, DatabaseError> {
+        let privileges = Privilege::parse_list(parts[1])?;
+        privileges::set_grants(&mut self.main_db().write().unwrap(), parts[5], &privileges, parts[3], granted)?;
+        Ok(json!({ "role": parts[5], "table": parts[3], "privileges": privileges.iter().map(|privilege| privilege.name()).collect::<Vec<_>>() }))
+    }
+
+    /// The `main` database, which holds the user accounts and roles.
+    fn main_db(&self) -> SharedDb {
+        self.catalog.get(DEFAULT_DATABASE).expect("default database is always registered")
+    }
+
+    fn use_database(&mut self, name: &str) -> Result<serde_json::Value, DatabaseError> {
+        if self.transaction.is_some() {
+            return Err(DatabaseError::TransactionError(
+                "cannot switch database inside a transaction".to_string(),
+            ));
+        }
+        self.db = self
+            .catalog
+            .get(name)
+            .ok_or_else(|| DatabaseError::DatabaseDoesNotExist(name.to_string()))?;
+        self.database = name.to_string();
+        Ok(json!(name))
+    }
+
+    fn begin(&mut self) -> Result<serde_json::Value, DatabaseError> {
+        if self.transaction.is_some() {
+            return Err(DatabaseError::TransactionError("transaction already in progress".to_string()));
+        }
+        self.transaction = Some(Vec::new());
+        Ok(json!("BEGIN"))
+    }
+
+    fn rollback(&mut self) -> Result<serde_json::Value, DatabaseError> {
+        let queued = self
+            .transaction
+            .take()
+            .ok_or_else(|| DatabaseError::TransactionError("no transaction in progress".to_string()))?;
+        Ok(json!({ "discarded": queued.len() }))
+    }
+
+    /// Apply every queued command under one exclusive lock. If any of them fails the
+    /// in-memory state and WAL are restored, so other sessions never see a partial transaction.
+    fn commit(&mut self) -> Result<serde_json::Value, DatabaseError> {
+        let queued = self
+            .transaction
+            .take()
+            .ok_or_else(|| DatabaseError::TransactionError("no transaction in progress".to_string()))?;
+
+        let limits = self.limits()?;
+        let mut db = self.db.write().unwrap();
+        // Load the touched tables first so the snapshot below covers them.
+        let touched: HashSet<&str> = queued.iter().filter_map(|l| executor::table_name(l)).collect();
+        let stored = db.storage.get_mut().table_names()?;
+        for table in touched.iter().filter(|table| stored.iter().any(|name| name == *table)) {
+            db.ensure_table_loaded(table)?;
+        }
+        let tables = db.tables.clone();
+        let wal_len = db.wal.get_mut().len();
+        let operations_since_save = *db.operations_since_save.get_mut();
+        // No threshold saves halfway through the transaction.
+        let save_threshold = std::mem::replace(&mut db.save_threshold, usize::MAX);
+
+        let mut results = Vec::new();
+        let mut failure = None;
+        for line in &queued {
+            let response = match limits.is_empty() {
+                true => executor::execute(&mut db, line),
+                false => policies::execute(&mut db, line, &limits),
+            };
+            match response {
+                Response::Ok(data) => results.push(data),
+                Response::Error(e) => {
+                    failure = Some(format!("'{}' failed: {}", line, e));
+                    break;
+                }
+                Response::Exit => {}
+            }
+        }
+        db.save_threshold = save_threshold;
+
+        let Some(failure) = failure else {
+            return Ok(json!(results));
+        };
+        db.tables = tables;
+        db.wal.get_mut().truncate(wal_len);
+        *db.operations_since_save.get_mut() = operations_since_save;
+        // Some writes (e.g. CREATE TABLE) persist immediately; write the restored tables
+        // back and remove the ones the transaction created.
+        for table in touched {
+            if db.check_table(table) {
+                db.persist_table(table)?;
+            } else {
+                db.storage.get_mut().drop_table(table)?;
+            }
+        }
+        Err(DatabaseError::TransactionError(format!("rolled back, {}", failure)))
+    }
+
+    /// `ROTATE KEY`: seal with a new data key from now on (see `keyring::rotate_key`) and write the
+    /// tables and WAL of every database again with it. Backups and what the LSM engine already
+    /// stored keep the older keys, which stay in the keyring to open them.
+    fn rotate_key(&self) -> Result<serde_json::Value, DatabaseError> {
+        let key = keyring::rotate_key()?;
+        let mut files = 0;
+        for name in self.catalog.names() {
+            if let Some(db) = self.catalog.get(&name) {
+                files += db.write().unwrap().reseal()?;
+            }
+        }
+        info!(key, files; "Data key now seals; files written again with it");
+        Ok(json!({ "key": key, "files": files }))
+    }
+
+    /// `COPY DATABASE <dir> [AS <name>]`: clone the current database into the new data directory
+    /// `dir`. The snapshot is taken under the write lock and written after releasing it, as for
+    /// `BACKUP`, so production writes go on meanwhile. With a name the copy is opened as a database
+    /// of the catalog straight away, ready for `USE <name>`.
+    fn copy_database(&self, dir: &str, name: Option<&str>) -> Result<serde_json::Value, DatabaseError> {
+        if let Some(name) = name {
+            self.catalog.check_new_name(name)?;
+        }
+        let snapshot = self.db.write().unwrap().snapshot()?;
+        snapshot.write_data_dir(dir)?;
+        info!(database = self.database, dir, tables = snapshot.tables.len(), lsn = snapshot.lsn(); "Database copied");
+        if let Some(name) = name {
+            self.catalog.open_database(name, dir)?;
+        }
+        Ok(json!({ "dir": dir, "database": name, "tables": snapshot.tables.len(), "lsn": snapshot.lsn() }))
+    }
+
+    /// The name of the API token the session logged in with, if any.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// The session's current database.
+    pub fn db(&self) -> SharedDb {
+        SharedDb::clone(&self.db)
+    }
+
+    /// Committed changes to `table` in the session's current database.
+    pub fn subscribe_changes(&self, table: &str) -> tokio::sync::mpsc::UnboundedReceiver<ChangeEvent> {
+        self.db.write().unwrap().subscribe_changes(table)
+    }
+
+    /// LSN of the newest write this node has: logged on a primary, applied on a replica.
+    fn lsn(&self) -> u64 {
+        match &self.catalog.replica {
+            Some(status) => status.applied_lsn(),
+            None => self.db.read().unwrap().current_lsn(),
+        }
+    }
+
+    /// `READ <mode> <command>`: run a read-only command if this node is fresh enough for `mode`.
+    /// A primary always is; a replica answers with an error so the client can go to the primary.
+    fn read_with_consistency(&mut self, args: &[&str]) -> Response {
+        let Some((consistency, used)) = ReadConsistency::parse(args) else {
+            return Response::Error(DatabaseError::InvalidConsistency(args.join(" ")).to_string());
+        };
+        let command = args[used..].join(" ");
+        if !executor::is_read_only(&command) {
+            return Response::Error("READ only wraps read-only commands.".to_string());
+        }
+        if let Some(status) = &self.catalog.replica {
+            if let Err(reason) = status.check(&consistency) {
+                return Response::Error(DatabaseError::ConsistencyNotMet(reason).to_string());
+            }
+        }
+        self.execute(&command)
+    }
+
+    fn execute(&mut self, line: &str) -> Response {
+        let limits = match self.limits() {
+            Ok(limits) => limits,
+            Err(e) => return Response::Error(e.to_string()),
+        };
+        if executor::is_read_only(line) {
+            // Reads held to row policies run on a filtered copy, which takes the write lock.
+            if let Some(response) = limits.is_empty().then(|| executor::execute_read(&self.db.read().unwrap(), line)).flatten() {
+                return response;
+            }
+        } else if let Some(queued) = self.transaction.as_mut() {
+            if !matches!(line.split_whitespace().next().map(str::to_lowercase).as_deref(), Some("exit" | "quit")) {
+                queued.push(line.to_string());
+                return Response::Ok(json!({ "queued": queued.len() }));
+            }
+        } else if let Some(response) = limits.is_empty().then(|| executor::execute_rows(&self.db.read().unwrap(), line)).flatten() {
+            // Row writes to a table in memory hold only that table, so other tables stay writable.
+            return response;
+        }
+        match limits.is_empty() {
+            true => executor::execute(&mut self.db.write().unwrap(), line),
+            false => policies::execute(&mut self.db.write().unwrap(), line, &limits),
+        }
+    }
+
+    /// The row policies the logged-in account is held to; none when no one is logged in.
+    fn limits(&self) -> Result<Limits, DatabaseError> {
+        match &self.user {
+            Some(user) => {
+                let main = self.main_db();
+                let main = main.read().unwrap();
+                policies::limits(&main, user, &self.roles(&main, user)?)
+            }
+            None => Ok(Limits::new()),
+        }
+    }
+
+    /// Fail if the logged-in account may only use some rows of `name`. For the commands that stream
+    /// a whole table or view instead of running through `execute` (SUBSCRIBE, EXPORT over TCP).
+    pub fn check_unlimited(&self, name: &str) -> Result<(), DatabaseError> {
+        let limits = self.limits()?;
+        policies::check_unlimited(&self.db.read().unwrap(), &limits, name)
+    }
+}
+
+/// Whether `parts` is `CREATE POLICY ON <table> FOR <role> WHERE <condition>` or
+/// `DROP POLICY ON <table> FOR <role>`.
+fn is_policy(parts: &[&str]) -> bool {
+    let word = |index: usize, expected: &str| parts.get(index).is_some_and(|word| word.eq_ignore_ascii_case(expected));
+    word(1, "policy") && word(2, "on") && word(4, "for") && (parts.len() == 6 || word(6, "where"))
+}
+
+/// Whether `parts` is `CREATE TOKEN <name> FOR <user> [ROLES <role,...>]`.
+fn is_token(parts: &[&str]) -> bool {
+    let word = |index: usize, expected: &str| parts.get(index).is_some_and(|word| word.eq_ignore_ascii_case(expected));
+    word(1, "token") && word(3, "for") && (parts.len() == 5 || word(5, "roles"))
+}
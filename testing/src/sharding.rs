@@ -0,0 +1,514 @@
+use crate::commands::db::{self, Database, DatabaseError};
+use crate::commands::executor::{self, Response};
+use crate::commands::walengine::{WalEngine, WalEngineConfig, WalEngineHandle};
+use crate::replication::ReadConsistency;
+use crate::session::SharedDb;
+use crate::storage::csv::CsvStorage;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+/// Directory holding the files of in-process shards (`local` entries of `RUSTDB_SHARDS`).
+const SHARDS_DIR: &str = "shards";
+
+/// One partition of the data: an in-process database or another RustDB node.
+pub trait Shard: Send + Sync {
+    /// Short name used in log output and error messages.
+    fn name(&self) -> String;
+
+    /// Run one command line (REPL syntax) on this shard.
+    fn execute(&self, line: &str) -> Response;
+
+    /// A handle for one client connection. Remote shards open their own connections,
+    /// so per-connection state such as `USE` stays with that client.
+    fn session(&self) -> Box<dyn Shard>;
+
+    /// Pass a client's `LOGIN`, `TOKEN` or `LOGOUT` line on to the shard's nodes, which check it
+    /// against their own accounts. In-process shards have no accounts, so there is nothing to do.
+    fn log_in(&self, _line: &str) -> Response {
+        Response::Ok(Value::Null)
+    }
+}
+
+/// A shard backed by a `Database` in this process.
+pub struct LocalShard {
+    name: String,
+    db: SharedDb,
+}
+
+impl LocalShard {
+    pub fn new(name: &str, db: SharedDb) -> Self {
+        LocalShard { name: name.to_string(), db }
+    }
+
+    /// A shard whose tables, WAL and archive live under `shards/<index>/`, with its own WalEngine
+    /// task cycling as `wal_config` says (so this must be called from within the tokio runtime),
+    /// which the returned handle stops.
+    pub fn in_dir(index: usize, wal_config: WalEngineConfig) -> Result<(Self, WalEngineHandle), DatabaseError> {
+        let dir = format!("{}/{}", SHARDS_DIR, index);
+        let mut database = Database::with_storage(Box::new(CsvStorage::in_dir(&dir)?));
+        database.wal_file = format!("{}/wal.log", dir);
+        database.wal_archive_file = format!("{}/wal_archive.log", dir);
+        database.load_wal()?;
+        let db = Arc::new(RwLock::new(database));
+        let wal_engine = WalEngine::new(Arc::clone(&db), wal_config).start();
+        Ok((LocalShard::new(&dir, db), wal_engine))
+    }
+}
+
+impl Shard for LocalShard {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn execute(&self, line: &str) -> Response {
+        if let Some(response) = executor::execute_read(&self.db.read().unwrap(), line) {
+            return response;
+        }
+        executor::execute(&mut self.db.write().unwrap(), line)
+    }
+
+    fn session(&self) -> Box<dyn Shard> {
+        Box::new(LocalShard::new(&self.name, SharedDb::clone(&self.db)))
+    }
+}
+
+/// One RustDB node reached over its TCP line protocol.
+/// The connection is opened on first use and reopened after an I/O error.
+struct Node {
+    addr: String,
+    connection: Mutex<Option<(BufReader<TcpStream>, TcpStream)>>,
+}
+
+impl Node {
+    fn new(addr: &str) -> Self {
+        Node { addr: addr.to_string(), connection: Mutex::new(None) }
+    }
+
+    fn request(&self, line: &str) -> std::io::Result<String> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            let stream = TcpStream::connect(&self.addr)?;
+            *connection = Some((BufReader::new(stream.try_clone()?), stream));
+        }
+        let (reader, writer) = connection.as_mut().unwrap();
+        let result = writeln!(writer, "{}", line).and_then(|_| {
+            let mut response = String::new();
+            match reader.read_line(&mut response)? {
+                0 => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "connection closed")),
+                _ => Ok(response),
+            }
+        });
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+
+    /// Run `line` on the node; `Err` when it cannot be reached or answers garbage.
+    fn execute(&self, line: &str) -> Result<Response, String> {
+        let response = self.request(line).map_err(|e| format!("shard {} unavailable: {}", self.addr, e))?;
+        let mut response: Value = serde_json::from_str(&response)
+            .map_err(|e| format!("shard {} sent invalid response: {}", self.addr, e))?;
+        Ok(match response["status"].as_str() {
+            Some("ok") => Response::Ok(response["data"].take()),
+            _ => Response::Error(response["message"].as_str().unwrap_or_default().to_string()),
+        })
+    }
+}
+
+/// A shard served by another RustDB node, optionally with replicas of it.
+/// `READ <mode> ...` commands go to the replicas in turn and fall back to the primary when a
+/// replica is unreachable or not fresh enough; everything else goes to the primary.
+pub struct RemoteShard {
+    primary: Node,
+    replicas: Vec<Node>,
+    next_replica: AtomicUsize,
+    // Replicas only follow `main`; after `USE` of another database reads stay on the primary.
+    replicas_usable: AtomicBool,
+}
+
+impl RemoteShard {
+    pub fn with_replicas(primary: &str, replicas: &[&str]) -> Self {
+        RemoteShard {
+            primary: Node::new(primary),
+            replicas: replicas.iter().map(|addr| Node::new(addr)).collect(),
+            next_replica: AtomicUsize::new(0),
+            replicas_usable: AtomicBool::new(true),
+        }
+    }
+
+    fn on_primary(&self, line: &str) -> Response {
+        self.primary.execute(line).unwrap_or_else(Response::Error)
+    }
+}
+
+impl Shard for RemoteShard {
+    fn name(&self) -> String {
+        self.primary.addr.clone()
+    }
+
+    fn execute(&self, line: &str) -> Response {
+        let keyword = line.split_whitespace().next().map(str::to_lowercase).unwrap_or_default();
+        if keyword == "use" {
+            let response = self.on_primary(line);
+            let usable = self.replicas.iter().all(|r| matches!(r.execute(line), Ok(Response::Ok(_))));
+            self.replicas_usable.store(usable, Ordering::SeqCst);
+            return response;
+        }
+        if keyword != "read" || self.replicas.is_empty() || !self.replicas_usable.load(Ordering::SeqCst) {
+            return self.on_primary(line);
+        }
+        let replica = &self.replicas[self.next_replica.fetch_add(1, Ordering::Relaxed) % self.replicas.len()];
+        match replica.execute(line) {
+            Ok(Response::Error(e)) if e.starts_with("Read consistency not met") => self.on_primary(line),
+            Ok(response) => response,
+            Err(_) => self.on_primary(line),
+        }
+    }
+
+    fn session(&self) -> Box<dyn Shard> {
+        let replicas: Vec<&str> = self.replicas.iter().map(|r| r.addr.as_str()).collect();
+        Box::new(RemoteShard::with_replicas(&self.primary.addr, &replicas))
+    }
+
+    /// Log in on the primary and on every replica, since `READ` commands may go to any of them.
+    fn log_in(&self, line: &str) -> Response {
+        for replica in &self.replicas {
+            match replica.execute(line) {
+                Ok(Response::Error(e)) => return Response::Error(format!("replica {}: {}", replica.addr, e)),
+                Err(e) => return Response::Error(e),
+                Ok(_) => {}
+            }
+        }
+        self.on_primary(line)
+    }
+}
+
+/// FNV-1a: stable across processes and Rust versions, unlike `DefaultHasher`,
+/// so every router sends a row id to the same shard.
+fn hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// **Shard router**
+/// Partitions every table's rows by a hash of `row_id` across its shards:
+/// - `INSERT`, `UPDATE`, `GET` and `DELETE` go to the one shard owning the row.
+/// - `CREATE TABLE`, `ADD COLUMN`, `CREATE DATABASE` and `USE` go to every shard, so each holds
+///   the full schema and every connection sees the same database everywhere.
+/// - `SEARCH`, `PRINT`, `TABLES` and `DATABASES` fan out to every shard and merge the results.
+/// - `READ <mode> <command>` is routed like its command; shards with replicas may answer it there.
+/// - `LOGIN`, `TOKEN` and `LOGOUT` are passed on to every node (see `Shard::log_in`), so nodes with
+///   accounts run a client's commands as the account it logged in with.
+pub struct ShardRouter {
+    shards: Vec<Box<dyn Shard>>,
+    // The in-process shards built by `from_spec`, with their WalEngines, for `shutdown`.
+    local: Mutex<Vec<(String, SharedDb, WalEngineHandle)>>,
+}
+
+impl ShardRouter {
+    pub fn new(shards: Vec<Box<dyn Shard>>) -> Self {
+        assert!(!shards.is_empty(), "a shard router needs at least one shard");
+        ShardRouter { shards, local: Mutex::new(Vec::new()) }
+    }
+
+    /// Build the shards listed in `RUSTDB_SHARDS` style: comma-separated node addresses,
+    /// where `local` stands for an in-process database under `shards/<index>/` and
+    /// `primary+replica+...` adds replicas that serve the shard's `READ` commands.
+    pub fn from_spec(spec: &str, wal_config: WalEngineConfig) -> Result<Self, DatabaseError> {
+        let mut shards: Vec<Box<dyn Shard>> = Vec::new();
+        let mut local = Vec::new();
+        for (index, entry) in spec.split(',').map(str::trim).filter(|e| !e.is_empty()).enumerate() {
+            if entry == "local" {
+                let (shard, wal_engine) = LocalShard::in_dir(index, wal_config)?;
+                local.push((shard.name(), SharedDb::clone(&shard.db), wal_engine));
+                shards.push(Box::new(shard));
+            } else {
+                let mut nodes = entry.split('+');
+                let primary = nodes.next().unwrap_or_default();
+                let replicas: Vec<&str> = nodes.collect();
+                shards.push(Box::new(RemoteShard::with_replicas(primary, &replicas)));
+            }
+        }
+        let router = ShardRouter::new(shards);
+        *router.local.lock().unwrap() = local;
+        Ok(router)
+    }
+
+    /// Final flush of the in-process shards before the process exits, as `Catalog::shutdown`
+    /// does for databases: stop their WalEngines after a last cycle, then persist what is left.
+    /// Returns the shards that could not be written out, with why. Remote shards see to their own.
+    pub async fn shutdown(&self) -> Vec<(String, DatabaseError)> {
+        let local = std::mem::take(&mut *self.local.lock().unwrap());
+        for (_, _, wal_engine) in &local {
+            wal_engine.stop();
+        }
+        let mut failed = Vec::new();
+        for (name, db, wal_engine) in local {
+            wal_engine.join().await;
+            let mut db = db.write().unwrap();
+            if let Err(e) = db.checkpoint().and_then(|_| db.commit_wal()) {
+                failed.push((name, e));
+            }
+        }
+        failed
+    }
+
+    /// A router for one client connection (see `Shard::session`).
+    pub fn session(&self) -> ShardRouter {
+        ShardRouter::new(self.shards.iter().map(|shard| shard.session()).collect())
+    }
+
+    /// The shard owning `row_id`.
+    pub fn shard_for(&self, row_id: &str) -> &dyn Shard {
+        self.shards[(hash(row_id) % self.shards.len() as u64) as usize].as_ref()
+    }
+
+    /// Route one command line and merge the shard responses into one.
+    pub fn execute(&self, line: &str) -> Response {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let keyword = parts.first().map(|p| p.to_lowercase()).unwrap_or_default();
+        match (keyword.as_str(), parts.len()) {
+            ("insert" | "upsert" | "replace", n) if n >= 4 => self.shard_for(parts[2]).execute(line),
+            ("update", 4..) | ("get", 3 | 5) | ("delete", 3..=4) | ("expire", 4) | ("restore", 3) | ("increment" | "decrement", 4..=5) => {
+                self.shard_for(parts[2]).execute(line)
+            }
+            ("create", 3 | 5..=10) | ("rename", 4..=5) | ("add", 4..=11) | ("use", 2) | ("views", 1) => self.broadcast(line),
+            ("drop", 3..=4) | ("alter", 6) | ("truncate", 3) | ("copy", 4..=9) => self.broadcast(line),
+            ("lookup", 3) => self.find_anywhere(line),
+            ("page", 3..) => self.page(parts[2], line),
+            ("distinct", 3..=4) | ("stats", 2) => self.fan_out(&keyword, line),
+            ("search", 5) | ("tables", 1) | ("databases", 1) => self.fan_out(&keyword, line),
+            ("print", 2..=8) => self.print("", &parts),
+            ("delete", 6..=7) | ("purge", 2) => self.fan_out(&keyword, line),
+            ("read", _) => self.route_read(&parts[1..], line),
+            ("login", 3) | ("token", 2) | ("logout", 1) => self.log_in(line),
+            ("exit" | "quit", _) => Response::Exit,
+            ("help", _) => Response::Ok(json!(executor::command_usage())),
+            _ => Response::Error(format!("'{}' is not supported on a sharded database.", line.trim())),
+        }
+    }
+
+    /// Route `READ <mode> <command>` like its inner command, keeping the mode for the shards.
+    fn route_read(&self, args: &[&str], line: &str) -> Response {
+        let Some((consistency, used)) = ReadConsistency::parse(args) else {
+            return Response::Error(DatabaseError::InvalidConsistency(args.join(" ")).to_string());
+        };
+        if let ReadConsistency::AtLeast(_) = consistency {
+            // Every shard numbers its own WAL, so one LSN means nothing across shards.
+            return Response::Error("READ AFTER is not supported on a sharded database.".to_string());
+        }
+        let command = &args[used..];
+        let keyword = command.first().map(|p| p.to_lowercase()).unwrap_or_default();
+        match (keyword.as_str(), command.len()) {
+            ("get", 3 | 5) => self.shard_for(command[2]).execute(line),
+            ("lookup", 3) => self.find_anywhere(line),
+            ("page", 3..) => self.page(command[2], line),
+            ("distinct", 3..=4) | ("stats", 2) => self.fan_out(&keyword, line),
+            ("search", 5) | ("tables", 1) => self.fan_out(&keyword, line),
+            ("print", 2..=8) => self.print(&format!("READ {} ", args[..used].join(" ")), command),
+            _ => Response::Error("READ only wraps read-only commands.".to_string()),
+        }
+    }
+
+    /// Run `line` on every shard at once; results are in shard order.
+    fn run_everywhere(&self, line: &str) -> Vec<(String, Response)> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .shards
+                .iter()
+                .map(|shard| scope.spawn(move || (shard.name(), shard.execute(line))))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+    }
+
+    /// Log in (or out) on every shard at once; fails if any shard refused.
+    fn log_in(&self, line: &str) -> Response {
+        let responses: Vec<(String, Response)> = thread::scope(|scope| {
+            let handles: Vec<_> = self.shards.iter().map(|shard| scope.spawn(move || (shard.name(), shard.log_in(line)))).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut first = None;
+        for (name, response) in responses {
+            match response {
+                Response::Error(e) => return Response::Error(format!("shard {}: {}", name, e)),
+                other => {
+                    first.get_or_insert(other);
+                }
+            }
+        }
+        first.unwrap_or(Response::Ok(Value::Null))
+    }
+
+    /// Apply a schema change everywhere; fails if any shard failed.
+    fn broadcast(&self, line: &str) -> Response {
+        let mut first = None;
+        for (name, response) in self.run_everywhere(line) {
+            match response {
+                Response::Error(e) => return Response::Error(format!("shard {}: {}", name, e)),
+                other => {
+                    first.get_or_insert(other);
+                }
+            }
+        }
+        first.unwrap_or(Response::Ok(Value::Null))
+    }
+
+    /// Ask every shard for a single row that may live on any of them, e.g. by primary key.
+    /// Each shard only enforces its primary key locally, so the first row found is returned.
+    fn find_anywhere(&self, line: &str) -> Response {
+        let mut first_error = None;
+        for (name, response) in self.run_everywhere(line) {
+            match response {
+                Response::Error(e) => {
+                    first_error.get_or_insert(format!("shard {}: {}", name, e));
+                }
+                found => return found,
+            }
+        }
+        Response::Error(first_error.unwrap_or_default())
+    }
+
+    /// Merge the shards' pages. Every shard orders its rows by row_id, so the first `limit` of
+    /// their rows form the page, and there is another page if any shard had more rows.
+    fn page(&self, limit: &str, line: &str) -> Response {
+        let Ok(limit) = limit.parse::<usize>() else {
+            return Response::Error(DatabaseError::InvalidPageSize(limit.to_string()).to_string());
+        };
+        let mut rows = Vec::new();
+        let mut more = false;
+        for (name, response) in self.run_everywhere(line) {
+            match response {
+                Response::Ok(mut data) => {
+                    more |= !data["next"].is_null();
+                    rows.extend(into_array(data["rows"].take()));
+                }
+                Response::Error(e) => return Response::Error(format!("shard {}: {}", name, e)),
+                Response::Exit => {}
+            }
+        }
+        let mut rows = sorted_by_row_id(rows);
+        more |= rows.len() > limit;
+        rows.truncate(limit);
+        let next = rows.last().and_then(|row| row["row_id"].as_str()).filter(|_| more).map(db::page_cursor);
+        Response::Ok(json!({ "rows": rows, "next": next }))
+    }
+
+    /// PRINT on every shard without its LIMIT and OFFSET, which only apply to the merged rows.
+    /// `prefix` is put before the PRINT sent to the shards, e.g. a READ mode.
+    fn print(&self, prefix: &str, parts: &[&str]) -> Response {
+        let Some((columns, options)) = executor::print_options(&parts[2..]) else {
+            return Response::Error(format!("'{}' is not supported on a sharded database.", parts.join(" ")));
+        };
+        let mut line = format!("{}PRINT {}", prefix, parts[1]);
+        if let Some(columns) = columns {
+            line.push_str(&format!(" COLUMNS {}", columns.join(",")));
+        }
+        match self.fan_out("print", &line) {
+            Response::Ok(mut data) => {
+                let rows = into_array(data["rows"].take());
+                data["total"] = json!(rows.len());
+                let page = rows.into_iter().skip(options.offset).take(options.limit.unwrap_or(usize::MAX));
+                data["rows"] = Value::Array(page.collect());
+                Response::Ok(data)
+            }
+            other => other,
+        }
+    }
+
+    fn fan_out(&self, keyword: &str, line: &str) -> Response {
+        let mut results = Vec::new();
+        for (name, response) in self.run_everywhere(line) {
+            match response {
+                Response::Ok(data) => results.push(data),
+                Response::Error(e) => return Response::Error(format!("shard {}: {}", name, e)),
+                Response::Exit => {}
+            }
+        }
+        Response::Ok(match keyword {
+            "tables" | "databases" => json!(strings(results.into_iter().flat_map(into_array))),
+            "delete" | "purge" => json!(results.iter().filter_map(Value::as_u64).sum::<u64>()),
+            "distinct" => merge_distinct(results),
+            "stats" => merge_stats(results),
+            "print" => {
+                let mut columns = BTreeSet::new();
+                let mut rows = Vec::new();
+                for mut data in results {
+                    columns.extend(strings(into_array(data["columns"].take())));
+                    rows.extend(into_array(data["rows"].take()));
+                }
+                json!({ "columns": columns, "rows": sorted_by_row_id(rows) })
+            }
+            _ => Value::Array(sorted_by_row_id(results.into_iter().flat_map(into_array).collect())),
+        })
+    }
+}
+
+/// Combine the shards' DISTINCT lists, adding up the counts of a value when they were asked for.
+/// Numbers sort numerically and NULL comes last, as on a single node.
+fn merge_distinct(results: Vec<Value>) -> Value {
+    let mut counts: Vec<(Value, Option<u64>)> = Vec::new();
+    for item in results.into_iter().flat_map(into_array) {
+        let (value, count) = match item {
+            Value::Object(mut entry) => (entry.remove("value").unwrap_or_default(), entry["count"].as_u64()),
+            value => (value, None),
+        };
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, total)) => *total = total.zip(count).map(|(a, b)| a + b),
+            None => counts.push((value, count)),
+        }
+    }
+    counts.sort_by(|(a, _), (b, _)| match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        _ => (a.is_null(), a.to_string()).cmp(&(b.is_null(), b.to_string())),
+    });
+    let merged = counts.into_iter().map(|(value, count)| match count {
+        Some(count) => json!({ "value": value, "count": count }),
+        None => value,
+    });
+    Value::Array(merged.collect())
+}
+
+/// Add up the shards' STATS. A value can sit on several shards, so the summed cardinality is an
+/// upper bound; the stored size is NULL only if no shard has stored the table.
+fn merge_stats(results: Vec<Value>) -> Value {
+    let sum = |field: &str| results.iter().filter_map(|stats| stats[field].as_u64()).sum::<u64>();
+    let mut columns = serde_json::Map::new();
+    for stats in &results {
+        for (name, column) in stats["columns"].as_object().into_iter().flatten() {
+            let merged = columns.entry(name.clone()).or_insert_with(|| json!({ "non_null": 0, "cardinality": 0 }));
+            for field in ["non_null", "cardinality"] {
+                merged[field] = json!(merged[field].as_u64().unwrap_or(0) + column[field].as_u64().unwrap_or(0));
+            }
+        }
+    }
+    let stored = results.iter().any(|stats| !stats["stored_bytes"].is_null());
+    json!({
+        "rows": sum("rows"),
+        "column_count": columns.len(),
+        "columns": columns,
+        "stored_bytes": stored.then(|| sum("stored_bytes")),
+    })
+}
+
+fn into_array(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(items) => items,
+        _ => Vec::new(),
+    }
+}
+
+/// The distinct strings among `values`, sorted.
+fn strings(values: impl IntoIterator<Item = Value>) -> BTreeSet<String> {
+    values.into_iter().filter_map(|v| v.as_str().map(str::to_string)).collect()
+}
+
+fn sorted_by_row_id(mut rows: Vec<Value>) -> Vec<Value> {
+    rows.sort_by(|a, b| a["row_id"].as_str().cmp(&b["row_id"].as_str()));
+    rows
+}
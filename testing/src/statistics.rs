@@ -0,0 +1,278 @@
+use crate::commands::db::{Database, Result};
+use crate::table::table::{ColumnSpec, Row};
+use crate::table::value::{self, ColumnType, Value};
+use log::info;
+use serde_json::json;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Table holding what `ANALYZE` found: one row per analyzed column (row_id `<table>.<column>`),
+/// with the row, NULL and distinct counts, the most common values and the histogram bounds
+/// (both as JSON arrays of values in their textual form), and when it was analyzed.
+pub const STATISTICS_TABLE: &str = "__statistics";
+
+/// Buckets in a column's histogram.
+const BUCKETS: usize = 10;
+
+/// Most common values kept per column.
+const COMMON_VALUES: usize = 10;
+
+/// **Column statistics**
+/// The distribution of one column's values when it was last analyzed. Values are kept in their
+/// textual form and read back with the column's type. A histogram is equi-depth: `bounds` are the
+/// smallest value, then the largest value of each of up to `BUCKETS` buckets that hold about the
+/// same number of non-NULL values, so a range condition's share of the rows is the share of the
+/// buckets it covers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    pub table: String,
+    pub column: String,
+    /// Rows that were not soft-deleted
+    pub rows: usize,
+    pub nulls: usize,
+    pub distinct: usize,
+    /// The most common non-NULL values with how many rows hold each, most common first
+    pub common: Vec<(String, usize)>,
+    /// Histogram bounds, ascending; empty for bool columns
+    pub bounds: Vec<String>,
+    /// Seconds since the Unix epoch
+    pub analyzed_at: i64,
+}
+
+impl ColumnStatistics {
+    fn from_row(row: &Row) -> Option<ColumnStatistics> {
+        let text = |column: &str| row.get(column).map(Value::to_string);
+        let count = |column: &str| match row.get(column) {
+            Some(Value::Int(n)) => usize::try_from(*n).ok(),
+            _ => None,
+        };
+        Some(ColumnStatistics {
+            table: text("table")?,
+            column: text("column")?,
+            rows: count("rows")?,
+            nulls: count("nulls")?,
+            distinct: count("distinct")?,
+            common: serde_json::from_str(&text("common")?).ok()?,
+            bounds: serde_json::from_str(&text("bounds")?).ok()?,
+            analyzed_at: match row.get("analyzed_at") {
+                Some(Value::Timestamp(secs)) => *secs,
+                _ => 0,
+            },
+        })
+    }
+
+    fn to_row(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("table".to_string(), self.table.clone()),
+            ("column".to_string(), self.column.clone()),
+            ("rows".to_string(), self.rows.to_string()),
+            ("nulls".to_string(), self.nulls.to_string()),
+            ("distinct".to_string(), self.distinct.to_string()),
+            ("common".to_string(), json!(self.common).to_string()),
+            ("bounds".to_string(), json!(self.bounds).to_string()),
+            ("analyzed_at".to_string(), self.analyzed_at.to_string()),
+        ])
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let common: Vec<serde_json::Value> = self.common.iter().map(|(value, count)| json!({ "value": value, "count": count })).collect();
+        json!({
+            "column": self.column,
+            "rows": self.rows,
+            "nulls": self.nulls,
+            "distinct": self.distinct,
+            "common": common,
+            "histogram": self.bounds,
+            "analyzed_at": Value::Timestamp(self.analyzed_at).to_string(),
+        })
+    }
+
+    /// Share of the rows (0 to 1) the estimate says match `<column> <operator> <operand>`, or
+    /// `None` for operators it knows nothing about (the text patterns).
+    fn selectivity(&self, column_type: ColumnType, operator: &str, operand: &str) -> Option<f64> {
+        if self.rows == 0 {
+            return Some(0.0);
+        }
+        let non_null = (self.rows - self.nulls) as f64;
+        let operand = Value::parse_operand(operand, column_type)?;
+        let matching = match operator {
+            "==" => match self.common.iter().find(|(value, _)| same(value, column_type, &operand)) {
+                Some((_, count)) => *count as f64,
+                // Values that are not among the common ones share the remaining rows evenly.
+                None => {
+                    let common_rows: usize = self.common.iter().map(|(_, count)| count).sum();
+                    let others = self.distinct.saturating_sub(self.common.len());
+                    if others == 0 { 0.0 } else { (non_null - common_rows as f64) / others as f64 }
+                }
+            },
+            "<" | "<=" => non_null * self.share_below(column_type, &operand)?,
+            ">" | ">=" => non_null * (1.0 - self.share_below(column_type, &operand)?),
+            _ => return None,
+        };
+        Some((matching / self.rows as f64).clamp(0.0, 1.0))
+    }
+
+    /// Share of the non-NULL values below `operand`, from the histogram.
+    fn share_below(&self, column_type: ColumnType, operand: &Value) -> Option<f64> {
+        let bounds: Vec<Value> = self.bounds.iter().filter_map(|bound| Value::parse(bound, column_type)).collect();
+        let (first, last) = (bounds.first()?, bounds.last()?);
+        if operand.compare(first)? != Ordering::Greater {
+            return Some(0.0);
+        }
+        if operand.compare(last)? == Ordering::Greater {
+            return Some(1.0);
+        }
+        let buckets = (bounds.len() - 1) as f64;
+        let bucket = bounds.windows(2).position(|pair| operand.compare(&pair[1]) != Some(Ordering::Greater))?;
+        // Within its bucket, a number is as far along as it is between the bucket's bounds;
+        // anything else counts as halfway.
+        let within = match (number(&bounds[bucket]), number(&bounds[bucket + 1]), number(operand)) {
+            (Some(low), Some(high), Some(at)) if high > low => (at - low) / (high - low),
+            _ => 0.5,
+        };
+        Some((bucket as f64 + within) / buckets)
+    }
+}
+
+/// Whether stored value `text` is `value` once read as a `column_type`.
+fn same(text: &str, column_type: ColumnType, value: &Value) -> bool {
+    Value::parse(text, column_type).is_some_and(|stored| stored.compare(value) == Some(Ordering::Equal))
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(n) | Value::Timestamp(n) => Some(*n as f64),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// Load the statistics table if it has been stored, so `get` and `selectivity` see it.
+pub fn load(db: &mut Database) -> Result<()> {
+    if !db.check_table(STATISTICS_TABLE) && db.storage.get_mut().table_names()?.iter().any(|name| name == STATISTICS_TABLE) {
+        db.ensure_table_loaded(STATISTICS_TABLE)?;
+    }
+    Ok(())
+}
+
+/// Compute the statistics of every column of `table_name` and store them, replacing those of an
+/// earlier `ANALYZE` of the table.
+pub fn analyze(db: &mut Database, table_name: &str) -> Result<Vec<ColumnStatistics>> {
+    load(db)?;
+    db.ensure_table_loaded(table_name)?;
+    let table = db.get_table(table_name)?;
+    let analyzed_at = value::now();
+    let rows: Vec<&Row> = table.live_rows().map(|(_, row)| row).collect();
+    let mut columns: Vec<&String> = table.columns.iter().collect();
+    columns.sort();
+    let statistics: Vec<ColumnStatistics> = columns
+        .into_iter()
+        .map(|column| {
+            let mut values: Vec<&Value> = rows.iter().filter_map(|row| row.get(column.as_str())).filter(|value| !value.is_null()).collect();
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for value in &values {
+                *counts.entry(value.to_string()).or_default() += 1;
+            }
+            let distinct = counts.len();
+            let mut common: Vec<(String, usize)> = counts.into_iter().filter(|(_, count)| *count > 1).collect();
+            common.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            common.truncate(COMMON_VALUES);
+            let bounds = if table.column_type(column) == ColumnType::Bool || values.is_empty() {
+                Vec::new()
+            } else {
+                values.sort_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal));
+                let buckets = BUCKETS.min(values.len());
+                let mut bounds = vec![values[0].to_string()];
+                bounds.extend((1..=buckets).map(|bucket| values[bucket * values.len() / buckets - 1].to_string()));
+                bounds
+            };
+            ColumnStatistics {
+                table: table_name.to_string(),
+                column: column.clone(),
+                rows: rows.len(),
+                nulls: rows.len() - values.len(),
+                distinct,
+                common,
+                bounds,
+                analyzed_at,
+            }
+        })
+        .collect();
+    drop(table);
+
+    if !db.check_table(STATISTICS_TABLE) {
+        db.create_table(STATISTICS_TABLE)?;
+        let columns = [
+            ("table", ColumnType::Text),
+            ("column", ColumnType::Text),
+            ("rows", ColumnType::Int),
+            ("nulls", ColumnType::Int),
+            ("distinct", ColumnType::Int),
+            ("common", ColumnType::Text),
+            ("bounds", ColumnType::Text),
+            ("analyzed_at", ColumnType::Timestamp),
+        ];
+        for (column, column_type) in columns {
+            db.add_typed_column(STATISTICS_TABLE, column, ColumnSpec { column_type, ..ColumnSpec::default() })?;
+        }
+    }
+    forget_rows(db, table_name)?;
+    for column in &statistics {
+        db.insert_row(STATISTICS_TABLE, &format!("{}.{}", table_name, column.column), column.to_row())?;
+    }
+    db.persist_table(STATISTICS_TABLE)?;
+    info!(table = table_name, columns = statistics.len(); "Table analyzed");
+    Ok(statistics)
+}
+
+/// Forget the statistics of `table_name`, e.g. once it is dropped.
+pub fn forget(db: &mut Database, table_name: &str) -> Result<()> {
+    load(db)?;
+    if forget_rows(db, table_name)? > 0 {
+        db.persist_table(STATISTICS_TABLE)?;
+    }
+    Ok(())
+}
+
+fn forget_rows(db: &mut Database, table_name: &str) -> Result<usize> {
+    let Some(statistics) = db.tables.get(STATISTICS_TABLE) else {
+        return Ok(0);
+    };
+    let table = Value::Text(table_name.to_string());
+    let row_ids: Vec<String> =
+        statistics.rows.iter().filter(|(_, row)| row.get("table") == Some(&table)).map(|(row_id, _)| row_id.clone()).collect();
+    drop(statistics);
+    for row_id in &row_ids {
+        db.delete_row(STATISTICS_TABLE, row_id)?;
+    }
+    Ok(row_ids.len())
+}
+
+/// The statistics of `column` of `table_name`, if it was analyzed. Only sees them once the
+/// statistics table is in memory; see `load`.
+pub fn get(db: &Database, table_name: &str, column: &str) -> Option<ColumnStatistics> {
+    let statistics = db.tables.get(STATISTICS_TABLE)?;
+    let row = statistics.rows.get(&format!("{}.{}", table_name, column))?;
+    ColumnStatistics::from_row(row)
+}
+
+/// The planner's estimate of the share of the rows of `table_name` (0 to 1) that match
+/// `condition` (as in `SEARCH`): from the NULL count for `IS [NOT] NULL`, the common values and
+/// distinct count for `==`, and the histogram for `<`, `<=`, `>` and `>=`. `None` if the column
+/// was never analyzed or the operator is a text pattern.
+pub fn selectivity(db: &Database, table_name: &str, condition: &str) -> Option<f64> {
+    let parts: Vec<&str> = condition.split_whitespace().collect();
+    let statistics = get(db, table_name, parts.first()?)?;
+    let share = |rows: usize| if statistics.rows == 0 { 0.0 } else { rows as f64 / statistics.rows as f64 };
+    match parts[..] {
+        [_, is, null] if is.eq_ignore_ascii_case("is") && null.eq_ignore_ascii_case("null") => Some(share(statistics.nulls)),
+        [_, is, not, null] if is.eq_ignore_ascii_case("is") && not.eq_ignore_ascii_case("not") && null.eq_ignore_ascii_case("null") => {
+            Some(share(statistics.rows - statistics.nulls))
+        }
+        [column, operator, operand] => {
+            let column_type = db.get_table(table_name).ok()?.column_type(column);
+            statistics.selectivity(column_type, operator, operand)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,151 @@
+use super::csv;
+use super::progress::Tracker;
+use crate::commands::db::{DatabaseError, Result};
+use crate::table::table::Table;
+use crate::table::value;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+
+/// Version of the layout `write` produces; `read` refuses any other.
+const FORMAT_VERSION: u64 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const WAL: &str = "wal.log";
+const WAL_ARCHIVE: &str = "wal_archive.log";
+
+/// Where a database's WAL stands, as a dump keeps it.
+#[derive(Debug, Default)]
+pub struct WalPosition {
+    /// LSN of the last archived entry
+    pub lsn: u64,
+    /// Entries not archived yet
+    pub pending: Vec<String>,
+    /// The WAL archive file, one line per archived entry
+    pub archive: String,
+}
+
+/// Write a whole database as one tar archive at `path`: `manifest.json` (format version, when it
+/// was written, the WAL position and every table's column declarations and row count), a
+/// `tables/<name>.csv` per table as CSV storage keeps it, and the WAL as `wal.log` and
+/// `wal_archive.log`. The archive is written next to `path` and then renamed over it, so `path`
+/// never holds half an archive. Returns the manifest.
+pub fn write(path: &str, tables: &[(&String, &Table)], wal: &WalPosition) -> Result<Value> {
+    let to_err = |e: std::io::Error| DatabaseError::FileCreationError(path.to_string(), e.to_string());
+    let manifest = json!({
+        "format": FORMAT_VERSION,
+        "created_at": value::now(),
+        "wal_lsn": wal.lsn,
+        "pending_wal_entries": wal.pending.len(),
+        "tables": tables
+            .iter()
+            .map(|(name, table)| json!({ "name": name, "columns": table.column_declarations(), "rows": table.rows.len() }))
+            .collect::<Vec<_>>(),
+    });
+
+    let partial = format!("{}.partial", path);
+    let file = File::create(&partial).map_err(to_err)?;
+    let mut builder = tar::Builder::new(BufWriter::new(file));
+    append(&mut builder, MANIFEST, manifest.to_string().as_bytes()).map_err(to_err)?;
+    for (name, table) in tables {
+        let mut text = Vec::new();
+        csv::write_to(table, &mut text, &mut Tracker::silent()).map_err(to_err)?;
+        append(&mut builder, &format!("tables/{}.csv", name), &text).map_err(to_err)?;
+    }
+    let pending: String = wal.pending.iter().map(|entry| format!("{}\n", entry)).collect();
+    append(&mut builder, WAL, pending.as_bytes()).map_err(to_err)?;
+    append(&mut builder, WAL_ARCHIVE, wal.archive.as_bytes()).map_err(to_err)?;
+    builder.into_inner().and_then(|mut writer| writer.flush()).map_err(to_err)?;
+    fs::rename(&partial, path).map_err(to_err)?;
+    Ok(manifest)
+}
+
+/// Read an archive `write` produced, checking its format version and that every table its
+/// manifest lists is there, complete. Nothing is applied; see `Database::restore`.
+pub fn read(path: &str) -> Result<(Vec<(String, Table)>, WalPosition)> {
+    let mut entries = read_entries(path, |_| true)?;
+    let manifest = manifest(path, &entries)?;
+    let mut tables = Vec::new();
+    for listed in listed_tables(path, &manifest)? {
+        let name = listed["name"].as_str().ok_or_else(|| malformed(path, "a table without a name".to_string()))?;
+        tables.push((name.to_string(), table(path, &entries, listed)?));
+    }
+    let wal = WalPosition {
+        lsn: manifest["wal_lsn"].as_u64().unwrap_or_default(),
+        pending: entries.get(WAL).map(|text| text.lines().map(str::to_string).collect()).unwrap_or_default(),
+        archive: entries.remove(WAL_ARCHIVE).unwrap_or_default(),
+    };
+    Ok((tables, wal))
+}
+
+/// Read the one table `name` of an archive `write` produced, checked as `read` checks it. Only
+/// the manifest and that table's file are kept in memory.
+pub fn read_table(path: &str, name: &str) -> Result<Table> {
+    let file_name = format!("tables/{}.csv", name);
+    let entries = read_entries(path, |entry| entry == MANIFEST || entry == file_name)?;
+    let manifest = manifest(path, &entries)?;
+    let listed = listed_tables(path, &manifest)?
+        .iter()
+        .find(|listed| listed["name"].as_str() == Some(name))
+        .ok_or_else(|| DatabaseError::TableNotInBackup(name.to_string(), path.to_string()))?;
+    table(path, &entries, listed)
+}
+
+fn malformed(path: &str, message: String) -> DatabaseError {
+    DatabaseError::MalformedArchive(path.to_string(), message)
+}
+
+/// The text of every file in the archive that `wanted` accepts, by name.
+fn read_entries(path: &str, wanted: impl Fn(&str) -> bool) -> Result<HashMap<String, String>> {
+    let file = File::open(path).map_err(|e| DatabaseError::FileCreationError(path.to_string(), e.to_string()))?;
+    let mut entries = HashMap::new();
+    let mut archive = tar::Archive::new(file);
+    for entry in archive.entries().map_err(|e| malformed(path, e.to_string()))? {
+        let mut entry = entry.map_err(|e| malformed(path, e.to_string()))?;
+        let name = entry.path().map_err(|e| malformed(path, e.to_string()))?.to_string_lossy().into_owned();
+        if !wanted(&name) {
+            continue;
+        }
+        let mut text = String::new();
+        entry.read_to_string(&mut text).map_err(|e| malformed(path, format!("{}: {}", name, e)))?;
+        entries.insert(name, text);
+    }
+    Ok(entries)
+}
+
+/// The archive's manifest, if it is of the format `write` produces.
+fn manifest(path: &str, entries: &HashMap<String, String>) -> Result<Value> {
+    let manifest: Value = entries
+        .get(MANIFEST)
+        .ok_or_else(|| malformed(path, format!("no {}", MANIFEST)))
+        .and_then(|text| serde_json::from_str(text).map_err(|e| malformed(path, format!("{}: {}", MANIFEST, e))))?;
+    if manifest["format"].as_u64() != Some(FORMAT_VERSION) {
+        return Err(malformed(path, format!("unsupported format {}", manifest["format"])));
+    }
+    Ok(manifest)
+}
+
+fn listed_tables<'a>(path: &str, manifest: &'a Value) -> Result<&'a Vec<Value>> {
+    manifest["tables"].as_array().ok_or_else(|| malformed(path, "the manifest lists no tables".to_string()))
+}
+
+/// The table a manifest entry lists, which must be in `entries` with as many rows as listed.
+fn table(path: &str, entries: &HashMap<String, String>, listed: &Value) -> Result<Table> {
+    let file_name = format!("tables/{}.csv", listed["name"].as_str().unwrap_or_default());
+    let text = entries.get(&file_name).ok_or_else(|| malformed(path, format!("no {}", file_name)))?;
+    let table = csv::read_text(text, &format!("{}:{}", path, file_name))?;
+    if listed["rows"].as_u64() != Some(table.rows.len() as u64) {
+        return Err(malformed(path, format!("{} has {} rows, the manifest says {}", file_name, table.rows.len(), listed["rows"])));
+    }
+    Ok(table)
+}
+
+fn append(builder: &mut tar::Builder<impl Write>, name: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(value::now().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
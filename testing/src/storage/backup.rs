@@ -0,0 +1,282 @@
+use super::checksum::{self, Checksummed};
+use super::csv::{self, CsvStorage};
+use super::encryption::{self, SealingWriter};
+use super::StorageEngine;
+use super::progress::Tracker;
+use crate::commands::db::{DatabaseError, Result};
+use crate::table::table::Table;
+use crate::table::tables::TableRef;
+use crate::table::value;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Version of the layout `Snapshot::write` produces; `verify` refuses any other.
+const FORMAT_VERSION: u64 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const WAL: &str = "wal.log";
+const WAL_ARCHIVE: &str = "wal_archive.log";
+
+/// How long a backup waits for the WAL engine to archive entries it had taken from the WAL just
+/// before the snapshot (it moves the LSN first and appends to the archive after).
+const ARCHIVE_WAIT: Duration = Duration::from_secs(5);
+
+/// Everything a backup holds, taken out of a database at one instant by `Database::snapshot`,
+/// so the slow part, writing it, needs no lock and writes can go on meanwhile.
+pub struct Snapshot {
+    /// Every table, sorted by name
+    pub tables: Vec<(String, TableRef)>,
+    /// LSN of the last archived entry; the archive is copied up to it and no further
+    pub archived_lsn: u64,
+    /// Entries logged after `archived_lsn`
+    pub pending: Vec<String>,
+    /// The WAL archive file, which only grows while the database runs
+    pub archive_file: String,
+}
+
+impl Snapshot {
+    /// LSN the backup stands at: it holds every entry up to this one and none after.
+    pub fn lsn(&self) -> u64 {
+        self.archived_lsn + self.pending.len() as u64
+    }
+
+    /// Write the snapshot as a full backup directory `dir`, which must not exist yet:
+    /// `manifest.json` (format version, LSN, tables with their columns and row counts, and the
+    /// size and checksum of every file), `tables/<name>.csv` per table as CSV storage keeps it,
+    /// `wal_archive.log` up to `archived_lsn` and the pending entries as `wal.log` (see
+    /// `write_backup`). Returns the manifest.
+    pub fn write(&self, dir: &str) -> Result<Value> {
+        write_backup(dir, |partial, files| {
+            fs::create_dir_all(Path::new(partial).join("tables"))
+                .map_err(|e| DatabaseError::FileCreationError(partial.to_string(), e.to_string()))?;
+            let mut tables = Vec::new();
+            for (name, table) in &self.tables {
+                let path = format!("tables/{}.csv", name);
+                let write_table = |writer: &mut Checksummed<BufWriter<File>>| {
+                    let mut writer = SealingWriter::new(writer);
+                    csv::write_to(table, &mut writer, &mut Tracker::silent())?;
+                    writer.finish().map(|_| ())
+                };
+                files.push(write_file(partial, &path, write_table)?.0);
+                tables.push(json!({ "name": name, "file": path, "columns": table.column_declarations(), "rows": table.rows.len() }));
+            }
+            files.push(copy_archive(&self.archive_file, partial, 0, self.archived_lsn)?);
+            files.push(write_pending(partial, &self.pending)?);
+            Ok(json!({ "kind": "full", "wal_lsn": self.lsn(), "archived_lsn": self.archived_lsn, "tables": tables }))
+        })
+    }
+
+    /// Write the snapshot as a new data directory `dir`, laid out as `CsvStorage` and the WAL
+    /// keep a database (`<table>.csv` with its checksum, `wal_archive.log` up to `archived_lsn`
+    /// and the pending entries as `wal.log`), so a database opened on it starts where this one
+    /// was. Like a backup it is written under `<dir>.partial` and only renamed to `dir`, which
+    /// must not exist yet, once complete.
+    pub fn write_data_dir(&self, dir: &str) -> Result<()> {
+        if Path::new(dir).exists() {
+            return Err(DatabaseError::FileCreationError(dir.to_string(), "it already exists".to_string()));
+        }
+        let partial = start_partial(dir)?;
+        let mut storage = CsvStorage::in_dir(&partial)?;
+        for (name, table) in &self.tables {
+            storage.save_table(name, table)?;
+        }
+        copy_archive(&self.archive_file, &partial, 0, self.archived_lsn)?;
+        write_pending(&partial, &self.pending)?;
+        fs::rename(&partial, dir).map_err(|e| DatabaseError::FileCreationError(partial.clone(), e.to_string()))
+    }
+}
+
+/// The WAL entries logged after `since`, copied out of a database at one instant by
+/// `Database::wal_since`: what an incremental backup holds on top of the backup before it.
+pub struct Increment {
+    /// LSN of the backup this one follows
+    pub since: u64,
+    /// LSN of the last archived entry
+    pub archived_lsn: u64,
+    /// Entries logged after `archived_lsn`
+    pub pending: Vec<String>,
+    /// The WAL archive file, which only grows while the database runs
+    pub archive_file: String,
+}
+
+impl Increment {
+    /// LSN the backup stands at: it holds every entry after `since` up to this one.
+    pub fn lsn(&self) -> u64 {
+        self.archived_lsn + self.pending.len() as u64
+    }
+
+    /// Write the increment as an incremental backup directory `dir`, which must not exist yet:
+    /// `wal_archive.log` holds the archived entries after `since` and `wal.log` the pending ones
+    /// after it, and `manifest.json` lists no tables. Applying the entries of every increment in
+    /// turn to the full backup before them brings it up to the last one's LSN. Returns the
+    /// manifest.
+    pub fn write(&self, dir: &str) -> Result<Value> {
+        write_backup(dir, |partial, files| {
+            files.push(copy_archive(&self.archive_file, partial, self.since, self.archived_lsn)?);
+            let skip = self.since.saturating_sub(self.archived_lsn) as usize;
+            files.push(write_pending(partial, self.pending.get(skip..).unwrap_or_default())?);
+            let archived_lsn = self.archived_lsn.max(self.since);
+            Ok(json!({ "kind": "incremental", "since_lsn": self.since, "wal_lsn": self.lsn(), "archived_lsn": archived_lsn, "tables": [] }))
+        })
+    }
+}
+
+/// Write a backup directory `dir`, which must not exist yet: `fill` writes the backup's files
+/// into the directory it is given, adding each one's manifest entry to the list, and returns the
+/// rest of the manifest. Everything is written and synced under `<dir>.partial`, read back and
+/// checked against its checksums (see `verify`), and only then renamed to `dir`, so `dir` only
+/// ever holds a complete, checked backup. Returns the manifest.
+fn write_backup(dir: &str, fill: impl FnOnce(&str, &mut Vec<Value>) -> Result<Value>) -> Result<Value> {
+    if Path::new(dir).exists() {
+        return Err(DatabaseError::BackupFailed(dir.to_string(), "it already exists".to_string()));
+    }
+    let partial = start_partial(dir)?;
+    let mut files = Vec::new();
+    let mut manifest = fill(&partial, &mut files)?;
+    manifest["format"] = json!(FORMAT_VERSION);
+    manifest["created_at"] = json!(value::now());
+    manifest["files"] = json!(files);
+    write_file(&partial, MANIFEST, |writer| writer.write_all(manifest.to_string().as_bytes()))?;
+    verify(&partial)?;
+    fs::rename(&partial, dir).map_err(|e| DatabaseError::FileCreationError(partial.clone(), e.to_string()))?;
+    Ok(manifest)
+}
+
+/// Create the empty directory `<dir>.partial` to write `dir` in, removing what a failed attempt
+/// left there. Returns its name.
+fn start_partial(dir: &str) -> Result<String> {
+    let partial = format!("{}.partial", dir);
+    let to_err = |e: io::Error| DatabaseError::FileCreationError(partial.clone(), e.to_string());
+    if Path::new(&partial).exists() {
+        fs::remove_dir_all(&partial).map_err(to_err)?;
+    }
+    fs::create_dir_all(&partial).map_err(to_err)?;
+    Ok(partial)
+}
+
+/// Copy the lines after `from` up to `to` of the WAL archive as the backup's `wal_archive.log`,
+/// waiting up to `ARCHIVE_WAIT` for the WAL engine to finish appending them. Returns the file's
+/// manifest entry.
+fn copy_archive(archive_file: &str, dir: &str, from: u64, to: u64) -> Result<Value> {
+    let wanted = to.saturating_sub(from);
+    let started = Instant::now();
+    loop {
+        let (entry, copied) = write_file(dir, WAL_ARCHIVE, |writer| {
+            let mut copied = 0;
+            if let Ok(archive) = File::open(archive_file) {
+                for line in BufReader::new(archive).lines().skip(from as usize).take(wanted as usize) {
+                    writeln!(writer, "{}", line?)?;
+                    copied += 1;
+                }
+            }
+            Ok(copied)
+        })?;
+        if copied == wanted {
+            return Ok(entry);
+        }
+        if started.elapsed() >= ARCHIVE_WAIT {
+            let message = format!("'{}' holds {} entries, the backup needs {}", archive_file, from + copied, to);
+            return Err(DatabaseError::BackupFailed(dir.to_string(), message));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Write entries not archived yet as the backup's `wal.log`. Returns its manifest entry.
+fn write_pending(dir: &str, pending: &[String]) -> Result<Value> {
+    Ok(write_file(dir, WAL, |writer| pending.iter().try_for_each(|entry| writeln!(writer, "{}", encryption::seal(entry))))?.0)
+}
+
+/// The backups in directory `dir` (one subdirectory each, as `BackupSchedule` names them), with
+/// their manifests, oldest first. Anything that is not a readable backup is left out.
+pub fn list(dir: &str) -> Vec<(String, Value)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<(String, Value)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path().display().to_string();
+            if path.ends_with(".partial") {
+                return None;
+            }
+            let manifest = manifest(&path).ok()?;
+            Some((path, manifest))
+        })
+        .collect();
+    backups.sort_by(|(a, first), (b, second)| (first["created_at"].as_i64(), a).cmp(&(second["created_at"].as_i64(), b)));
+    backups
+}
+
+/// Check a backup directory `Snapshot::write` produced: its format version, and that every file
+/// its manifest lists is there with the size and checksum it was written with. Returns the
+/// manifest.
+pub fn verify(dir: &str) -> Result<Value> {
+    let manifest = manifest(dir)?;
+    let files = manifest["files"].as_array().ok_or_else(|| failed(dir, "the manifest lists no files".to_string()))?;
+    for file in files {
+        check_file(dir, file)?;
+    }
+    Ok(manifest)
+}
+
+/// Read the one table `name` of a backup directory, after checking its file against the
+/// manifest as `verify` does.
+pub fn read_table(dir: &str, name: &str) -> Result<Table> {
+    let manifest = manifest(dir)?;
+    let listed = manifest["tables"]
+        .as_array()
+        .and_then(|tables| tables.iter().find(|table| table["name"].as_str() == Some(name)))
+        .ok_or_else(|| DatabaseError::TableNotInBackup(name.to_string(), dir.to_string()))?;
+    let path = listed["file"].as_str().ok_or_else(|| failed(dir, format!("no file for table '{}'", name)))?;
+    let file = manifest["files"]
+        .as_array()
+        .and_then(|files| files.iter().find(|file| file["path"].as_str() == Some(path)))
+        .ok_or_else(|| failed(dir, format!("{} has no checksum", path)))?;
+    check_file(dir, file)?;
+    let table = csv::read_table(&Path::new(dir).join(path).display().to_string())?;
+    if listed["rows"].as_u64() != Some(table.rows.len() as u64) {
+        return Err(failed(dir, format!("{} has {} rows, the manifest says {}", path, table.rows.len(), listed["rows"])));
+    }
+    Ok(table)
+}
+
+fn failed(dir: &str, message: String) -> DatabaseError {
+    DatabaseError::BackupFailed(dir.to_string(), message)
+}
+
+/// The backup's manifest, if it is of the format `Snapshot::write` produces.
+fn manifest(dir: &str) -> Result<Value> {
+    let manifest: Value = fs::read_to_string(Path::new(dir).join(MANIFEST))
+        .map_err(|e| failed(dir, format!("{}: {}", MANIFEST, e)))
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| failed(dir, format!("{}: {}", MANIFEST, e))))?;
+    if manifest["format"].as_u64() != Some(FORMAT_VERSION) {
+        return Err(failed(dir, format!("unsupported format {}", manifest["format"])));
+    }
+    Ok(manifest)
+}
+
+/// Check that the file a manifest entry lists has the size and checksum it was written with.
+fn check_file(dir: &str, file: &Value) -> Result<()> {
+    let path = file["path"].as_str().ok_or_else(|| failed(dir, "a file without a path".to_string()))?;
+    let (bytes, checksum) = checksum::of_file(Path::new(dir).join(path)).map_err(|e| failed(dir, format!("{}: {}", path, e)))?;
+    if file["bytes"].as_u64() != Some(bytes) || file["checksum"].as_str() != Some(&checksum) {
+        return Err(failed(dir, format!("{} does not match its checksum", path)));
+    }
+    Ok(())
+}
+
+/// Create `dir/path`, have `fill` write it, and sync it to disk. Returns the file's manifest
+/// entry (path, size and checksum) and what `fill` returned.
+fn write_file<T>(dir: &str, path: &str, fill: impl FnOnce(&mut Checksummed<BufWriter<File>>) -> io::Result<T>) -> Result<(Value, T)> {
+    let full_path = Path::new(dir).join(path);
+    let to_err = |e: io::Error| DatabaseError::FileCreationError(full_path.display().to_string(), e.to_string());
+    let mut writer = Checksummed::new(BufWriter::new(File::create(&full_path).map_err(to_err)?));
+    let filled = fill(&mut writer).map_err(to_err)?;
+    writer.flush().map_err(to_err)?;
+    writer.inner.get_ref().sync_all().map_err(to_err)?;
+    Ok((json!({ "path": path, "bytes": writer.bytes, "checksum": writer.hex() }), filled))
+}
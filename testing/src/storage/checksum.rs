@@ -0,0 +1,42 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Passes writes through, keeping a running FNV-1a checksum and byte count of them. The checksum
+/// catches damaged or truncated files, not deliberate tampering.
+pub struct Checksummed<W> {
+    pub inner: W,
+    hash: u64,
+    pub bytes: u64,
+}
+
+impl<W: Write> Checksummed<W> {
+    pub fn new(inner: W) -> Self {
+        Checksummed { inner, hash: 0xcbf29ce484222325, bytes: 0 }
+    }
+
+    /// The checksum of everything written so far, as 16 hex digits.
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hash = buf[..written].iter().fold(self.hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The size and checksum of a whole file.
+pub fn of_file(path: impl AsRef<Path>) -> io::Result<(u64, String)> {
+    let mut checksum = Checksummed::new(io::sink());
+    io::copy(&mut File::open(path)?, &mut checksum)?;
+    Ok((checksum.bytes, checksum.hex()))
+}
@@ -0,0 +1,403 @@
+use crate::commands::db::{DatabaseError, Result};
+use crate::table::table::Table;
+use crate::table::value::NULL_TEXT;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Write, BufWriter};
+use tracing::instrument;
+
+use super::checksum::{self, Checksummed};
+use super::encryption::{self, SealingWriter};
+use super::progress::Tracker;
+use super::verify::Issue;
+use super::{StorageEngine, INFER_SAMPLE};
+
+/// Stores every table as `<table_name>.csv` in `dir` (the working directory by default), with
+/// its size and checksum in `<table_name>.csv.sum` (see `write_durably`). The previous versions
+/// of a table file are kept as `<table_name>.csv.1` (the latest) to `.csv.<n>` if asked to.
+pub struct CsvStorage {
+    dir: String,
+    generations: usize,
+}
+
+impl CsvStorage {
+    pub fn new() -> Self {
+        CsvStorage { dir: ".".to_string(), generations: 0 }
+    }
+
+    /// Keep the CSV files in `dir`, creating it if needed.
+    pub fn in_dir(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .map_err(|e| DatabaseError::FileCreationError(dir.to_string(), e.to_string()))?;
+        Ok(CsvStorage { dir: dir.to_string(), generations: 0 })
+    }
+
+    /// Keep the `generations` versions of every table file before the current one, each saved
+    /// as the next one replaces it (see `keep_generation`). 0, the default, keeps none.
+    pub fn keep_generations(mut self, generations: usize) -> Self {
+        self.generations = generations;
+        self
+    }
+
+    fn file_name(&self, table_name: &str) -> String {
+        format!("{}/{}.csv", self.dir, table_name)
+    }
+}
+
+impl Default for CsvStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageEngine for CsvStorage {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    #[instrument(name = "storage.load", skip_all, fields(storage = "csv", table = table_name))]
+    fn load_table(&self, table_name: &str) -> Result<Option<Table>> {
+        let file_name = self.file_name(table_name);
+        if fs::metadata(&file_name).is_err() {
+            return Ok(None);
+        }
+        read_table(&file_name).map(Some)
+    }
+
+    fn table_names(&self) -> Result<Vec<String>> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| DatabaseError::FileCreationError(self.dir.clone(), e.to_string()))?;
+        let mut names: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "csv" {
+                    return None;
+                }
+                path.file_stem()?.to_str().map(str::to_string)
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    #[instrument(name = "storage.save", skip_all, fields(storage = "csv", table = table_name, rows = table.rows.len()))]
+    fn save_table(&mut self, table_name: &str, table: &Table) -> Result<()> {
+        let file_name = self.file_name(table_name);
+        if self.generations > 0 && fs::metadata(&file_name).is_ok() {
+            keep_generation(&file_name, self.generations)
+                .map_err(|e| DatabaseError::StorageError(table_name.to_string(), e.to_string()))?;
+        }
+        write_durably(table, &file_name)
+    }
+
+    #[instrument(name = "storage.rename", skip_all, fields(storage = "csv", table = old_name, new_name = new_name))]
+    fn rename_table(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        let old_file = self.file_name(old_name);
+        if fs::metadata(&old_file).is_err() {
+            return Ok(());
+        }
+        let new_file = self.file_name(new_name);
+        let moved = fs::rename(&old_file, &new_file).and_then(|()| match fs::rename(sum_file(&old_file), sum_file(&new_file)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            renamed => renamed,
+        });
+        let moved = moved.and_then(|()| {
+            generations(&old_file).try_for_each(|(n, old)| fs::rename(old, generation(&new_file, n)))
+        });
+        moved.map_err(|e| DatabaseError::StorageError(old_name.to_string(), e.to_string()))
+    }
+
+    #[instrument(name = "storage.drop", skip_all, fields(storage = "csv", table = table_name))]
+    fn drop_table(&mut self, table_name: &str) -> Result<()> {
+        let file_name = self.file_name(table_name);
+        if fs::metadata(&file_name).is_err() {
+            return Ok(());
+        }
+        let _ = fs::remove_file(sum_file(&file_name));
+        for (_, old) in generations(&file_name) {
+            let _ = fs::remove_file(old);
+        }
+        fs::remove_file(&file_name).map_err(|e| DatabaseError::StorageError(table_name.to_string(), e.to_string()))
+    }
+
+    fn stored_size(&self, table_name: &str) -> Result<Option<u64>> {
+        Ok(fs::metadata(self.file_name(table_name)).ok().map(|metadata| metadata.len()))
+    }
+
+    /// Check every table file, and what an interrupted `write_durably` left next to it.
+    fn verify(&mut self, repair: bool) -> Result<Vec<Issue>> {
+        let entries = fs::read_dir(&self.dir).map_err(|e| DatabaseError::FileCreationError(self.dir.clone(), e.to_string()))?;
+        let mut files: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_str()?.to_string();
+                let stem = [".csv.sum.partial", ".csv.sum", ".csv.partial", ".csv"].iter().find_map(|suffix| name.strip_suffix(suffix))?;
+                Some(self.file_name(stem))
+            })
+            .collect();
+        files.sort();
+        files.dedup();
+        let mut issues = Vec::new();
+        for file in files {
+            issues.extend(verify_file(&file, repair)?);
+        }
+        Ok(issues)
+    }
+}
+
+/// Write a table file so that a crash never leaves it half written: the rows go to
+/// `<file>.partial`, which is synced and then renamed over `file`, and `<file>.sum` records its
+/// size and checksum. The checksum is staged as `<file>.sum.partial` before the rename, so a
+/// crash between the two renames leaves what `verify_file` needs to finish them.
+fn write_durably(table: &Table, file_name: &str) -> Result<()> {
+    let to_err = |e: std::io::Error| DatabaseError::FileCreationError(file_name.to_string(), e.to_string());
+    let partial = format!("{}.partial", file_name);
+    let staged = format!("{}.partial", sum_file(file_name));
+    let mut writer = SealingWriter::new(Checksummed::new(BufWriter::new(File::create(&partial).map_err(to_err)?)));
+    write_to(table, &mut writer, &mut Tracker::silent()).map_err(to_err)?;
+    let writer = writer.finish().map_err(to_err)?;
+    writer.inner.get_ref().sync_all().map_err(to_err)?;
+    let mut sum = File::create(&staged).map_err(to_err)?;
+    writeln!(sum, "{} {}", writer.bytes, writer.hex()).and_then(|()| sum.sync_all()).map_err(to_err)?;
+    fs::rename(&partial, file_name).map_err(to_err)?;
+    fs::rename(&staged, sum_file(file_name)).map_err(to_err)
+}
+
+/// Before a table file is replaced, keep what it holds as generation 1, `<file>.1`, moving the
+/// older ones up a number and dropping the one past `keep`. Generation 1 is a hard link to the
+/// current file (or a copy where links are not supported), which `write_durably` then renames
+/// its new version over, so the file is never missing and is never copied in the usual case.
+fn keep_generation(file_name: &str, keep: usize) -> std::io::Result<()> {
+    for n in (1..keep).rev() {
+        match fs::rename(generation(file_name, n), generation(file_name, n + 1)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    let latest = generation(file_name, 1);
+    match fs::remove_file(&latest) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::hard_link(file_name, &latest).or_else(|_| fs::copy(file_name, &latest).map(|_| ()))
+}
+
+fn generation(file_name: &str, n: usize) -> String {
+    format!("{}.{}", file_name, n)
+}
+
+/// The generations kept of a table file, from the latest, as far as they go without a gap.
+fn generations(file_name: &str) -> impl Iterator<Item = (usize, String)> + '_ {
+    (1..).map(|n| (n, generation(file_name, n))).take_while(|(_, path)| fs::metadata(path).is_ok())
+}
+
+fn sum_file(file_name: &str) -> String {
+    format!("{}.sum", file_name)
+}
+
+/// The size and checksum a `.sum` file records.
+fn read_sum(path: &str) -> Option<(u64, String)> {
+    let text = fs::read_to_string(path).ok()?;
+    let (bytes, checksum) = text.trim().split_once(' ')?;
+    Some((bytes.parse().ok()?, checksum.to_string()))
+}
+
+/// Check one table file: finish or throw away a write a crash interrupted (see
+/// `write_durably`), then compare the file with its recorded checksum and parse it. A file from
+/// before checksums were kept gets one on repair if it parses.
+fn verify_file(file: &str, repair: bool) -> Result<Vec<Issue>> {
+    let (partial, sum) = (format!("{}.partial", file), sum_file(file));
+    let staged = format!("{}.partial", sum);
+    let staged_sum = read_sum(&staged);
+    let mut issues = Vec::new();
+    let mut staged_used = false;
+    if let Ok(written) = checksum::of_file(&partial) {
+        if staged_sum.as_ref() == Some(&written) {
+            let problem = "a complete write was never renamed into place".to_string();
+            let finish = || fs::rename(&partial, file).and_then(|()| fs::rename(&staged, &sum));
+            issues.push(Issue::new(file, problem).repair(repair, "finished the write", finish)?);
+            staged_used = true;
+        } else {
+            let problem = format!("a torn write was left in '{}'", partial);
+            issues.push(Issue::new(file, problem).repair(repair, "removed it; the table is as last written", || fs::remove_file(&partial))?);
+        }
+    }
+    if staged_sum.is_some() && !staged_used {
+        if staged_sum == checksum::of_file(file).ok() {
+            let problem = "its checksum was never renamed into place".to_string();
+            issues.push(Issue::new(file, problem).repair(repair, "finished the write", || fs::rename(&staged, &sum))?);
+        } else {
+            let problem = format!("a stale checksum was left in '{}'", staged);
+            issues.push(Issue::new(file, problem).repair(repair, "removed it", || fs::remove_file(&staged))?);
+        }
+    }
+    if fs::metadata(file).is_err() || (staged_used && !repair) {
+        return Ok(issues);
+    }
+
+    let actual = checksum::of_file(file).map_err(|e| DatabaseError::StorageError(file.to_string(), e.to_string()))?;
+    let parsed = read_table(file);
+    match read_sum(&sum) {
+        Some(expected) if expected != actual => {
+            let problem = format!("does not match its checksum ({} bytes, {} recorded): torn or damaged", actual.0, expected.0);
+            issues.push(Issue::new(file, problem));
+        }
+        Some(_) => {}
+        None if parsed.is_ok() => {
+            let record = || fs::write(&sum, format!("{} {}\n", actual.0, actual.1));
+            issues.push(Issue::new(file, "has no checksum".to_string()).repair(repair, "recorded one", record)?);
+        }
+        None => {}
+    }
+    if let Err(e) = parsed {
+        issues.push(Issue::new(file, format!("cannot be read: {}", e)));
+    }
+    Ok(issues)
+}
+
+/// Parse a CSV file whose header is `row_id,<col1>,<col2>,...` into a table.
+/// Columns are declared in the header as `<col>[:<type>][=<default>]`, e.g. `age:int=0`.
+/// A `\N` field (or a missing trailing one) is NULL; an empty field is an empty string.
+/// Fields are read as RFC 4180 has them (see `records`), so values may hold commas, quotes and
+/// line breaks.
+pub fn read_table(file_name: &str) -> Result<Table> {
+    parse(file_name, &mut Tracker::silent()).map(|(table, _)| table)
+}
+
+/// Parse a CSV file to import, as `read_table` does. A file whose header declares no column
+/// types or constraints, as other tools write them, gets its columns typed by their values (see
+/// `Table::infer_types`).
+pub fn import_table(file_name: &str, tracker: &mut Tracker) -> Result<Table> {
+    let (mut table, declared) = parse(file_name, tracker)?;
+    if !declared {
+        table.infer_types(INFER_SAMPLE);
+    }
+    Ok(table)
+}
+
+/// Parse CSV text as `read_table` does; `source` names it in errors.
+pub fn read_text(text: &str, source: &str) -> Result<Table> {
+    parse_text(text, source, &mut Tracker::silent()).map(|(table, _)| table)
+}
+
+/// The table in a CSV file, and whether its header declares anything but column names.
+fn parse(file_name: &str, tracker: &mut Tracker) -> Result<(Table, bool)> {
+    let text = fs::read_to_string(file_name)
+        .map_err(|e| DatabaseError::FileCreationError(file_name.to_string(), e.to_string()))?;
+    parse_text(&encryption::open_text(&text, file_name)?, file_name, tracker)
+}
+
+fn parse_text(text: &str, file_name: &str, tracker: &mut Tracker) -> Result<(Table, bool)> {
+    let records = records(text).map_err(|e| DatabaseError::MalformedCsv(file_name.to_string(), e))?;
+    tracker.expect_rows(records.len().saturating_sub(1));
+    let mut records = records.into_iter();
+    let Some(header) = records.next() else {
+        return Err(DatabaseError::FileCreationError(file_name.to_string(), "file is empty".to_string()));
+    };
+    let declared = header.iter().skip(1).any(|declaration| declaration.contains([':', '*', '!', '=']));
+    let mut headers = vec!["row_id".to_string()];
+    let mut table = Table::new();
+    // Add columns if header has more than one value.
+    for declaration in header.iter().skip(1) {
+        headers.push(table.declare_column(declaration)?.to_string());
+    }
+    // Process rows, skipping blank lines.
+    for values in records.filter(|values| values.len() > 1 || !values[0].is_empty()) {
+        if let Some((row_id, row_values)) = values.split_first() {
+            let mut data = HashMap::new();
+            for (col, val) in headers.iter().skip(1).zip(row_values.iter()) {
+                data.insert(col.to_string(), val.to_string());
+            }
+            table.insert_row(row_id, data)?;
+        }
+        // The fields and the separators between them; quotes are not counted.
+        tracker.advance(1, values.iter().map(|value| value.len() as u64 + 1).sum());
+    }
+    tracker.finish();
+    Ok((table, declared))
+}
+
+/// Write a table as CSV to `writer`.
+/// The first row lists column declarations in alphabetical order, preceded by "row_id".
+/// Fields holding a comma, quote or line break are quoted (see `field`).
+pub fn write_to(table: &Table, mut writer: impl Write, tracker: &mut Tracker) -> std::io::Result<()> {
+    tracker.expect_rows(table.rows.len());
+    let mut columns_in_order: Vec<_> = table.columns.iter().cloned().collect();
+    columns_in_order.sort();
+    let header = {
+        let mut hdr = vec!["row_id".to_string()];
+        hdr.extend(table.column_declarations());
+        record(&hdr)
+    };
+    writeln!(writer, "{}", header)?;
+    for (row_id, row_data) in &table.rows {
+        let mut row_vec = vec![row_id.clone()];
+        for col in &columns_in_order {
+            row_vec.push(row_data.get(col.as_str()).map_or(NULL_TEXT.to_string(), |value| value.to_string()));
+        }
+        let line = record(&row_vec);
+        writeln!(writer, "{}", line)?;
+        tracker.advance(1, line.len() as u64 + 1);
+    }
+    tracker.finish();
+    writer.flush()
+}
+
+/// One CSV field, in double quotes (with `"` doubled) if it holds a comma, quote or line break.
+pub fn field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// One CSV line of `fields`, without the line break.
+pub fn record<S: AsRef<str>>(fields: &[S]) -> String {
+    fields.iter().map(|text| field(text.as_ref())).collect::<Vec<_>>().join(",")
+}
+
+/// Split CSV text into records of fields as RFC 4180 has it: fields are separated by `,` and
+/// records by `\n` or `\r\n`, and a field in double quotes may hold both, with `""` for a quote.
+/// Fails if a quoted field is never closed.
+fn records(text: &str) -> std::result::Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    // The line the open quoted field started on, if inside one.
+    let mut quoted_from = None;
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        match (c, quoted_from) {
+            ('"', Some(_)) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', Some(_)) => quoted_from = None,
+            (c, Some(_)) => field.push(c),
+            ('"', None) if field.is_empty() => quoted_from = Some(line),
+            (',', None) => fields.push(std::mem::take(&mut field)),
+            ('\r', None) if chars.peek() == Some(&'\n') => {}
+            ('\n', None) => {
+                fields.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut fields));
+            }
+            (c, None) => field.push(c),
+        }
+    }
+    if let Some(start) = quoted_from {
+        return Err(format!("the quoted field starting on line {} is not closed", start));
+    }
+    // The last line may lack its line break.
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push(fields);
+    }
+    Ok(records)
+}
@@ -0,0 +1,207 @@
+use crate::commands::db::{DatabaseError, Result};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64ct::{Base64, Encoding};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::RwLock;
+
+/// Start of every encrypted line; the rest is the base64 of the nonce followed by the ciphertext,
+/// after `<key id>:` for every key but the first (see `rotate`).
+const MARKER: &str = "aes:";
+
+/// Start of a line stored unsealed that would otherwise be taken for a sealed one: one that
+/// starts with `MARKER`, or with `ESCAPE` itself. The rest is the line as it was.
+const ESCAPE: &str = "plain:";
+
+/// Bytes of the random nonce each line is encrypted with.
+pub const NONCE_LEN: usize = 12;
+
+/// The installed keys by id; the last one seals.
+static KEYS: RwLock<Vec<Aes256Gcm>> = RwLock::new(Vec::new());
+
+/// Parse a key written as 64 hex digits, i.e. 32 bytes.
+pub fn parse_key(text: &str) -> Option<[u8; 32]> {
+    let text = text.trim();
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (byte, digits) in key.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
+
+/// **Encryption at rest**
+/// Once a key is installed, every line the database stores (table files, WAL files and
+/// segments, backups, the LSM engine's WAL and SSTables) is sealed on its own with AES-256-GCM
+/// and a fresh nonce, and written as `aes:<base64>`. Files keep one line per line, so line
+/// counts (LSNs), torn-line repair and checksums work on them as before. Lines written before
+/// the key was installed stay readable and are sealed the next time their file is rewritten.
+/// Without a key, a line that starts like a sealed one is stored after `plain:`, so a value
+/// such as `aes:1` reads back as it was written.
+///
+/// Lines are sealed without associated data, so a line is not bound to its file or its place in
+/// it: sealed lines are copied between files as they are (renamed tables, generations, WAL
+/// segments, backups). Sealing keeps the contents secret and detects any change to a line, but
+/// someone able to write the files can still move, drop or repeat whole lines unnoticed; the
+/// checksums next to table files (see `checksum`) do not stop that either, as they take no key.
+///
+/// `keys` are the data keys by id (see `storage::keyring`): the last seals, the others only open
+/// what they sealed before it was rotated in. An installed key stays installed.
+pub fn install(keys: &[[u8; 32]]) {
+    let mut installed = KEYS.write().unwrap();
+    for key in keys.iter().skip(installed.len()) {
+        installed.push(Aes256Gcm::new(&(*key).into()));
+    }
+}
+
+/// Seal with `key` from now on, keeping the keys installed before to open what they sealed.
+pub fn rotate(key: [u8; 32]) {
+    KEYS.write().unwrap().push(Aes256Gcm::new(&key.into()));
+}
+
+/// Whether a key is installed.
+pub fn enabled() -> bool {
+    !KEYS.read().unwrap().is_empty()
+}
+
+/// `line` as it is to be stored: sealed if a key is installed, as it is otherwise (escaped if
+/// it starts like a sealed line).
+pub fn seal(line: &str) -> String {
+    let keys = KEYS.read().unwrap();
+    let Some(cipher) = keys.last() else {
+        return escape(line).into_owned();
+    };
+    let sealed = Base64::encode_string(&encrypt(cipher, line.as_bytes()));
+    match keys.len() - 1 {
+        0 => format!("{}{}", MARKER, sealed),
+        id => format!("{}{}:{}", MARKER, id, sealed),
+    }
+}
+
+/// A stored line as it was before `seal`. A line that was never sealed is returned as it is.
+pub fn open(line: &str) -> std::result::Result<Cow<'_, str>, String> {
+    if let Some(line) = line.strip_prefix(ESCAPE) {
+        return Ok(Cow::Borrowed(line));
+    }
+    let Some(sealed) = line.strip_prefix(MARKER) else {
+        return Ok(Cow::Borrowed(line));
+    };
+    let (id, encoded) = match sealed.split_once(':') {
+        Some((id, encoded)) => (id.parse().map_err(|_| "an encrypted line is damaged")?, encoded),
+        None => (0, sealed),
+    };
+    let keys = KEYS.read().unwrap();
+    if keys.is_empty() {
+        return Err("it is encrypted and no key is set (RUSTDB_ENCRYPTION_KEY or RUSTDB_PASSPHRASE)".to_string());
+    }
+    let cipher = keys.get(id).ok_or_else(|| format!("it was sealed with key {}, which is not installed", id))?;
+    let bytes = Base64::decode_vec(encoded).map_err(|_| "an encrypted line is damaged")?;
+    let opened = decrypt(cipher, &bytes).ok_or("the key is wrong or the data is damaged")?;
+    String::from_utf8(opened).map(Cow::Owned).map_err(|_| "an encrypted line is damaged".to_string())
+}
+
+/// `line` as stored unsealed: after `ESCAPE` if it starts like a sealed or escaped line.
+fn escape(line: &str) -> Cow<'_, str> {
+    match needs_escape(line.as_bytes()) {
+        true => Cow::Owned(format!("{}{}", ESCAPE, line)),
+        false => Cow::Borrowed(line),
+    }
+}
+
+fn needs_escape(line: &[u8]) -> bool {
+    line.starts_with(MARKER.as_bytes()) || line.starts_with(ESCAPE.as_bytes())
+}
+
+/// `plain` encrypted under a fresh nonce, which comes first.
+pub fn encrypt(cipher: &Aes256Gcm, plain: &[u8]) -> Vec<u8> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let sealed = cipher.encrypt(&nonce, plain).expect("AES-GCM encrypts any input");
+    [nonce.as_slice(), &sealed].concat()
+}
+
+/// What `encrypt` was given, or `None` if `bytes` were not encrypted with this key or were changed.
+pub fn decrypt(cipher: &Aes256Gcm, bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+    cipher.decrypt(Nonce::from_slice(nonce), sealed).ok()
+}
+
+/// `open` every line of `text`, which `source` names in errors.
+pub fn open_text(text: &str, source: &str) -> Result<String> {
+    if !text.contains(MARKER) && !text.contains(ESCAPE) {
+        return Ok(text.to_string());
+    }
+    let mut opened = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (line, end) = line.strip_suffix('\n').map_or((line, ""), |line| (line, "\n"));
+        opened.push_str(&open(line).map_err(|e| DatabaseError::Undecryptable(source.to_string(), e))?);
+        opened.push_str(end);
+    }
+    Ok(opened)
+}
+
+/// `seal` every line of `text`.
+pub fn seal_text(text: &str) -> String {
+    match enabled() || text.contains(MARKER) || text.contains(ESCAPE) {
+        true => text.lines().map(|line| seal(line) + "\n").collect(),
+        false => text.to_string(),
+    }
+}
+
+/// Seals what is written through it a line at a time (see `seal`). A line is only written once
+/// it is complete, so call `finish` at the end to write a last line that has no line break.
+pub struct SealingWriter<W: Write> {
+    inner: W,
+    line: Vec<u8>,
+}
+
+impl<W: Write> SealingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        SealingWriter { inner, line: Vec::new() }
+    }
+
+    /// Write what is left of the last line, flush, and hand back the writer underneath.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.write_line(&line)?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Write `line`, without its line break, as `seal` stores it. Unsealed lines are written as
+    /// they are, so bytes that are not UTF-8 go through unchanged.
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if enabled() {
+            return self.inner.write_all(seal(&String::from_utf8_lossy(line)).as_bytes());
+        }
+        if needs_escape(line) {
+            self.inner.write_all(ESCAPE.as_bytes())?;
+        }
+        self.inner.write_all(line)
+    }
+}
+
+impl<W: Write> Write for SealingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|byte| *byte == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                let line = std::mem::take(&mut self.line);
+                self.write_line(&line[..line.len() - 1])?;
+                self.inner.write_all(b"\n")?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,147 @@
+use super::progress::Tracker;
+use super::INFER_SAMPLE;
+use crate::commands::db::{DatabaseError, Result};
+use crate::table::table::{self, ColumnSpec, Row, Table};
+use crate::table::value::ColumnType;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::Write;
+
+/// How a JSON table file holds its rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// One array of objects (`.json`)
+    Array,
+    /// One object per line, JSON Lines (`.jsonl` or `.ndjson`)
+    Lines,
+}
+
+impl Layout {
+    /// The layout a file name asks for by its extension, or `None` if it is not a JSON file.
+    pub fn of(file_name: &str) -> Option<Self> {
+        match file_name.rsplit_once('.')?.1.to_lowercase().as_str() {
+            "json" => Some(Layout::Array),
+            "jsonl" | "ndjson" => Some(Layout::Lines),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a JSON table file, an array of objects or JSON Lines (whichever the file holds).
+/// Each object is a row: its `row_id` and a value per column, `null` for NULL. A first object
+/// without a `row_id`, `{"columns": [...]}`, declares the columns as storage does (see
+/// `ColumnSpec::declaration`); files without one, as other tools write them, get a column per key
+/// typed by its values, strings included (see `Table::infer_types`). Rows without a `row_id`
+/// are numbered from 1.
+pub fn read_table(file_name: &str, tracker: &mut Tracker) -> Result<Table> {
+    let malformed = |message: String| DatabaseError::MalformedJson(file_name.to_string(), message);
+    let text = fs::read_to_string(file_name)
+        .map_err(|e| DatabaseError::FileCreationError(file_name.to_string(), e.to_string()))?;
+    // The whole file is read before the rows are loaded.
+    tracker.advance(0, text.len() as u64);
+    let items: Vec<Value> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(&text).map_err(|e| malformed(e.to_string()))?
+    } else {
+        let lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        lines
+            .map(|(i, line)| serde_json::from_str(line).map_err(|e| malformed(format!("line {}: {}", i + 1, e))))
+            .collect::<Result<_>>()?
+    };
+    let mut objects = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        match item {
+            Value::Object(object) => objects.push(object),
+            other => return Err(malformed(format!("row {} is {}, not an object", i + 1, other))),
+        }
+    }
+
+    let mut table = Table::new();
+    let declared = objects.first().is_some_and(|first| !first.contains_key("row_id") && first.contains_key("columns"));
+    let declarations = if declared {
+        let columns = objects.remove(0).remove("columns").unwrap_or_default();
+        let declarations = columns.as_array().and_then(|columns| columns.iter().map(|c| c.as_str().map(str::to_string)).collect());
+        declarations.ok_or_else(|| malformed("\"columns\" must be a list of column declarations".to_string()))?
+    } else {
+        guess_columns(&objects)
+    };
+    for declaration in &declarations {
+        table.declare_column(declaration)?;
+    }
+    tracker.expect_rows(objects.len());
+    for (i, mut object) in objects.into_iter().enumerate() {
+        let row_id = match object.remove("row_id") {
+            Some(Value::String(row_id)) => row_id,
+            Some(Value::Null) | None => (i + 1).to_string(),
+            Some(other) => other.to_string(),
+        };
+        table.insert_row(&row_id, table::json_to_text(object))?;
+        tracker.advance(1, 0);
+    }
+    tracker.finish();
+    if !declared {
+        // Strings such as "42" or "2024-01-31" get a type too.
+        table.infer_types(INFER_SAMPLE);
+    }
+    Ok(table)
+}
+
+/// Declarations for the keys of `objects`: int, float or bool when every non-null value of a key
+/// is one, text otherwise.
+fn guess_columns(objects: &[Map<String, Value>]) -> Vec<String> {
+    let mut columns: Vec<&String> = objects.iter().flat_map(|object| object.keys()).filter(|key| *key != "row_id").collect();
+    columns.sort();
+    columns.dedup();
+    columns
+        .into_iter()
+        .map(|column| {
+            let values: Vec<&Value> = objects.iter().filter_map(|object| object.get(column)).filter(|v| !v.is_null()).collect();
+            let column_type = if values.is_empty() {
+                ColumnType::Text
+            } else if values.iter().all(|v| v.is_i64()) {
+                ColumnType::Int
+            } else if values.iter().all(|v| v.is_number()) {
+                ColumnType::Float
+            } else if values.iter().all(|v| v.is_boolean()) {
+                ColumnType::Bool
+            } else {
+                ColumnType::Text
+            };
+            ColumnSpec { column_type, ..ColumnSpec::default() }.declaration(column)
+        })
+        .collect()
+}
+
+/// Write `rows` as JSON in `layout`: first `{"columns": [...]}` with the column declarations of
+/// `schema` (the rows' table, or a projection of it, see `Table::projection`), then an object per
+/// row holding its `row_id` and every column of `schema`, typed as the column is and `null` where
+/// the row has no value. Each row is encoded and written as it is read, so the output, to a file
+/// or a socket, is never held in memory. Returns the number of rows, which `tracker` counts too.
+pub fn write_rows<'a, W: Write>(
+    mut writer: W,
+    schema: &Table,
+    rows: impl Iterator<Item = (&'a String, &'a Row)>,
+    layout: Layout,
+    tracker: &mut Tracker,
+) -> std::io::Result<usize> {
+    // Unfiltered rows know how many they are.
+    if let (low, Some(high)) = rows.size_hint() {
+        if low == high {
+            tracker.expect_rows(high);
+        }
+    }
+    let (open, separator, close) = match layout {
+        Layout::Array => ("[\n", ",\n", "\n]\n"),
+        Layout::Lines => ("", "\n", "\n"),
+    };
+    write!(writer, "{}{}", open, json!({ "columns": schema.column_declarations() }))?;
+    for (row_id, row) in rows {
+        let mut object = schema.row_to_json(row);
+        object["row_id"] = json!(row_id);
+        let line = object.to_string();
+        write!(writer, "{}{}", separator, line)?;
+        tracker.advance(1, (separator.len() + line.len()) as u64);
+    }
+    write!(writer, "{}", close)?;
+    writer.flush()?;
+    Ok(tracker.finish())
+}
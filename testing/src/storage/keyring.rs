@@ -0,0 +1,127 @@
+use super::{encryption, replace_file};
+use crate::commands::db::{DatabaseError, Result};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{KeyInit, OsRng};
+use aes_gcm::Aes256Gcm;
+use argon2::Argon2;
+use base64ct::{Base64, Encoding};
+use log::info;
+use serde_json::json;
+use std::sync::Mutex;
+
+/// File in the data directory holding the data keys, each sealed with the key derived from the
+/// passphrase: `{"salt": <base64>, "keys": [<base64 of nonce and sealed key>, ...]}`, by key id.
+pub const KEYRING_FILE: &str = "keyring.json";
+
+/// Bytes of the random salt the passphrase is stretched with.
+const SALT_LEN: usize = 16;
+
+/// The keyring as unlocked, which `rotate_key` and `change_passphrase` write back.
+struct Unlocked {
+    salt: [u8; SALT_LEN],
+    wrapping: Aes256Gcm,
+    keys: Vec<[u8; 32]>,
+}
+
+static UNLOCKED: Mutex<Option<Unlocked>> = Mutex::new(None);
+
+/// Whether the data directory has a keyring, so it cannot be opened without its passphrase.
+pub fn exists() -> bool {
+    std::path::Path::new(KEYRING_FILE).exists()
+}
+
+/// **Passphrase-protected keys**
+/// Instead of a raw key, a database can be opened with a passphrase. The data keys are random and
+/// kept in `keyring.json`, each sealed with a key stretched from the passphrase with argon2id, so
+/// the passphrase can be changed without touching the data (`ROTATE PASSPHRASE`). `ROTATE KEY`
+/// adds a new data key that seals from then on; the old ones stay in the keyring to open what
+/// they sealed, such as earlier backups.
+///
+/// Unlock the keyring with `passphrase` and install its keys (see `encryption::install`). The
+/// first time, the keyring is created with `adopt` as its first key, so a database encrypted with
+/// RUSTDB_ENCRYPTION_KEY can move to a passphrase, or with a new random key.
+pub fn unlock(passphrase: &str, adopt: Option<[u8; 32]>) -> Result<()> {
+    let unlocked = match std::fs::read_to_string(KEYRING_FILE) {
+        Ok(text) => read(&text, passphrase)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let salt = random();
+            let unlocked = Unlocked { wrapping: wrapping_key(passphrase, &salt)?, salt, keys: vec![adopt.unwrap_or_else(random)] };
+            write(&unlocked)?;
+            info!(file = KEYRING_FILE; "Keyring created");
+            unlocked
+        }
+        Err(e) => return Err(DatabaseError::StorageError(KEYRING_FILE.to_string(), e.to_string())),
+    };
+    encryption::install(&unlocked.keys);
+    *UNLOCKED.lock().unwrap() = Some(unlocked);
+    Ok(())
+}
+
+/// `ROTATE KEY`: add a new data key to the keyring and seal with it from now on. The keyring is
+/// written before anything is sealed with the key. Returns the key's id.
+pub fn rotate_key() -> Result<usize> {
+    let mut unlocked = UNLOCKED.lock().unwrap();
+    let unlocked = unlocked.as_mut().ok_or(DatabaseError::NoKeyring)?;
+    let key = random();
+    unlocked.keys.push(key);
+    if let Err(e) = write(unlocked) {
+        unlocked.keys.pop();
+        return Err(e);
+    }
+    encryption::rotate(key);
+    info!(key = unlocked.keys.len() - 1, file = KEYRING_FILE; "Data key added to keyring");
+    Ok(unlocked.keys.len() - 1)
+}
+
+/// `ROTATE PASSPHRASE <passphrase>`: seal the data keys with a new passphrase (and salt).
+pub fn change_passphrase(passphrase: &str) -> Result<()> {
+    let mut unlocked = UNLOCKED.lock().unwrap();
+    let unlocked = unlocked.as_mut().ok_or(DatabaseError::NoKeyring)?;
+    let salt = random();
+    let changed = Unlocked { wrapping: wrapping_key(passphrase, &salt)?, salt, keys: unlocked.keys.clone() };
+    write(&changed)?;
+    *unlocked = changed;
+    info!(file = KEYRING_FILE; "Keyring passphrase changed");
+    Ok(())
+}
+
+fn read(text: &str, passphrase: &str) -> Result<Unlocked> {
+    let damaged = || DatabaseError::Undecryptable(KEYRING_FILE.to_string(), "the keyring is damaged".to_string());
+    let stored: serde_json::Value = serde_json::from_str(text).map_err(|_| damaged())?;
+    let decode = |value: &serde_json::Value| value.as_str().and_then(|text| Base64::decode_vec(text).ok()).ok_or_else(damaged);
+    let salt: [u8; SALT_LEN] = decode(&stored["salt"])?.try_into().map_err(|_| damaged())?;
+    let wrapping = wrapping_key(passphrase, &salt)?;
+    let keys = stored["keys"].as_array().filter(|keys| !keys.is_empty()).ok_or_else(damaged)?;
+    let keys = keys
+        .iter()
+        .map(|key| {
+            let key = encryption::decrypt(&wrapping, &decode(key)?).ok_or_else(|| DatabaseError::WrongPassphrase(KEYRING_FILE.to_string()))?;
+            key.try_into().map_err(|_| damaged())
+        })
+        .collect::<Result<_>>()?;
+    Ok(Unlocked { salt, wrapping, keys })
+}
+
+fn write(unlocked: &Unlocked) -> Result<()> {
+    let keys: Vec<String> = unlocked.keys.iter().map(|key| Base64::encode_string(&encryption::encrypt(&unlocked.wrapping, key))).collect();
+    let text = json!({ "salt": Base64::encode_string(&unlocked.salt), "keys": keys }).to_string();
+    replace_file(KEYRING_FILE, |partial| {
+        std::fs::write(partial, &text).map_err(|e| DatabaseError::FileCreationError(partial.to_string(), e.to_string()))
+    })
+}
+
+/// The key the data keys are sealed with: `passphrase` stretched with argon2id.
+fn wrapping_key(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm> {
+    if passphrase.is_empty() {
+        return Err(DatabaseError::InvalidPassphrase);
+    }
+    let mut key = [0; 32];
+    Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key).map_err(|_| DatabaseError::InvalidPassphrase)?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
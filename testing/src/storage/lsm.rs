@@ -0,0 +1,213 @@
+use crate::commands::db::{DatabaseError, Result};
+use crate::table::table::{self, Table};
+use lsm::compaction::SizeTieredCompaction;
+use lsm::storage::{LSMTree, LineCodec};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::instrument;
+
+use super::{encryption, StorageEngine};
+
+/// Memtable size (in keys) at which the LSM tree flushes to a new SSTable.
+const MEMTABLE_THRESHOLD: usize = 64;
+
+/// Stores tables in the LSM tree from the `DB` crate.
+/// - `schema/<table>` holds the JSON list of column declarations (`age:int`, `name`).
+/// - `row/<table>/<row_id>` holds the JSON object of one row, with values in textual form.
+///
+/// Table names and row ids are escaped in keys (see `escape`), so neither ever holds the `/`
+/// that ends a component nor the `:` that ends a key in the tree's `key:value` lines.
+pub struct LsmStorage {
+    tree: LSMTree,
+}
+
+/// Seals the lines of the tree's WAL and SSTables when a key is installed (see `encryption`).
+struct EncryptedLines;
+
+impl LineCodec for EncryptedLines {
+    fn encode(&self, line: &str) -> String {
+        encryption::seal(line)
+    }
+
+    fn decode(&self, line: &str) -> std::result::Result<String, String> {
+        encryption::open(line).map(|line| line.into_owned())
+    }
+}
+
+impl LsmStorage {
+    /// Open (or create) an LSM store under `dir`, replaying its WAL if one is left over.
+    pub fn new(dir: &str) -> Self {
+        let tree = LSMTree::with_codec(
+            &format!("{}/wal.log", dir),
+            &format!("{}/sstables", dir),
+            MEMTABLE_THRESHOLD,
+            Box::new(SizeTieredCompaction::default()),
+            Arc::new(EncryptedLines),
+        );
+        LsmStorage { tree }
+    }
+
+    fn schema_key(table_name: &str) -> String {
+        format!("schema/{}", escape(table_name))
+    }
+
+    fn row_prefix(table_name: &str) -> String {
+        format!("row/{}/", escape(table_name))
+    }
+
+    fn row_key(table_name: &str, row_id: &str) -> String {
+        format!("{}{}", Self::row_prefix(table_name), escape(row_id))
+    }
+
+    /// All stored rows of a table. `'0'` is the character right after `'/'`, and an escaped
+    /// table name has no `'/'`, so the range `[row/<table>/, row/<table>0)` covers exactly the
+    /// keys of this table's rows.
+    fn stored_rows(&self, table_name: &str) -> Vec<(String, String)> {
+        let start = Self::row_prefix(table_name);
+        let end = format!("row/{}0", escape(table_name));
+        self.tree
+            .scan(&start, &end)
+            .map(|(key, value)| (unescape(&key[start.len()..]), value))
+            .collect()
+    }
+}
+
+impl StorageEngine for LsmStorage {
+    fn name(&self) -> &str {
+        "lsm"
+    }
+
+    #[instrument(name = "storage.load", skip_all, fields(storage = "lsm", table = table_name))]
+    fn load_table(&self, table_name: &str) -> Result<Option<Table>> {
+        let Some(schema) = self.tree.get(&Self::schema_key(table_name)) else {
+            return Ok(None);
+        };
+        let columns: Vec<String> = serde_json::from_str(&schema)
+            .map_err(|e| DatabaseError::StorageError(table_name.to_string(), e.to_string()))?;
+        let mut table = Table::new();
+        for declaration in &columns {
+            table.declare_column(declaration)?;
+        }
+        for (row_id, row_json) in self.stored_rows(table_name) {
+            let data: HashMap<String, String> = serde_json::from_str(&row_json)
+                .map_err(|e| DatabaseError::StorageError(table_name.to_string(), e.to_string()))?;
+            table.insert_row(&row_id, data)?;
+        }
+        Ok(Some(table))
+    }
+
+    fn table_names(&self) -> Result<Vec<String>> {
+        // Same trick as `stored_rows`: '0' follows '/', so this covers every `schema/` key.
+        Ok(self
+            .tree
+            .scan("schema/", "schema0")
+            .map(|(key, _)| unescape(&key["schema/".len()..]))
+            .collect())
+    }
+
+    #[instrument(name = "storage.save", skip_all, fields(storage = "lsm", table = table_name, rows = table.rows.len()))]
+    fn save_table(&mut self, table_name: &str, table: &Table) -> Result<()> {
+        let columns = table.column_declarations();
+        self.tree.insert(Self::schema_key(table_name), serde_json::to_string(&columns).unwrap());
+
+        // Only write rows that changed, and tombstone the ones that disappeared.
+        let stored: HashMap<String, String> = self.stored_rows(table_name).into_iter().collect();
+        for (row_id, row_data) in &table.rows {
+            let row_data = table::row_to_text(row_data);
+            let row_json = serde_json::to_string(&row_data).unwrap();
+            let unchanged = stored
+                .get(row_id)
+                .and_then(|old| serde_json::from_str::<HashMap<String, String>>(old).ok())
+                .is_some_and(|old| old == row_data);
+            if !unchanged {
+                self.tree.insert(Self::row_key(table_name, row_id), row_json);
+            }
+        }
+        let live: HashSet<&String> = table.rows.keys().collect();
+        for row_id in stored.keys().filter(|id| !live.contains(id)) {
+            self.tree.delete(Self::row_key(table_name, row_id));
+        }
+        Ok(())
+    }
+    #[instrument(name = "storage.rename", skip_all, fields(storage = "lsm", table = old_name, new_name = new_name))]
+    fn rename_table(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        let Some(schema) = self.tree.get(&Self::schema_key(old_name)) else {
+            return Ok(());
+        };
+        for (row_id, row_json) in self.stored_rows(old_name) {
+            self.tree.insert(Self::row_key(new_name, &row_id), row_json);
+            self.tree.delete(Self::row_key(old_name, &row_id));
+        }
+        self.tree.insert(Self::schema_key(new_name), schema);
+        self.tree.delete(Self::schema_key(old_name));
+        Ok(())
+    }
+    #[instrument(name = "storage.drop", skip_all, fields(storage = "lsm", table = table_name))]
+    fn drop_table(&mut self, table_name: &str) -> Result<()> {
+        for (row_id, _) in self.stored_rows(table_name) {
+            self.tree.delete(Self::row_key(table_name, &row_id));
+        }
+        self.tree.delete(Self::schema_key(table_name));
+        Ok(())
+    }
+
+    /// The table shares its SSTables with the others, so this counts the bytes of its live keys and values.
+    fn stored_size(&self, table_name: &str) -> Result<Option<u64>> {
+        let schema_key = Self::schema_key(table_name);
+        let Some(schema) = self.tree.get(&schema_key) else {
+            return Ok(None);
+        };
+        let rows: usize = self
+            .stored_rows(table_name)
+            .iter()
+            .map(|(row_id, row_json)| Self::row_key(table_name, row_id).len() + row_json.len())
+            .sum();
+        Ok(Some((schema_key.len() + schema.len() + rows) as u64))
+    }
+}
+
+/// `text` as a key component: `%`, `/`, `:` and line breaks written as `%XX`. Anything else is
+/// kept, so keys stored before escaping read back the same unless they held one of these.
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['%', '/', ':', '\n', '\r']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '%' | '/' | ':' | '\n' | '\r' => escaped.push_str(&format!("%{:02X}", c as u8)),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// A key component as it was before `escape`.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('%') {
+        unescaped.push_str(&rest[..at]);
+        let escaped = match rest.get(at + 1..at + 3) {
+            Some("25") => Some('%'),
+            Some("2F") => Some('/'),
+            Some("3A") => Some(':'),
+            Some("0A") => Some('\n'),
+            Some("0D") => Some('\r'),
+            _ => None,
+        };
+        match escaped {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[at + 3..];
+            }
+            None => {
+                unescaped.push('%');
+                rest = &rest[at + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
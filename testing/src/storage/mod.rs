@@ -0,0 +1,110 @@
+use crate::commands::db::{Result, RowIter};
+use crate::table::table::Table;
+
+pub mod archive;
+pub mod backup;
+pub mod checksum;
+pub mod csv;
+pub mod encryption;
+pub mod json;
+pub mod keyring;
+pub mod lsm;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod progress;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod verify;
+pub mod wal_segments;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+
+/// Values per column looked at to guess its type when a file without column types is imported
+/// (see `Table::infer_types`).
+pub const INFER_SAMPLE: usize = 1000;
+
+/// Where the table `Database` keeps its rows between runs.
+/// The in-memory `Table` stays the working copy; an engine only loads tables that are not
+/// in memory yet and persists them when the database decides to save.
+pub trait StorageEngine: Send + Sync {
+    /// Short name used in log output.
+    fn name(&self) -> &str;
+
+    /// Load a table from storage. Returns `Ok(None)` if the engine has never stored it.
+    fn load_table(&self, table_name: &str) -> Result<Option<Table>>;
+
+    /// Names of every table the engine has stored, sorted.
+    fn table_names(&self) -> Result<Vec<String>>;
+
+    /// Persist the full contents of a table, replacing whatever was stored before.
+    fn save_table(&mut self, table_name: &str, table: &Table) -> Result<()>;
+
+    /// Move a stored table to `new_name`. Does nothing if the engine has never stored it.
+    fn rename_table(&mut self, old_name: &str, new_name: &str) -> Result<()>;
+
+    /// Forget a stored table. Does nothing if the engine has never stored it.
+    fn drop_table(&mut self, table_name: &str) -> Result<()>;
+
+    /// Bytes the stored table takes up. Returns `Ok(None)` if the engine has never stored it.
+    fn stored_size(&self, table_name: &str) -> Result<Option<u64>>;
+
+    /// Check the stored files for torn writes and damage, fixing what can be fixed safely if
+    /// `repair` is set. Engines that check themselves as they open find nothing here.
+    fn verify(&mut self, _repair: bool) -> Result<Vec<verify::Issue>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Write `file_name` as a whole or not at all: `write` writes the file under the temporary name
+/// it is given, `<file_name>.partial`, which is synced and renamed over `file_name` only once it
+/// is complete. If writing fails the temporary file is removed and `file_name` is as it was.
+pub fn replace_file<T>(file_name: &str, write: impl FnOnce(&str) -> Result<T>) -> Result<T> {
+    let partial = format!("{}.partial", file_name);
+    let written = write(&partial).and_then(|written| {
+        std::fs::File::open(&partial)
+            .and_then(|file| file.sync_all())
+            .and_then(|()| std::fs::rename(&partial, file_name))
+            .map_err(|e| crate::commands::db::DatabaseError::FileCreationError(file_name.to_string(), e.to_string()))?;
+        Ok(written)
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    written
+}
+
+/// Write a table as a Parquet file (see `parquet::write_table`).
+#[cfg(feature = "parquet")]
+pub fn write_parquet(table: &Table, file_name: &str) -> Result<()> {
+    parquet::write_table(table, file_name)
+}
+
+/// Parquet needs the `parquet` feature; without it this always fails.
+#[cfg(not(feature = "parquet"))]
+pub fn write_parquet(_table: &Table, _file_name: &str) -> Result<()> {
+    Err(crate::commands::db::DatabaseError::FeatureDisabled("Writing Parquet files".to_string(), "parquet".to_string()))
+}
+
+/// Write an Excel workbook of one sheet per `(name, schema, rows)` (see `xlsx::write_workbook`).
+#[cfg(feature = "xlsx")]
+pub fn write_xlsx(file_name: &str, sheets: Vec<(String, &Table, RowIter<'_>)>, tracker: &mut progress::Tracker) -> Result<usize> {
+    xlsx::write_workbook(file_name, sheets, tracker)
+}
+
+/// Excel workbooks need the `xlsx` feature; without it this always fails.
+#[cfg(not(feature = "xlsx"))]
+pub fn write_xlsx(_file_name: &str, _sheets: Vec<(String, &Table, RowIter<'_>)>, _tracker: &mut progress::Tracker) -> Result<usize> {
+    Err(crate::commands::db::DatabaseError::FeatureDisabled("Writing Excel workbooks".to_string(), "xlsx".to_string()))
+}
+
+/// Read the tables of a SQLite database (see `sqlite::read_tables`).
+#[cfg(feature = "sqlite")]
+pub fn read_sqlite(path: &str) -> Result<Vec<(String, Table)>> {
+    sqlite::read_tables(path)
+}
+
+/// SQLite needs the `sqlite` feature; without it this always fails.
+#[cfg(not(feature = "sqlite"))]
+pub fn read_sqlite(_path: &str) -> Result<Vec<(String, Table)>> {
+    Err(crate::commands::db::DatabaseError::FeatureDisabled("Importing SQLite databases".to_string(), "sqlite".to_string()))
+}
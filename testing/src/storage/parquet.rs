@@ -0,0 +1,89 @@
+use crate::commands::db::{DatabaseError, Result};
+use crate::table::table::Table;
+use crate::table::value::{ColumnType, Value};
+use ::parquet::basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use ::parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use ::parquet::file::properties::WriterProperties;
+use ::parquet::file::writer::SerializedFileWriter;
+use ::parquet::format::MilliSeconds;
+use ::parquet::schema::types::Type;
+use std::fs::File;
+use std::sync::Arc;
+
+/// Write a table as a Parquet file, recreating it, for tools such as pandas, Spark or DuckDB.
+/// The file has a required `row_id` string column and then one optional column per table column
+/// in alphabetical order, typed as the column is: int as INT64, float as DOUBLE, bool as BOOLEAN,
+/// text as a UTF-8 string and timestamp as a UTC timestamp in milliseconds. NULLs stay null.
+pub fn write_table(table: &Table, file_name: &str) -> Result<()> {
+    let to_err = |e: ::parquet::errors::ParquetError| DatabaseError::FileCreationError(file_name.to_string(), e.to_string());
+    let mut columns: Vec<&String> = table.columns.iter().collect();
+    columns.sort();
+
+    let mut fields = vec![field("row_id", ColumnType::Text, Repetition::REQUIRED).map_err(to_err)?];
+    for column in &columns {
+        fields.push(field(column, table.column_type(column), Repetition::OPTIONAL).map_err(to_err)?);
+    }
+    let schema = Type::group_type_builder("schema").with_fields(fields).build().map_err(to_err)?;
+
+    let file = File::create(file_name).map_err(|e| DatabaseError::FileCreationError(file_name.to_string(), e.to_string()))?;
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(file, Arc::new(schema), properties).map_err(to_err)?;
+    let mut row_group = writer.next_row_group().map_err(to_err)?;
+
+    let row_ids: Vec<ByteArray> = table.rows.keys().map(|row_id| ByteArray::from(row_id.as_str())).collect();
+    let mut row_id_column = row_group.next_column().map_err(to_err)?.expect("the schema starts with row_id");
+    row_id_column.typed::<ByteArrayType>().write_batch(&row_ids, None, None).map_err(to_err)?;
+    row_id_column.close().map_err(to_err)?;
+
+    for column in columns {
+        let values: Vec<&Value> = table.rows.values().map(|row| row.get(column.as_str()).unwrap_or(&Value::Null)).collect();
+        // Definition level 1 marks a value, 0 a NULL.
+        let levels: Vec<i16> = values.iter().map(|value| i16::from(!value.is_null())).collect();
+        let mut writer = row_group.next_column().map_err(to_err)?.expect("the schema has a field per column");
+        match table.column_type(column) {
+            ColumnType::Int => {
+                let values: Vec<i64> = values.iter().filter_map(|value| if let Value::Int(i) = value { Some(*i) } else { None }).collect();
+                writer.typed::<Int64Type>().write_batch(&values, Some(&levels), None)
+            }
+            ColumnType::Timestamp => {
+                let values: Vec<i64> =
+                    values.iter().filter_map(|value| if let Value::Timestamp(secs) = value { Some(secs * 1000) } else { None }).collect();
+                writer.typed::<Int64Type>().write_batch(&values, Some(&levels), None)
+            }
+            ColumnType::Float => {
+                let values: Vec<f64> = values.iter().filter_map(|value| if let Value::Float(x) = value { Some(*x) } else { None }).collect();
+                writer.typed::<DoubleType>().write_batch(&values, Some(&levels), None)
+            }
+            ColumnType::Bool => {
+                let values: Vec<bool> = values.iter().filter_map(|value| if let Value::Bool(b) = value { Some(*b) } else { None }).collect();
+                writer.typed::<BoolType>().write_batch(&values, Some(&levels), None)
+            }
+            ColumnType::Text => {
+                let values: Vec<ByteArray> =
+                    values.iter().filter(|value| !value.is_null()).map(|value| ByteArray::from(value.to_string().as_str())).collect();
+                writer.typed::<ByteArrayType>().write_batch(&values, Some(&levels), None)
+            }
+        }
+        .map_err(to_err)?;
+        writer.close().map_err(to_err)?;
+    }
+    row_group.close().map_err(to_err)?;
+    writer.close().map_err(to_err)?;
+    Ok(())
+}
+
+/// The Parquet field for a column of `column_type`.
+fn field(name: &str, column_type: ColumnType, repetition: Repetition) -> ::parquet::errors::Result<Arc<Type>> {
+    let (physical, logical) = match column_type {
+        ColumnType::Int => (PhysicalType::INT64, None),
+        ColumnType::Float => (PhysicalType::DOUBLE, None),
+        ColumnType::Bool => (PhysicalType::BOOLEAN, None),
+        ColumnType::Text => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        ColumnType::Timestamp => (
+            PhysicalType::INT64,
+            Some(LogicalType::Timestamp { is_adjusted_to_u_t_c: true, unit: TimeUnit::MILLIS(MilliSeconds {}) }),
+        ),
+    };
+    let field = Type::primitive_type_builder(name, physical).with_repetition(repetition).with_logical_type(logical).build()?;
+    Ok(Arc::new(field))
+}
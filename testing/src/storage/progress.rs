@@ -0,0 +1,112 @@
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Receives the progress of long imports and exports (see `Database::progress`).
+pub type Listener = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Least time between two reports of one operation, so a listener is not flooded.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How far an import or export has got.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// What is running, e.g. `import people.csv`
+    pub operation: String,
+    pub rows: usize,
+    /// Rows in all, when known up front
+    pub total_rows: Option<usize>,
+    /// Bytes read or written so far
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Set on the last report of the operation
+    pub done: bool,
+}
+
+impl Progress {
+    /// Share of the rows done, from 0 to 1, when the total is known.
+    pub fn fraction(&self) -> Option<f64> {
+        self.total_rows.map(|total| if total == 0 { 1.0 } else { self.rows as f64 / total as f64 })
+    }
+
+    /// Time left at the rate so far, when the total is known and some rows are done.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction().filter(|fraction| *fraction > 0.0)?;
+        Some(self.elapsed.mul_f64((1.0 - fraction) / fraction))
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "operation": self.operation,
+            "rows": self.rows,
+            "total_rows": self.total_rows,
+            "bytes": self.bytes,
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+            "eta_ms": self.eta().map(|eta| eta.as_millis() as u64),
+            "done": self.done,
+        })
+    }
+}
+
+/// Counts the rows and bytes of one operation and reports them to a listener, at most every
+/// `REPORT_INTERVAL` and then once more when it finishes. An operation that finishes before its
+/// first report is never reported, so quick ones stay quiet. Without a listener it only counts.
+pub struct Tracker {
+    listener: Option<Listener>,
+    progress: Progress,
+    started: Instant,
+    last_report: Instant,
+    reported: bool,
+}
+
+impl Tracker {
+    pub fn new(listener: Option<Listener>, operation: String) -> Self {
+        let now = Instant::now();
+        Tracker {
+            listener,
+            progress: Progress { operation, rows: 0, total_rows: None, bytes: 0, elapsed: Duration::ZERO, done: false },
+            started: now,
+            last_report: now,
+            reported: false,
+        }
+    }
+
+    /// A tracker nobody listens to, for storage's own reads and writes.
+    pub fn silent() -> Self {
+        Self::new(None, String::new())
+    }
+
+    /// The operation will handle `rows` rows in all, so reports can tell how far it has got.
+    pub fn expect_rows(&mut self, rows: usize) {
+        self.progress.total_rows = Some(rows);
+        // Whatever came first (reading the file) says nothing about the pace of the rows.
+        self.last_report = Instant::now();
+    }
+
+    /// Count `rows` more rows and `bytes` more bytes.
+    pub fn advance(&mut self, rows: usize, bytes: u64) {
+        self.progress.rows += rows;
+        self.progress.bytes += bytes;
+        if self.listener.is_some() && self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+        }
+    }
+
+    /// The operation is over; report so if it was reported before. Returns the rows counted.
+    pub fn finish(&mut self) -> usize {
+        self.progress.done = true;
+        if self.reported {
+            self.report();
+        }
+        self.progress.rows
+    }
+
+    fn report(&mut self) {
+        if let Some(listener) = &self.listener {
+            self.progress.elapsed = self.started.elapsed();
+            listener(&self.progress);
+            self.last_report = Instant::now();
+            self.reported = true;
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::commands::db::{DatabaseError, Result};
+use crate::table::table::{ColumnSpec, Table};
+use crate::table::value::{ColumnType, Value, NULL_TEXT};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+
+/// A column as `PRAGMA table_info` describes it.
+struct SqliteColumn {
+    name: String,
+    declared_type: String,
+    not_null: bool,
+    default: Option<String>,
+    primary_key: bool,
+}
+
+/// Read every table of the SQLite database in `path`, leaving out SQLite's own `sqlite_*` tables.
+/// Columns are typed by SQLite's affinity rules for their declared type (see `column_type`), but
+/// as SQLite does not enforce types, a column holding a value that does not fit its type is read
+/// as text. A single-column primary key stays the table's primary key and its values become the
+/// row ids; rows of other tables are numbered from 1. NULL stays NULL and blobs are read as hex.
+pub fn read_tables(path: &str) -> Result<Vec<(String, Table)>> {
+    let to_err = |e: rusqlite::Error| DatabaseError::SqliteError(path.to_string(), e.to_string());
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(to_err)?;
+    let mut statement = connection
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")
+        .map_err(to_err)?;
+    let names = statement.query_map([], |row| row.get::<_, String>(0)).map_err(to_err)?;
+    let names = names.collect::<rusqlite::Result<Vec<String>>>().map_err(to_err)?;
+    names
+        .into_iter()
+        .map(|name| Ok((name.clone(), read_table(&connection, path, &name)?)))
+        .collect()
+}
+
+fn read_table(connection: &Connection, path: &str, name: &str) -> Result<Table> {
+    let to_err = |e: rusqlite::Error| DatabaseError::SqliteError(path.to_string(), e.to_string());
+    let mut info = connection.prepare(&format!("PRAGMA table_info({})", quote(name))).map_err(to_err)?;
+    let columns = info.query_map([], |row| {
+        Ok(SqliteColumn {
+            name: row.get("name")?,
+            declared_type: row.get::<_, Option<String>>("type")?.unwrap_or_default(),
+            not_null: row.get("notnull")?,
+            default: row.get("dflt_value")?,
+            primary_key: row.get::<_, i64>("pk")? > 0,
+        })
+    });
+    let columns = columns.and_then(Iterator::collect::<rusqlite::Result<Vec<_>>>).map_err(to_err)?;
+
+    let mut select = connection.prepare(&format!("SELECT * FROM {}", quote(name))).map_err(to_err)?;
+    let rows = select.query_map([], |row| {
+        (0..columns.len()).map(|i| Ok(text(row.get_ref(i)?))).collect::<rusqlite::Result<Vec<Option<String>>>>()
+    });
+    let rows = rows.and_then(Iterator::collect::<rusqlite::Result<Vec<_>>>).map_err(to_err)?;
+
+    let key = match columns.iter().filter(|column| column.primary_key).collect::<Vec<_>>()[..] {
+        [key] => Some(key.name.as_str()),
+        _ => None,
+    };
+    let mut table = Table::new();
+    for (i, column) in columns.iter().enumerate() {
+        let declared = column_type(&column.declared_type);
+        let fits = rows.iter().filter_map(|row| row[i].as_deref()).all(|text| Value::parse(text, declared).is_some());
+        let column_type = if fits { declared } else { ColumnType::Text };
+        let spec = ColumnSpec {
+            column_type,
+            primary_key: key == Some(column.name.as_str()),
+            not_null: column.not_null || key == Some(column.name.as_str()),
+            default: column.default.as_deref().and_then(literal).filter(|value| Value::parse(value, column_type).is_some()),
+        };
+        table.declare_column(&spec.declaration(&column.name))?;
+    }
+    for (number, row) in rows.into_iter().enumerate() {
+        let row_id = key
+            .and_then(|key| columns.iter().position(|column| column.name == key))
+            .and_then(|i| row[i].clone())
+            .unwrap_or_else(|| (number + 1).to_string());
+        let data: HashMap<String, String> = columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| (column.name.clone(), value.unwrap_or_else(|| NULL_TEXT.to_string())))
+            .collect();
+        table.insert_row(&row_id, data)?;
+    }
+    Ok(table)
+}
+
+/// The column type for a declared SQLite type, by SQLite's affinity rules: INT means int, CHAR,
+/// CLOB, TEXT or BLOB (or no type) text, REAL, FLOA or DOUB float. On top of those, BOOL means
+/// bool and DATE or TIME timestamp; anything else (NUMERIC, DECIMAL, ...) is float.
+fn column_type(declared_type: &str) -> ColumnType {
+    let declared_type = declared_type.to_uppercase();
+    let has = |part: &str| declared_type.contains(part);
+    if has("BOOL") {
+        ColumnType::Bool
+    } else if has("INT") {
+        ColumnType::Int
+    } else if declared_type.is_empty() || has("CHAR") || has("CLOB") || has("TEXT") || has("BLOB") {
+        ColumnType::Text
+    } else if has("DATE") || has("TIME") {
+        ColumnType::Timestamp
+    } else {
+        ColumnType::Float
+    }
+}
+
+/// A cell in its textual form, `None` for NULL.
+fn text(value: ValueRef<'_>) -> Option<String> {
+    match value {
+        ValueRef::Null => None,
+        ValueRef::Integer(i) => Some(i.to_string()),
+        ValueRef::Real(x) => Some(x.to_string()),
+        ValueRef::Text(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        ValueRef::Blob(bytes) => Some(bytes.iter().map(|byte| format!("{:02x}", byte)).collect()),
+    }
+}
+
+/// A column default, if it is a literal: a quoted string or a number. Expressions such as
+/// `CURRENT_TIMESTAMP` have no value to copy.
+fn literal(default: &str) -> Option<String> {
+    if let Some(quoted) = default.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')) {
+        return Some(quoted.replace("''", "'"));
+    }
+    default.parse::<f64>().is_ok().then(|| default.to_string())
+}
+
+/// `name` as a quoted SQL identifier.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,89 @@
+use crate::commands::db::{DatabaseError, Result};
+use serde_json::{json, Map, Value};
+use std::fs::{self, OpenOptions};
+use std::io;
+
+use super::encryption;
+
+/// One problem `Database::verify` found in a file.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub file: String,
+    pub problem: String,
+    /// What was done about it, if it was repaired
+    pub repaired: Option<String>,
+}
+
+impl Issue {
+    pub fn new(file: &str, problem: String) -> Self {
+        Issue { file: file.to_string(), problem, repaired: None }
+    }
+
+    /// Fix the problem with `apply` if `repair` is set, noting it as `action`.
+    pub fn repair(mut self, repair: bool, action: &str, apply: impl FnOnce() -> io::Result<()>) -> Result<Self> {
+        if repair {
+            apply().map_err(|e| DatabaseError::StorageError(self.file.clone(), e.to_string()))?;
+            self.repaired = Some(action.to_string());
+        }
+        Ok(self)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "file": self.file, "problem": self.problem, "repaired": self.repaired })
+    }
+}
+
+/// Check a WAL file (the working WAL or its archive), one entry per line: the last line must be
+/// complete, as a write cut short by a crash leaves it without its line break, and every entry
+/// must be one replay understands. With `repair` a torn last entry is cut off; it was never
+/// committed, since an entry is archived before the working WAL lets go of it. A missing file
+/// has nothing logged and is fine.
+pub fn check_wal(path: &str, repair: bool) -> Result<Vec<Issue>> {
+    let Ok(bytes) = fs::read(path) else {
+        return Ok(Vec::new());
+    };
+    let mut issues = Vec::new();
+    let complete = bytes.iter().rposition(|byte| *byte == b'\n').map_or(0, |newline| newline + 1);
+    if complete < bytes.len() {
+        let problem = format!("ends in a torn write ({} bytes after the last complete entry)", bytes.len() - complete);
+        let truncate = || OpenOptions::new().write(true).open(path)?.set_len(complete as u64);
+        issues.push(Issue::new(path, problem).repair(repair, "cut off the torn entry", truncate)?);
+    }
+    for (index, line) in String::from_utf8_lossy(&bytes[..complete]).lines().enumerate() {
+        let line = match encryption::open(line) {
+            Ok(line) => line,
+            Err(e) => {
+                issues.push(Issue::new(path, format!("line {}: cannot be decrypted: {}", index + 1, e)));
+                continue;
+            }
+        };
+        if let Some(problem) = entry_problem(&line) {
+            issues.push(Issue::new(path, format!("line {}: {}: {}", index + 1, problem, line)));
+        }
+    }
+    Ok(issues)
+}
+
+/// Why replay could not apply a WAL entry, if it could not: an unknown operation, missing fields
+/// or row data that is not a JSON object.
+pub fn entry_problem(entry: &str) -> Option<String> {
+    let parts: Vec<&str> = entry.split(':').collect();
+    let (fields, json_from) = match parts[0] {
+        "create_table" | "drop_table" | "truncate_table" => (2, None),
+        "rename_table" | "drop_column" | "delete_row" | "add_column" | "copy_table" => (3, None),
+        "rename_column" | "alter_column" => (4, None),
+        "insert_row" | "upsert_row" | "replace_row" | "update_row_multi" => (4, Some(3)),
+        "update_row" => (5, None),
+        "" => return Some("empty entry".to_string()),
+        operation => return Some(format!("unknown operation '{}'", operation)),
+    };
+    if parts.len() < fields || parts[1].is_empty() {
+        return Some(format!("'{}' needs {} fields", parts[0], fields));
+    }
+    if let Some(from) = json_from {
+        if let Err(e) = serde_json::from_str::<Map<String, Value>>(&parts[from..].join(":")) {
+            return Some(format!("row data is not a JSON object ({})", e));
+        }
+    }
+    None
+}
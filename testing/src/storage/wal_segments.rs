@@ -0,0 +1,101 @@
+use super::{encryption, verify};
+use crate::commands::db::{DatabaseError, Result};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Extension of a WAL segment file.
+const EXTENSION: &str = "wal";
+
+/// A file of consecutive archived WAL entries, one per line, `first` to `last` (LSNs), named
+/// `<first>-<last>.wal` with both zero-padded to 20 digits so the files sort in LSN order.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub path: String,
+    pub first: u64,
+    pub last: u64,
+}
+
+/// The segments in `dir`, in LSN order. Other files are left out; a missing directory has none.
+pub fn list(dir: &str) -> Result<Vec<Segment>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(DatabaseError::FileCreationError(dir.to_string(), e.to_string())),
+    };
+    let mut segments: Vec<Segment> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != EXTENSION {
+                return None;
+            }
+            let (first, last) = path.file_stem()?.to_str()?.split_once('-')?;
+            let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+            (0 < first && first <= last).then(|| Segment { path: path.display().to_string(), first, last })
+        })
+        .collect();
+    segments.sort_by_key(|segment| (segment.first, segment.last));
+    Ok(segments)
+}
+
+/// Write `entries`, the first of which has LSN `first`, as a new segment in `dir` (created if
+/// needed), sealed like the archive (see `encryption`). The file only appears once it is complete
+/// (see `storage::replace_file`).
+pub fn write(dir: &str, first: u64, entries: &[String]) -> Result<Segment> {
+    fs::create_dir_all(dir).map_err(|e| DatabaseError::FileCreationError(dir.to_string(), e.to_string()))?;
+    let last = first + entries.len() as u64 - 1;
+    let path = Path::new(dir).join(format!("{:020}-{:020}.{}", first, last, EXTENSION)).display().to_string();
+    super::replace_file(&path, |partial| {
+        let mut writer = BufWriter::new(File::create(partial).map_err(|e| DatabaseError::FileCreationError(path.clone(), e.to_string()))?);
+        entries
+            .iter()
+            .try_for_each(|entry| writeln!(writer, "{}", encryption::seal(entry)))
+            .and_then(|()| writer.flush())
+            .map_err(|e| DatabaseError::FileCreationError(path.clone(), e.to_string()))
+    })?;
+    Ok(Segment { path, first, last })
+}
+
+/// The entries after LSN `after` held by the segments in `dir`, checked first: the segments must
+/// follow one another with no gap or overlap, starting no later than `after + 1`, each must hold
+/// as many entries as its name says, and every entry must be one replay understands.
+pub fn read_after(dir: &str, after: u64) -> Result<Vec<String>> {
+    let invalid = |message: String| DatabaseError::InvalidWalArchive(dir.to_string(), message);
+    let segments = list(dir)?;
+    let Some(start) = segments.first() else {
+        return Err(invalid("it holds no WAL segments".to_string()));
+    };
+    if start.first > after + 1 {
+        return Err(invalid(format!("the first segment starts at LSN {}, entries {} to {} are missing", start.first, after + 1, start.first - 1)));
+    }
+    let mut entries = Vec::new();
+    let mut expected = start.first;
+    for segment in &segments {
+        if segment.first != expected {
+            let problem = if segment.first > expected { "missing" } else { "in two segments" };
+            return Err(invalid(format!("'{}' starts at LSN {} but the one before ends at {}: entries are {}", segment.path, segment.first, expected - 1, problem)));
+        }
+        let file = File::open(&segment.path).map_err(|e| DatabaseError::FileCreationError(segment.path.clone(), e.to_string()))?;
+        let lines: Vec<String> = BufReader::new(file)
+            .lines()
+            .collect::<std::io::Result<_>>()
+            .map_err(|e| DatabaseError::FileCreationError(segment.path.clone(), e.to_string()))?;
+        if lines.len() as u64 != segment.last + 1 - segment.first {
+            return Err(invalid(format!("'{}' should hold {} entries but holds {}", segment.path, segment.last + 1 - segment.first, lines.len())));
+        }
+        for (lsn, line) in (segment.first..).zip(lines) {
+            let line = encryption::open(&line)
+                .map_err(|e| invalid(format!("entry {} in '{}' cannot be decrypted: {}", lsn, segment.path, e)))?
+                .into_owned();
+            if let Some(problem) = verify::entry_problem(&line) {
+                return Err(invalid(format!("entry {} in '{}': {}", lsn, segment.path, problem)));
+            }
+            if lsn > after {
+                entries.push(line);
+            }
+        }
+        expected = segment.last + 1;
+    }
+    Ok(entries)
+}
@@ -0,0 +1,73 @@
+use super::progress::Tracker;
+use crate::commands::db::{DatabaseError, Result, RowIter};
+use crate::table::table::Table;
+use crate::table::value::Value;
+use rust_xlsxwriter::{ColNum, ExcelDateTime, Format, RowNum, Workbook, Worksheet, XlsxError};
+
+/// Integers beyond this lose digits as Excel numbers, so they are written as text instead.
+const EXACT_INT: u64 = 1 << 53;
+
+/// Write an Excel workbook, recreating it, with one sheet per entry of `sheets`: its name, the
+/// columns of its rows' table (or a projection of it, see `Table::projection`) and the rows
+/// themselves. A sheet starts with a bold, frozen header row of `row_id` and then the columns in
+/// alphabetical order; cells are typed as their column is: int and float as numbers, bool as
+/// booleans, timestamp as dates and text as strings, with NULLs left blank. Returns the number of
+/// rows, which `tracker` counts too.
+pub fn write_workbook(file_name: &str, sheets: Vec<(String, &Table, RowIter<'_>)>, tracker: &mut Tracker) -> Result<usize> {
+    let to_err = |e: XlsxError| DatabaseError::FileCreationError(file_name.to_string(), e.to_string());
+    // Unfiltered rows know how many they are.
+    let hints: Option<Vec<usize>> = sheets
+        .iter()
+        .map(|(_, _, rows)| match rows.size_hint() {
+            (low, Some(high)) if low == high => Some(high),
+            _ => None,
+        })
+        .collect();
+    if let Some(hints) = hints {
+        tracker.expect_rows(hints.iter().sum());
+    }
+
+    let header = Format::new().set_bold();
+    let date = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+    let mut workbook = Workbook::new();
+    for (name, schema, rows) in sheets {
+        let mut columns: Vec<&String> = schema.columns.iter().collect();
+        columns.sort();
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(&name).map_err(to_err)?;
+        sheet.write_string_with_format(0, 0, "row_id", &header).map_err(to_err)?;
+        for (index, column) in columns.iter().enumerate() {
+            sheet.write_string_with_format(0, index as ColNum + 1, column.as_str(), &header).map_err(to_err)?;
+        }
+        sheet.set_freeze_panes(1, 0).map_err(to_err)?;
+        for (index, (row_id, row)) in rows.enumerate() {
+            let line = index as RowNum + 1;
+            sheet.write_string(line, 0, row_id.as_str()).map_err(to_err)?;
+            for (index, column) in columns.iter().enumerate() {
+                let value = row.get(column.as_str()).unwrap_or(&Value::Null);
+                write_cell(sheet, line, index as ColNum + 1, value, &date).map_err(to_err)?;
+            }
+            tracker.advance(1, 0);
+        }
+        sheet.autofit();
+    }
+    workbook.save(file_name).map_err(to_err)?;
+    Ok(tracker.finish())
+}
+
+/// Write `value` typed as it is; NULL leaves the cell blank. Values Excel cannot hold as their
+/// type (huge integers, NaN, dates outside 1900-9999) are written as their text.
+fn write_cell(sheet: &mut Worksheet, row: RowNum, column: ColNum, value: &Value, date: &Format) -> std::result::Result<(), XlsxError> {
+    match value {
+        Value::Null => return Ok(()),
+        Value::Int(i) if i.unsigned_abs() <= EXACT_INT => sheet.write_number(row, column, *i as f64),
+        Value::Float(x) if x.is_finite() => sheet.write_number(row, column, *x),
+        Value::Bool(b) => sheet.write_boolean(row, column, *b),
+        Value::Timestamp(secs) => match ExcelDateTime::from_timestamp(*secs) {
+            Ok(datetime) => sheet.write_datetime_with_format(row, column, datetime, date),
+            Err(_) => sheet.write_string(row, column, value.to_string()),
+        },
+        _ => sheet.write_string(row, column, value.to_string()),
+    }?;
+    Ok(())
+}
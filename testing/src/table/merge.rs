@@ -0,0 +1,184 @@
+use super::table::{self, Row, Table};
+use super::value::Value;
+use crate::commands::changes::ChangeKind;
+use crate::commands::db::Result;
+use std::collections::BTreeSet;
+
+/// Two sides changed the same row (`column: None`) or cell differently since the common base.
+/// A `None` value means the row is deleted (or the cell NULL) on that side; values are textual.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub row_id: String,
+    pub column: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+}
+
+/// Which side's version a resolver keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+}
+
+/// Merged table plus every conflict that had to be resolved, in row order.
+#[derive(Debug)]
+pub struct MergeOutcome {
+    pub table: Table,
+    pub conflicts: Vec<(Conflict, Resolution)>,
+}
+
+/// Last-writer-wins, with `theirs` being the later writer (e.g. the offline copy being merged in).
+pub fn last_writer_wins(_conflict: &Conflict) -> Resolution {
+    Resolution::Theirs
+}
+
+/// Replay the WAL entries for `table_name` on top of `base`; entries for other tables are skipped.
+/// After a rename, the table's entries are the ones under its new name.
+/// Fails on a value that does not fit its column's type.
+pub fn apply_wal_segment(base: &Table, table_name: &str, entries: &[String]) -> Result<Table> {
+    let mut table = base.clone();
+    let mut table_name = table_name.to_string();
+    for (name, kind) in entries.iter().filter_map(|entry| ChangeKind::parse(entry)) {
+        if name != table_name {
+            continue;
+        }
+        match kind {
+            ChangeKind::RenameTable { new_name } => table_name = new_name,
+            ChangeKind::CreateTable | ChangeKind::CopyTable { .. } => {}
+            ChangeKind::DropTable => table = Table::new(),
+            ChangeKind::AddColumn { column, spec } => {
+                table.apply_spec(&column, &spec)?;
+                table.fill_default(&column);
+            }
+            ChangeKind::DropColumn { column } => {
+                table.drop_column(&column);
+            }
+            ChangeKind::RenameColumn { column, new_name } => {
+                if !table.columns.contains(&new_name) {
+                    table.rename_column(&column, &new_name);
+                }
+            }
+            ChangeKind::AlterColumn { column, column_type } => {
+                let conversion = table.convert_column(&column, column_type);
+                table.set_column_type(&column, column_type, conversion.converted);
+            }
+            ChangeKind::Insert { row_id, data } | ChangeKind::Upsert { row_id, data } => table.insert_row(&row_id, data)?,
+            ChangeKind::Replace { row_id, data } => {
+                table.restore_row(&row_id, None);
+                table.insert_row(&row_id, data)?;
+            }
+            ChangeKind::Update { row_id, column, value } => {
+                table.add_column(&column);
+                table.set_value(&row_id, &column, &value)?;
+            }
+            ChangeKind::UpdateMulti { row_id, values } => {
+                for column in values.keys() {
+                    table.add_column(column);
+                }
+                if table.get_row(&row_id).is_some() {
+                    table.insert_row(&row_id, values)?;
+                }
+            }
+            ChangeKind::Delete { row_id } => {
+                table.delete_row(&row_id);
+            }
+            ChangeKind::Truncate => {
+                table.truncate();
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// Rows never store `Null`, but filter it anyway so a NULL cell and a missing one always agree.
+fn cell<'a>(row: Option<&'a Row>, column: &str) -> Option<&'a Value> {
+    row?.get(column).filter(|v| !v.is_null())
+}
+
+fn same_row(a: Option<&Row>, b: Option<&Row>, columns: &BTreeSet<&String>) -> bool {
+    a.is_some() == b.is_some() && columns.iter().all(|c| cell(a, c) == cell(b, c))
+}
+
+/// Three-way merge of two divergent versions of a table.
+/// A row or cell changed on one side only takes that change; changed differently on both sides
+/// it is a conflict, settled by `resolve`. Without a `base`, a row present on only one side is
+/// kept (it cannot be told apart from an insert) and every differing cell is a conflict.
+pub fn merge_tables(
+    base: Option<&Table>,
+    ours: &Table,
+    theirs: &Table,
+    resolve: impl Fn(&Conflict) -> Resolution,
+) -> MergeOutcome {
+    let mut merged = Table::new();
+    let columns: BTreeSet<&String> = ours.columns.iter().chain(&theirs.columns).collect();
+    for column in &columns {
+        // Our declaration wins for a column both sides have.
+        let side = if ours.columns.contains(*column) { ours } else { theirs };
+        merged.add_typed_column(column, side.column_type(column));
+        if let Some(default) = side.defaults.get(*column) {
+            merged.defaults.insert(column.to_string(), default.clone());
+        }
+        if side.not_null.contains(*column) {
+            merged.not_null.insert(column.to_string());
+        }
+    }
+    // So does our primary key.
+    if let Some(primary_key) = ours.primary_key.as_ref().or(theirs.primary_key.as_ref()) {
+        merged.set_primary_key(primary_key);
+    }
+
+    let row_ids: BTreeSet<&String> = ours.rows.keys().chain(theirs.rows.keys()).collect();
+    let mut conflicts = Vec::new();
+    for row_id in row_ids {
+        let (o, t) = (ours.rows.get(row_id), theirs.rows.get(row_id));
+        let b = base.and_then(|base| base.rows.get(row_id));
+        // Without a base, a row missing on one side is treated as never having existed there.
+        let base_known = base.is_some() || o.is_none() || t.is_none();
+
+        let row = if same_row(o, t, &columns) {
+            o.cloned()
+        } else if base_known && same_row(o, b, &columns) {
+            t.cloned()
+        } else if base_known && same_row(t, b, &columns) {
+            o.cloned()
+        } else if let (Some(o), Some(t)) = (o, t) {
+            // Both sides kept the row: merge it cell by cell.
+            let mut row = Row::new();
+            for column in &columns {
+                let (oc, tc, bc) = (cell(Some(o), column), cell(Some(t), column), cell(b, column));
+                let value = if oc == tc || (b.is_some() && tc == bc) {
+                    oc
+                } else if b.is_some() && oc == bc {
+                    tc
+                } else {
+                    let conflict = Conflict {
+                        row_id: row_id.clone(),
+                        column: Some(column.to_string()),
+                        ours: oc.map(Value::to_string),
+                        theirs: tc.map(Value::to_string),
+                    };
+                    let resolution = resolve(&conflict);
+                    conflicts.push((conflict, resolution));
+                    if resolution == Resolution::Ours { oc } else { tc }
+                };
+                if let Some(value) = value {
+                    row.insert(table::intern(column), value.clone());
+                }
+            }
+            Some(row)
+        } else {
+            // Deleted on one side, changed on the other.
+            let describe = |row: Option<&Row>| row.map(|r| serde_json::to_string(&table::row_to_text(r)).unwrap());
+            let conflict = Conflict { row_id: row_id.clone(), column: None, ours: describe(o), theirs: describe(t) };
+            let resolution = resolve(&conflict);
+            conflicts.push((conflict, resolution));
+            if resolution == Resolution::Ours { o.cloned() } else { t.cloned() }
+        };
+
+        if let Some(row) = row {
+            merged.insert_values(row_id, row);
+        }
+    }
+    MergeOutcome { table: merged, conflicts }
+}
@@ -1 +1,7 @@
-pub mod table;
\ No newline at end of file
+pub mod merge;
+pub mod pattern;
+pub mod rows;
+#[allow(clippy::module_inception)]
+pub mod table;
+pub mod tables;
+pub mod value;
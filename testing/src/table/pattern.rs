@@ -0,0 +1,65 @@
+use super::value::Value;
+use regex::{Regex, RegexBuilder};
+
+/// **Text matching in conditions**
+/// `<column> <operator> <pattern>` compares the textual form of a cell:
+/// - `CONTAINS` / `ICONTAINS`: the pattern appears anywhere in it
+/// - `LIKE` / `ILIKE`: SQL glob over the whole value, `%` for any run of characters and `_` for one
+///   (`\%` and `\_` match them literally)
+/// - `~` / `~*`: regular expression, matching anywhere unless anchored with `^`/`$`
+///
+/// The `I` forms and `~*` ignore case. The pattern may be wrapped in single quotes, as in
+/// `name LIKE 'a%'`; it cannot contain spaces. NULL never matches.
+#[derive(Debug, Clone)]
+pub struct TextPattern {
+    regex: Regex,
+}
+
+impl TextPattern {
+    /// The pattern for `operator`, or `None` if it is not a text matching operator.
+    /// Fails if a regular expression does not compile.
+    pub fn parse(operator: &str, pattern: &str) -> Option<Result<TextPattern, regex::Error>> {
+        let pattern = pattern
+            .strip_prefix('\'')
+            .and_then(|p| p.strip_suffix('\''))
+            .unwrap_or(pattern);
+        let (source, case_insensitive) = match operator.to_uppercase().as_str() {
+            "CONTAINS" => (regex::escape(pattern), false),
+            "ICONTAINS" => (regex::escape(pattern), true),
+            "LIKE" => (like_to_regex(pattern), false),
+            "ILIKE" => (like_to_regex(pattern), true),
+            "~" => (pattern.to_string(), false),
+            "~*" => (pattern.to_string(), true),
+            _ => return None,
+        };
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(case_insensitive)
+            .dot_matches_new_line(true)
+            .build();
+        Some(regex.map(|regex| TextPattern { regex }))
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        !value.is_null() && self.regex.is_match(&value.to_string())
+    }
+}
+
+/// Translate a LIKE pattern into an anchored regular expression.
+fn like_to_regex(pattern: &str) -> String {
+    let mut source = String::from("^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => source.push_str(".*"),
+            '_' => source.push('.'),
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    source.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => source.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    source.push('$');
+    source
+}
@@ -0,0 +1,189 @@
+use super::table::Row;
+use rayon::iter::{Either, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::collections::btree_map::{self, BTreeMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::iter::Peekable;
+use std::ops::Index;
+use std::sync::Arc;
+
+/// Most shards a table's rows can be split across (see `Rows::reshard`).
+pub const MAX_ROW_SHARDS: usize = 64;
+
+/// **Rows of a table**
+/// row_id -> row, split across shards: maps of their own, each shared between the versions of
+/// the table until one of them changes it, so a write copies at most the shard of its row. Which
+/// shard holds a row follows from a hash of its row_id; with one shard (the default) nothing is
+/// hashed. Iterating goes through every shard at once, so rows still come in row_id order.
+#[derive(Debug, Clone)]
+pub struct Rows {
+    shards: Vec<Arc<BTreeMap<String, Row>>>,
+}
+
+impl Default for Rows {
+    fn default() -> Self {
+        Rows::with_shards(1)
+    }
+}
+
+impl Rows {
+    /// No rows, in `shards` shards (at least 1, at most `MAX_ROW_SHARDS`).
+    pub fn with_shards(shards: usize) -> Self {
+        Rows { shards: (0..shards.clamp(1, MAX_ROW_SHARDS)).map(|_| Arc::default()).collect() }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Spread the rows across `shards` shards instead (clamped as in `with_shards`).
+    pub fn reshard(&mut self, shards: usize) {
+        if shards.clamp(1, MAX_ROW_SHARDS) == self.shards.len() {
+            return;
+        }
+        let mut rows = Rows::with_shards(shards);
+        for shard in std::mem::take(&mut self.shards) {
+            for (row_id, row) in Arc::unwrap_or_clone(shard) {
+                rows.insert(row_id, row);
+            }
+        }
+        *self = rows;
+    }
+
+    /// The shard that holds (or would hold) row `row_id`.
+    pub fn shard_of(&self, row_id: &str) -> usize {
+        match self.shards.len() {
+            1 => 0,
+            shards => {
+                let mut hasher = DefaultHasher::new();
+                row_id.hash(&mut hasher);
+                (hasher.finish() % shards as u64) as usize
+            }
+        }
+    }
+
+    /// Shard `shard` of `other`, the same table in another version, in place of this one's.
+    pub fn take_shard(&mut self, other: &Rows, shard: usize) {
+        self.shards[shard] = Arc::clone(&other.shards[shard]);
+    }
+
+    pub fn get(&self, row_id: &str) -> Option<&Row> {
+        self.shards[self.shard_of(row_id)].get(row_id)
+    }
+
+    pub fn get_mut(&mut self, row_id: &str) -> Option<&mut Row> {
+        let shard = self.shard_of(row_id);
+        Arc::make_mut(&mut self.shards[shard]).get_mut(row_id)
+    }
+
+    pub fn contains_key(&self, row_id: &str) -> bool {
+        self.shards[self.shard_of(row_id)].contains_key(row_id)
+    }
+
+    pub fn insert(&mut self, row_id: String, row: Row) -> Option<Row> {
+        let shard = self.shard_of(&row_id);
+        Arc::make_mut(&mut self.shards[shard]).insert(row_id, row)
+    }
+
+    /// Row `row_id`, inserted empty if there is none.
+    pub fn get_or_insert(&mut self, row_id: &str) -> &mut Row {
+        let shard = self.shard_of(row_id);
+        Arc::make_mut(&mut self.shards[shard]).entry(row_id.to_string()).or_default()
+    }
+
+    pub fn remove(&mut self, row_id: &str) -> Option<Row> {
+        let shard = self.shard_of(row_id);
+        if !self.shards[shard].contains_key(row_id) {
+            return None;
+        }
+        Arc::make_mut(&mut self.shards[shard]).remove(row_id)
+    }
+
+    /// Remove every row, keeping the shards. Returns how many there were.
+    pub fn clear(&mut self) -> usize {
+        let len = self.len();
+        for shard in &mut self.shards {
+            *shard = Arc::default();
+        }
+        len
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+
+    /// Every row in row_id order.
+    pub fn iter(&self) -> Iter<'_> {
+        match &self.shards[..] {
+            [shard] => Iter::Single(shard.iter()),
+            shards => Iter::Merge(shards.iter().map(|shard| shard.iter().peekable()).collect()),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(row_id, _)| row_id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Row> {
+        self.iter().map(|(_, row)| row)
+    }
+
+    /// Every row for changing, shard by shard (so not in row_id order).
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Row> {
+        self.shards.iter_mut().flat_map(|shard| Arc::make_mut(shard).values_mut())
+    }
+
+    /// `iter` handed to the threads of the rayon pool; collected, the rows keep their order.
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = (&String, &Row)> {
+        match &self.shards[..] {
+            [shard] => Either::Left(shard.par_iter()),
+            _ => Either::Right(self.iter().collect::<Vec<_>>().into_par_iter()),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Rows {
+    type Item = (&'a String, &'a Row);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl Index<&str> for Rows {
+    type Output = Row;
+
+    fn index(&self, row_id: &str) -> &Row {
+        self.get(row_id).expect("no row with that row_id")
+    }
+}
+
+/// The rows of every shard in row_id order (see `Rows::iter`).
+pub enum Iter<'a> {
+    Single(btree_map::Iter<'a, String, Row>),
+    Merge(Vec<Peekable<btree_map::Iter<'a, String, Row>>>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a Row);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Single(rows) => rows.next(),
+            Iter::Merge(shards) => {
+                let next = shards
+                    .iter_mut()
+                    .enumerate()
+                    .filter_map(|(shard, rows)| Some((shard, rows.peek()?.0)))
+                    .min_by(|(_, a), (_, b)| a.cmp(b))?
+                    .0;
+                shards[next].next()
+            }
+        }
+    }
+}
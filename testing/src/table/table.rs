@@ -1,96 +1,694 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use super::rows::Rows;
+use super::value::{ColumnType, Value, NULL_TEXT};
+use crate::commands::db::{DatabaseError, Result};
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::sync::{Arc, LazyLock};
 
-#[derive(Debug)]
+/// column_name -> typed value. A column missing from the row is NULL; `Value::Null` is never stored.
+/// The names are interned (see `intern`), so every row holds the same copy of each.
+pub type Row = HashMap<Arc<str>, Value>;
+
+/// Every column name a row has held, once; see `intern`.
+static COLUMN_NAMES: LazyLock<RwLock<HashSet<Arc<str>>>> = LazyLock::new(RwLock::default);
+
+/// The one shared copy of column name `name`, for rows to key their values by: a table of many
+/// rows then keeps each name once rather than once a row. Names stay for the life of the process,
+/// which is fine for the few a schema has.
+pub fn intern(name: &str) -> Arc<str> {
+    if let Some(name) = COLUMN_NAMES.read().get(name) {
+        return Arc::clone(name);
+    }
+    let mut names = COLUMN_NAMES.write();
+    match names.get(name) {
+        Some(name) => Arc::clone(name),
+        None => {
+            let name: Arc<str> = Arc::from(name);
+            names.insert(Arc::clone(&name));
+            name
+        }
+    }
+}
+
+/// Rows `Table::approximate_bytes` measures to estimate the size of the rest.
+const SIZE_SAMPLE_ROWS: usize = 64;
+
+/// Timestamp column holding when a row expires, added to a table the first time one of its rows
+/// gets a TTL. Rows without a value in it never expire.
+pub const EXPIRES_COLUMN: &str = "_expires_at";
+
+/// Timestamp column marking a row as soft-deleted, and when. It is added to a table the first time
+/// one of its rows is soft-deleted; such rows stay stored, skipped by queries, until restored or purged.
+pub const DELETED_COLUMN: &str = "_deleted_at";
+
+/// How a column is declared besides its name. Storage and the WAL keep it as
+/// `<name>[:<type>][*][!][=<default>]`, e.g. `age:int!=0` for a NOT NULL int defaulting to 0
+/// and `email*` for a text primary key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnSpec {
+    pub column_type: ColumnType,
+    pub not_null: bool,
+    pub default: Option<String>,
+    /// Unique and NOT NULL; lookups by it go through an index.
+    pub primary_key: bool,
+}
+
+impl ColumnSpec {
+    /// Split a declaration into the column name and its spec; `None` for an unknown type.
+    pub fn parse_declaration(declaration: &str) -> Option<(&str, ColumnSpec)> {
+        // The default goes last and may itself contain ':' (timestamps do).
+        let (column, default) = match declaration.split_once('=') {
+            Some((column, default)) => (column, Some(default.to_string())),
+            None => (declaration, None),
+        };
+        let (column, not_null) = match column.strip_suffix('!') {
+            Some(column) => (column, true),
+            None => (column, false),
+        };
+        let (column, primary_key) = match column.strip_suffix('*') {
+            Some(column) => (column, true),
+            None => (column, false),
+        };
+        let (name, column_type) = match column.split_once(':') {
+            Some((name, ty)) => (name, ColumnType::parse(ty)?),
+            None => (column, ColumnType::Text),
+        };
+        Some((name, ColumnSpec { column_type, not_null: not_null || primary_key, default, primary_key }))
+    }
+
+    /// The declaration `parse_declaration` reads back; plain text columns stay bare for older readers.
+    pub fn declaration(&self, column: &str) -> String {
+        let mut declaration = column.to_string();
+        if self.column_type != ColumnType::Text {
+            declaration.push(':');
+            declaration.push_str(self.column_type.name());
+        }
+        if self.primary_key {
+            declaration.push('*');
+        } else if self.not_null {
+            declaration.push('!');
+        }
+        if let Some(default) = &self.default {
+            declaration.push('=');
+            declaration.push_str(default);
+        }
+        declaration
+    }
+}
+
+/// A column's cells converted to another type, as planned by `Table::convert_column`.
+#[derive(Debug, Default)]
+pub struct Conversion {
+    /// (row_id, converted value)
+    pub converted: Vec<(String, Value)>,
+    /// (row_id, textual value) of every cell that does not convert
+    pub failed: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Table {
     pub columns: HashSet<String>,  // List of allowed column names
-    pub rows: BTreeMap<String, HashMap<String, String>>, // row_id -> { column_name -> value }
+    pub column_types: HashMap<String, ColumnType>, // Declared types; undeclared columns are text
+    pub defaults: HashMap<String, Value>, // Values new rows get for columns they do not supply
+    pub not_null: HashSet<String>, // Columns every row must have a value for
+    pub primary_key: Option<String>, // Column whose values identify rows besides their row_id
+    pk_index: Arc<HashMap<String, String>>, // primary key value (textual) -> row_id; shared between versions until changed
+    pub rows: Rows, // row_id -> { column_name -> value }
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Table::new()
+    }
 }
 
 impl Table {
     pub fn new() -> Self {
         Table {
             columns: HashSet::new(),
-            rows: BTreeMap::new(),
+            column_types: HashMap::new(),
+            defaults: HashMap::new(),
+            not_null: HashSet::new(),
+            primary_key: None,
+            pk_index: Arc::default(),
+            rows: Rows::default(),
         }
     }
 
-    /// Add a new column to the table. Existing rows do not automatically get a value for this column.
+    /// Add a new text column to the table. Existing rows do not automatically get a value for this column.
     pub fn add_column(&mut self, column_name: &str) {
-        self.columns.insert(column_name.to_string());
+        self.add_typed_column(column_name, ColumnType::Text);
+    }
+
+    /// Add a new column holding values of `column_type`. An existing column keeps its type.
+    pub fn add_typed_column(&mut self, column_name: &str, column_type: ColumnType) {
+        if self.columns.insert(column_name.to_string()) {
+            self.column_types.insert(column_name.to_string(), column_type);
+        }
+    }
+
+    /// Remove a column, its constraints and its value in every row. Returns false if there is no such column.
+    pub fn drop_column(&mut self, column_name: &str) -> bool {
+        if !self.columns.remove(column_name) {
+            return false;
+        }
+        self.column_types.remove(column_name);
+        self.defaults.remove(column_name);
+        self.not_null.remove(column_name);
+        if self.primary_key.as_deref() == Some(column_name) {
+            self.primary_key = None;
+            self.pk_index = Arc::default();
+        }
+        for row in self.rows.values_mut() {
+            row.remove(column_name);
+        }
+        true
+    }
+
+    /// Rename a column, keeping its type, constraints and values. Returns false if there is no
+    /// column `old_name`; the caller makes sure `new_name` is not taken.
+    pub fn rename_column(&mut self, old_name: &str, new_name: &str) -> bool {
+        if !self.columns.remove(old_name) {
+            return false;
+        }
+        self.columns.insert(new_name.to_string());
+        if let Some(column_type) = self.column_types.remove(old_name) {
+            self.column_types.insert(new_name.to_string(), column_type);
+        }
+        if let Some(default) = self.defaults.remove(old_name) {
+            self.defaults.insert(new_name.to_string(), default);
+        }
+        if self.not_null.remove(old_name) {
+            self.not_null.insert(new_name.to_string());
+        }
+        // The index maps key values to row_ids, so only the column it is on changes.
+        if self.primary_key.as_deref() == Some(old_name) {
+            self.primary_key = Some(new_name.to_string());
+        }
+        for row in self.rows.values_mut() {
+            if let Some(value) = row.remove(old_name) {
+                row.insert(intern(new_name), value);
+            }
+        }
+        true
+    }
+
+    pub fn column_type(&self, column_name: &str) -> ColumnType {
+        self.column_types.get(column_name).copied().unwrap_or_default()
+    }
+
+    /// Record the default of `column_name`; a NULL default removes it. `Database` applies defaults
+    /// when it writes rows, so loading stored rows never fills in cells that were left NULL.
+    pub fn set_default(&mut self, column_name: &str, text: &str) -> Result<()> {
+        match self.parse_value(column_name, text)? {
+            Value::Null => self.defaults.remove(column_name),
+            default => self.defaults.insert(column_name.to_string(), default),
+        };
+        Ok(())
+    }
+
+    /// Give every existing row without a value for `column_name` the column's default.
+    pub fn fill_default(&mut self, column_name: &str) {
+        if let Some(default) = self.defaults.get(column_name) {
+            for row in self.rows.values_mut() {
+                row.entry(intern(column_name)).or_insert_with(|| default.clone());
+            }
+        }
+    }
+
+    /// Add a column as declared by `spec`, or apply `spec`'s constraints to an existing column
+    /// (which keeps its type). Rows are neither filled nor checked; see `Database::add_typed_column`.
+    pub fn apply_spec(&mut self, column_name: &str, spec: &ColumnSpec) -> Result<()> {
+        self.add_typed_column(column_name, spec.column_type);
+        if let Some(default) = &spec.default {
+            self.set_default(column_name, default)?;
+        }
+        if spec.not_null {
+            self.not_null.insert(column_name.to_string());
+        }
+        if spec.primary_key {
+            self.set_primary_key(column_name);
+        }
+        Ok(())
+    }
+
+    /// Make `column_name` the primary key (and NOT NULL), indexing the rows' current values.
+    /// Uniqueness is not checked here; see `Database::add_typed_column`.
+    pub fn set_primary_key(&mut self, column_name: &str) {
+        self.not_null.insert(column_name.to_string());
+        if self.primary_key.as_deref() != Some(column_name) {
+            self.primary_key = Some(column_name.to_string());
+            self.rebuild_index();
+        }
+    }
+
+    fn rebuild_index(&mut self) {
+        let Some(column) = &self.primary_key else {
+            return;
+        };
+        self.pk_index = Arc::new(
+            self.rows.iter().filter_map(|(row_id, row)| Some((row.get(column.as_str())?.to_string(), row_id.clone()))).collect(),
+        );
+    }
+
+    /// Plan converting `column_name` to `column_type` (see `Value::convert`) without changing anything.
+    pub fn convert_column(&self, column_name: &str, column_type: ColumnType) -> Conversion {
+        let mut conversion = Conversion::default();
+        for (row_id, row) in &self.rows {
+            if let Some(value) = row.get(column_name) {
+                match value.convert(column_type) {
+                    Some(value) => conversion.converted.push((row_id.clone(), value)),
+                    None => conversion.failed.push((row_id.clone(), value.to_string())),
+                }
+            }
+        }
+        conversion
+    }
+
+    /// Change the type of `column_name`, storing the `converted` cells from `convert_column` and
+    /// clearing every other cell of the column. A default that does not convert is dropped.
+    pub fn set_column_type(&mut self, column_name: &str, column_type: ColumnType, converted: Vec<(String, Value)>) {
+        self.column_types.insert(column_name.to_string(), column_type);
+        if let Some(default) = self.defaults.remove(column_name) {
+            if let Some(default) = default.convert(column_type) {
+                self.defaults.insert(column_name.to_string(), default);
+            }
+        }
+        for row in self.rows.values_mut() {
+            row.remove(column_name);
+        }
+        for (row_id, value) in converted {
+            if let Some(row) = self.rows.get_mut(&row_id) {
+                row.insert(intern(column_name), value);
+            }
+        }
+        if self.primary_key.as_deref() == Some(column_name) {
+            self.rebuild_index();
+        }
+    }
+
+    /// Type the text columns of an imported table by their values: each gets the type its first
+    /// `sample` values suggest (see `ColumnType::infer`), unless a later value does not fit it,
+    /// in which case it stays text. Empty values become NULL. Returns the columns that changed type.
+    pub fn infer_types(&mut self, sample: usize) -> Vec<(String, ColumnType)> {
+        let mut columns: Vec<String> = self.columns.iter().filter(|column| self.column_type(column) == ColumnType::Text).cloned().collect();
+        columns.sort();
+        let mut inferred = Vec::new();
+        for column in columns {
+            let values: Vec<String> = self.rows.values().filter_map(|row| row.get(column.as_str())).take(sample).map(Value::to_string).collect();
+            let column_type = ColumnType::infer(values.iter().map(String::as_str));
+            if column_type == ColumnType::Text {
+                continue;
+            }
+            let Conversion { converted, failed } = self.convert_column(&column, column_type);
+            if failed.iter().all(|(_, text)| text.is_empty()) {
+                self.set_column_type(&column, column_type, converted);
+                inferred.push((column, column_type));
+            }
+        }
+        inferred
+    }
+
+    /// Add the column described by a stored declaration (see `ColumnSpec::parse_declaration`)
+    /// and return its name.
+    pub fn declare_column<'a>(&mut self, declaration: &'a str) -> Result<&'a str> {
+        let (column, spec) = ColumnSpec::parse_declaration(declaration)
+            .ok_or_else(|| DatabaseError::UnknownColumnType(declaration.to_string()))?;
+        self.apply_spec(column, &spec)?;
+        Ok(column)
+    }
+
+    /// How `column_name` is currently declared.
+    pub fn spec(&self, column_name: &str) -> ColumnSpec {
+        ColumnSpec {
+            column_type: self.column_type(column_name),
+            not_null: self.not_null.contains(column_name),
+            default: self.defaults.get(column_name).map(Value::to_string),
+            primary_key: self.primary_key.as_deref() == Some(column_name),
+        }
+    }
+
+    /// How `column_name` is declared, with its type and constraints, e.g. `age:int!=0`.
+    pub fn declaration(&self, column_name: &str) -> String {
+        self.spec(column_name).declaration(column_name)
+    }
+
+    /// Fail if row `row_id` lacks a value for a NOT NULL column.
+    pub fn check_not_null(&self, table_name: &str, row_id: &str) -> Result<()> {
+        let Some(row) = self.rows.get(row_id) else {
+            return Ok(());
+        };
+        let mut missing: Vec<&String> = self.not_null.iter().filter(|col| !row.contains_key(col.as_str())).collect();
+        missing.sort();
+        match missing.first() {
+            Some(column) => Err(DatabaseError::ConstraintViolation(format!(
+                "column '{}' of table '{}' is NOT NULL but row '{}' has no value for it",
+                column, table_name, row_id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail if `key` cannot be the primary key of row `row_id`: it is NULL or another row has it.
+    pub fn check_primary_key(&self, table_name: &str, row_id: &str, key: Option<&Value>) -> Result<()> {
+        let Some(column) = &self.primary_key else {
+            return Ok(());
+        };
+        match key.filter(|key| !key.is_null()) {
+            None => Err(DatabaseError::ConstraintViolation(format!(
+                "primary key '{}' of table '{}' cannot be NULL (row '{}')",
+                column, table_name, row_id
+            ))),
+            Some(key) => match self.pk_index.get(&key.to_string()) {
+                Some(owner) if owner != row_id => Err(DatabaseError::ConstraintViolation(format!(
+                    "duplicate primary key '{}' in table '{}': row '{}' already has it",
+                    key, table_name, owner
+                ))),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Fail if `value` cannot go in `column_name` of row `row_id`: it is NULL in a NOT NULL
+    /// column, or a primary key another row has.
+    pub fn check_cell(&self, table_name: &str, row_id: &str, column_name: &str, value: &Value) -> Result<()> {
+        if self.not_null.contains(column_name) && value.is_null() {
+            return Err(DatabaseError::ConstraintViolation(format!(
+                "column '{}' of table '{}' is NOT NULL", column_name, table_name
+            )));
+        }
+        if self.primary_key.as_deref() == Some(column_name) {
+            self.check_primary_key(table_name, row_id, Some(value))?;
+        }
+        Ok(())
+    }
+
+    /// The row_id of the row whose primary key is `key`, using the primary key index.
+    pub fn row_id_for_key(&self, key: &Value) -> Option<&String> {
+        self.pk_index.get(&key.to_string())
+    }
+
+    fn unindex_row(&mut self, row_id: &str) {
+        let Some(column) = &self.primary_key else {
+            return;
+        };
+        if let Some(key) = self.rows.get(row_id).and_then(|row| row.get(column.as_str())) {
+            let key = key.to_string();
+            if self.pk_index.get(&key).is_some_and(|owner| owner == row_id) {
+                Arc::make_mut(&mut self.pk_index).remove(&key);
+            }
+        }
+    }
+
+    fn index_row(&mut self, row_id: &str) {
+        let Some(column) = &self.primary_key else {
+            return;
+        };
+        if let Some(key) = self.rows.get(row_id).and_then(|row| row.get(column.as_str())) {
+            Arc::make_mut(&mut self.pk_index).insert(key.to_string(), row_id.to_string());
+        }
+    }
+
+    /// Column declarations in alphabetical order, as storage engines keep them.
+    pub fn column_declarations(&self) -> Vec<String> {
+        let mut columns: Vec<&String> = self.columns.iter().collect();
+        columns.sort();
+        columns.into_iter().map(|col| self.declaration(col)).collect()
+    }
+
+    /// Parse a textual cell for `column_name`, failing if it is not a valid value of the column's type.
+    pub fn parse_value(&self, column_name: &str, text: &str) -> Result<Value> {
+        let column_type = self.column_type(column_name);
+        Value::parse(text, column_type).ok_or_else(|| {
+            DatabaseError::InvalidValue(text.to_string(), column_name.to_string(), column_type)
+        })
     }
 
     /// Insert or update a row with (column -> value) pairs; restrict columns to those known in `columns`.
-    pub fn insert_row(&mut self, row_id: &str, data: HashMap<String, String>) {
+    /// Every value is parsed with its column's type first, so a bad value changes nothing.
+    pub fn insert_row(&mut self, row_id: &str, data: HashMap<String, String>) -> Result<()> {
         // Only allow data for columns that exist in this table.
-        let valid_data: HashMap<String, String> = data
-            .into_iter()
-            .filter(|(col, _)| self.columns.contains(col))
-            .collect();
+        let mut valid_data = Row::new();
+        for (col, text) in data.into_iter().filter(|(col, _)| self.columns.contains(col)) {
+            let value = self.parse_value(&col, &text)?;
+            valid_data.insert(intern(&col), value);
+        }
+        self.insert_values(row_id, valid_data);
+        Ok(())
+    }
 
-        // Upsert (insert if none, update if it exists).
-        self.rows
-            .entry(row_id.to_string())
-            .and_modify(|existing| {
-                for (col, val) in &valid_data {
-                    existing.insert(col.clone(), val.clone());
-                }
-            })
-            .or_insert(valid_data);
+    /// Upsert already typed values (insert if none, update if it exists). A `Null` value clears the cell.
+    pub fn insert_values(&mut self, row_id: &str, values: Row) {
+        self.unindex_row(row_id);
+        let row = self.rows.get_or_insert(row_id);
+        for (col, value) in values {
+            if value.is_null() {
+                row.remove(&col);
+            } else {
+                row.insert(col, value);
+            }
+        }
+        self.index_row(row_id);
+    }
+
+    /// Put row `row_id` back the way it was (`None`: absent), e.g. to undo a rejected write.
+    pub fn restore_row(&mut self, row_id: &str, previous: Option<Row>) {
+        self.unindex_row(row_id);
+        match previous {
+            Some(row) => {
+                self.rows.insert(row_id.to_string(), row);
+                self.index_row(row_id);
+            }
+            None => {
+                self.rows.remove(row_id);
+            }
+        }
+    }
+
+    /// Set one cell of an existing row from its textual form. Returns false if the row does not exist.
+    pub fn set_value(&mut self, row_id: &str, column_name: &str, text: &str) -> Result<bool> {
+        let value = self.parse_value(column_name, text)?;
+        Ok(self.update_cell(row_id, column_name, value))
+    }
+
+    /// Set one cell of an existing row to `value` where it is stored (`Null` clears it): the rest
+    /// of the row is left as it is, and the primary key index is only touched for the key column.
+    /// Returns false if the row does not exist.
+    pub fn update_cell(&mut self, row_id: &str, column_name: &str, value: Value) -> bool {
+        let is_key = self.primary_key.as_deref() == Some(column_name);
+        if is_key {
+            self.unindex_row(row_id);
+        }
+        let Some(row) = self.rows.get_mut(row_id) else {
+            return false;
+        };
+        if value.is_null() {
+            row.remove(column_name);
+        } else if let Some(cell) = row.get_mut(column_name) {
+            *cell = value;
+        } else {
+            row.insert(intern(column_name), value);
+        }
+        if is_key {
+            self.index_row(row_id);
+        }
+        true
+    }
+
+    /// About how many bytes the rows take in memory, extrapolated from the first
+    /// `SIZE_SAMPLE_ROWS`, so it costs the same however many rows there are.
+    pub fn approximate_bytes(&self) -> usize {
+        let sample: Vec<usize> = self.rows.iter().take(SIZE_SAMPLE_ROWS).map(|(row_id, row)| row_bytes(row_id, row)).collect();
+        match sample.len() {
+            0 => 0,
+            sampled => sample.iter().sum::<usize>() * self.rows.len() / sampled,
+        }
     }
 
     /// Retrieve data for a specific row.
-    pub fn get_row(&self, row_id: &str) -> Option<&HashMap<String, String>> {
+    pub fn get_row(&self, row_id: &str) -> Option<&Row> {
         self.rows.get(row_id)
     }
+    /// The row, unless it does not exist or is soft-deleted.
+    pub fn live_row(&self, row_id: &str) -> Option<&Row> {
+        self.get_row(row_id).filter(|row| !row.contains_key(DELETED_COLUMN))
+    }
+
+    /// Every row that is not soft-deleted.
+    pub fn live_rows(&self) -> impl Iterator<Item = (&String, &Row)> {
+        self.rows.iter().filter(|(_, row)| !row.contains_key(DELETED_COLUMN))
+    }
+
+    /// `live_rows` (every row with `deleted`) split into ranges handed to the threads of the
+    /// rayon pool. Collected, the rows keep their row_id order.
+    pub fn par_rows(&self, deleted: bool) -> impl ParallelIterator<Item = (&String, &Row)> {
+        self.rows.par_iter().filter(move |(_, row)| deleted || !row.contains_key(DELETED_COLUMN))
+    }
+
+    /// An empty table declaring only `columns` (those it has), with their types and constraints.
+    /// Rows rendered through it (`row_to_json`) show just those columns.
+    pub fn projection(&self, columns: &[&str]) -> Table {
+        let mut projection = Table::new();
+        for &column in columns.iter().filter(|column| self.columns.contains(**column)) {
+            projection.add_typed_column(column, self.column_type(column));
+            if let Some(default) = self.defaults.get(column) {
+                projection.defaults.insert(column.to_string(), default.clone());
+            }
+            if self.not_null.contains(column) {
+                projection.not_null.insert(column.to_string());
+            }
+            if self.primary_key.as_deref() == Some(column) {
+                projection.set_primary_key(column);
+            }
+        }
+        projection
+    }
+
+    /// The cells of `row` in this table's columns.
+    pub fn project_row(&self, row: &Row) -> Row {
+        row.iter()
+            .filter(|(column, _)| self.columns.contains(&column[..]))
+            .map(|(column, value)| (column.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Ids of the rows that expire at or before `now` (Unix seconds).
+    pub fn expired_rows(&self, now: i64) -> Vec<String> {
+        self.rows
+            .iter()
+            .filter(|(_, row)| matches!(row.get(EXPIRES_COLUMN), Some(Value::Timestamp(at)) if *at <= now))
+            .map(|(row_id, _)| row_id.clone())
+            .collect()
+    }
+
     /// Delete a specific row by row_id.
     pub fn delete_row(&mut self, row_id: &str) -> bool {
+        self.unindex_row(row_id);
         self.rows.remove(row_id).is_some()
     }
 
-    /// Print the table contents (for demo).
-    pub fn print_table(&self) {
-        println!("Columns: {:?}", self.columns);
-        for (row_id, row_data) in &self.rows {
-            println!("Row '{}': {:?}", row_id, row_data);
-        }
+    /// Delete every row, keeping the columns and their constraints. Returns how many rows there were.
+    pub fn truncate(&mut self) -> usize {
+        self.pk_index = Arc::default();
+        self.rows.clear()
     }
 
-    pub fn get_table(&self) -> &BTreeMap<String, HashMap<String, String>> {
+    pub fn get_table(&self) -> &Rows {
         &self.rows
     }
+
+    /// A row as a JSON object with typed values and an explicit `null` for every column it lacks.
+    pub fn row_to_json(&self, row: &Row) -> serde_json::Value {
+        let cells = self
+            .columns
+            .iter()
+            .map(|col| (col.clone(), row.get(col.as_str()).map_or(serde_json::Value::Null, Value::to_json)));
+        serde_json::Value::Object(cells.collect())
+    }
+}
+
+/// Bytes one row takes: its row_id, and a slot per cell of its map holding a (shared) column name
+/// and a value, plus the text a value holds.
+fn row_bytes(row_id: &String, row: &Row) -> usize {
+    let slot = std::mem::size_of::<(Arc<str>, Value)>() + 1;
+    let text: usize = row
+        .values()
+        .map(|value| match value {
+            Value::Text(text) => text.capacity(),
+            _ => 0,
+        })
+        .sum();
+    std::mem::size_of::<(String, Row)>() + row_id.capacity() + row.capacity() * slot + text
+}
+
+/// A row in its textual form, as logged to the WAL and written to storage.
+pub fn row_to_text(row: &Row) -> HashMap<String, String> {
+    row.iter().map(|(col, value)| (col.to_string(), value.to_string())).collect()
+}
+
+/// JSON cells in their textual form, as `insert_row` takes them: strings verbatim, `null` as NULL and
+/// anything else (numbers, booleans) as its JSON text.
+pub fn json_to_text(cells: serde_json::Map<String, serde_json::Value>) -> HashMap<String, String> {
+    cells
+        .into_iter()
+        .map(|(col, val)| match val {
+            serde_json::Value::String(s) => (col, s),
+            serde_json::Value::Null => (col, NULL_TEXT.to_string()),
+            other => (col, other.to_string()),
+        })
+        .collect()
+}
+
+/// How `render_grid` lays out rows.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    /// Widest a column may get; longer values are cut short and end in '…'. `None` fits every value.
+    pub max_width: Option<usize>,
+    /// Most rows to show after skipping `offset`; `None` shows the rest.
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+/// Lay out `rows` under `headers` as a text grid, every column as wide as its widest cell (up to
+/// `max_width`). If rows are left out, a last line says which ones are shown.
+pub fn render_grid(headers: &[String], rows: &[Vec<String>], options: &RenderOptions) -> String {
+    let fit = |text: &String| match options.max_width {
+        Some(width) if text.chars().count() > width => {
+            let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+            cut.push('…');
+            cut
+        }
+        _ => text.clone(),
+    };
+    let shown: Vec<Vec<String>> = rows
+        .iter()
+        .skip(options.offset)
+        .take(options.limit.unwrap_or(usize::MAX))
+        .map(|row| row.iter().map(fit).collect())
+        .collect();
+    let headers: Vec<String> = headers.iter().map(fit).collect();
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|i| shown.iter().map(|row| row[i].chars().count()).chain([headers[i].chars().count()]).max().unwrap_or(0))
+        .collect();
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = cells.iter().zip(&widths).map(|(cell, &width)| format!("{:<width$}", cell)).collect();
+        format!("{}\n", padded.join(" | ").trim_end())
+    };
+
+    let mut grid = line(&headers);
+    grid += &format!("{}\n", widths.iter().map(|&width| "-".repeat(width)).collect::<Vec<_>>().join("-+-"));
+    for row in &shown {
+        grid += &line(row);
+    }
+    if shown.len() < rows.len() {
+        grid += &format!("({} of {} rows, from row {})\n", shown.len(), rows.len(), options.offset + 1);
+    }
+    grid
 }
 
+impl Table {
+    /// The rows that are not soft-deleted as a text grid: row ID first, then the columns by name.
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let mut columns: Vec<&String> = self.columns.iter().collect();
+        columns.sort();
+        let headers: Vec<String> = std::iter::once("Row ID".to_string()).chain(columns.iter().map(|c| c.to_string())).collect();
+        let rows: Vec<Vec<String>> = self
+            .live_rows()
+            .map(|(row_id, row)| {
+                let cells = columns.iter().map(|col| row.get(col.as_str()).map_or_else(|| "NULL".to_string(), Value::to_string));
+                std::iter::once(row_id.clone()).chain(cells).collect()
+            })
+            .collect();
+        render_grid(&headers, &rows, options)
+    }
+}
+
+/// The whole table, as `render` lays it out. The precision caps the column width:
+/// `format!("{:.20}", table)` cuts longer values short.
 impl fmt::Display for Table {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Sort columns for predictable order
-        let mut cols: Vec<&String> = self.columns.iter().collect();
-        cols.sort();
-        
-        // Write header row
-        write!(f, "{:<10}", "Row ID")?;
-        for col in &cols {
-            write!(f, " | {:<15}", col)?;
-        }
-        writeln!(f)?;
-        writeln!(f, "{}", "-".repeat(10 + cols.len() * 18))?;
-        
-        // Write each row sorted by row_id
-        let mut row_ids: Vec<&String> = self.rows.keys().collect();
-        row_ids.sort();
-        for row_id in row_ids {
-            write!(f, "{:<10}", row_id)?;
-            for col in &cols {
-                let value = self
-                    .rows
-                    .get(row_id)
-                    .and_then(|r| r.get(col.as_str()))
-                    .cloned()
-                    .unwrap_or_default();
-                write!(f, " | {:<15}", value)?;
-            }
-            writeln!(f)?;
-        }
-        Ok(())
+        f.write_str(&self.render(&RenderOptions { max_width: f.precision(), ..RenderOptions::default() }))
     }
-}
\ No newline at end of file
+}
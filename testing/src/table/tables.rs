@@ -0,0 +1,280 @@
+use super::rows::MAX_ROW_SHARDS;
+use super::table::Table;
+use arc_swap::ArcSwapOption;
+use parking_lot::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A table in memory as it was when taken (see `Tables::get`). A version never changes: a write
+/// makes a new one for the readers that come after, so whoever holds this one keeps reading it.
+pub type TableRef = Arc<Table>;
+
+/// A table in memory held for writing its rows (see `Tables::write`). The first change copies the
+/// version readers have, unless nobody else holds it; readers get the changed table as a new
+/// version once the writer is dropped, never halfway through.
+/// Held for one row only (see `Tables::write_row`), only that row's shard may change: copying the
+/// table then copies just that shard, and the shard alone is put into the latest version.
+pub struct TableWriteRef<'a> {
+    slot: &'a Slot,
+    hold: Hold<'a>,
+    table: TableRef,
+}
+
+enum Hold<'a> {
+    Table { _writer: RwLockWriteGuard<'a, ()> },
+    Shard { shard: usize, _writer: RwLockReadGuard<'a, ()>, _shard_writer: MutexGuard<'a, ()> },
+    // Only while `widen` swaps one for the other.
+    Released,
+}
+
+impl TableWriteRef<'_> {
+    /// Whether writing row `row_id` is allowed: the whole table is held, or the shard of that row.
+    pub fn covers(&self, row_id: &str) -> bool {
+        match self.hold {
+            Hold::Shard { shard, .. } => self.table.rows.shard_of(row_id) == shard,
+            _ => true,
+        }
+    }
+
+    /// Hold the whole table rather than the shard of one row, e.g. to add a column. What was
+    /// written so far goes to the readers first; the table is then taken again as the writers of
+    /// its other shards left it.
+    pub fn widen(&mut self) {
+        if let Hold::Shard { .. } = self.hold {
+            self.publish();
+            self.hold = Hold::Released;
+            self.hold = Hold::Table { _writer: self.slot.writer.write() };
+            self.table = self.slot.current.load_full().expect("a table held for writing is never taken out");
+        }
+    }
+
+    fn publish(&self) {
+        match self.hold {
+            Hold::Shard { shard, .. } => {
+                self.slot.current.rcu(|latest| {
+                    let mut table = Table::clone(latest.as_ref()?);
+                    table.rows.take_shard(&self.table.rows, shard);
+                    Some(Arc::new(table))
+                });
+            }
+            _ => self.slot.current.store(Some(self.table.clone())),
+        }
+    }
+}
+
+impl Deref for TableWriteRef<'_> {
+    type Target = Table;
+
+    fn deref(&self) -> &Table {
+        &self.table
+    }
+}
+
+impl DerefMut for TableWriteRef<'_> {
+    fn deref_mut(&mut self) -> &mut Table {
+        Arc::make_mut(&mut self.table)
+    }
+}
+
+impl Drop for TableWriteRef<'_> {
+    fn drop(&mut self) {
+        self.publish();
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    // The latest version; none while the table is taken out (see `Tables::take`).
+    current: ArcSwapOption<Table>,
+    // Held exclusively by a writer of the whole table, shared by the writers of single rows.
+    writer: RwLock<()>,
+    // One per row shard (see `Rows::shard_of`), held by the writer of a row in it.
+    shard_writers: [Mutex<()>; MAX_ROW_SHARDS],
+    // `Tables::clock` when the table was last read or written.
+    used: AtomicU64,
+}
+
+impl Slot {
+    fn new(table: TableRef) -> Slot {
+        Slot {
+            current: ArcSwapOption::from(Some(table)),
+            writer: RwLock::new(()),
+            shard_writers: std::array::from_fn(|_| Mutex::new(())),
+            used: AtomicU64::new(0),
+        }
+    }
+}
+
+/// **Tables in memory**
+/// Every table of a database as its latest version, an immutable `Arc<Table>` that each write
+/// swaps for a new one. `get`, `iter` and `snapshot` take versions without locking anything, so
+/// readers never wait for writers and never see a write half applied. Changing which tables there
+/// are (creating, loading, renaming or dropping one) takes `&mut Tables`, i.e. the database's
+/// lock held exclusively; with it `get_mut` changes a table in place. With `&Tables` only, `write`
+/// holds one table for writing, so writers to different tables do not wait for each other, and
+/// `write_row` one row of it, so that in a table whose rows are sharded (see `set_row_shards`)
+/// writers to rows of different shards do not either.
+/// Every table also remembers when it was last read or written, so the coldest can be dropped
+/// from memory first (see `least_recently_used`).
+#[derive(Debug, Default)]
+pub struct Tables {
+    tables: HashMap<String, Slot>,
+    // Shards to split the rows of these tables across whenever they are in memory; one otherwise.
+    row_shards: HashMap<String, usize>,
+    // Ticks once for every table read or written, so the slots can tell which was used last.
+    clock: AtomicU64,
+}
+
+impl Tables {
+    pub fn get(&self, name: &str) -> Option<TableRef> {
+        self.slot(name).and_then(|slot| slot.current.load_full())
+    }
+
+    pub fn write(&self, name: &str) -> Option<TableWriteRef<'_>> {
+        let slot = self.slot(name)?;
+        let writer = slot.writer.write();
+        let table = slot.current.load_full()?;
+        Some(TableWriteRef { slot, hold: Hold::Table { _writer: writer }, table })
+    }
+
+    /// `write` for changing row `row_id` only: with its rows in more than one shard, just the
+    /// shard of that row is held, so writers of the others go on meanwhile.
+    pub fn write_row(&self, name: &str, row_id: &str) -> Option<TableWriteRef<'_>> {
+        let slot = self.slot(name)?;
+        let shard = match &*slot.current.load() {
+            Some(table) if table.rows.shard_count() > 1 => table.rows.shard_of(row_id),
+            _ => return self.write(name),
+        };
+        let writer = slot.writer.read();
+        let shard_writer = slot.shard_writers[shard].lock();
+        let table = slot.current.load_full()?;
+        Some(TableWriteRef { slot, hold: Hold::Shard { shard, _writer: writer, _shard_writer: shard_writer }, table })
+    }
+
+    /// `write` for a caller holding the tables exclusively: the table is changed in place rather
+    /// than copied, unless a reader still holds its version.
+    pub fn get_mut(&mut self, name: &str) -> Option<TableWriteRef<'_>> {
+        let table = self.take(name)?;
+        self.write_taken(name, table)
+    }
+
+    /// Take `name` out, so that writing it with `write_taken` need not copy it. Until the writer
+    /// is dropped readers find no table by that name.
+    pub fn take(&mut self, name: &str) -> Option<TableRef> {
+        self.tables.get(name)?.current.swap(None)
+    }
+
+    /// Hold `table`, taken out of `name` with `take`, for writing; it is back once the writer is
+    /// dropped.
+    pub fn write_taken(&self, name: &str, table: TableRef) -> Option<TableWriteRef<'_>> {
+        let slot = self.slot(name)?;
+        Some(TableWriteRef { slot, hold: Hold::Table { _writer: slot.writer.write() }, table })
+    }
+
+    pub fn insert(&mut self, name: String, table: impl Into<TableRef>) -> Option<TableRef> {
+        let mut table = table.into();
+        let shards = self.row_shards(&name);
+        if table.rows.shard_count() != shards {
+            Arc::make_mut(&mut table).rows.reshard(shards);
+        }
+        let mut slot = Slot::new(table);
+        *slot.used.get_mut() = self.clock.fetch_add(1, Ordering::Relaxed);
+        self.tables.insert(name, slot).and_then(|slot| slot.current.into_inner())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<TableRef> {
+        self.tables.remove(name).and_then(|slot| slot.current.into_inner())
+    }
+
+    /// Split the rows of `name` across `shards` shards (see `Rows`), now if it is in memory and
+    /// whenever it is inserted again; 1 keeps them in one map.
+    pub fn set_row_shards(&mut self, name: &str, shards: usize) {
+        match shards {
+            0 | 1 => self.row_shards.remove(name),
+            shards => self.row_shards.insert(name.to_string(), shards),
+        };
+        if let Some(mut table) = self.get_mut(name) {
+            table.rows.reshard(shards);
+        }
+    }
+
+    pub fn row_shards(&self, name: &str) -> usize {
+        self.row_shards.get(name).copied().unwrap_or(1)
+    }
+
+    /// Every table set to more than one row shard, with its count.
+    pub fn sharded(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.row_shards.iter().map(|(name, shards)| (name, *shards))
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// The names of the tables, the one read or written longest ago first.
+    pub fn least_recently_used(&self) -> Vec<String> {
+        let mut names: Vec<(&String, u64)> = self.tables.iter().map(|(name, slot)| (name, slot.used.load(Ordering::Relaxed))).collect();
+        names.sort_by_key(|(_, used)| *used);
+        names.into_iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// The slot of `name`, marked as used now.
+    fn slot(&self, name: &str) -> Option<&Slot> {
+        let slot = self.tables.get(name)?;
+        slot.used.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        Some(slot)
+    }
+
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = &String> {
+        self.tables.keys()
+    }
+
+    pub fn clear(&mut self) {
+        self.tables.clear();
+    }
+
+    /// Every table with its name and latest version.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, TableRef)> {
+        self.tables.iter().filter_map(|(name, slot)| Some((name, slot.current.load_full()?)))
+    }
+
+    /// The latest version of every table, sorted by name: the database's tables as they are now,
+    /// for as long as the caller keeps them, whatever is written meanwhile. Copies no rows.
+    pub fn snapshot(&self) -> Vec<(String, TableRef)> {
+        let mut tables: Vec<(String, TableRef)> = self.iter().map(|(name, table)| (name.clone(), table)).collect();
+        tables.sort_by(|(a, _), (b, _)| a.cmp(b));
+        tables
+    }
+}
+
+/// Every table as it is now, e.g. to roll back to. The versions are shared, so no rows are
+/// copied until one side writes.
+impl Clone for Tables {
+    fn clone(&self) -> Self {
+        let mut tables = Tables { row_shards: self.row_shards.clone(), ..Tables::default() };
+        for (name, table) in self.iter() {
+            tables.insert(name.clone(), table);
+        }
+        tables
+    }
+}
+
+impl<T: Into<TableRef>> FromIterator<(String, T)> for Tables {
+    fn from_iter<I: IntoIterator<Item = (String, T)>>(iter: I) -> Self {
+        let mut tables = Tables::default();
+        for (name, table) in iter {
+            tables.insert(name, table);
+        }
+        tables
+    }
+}
@@ -0,0 +1,281 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Textual form of NULL in storage and the WAL (as in PostgreSQL's COPY format), so that an
+/// empty string stays an empty string.
+pub const NULL_TEXT: &str = "\\N";
+
+/// Latest year a `YYYY-MM-DD` timestamp may name; later ones are not a valid timestamp.
+const MAX_YEAR: i64 = 9999;
+
+/// Declared type of a column. Columns without a declaration hold text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColumnType {
+    Int,
+    Float,
+    Bool,
+    #[default]
+    Text,
+    Timestamp,
+}
+
+/// One cell. `Null` is the same as the column being missing from the row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    /// Seconds since the Unix epoch, UTC.
+    Timestamp(i64),
+    Null,
+}
+
+impl ColumnType {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "int" | "integer" => Some(ColumnType::Int),
+            "float" | "double" => Some(ColumnType::Float),
+            "bool" | "boolean" => Some(ColumnType::Bool),
+            "text" | "string" => Some(ColumnType::Text),
+            "timestamp" => Some(ColumnType::Timestamp),
+            _ => None,
+        }
+    }
+
+    /// The narrowest type every value of a sample parses as, trying int, float, bool (words only,
+    /// so 0/1 stays int) and timestamp (dates, not plain numbers) before text. Numbers with a
+    /// leading zero (zip codes, `007`) stay text, which keeps the zero. NULLs and empty values
+    /// say nothing; a sample of only those is text.
+    pub fn infer<'a>(values: impl IntoIterator<Item = &'a str>) -> ColumnType {
+        let values: Vec<&str> = values.into_iter().filter(|text| *text != NULL_TEXT && !text.is_empty()).collect();
+        let all = |test: fn(&str) -> bool| !values.is_empty() && values.iter().all(|text| test(text));
+        if all(|text| text.parse::<i64>().is_ok() && !leading_zero(text)) {
+            ColumnType::Int
+        } else if all(|text| text.parse::<f64>().is_ok_and(f64::is_finite) && !leading_zero(text)) {
+            ColumnType::Float
+        } else if all(|text| matches!(text.to_lowercase().as_str(), "true" | "false" | "yes" | "no")) {
+            ColumnType::Bool
+        } else if all(|text| text.parse::<i64>().is_err() && parse_timestamp(text).is_some()) {
+            ColumnType::Timestamp
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::Text => "text",
+            ColumnType::Timestamp => "timestamp",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Value {
+    /// Parse the textual form of a cell as `ty`. `NULL_TEXT` is `Null`, and so is an empty
+    /// string for every type but text, where it is just an empty string.
+    pub fn parse(text: &str, ty: ColumnType) -> Option<Value> {
+        if text == NULL_TEXT || (text.is_empty() && ty != ColumnType::Text) {
+            return Some(Value::Null);
+        }
+        match ty {
+            ColumnType::Int => text.parse().ok().map(Value::Int),
+            ColumnType::Float => text.parse().ok().map(Value::Float),
+            ColumnType::Bool => match text.to_lowercase().as_str() {
+                "true" | "t" | "yes" | "1" => Some(Value::Bool(true)),
+                "false" | "f" | "no" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            ColumnType::Text => Some(Value::Text(text.to_string())),
+            ColumnType::Timestamp => parse_timestamp(text).map(Value::Timestamp),
+        }
+    }
+
+    /// Best-effort conversion to `ty` for `ALTER COLUMN`: the textual form is parsed as `ty`, and
+    /// booleans and timestamps also become numbers. `None` if the value does not fit `ty`.
+    pub fn convert(&self, ty: ColumnType) -> Option<Value> {
+        match (self, ty) {
+            (Value::Bool(b), ColumnType::Int) => Some(Value::Int(*b as i64)),
+            (Value::Bool(b), ColumnType::Float) => Some(Value::Float(if *b { 1.0 } else { 0.0 })),
+            (Value::Timestamp(secs), ColumnType::Int) => Some(Value::Int(*secs)),
+            (Value::Timestamp(secs), ColumnType::Float) => Some(Value::Float(*secs as f64)),
+            _ => Value::parse(&self.to_string(), ty).filter(|value| !value.is_null()),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// JSON form for responses: numbers and booleans stay native, timestamps use ISO 8601.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Int(i) => serde_json::Value::from(*i),
+            Value::Float(x) => serde_json::Value::from(*x),
+            Value::Bool(b) => serde_json::Value::Bool(*b),
+            Value::Text(_) | Value::Timestamp(_) => serde_json::Value::String(self.to_string()),
+            Value::Null => serde_json::Value::Null,
+        }
+    }
+
+    /// Parse the value a column of type `ty` is compared with, e.g. the `10` of `age < 10`. Like
+    /// `parse`, except that an int column can also be compared with a fractional number (`age < 10.5`).
+    pub fn parse_operand(text: &str, ty: ColumnType) -> Option<Value> {
+        match (Value::parse(text, ty), ty) {
+            (None, ColumnType::Int) => text.parse().ok().map(Value::Float),
+            (value, _) => value,
+        }
+    }
+
+    /// Order two values of compatible types; `None` if they cannot be compared (e.g. with `Null`).
+    /// Text that looks numeric on both sides compares as numbers, as untyped columns always have,
+    /// and text that is a date or date-time (`2024-01-31`, `2024-01-31T08:00:00Z`) on both sides
+    /// compares as timestamps, so `2024-1-5` comes before `2024-01-31`. Other text compares by its
+    /// characters.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Timestamp(a), Value::Timestamp(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Text(b)) => match (a.parse::<f64>(), b.parse::<f64>()) {
+                (Ok(a), Ok(b)) => a.partial_cmp(&b),
+                (Err(_), Err(_)) => match (parse_timestamp(a), parse_timestamp(b)) {
+                    (Some(a), Some(b)) => Some(a.cmp(&b)),
+                    _ => Some(a.cmp(b)),
+                },
+                _ => Some(a.cmp(b)),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Whether a number is written with a leading zero, as codes are (`007`, `-01.5`) and numbers are not.
+fn leading_zero(text: &str) -> bool {
+    let digits = text.trim_start_matches(['-', '+']);
+    digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.")
+}
+
+/// The textual form `Value::parse` reads back; `Null` is `NULL_TEXT`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Text(s) => f.write_str(s),
+            Value::Timestamp(secs) => {
+                let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+                let (year, month, day) = civil_from_days(days);
+                write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+            }
+            Value::Null => f.write_str(NULL_TEXT),
+        }
+    }
+}
+
+/// The current time in the form `Value::Timestamp` holds: seconds since the Unix epoch.
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Accept Unix seconds, `YYYY-MM-DD`, or `YYYY-MM-DDTHH:MM:SS` with an optional trailing `Z`.
+fn parse_timestamp(text: &str) -> Option<i64> {
+    if let Ok(secs) = text.parse::<i64>() {
+        return Some(secs);
+    }
+    let text = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if !(0..=MAX_YEAR).contains(&year) || !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let fields: Vec<i64> = time.split(':').map(str::parse).collect::<Result<_, _>>().ok()?;
+        let [h, m, s] = fields[..] else {
+            return None;
+        };
+        if !(0..=23).contains(&h) || !(0..=59).contains(&m) || !(0..=59).contains(&s) {
+            return None;
+        }
+        seconds = h * 3600 + m * 60 + s;
+    }
+    days_from_civil(year, month, day).checked_mul(86_400)?.checked_add(seconds)
+}
+
+/// Days in `month` (1 to 12) of `year`.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates_and_times() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2024-02-29T12:00:00Z"), Some(1_709_208_000));
+        assert_eq!(parse_timestamp("1700000000"), Some(1_700_000_000));
+    }
+
+    #[test]
+    fn rejects_impossible_dates() {
+        for text in ["2023-02-31", "2023-02-29", "2023-04-31", "2023-13-01", "2023-01-01T24:00:00", "2023-01-01T-1:00:00"] {
+            assert_eq!(parse_timestamp(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_years_that_would_overflow() {
+        assert_eq!(parse_timestamp("99999999999999999-01-01"), None);
+        assert_eq!(parse_timestamp("10000-01-01"), None);
+        assert!(parse_timestamp("9999-12-31T23:59:59").is_some());
+    }
+}
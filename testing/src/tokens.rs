@@ -0,0 +1,158 @@
+use crate::auth::{self, ADMIN_ROLE};
+use crate::commands::db::{Database, DatabaseError, Result};
+use crate::table::table::ColumnSpec;
+use crate::table::value::{self, ColumnType, Value, NULL_TEXT};
+use blake2::{Blake2b512, Digest};
+use log::info;
+use password_hash::rand_core::{OsRng, RngCore};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Table holding the API tokens: one row per token name (row_id), with the BLAKE2 hash of its
+/// secret, the account it acts as, the roles it is limited to (comma-separated; NULL for all of
+/// the account's) and when it was created. Like the user table it lives in `main` and sessions
+/// cannot use it directly.
+pub const TOKEN_TABLE: &str = "__tokens";
+
+/// Start of every token, so one is easy to tell from a password (or to find where it leaked).
+const PREFIX: &str = "rdb_";
+
+/// **API tokens**
+/// A token lets a service connect as an account without its password: `TOKEN <token>` over TCP,
+/// or `Authorization: Bearer <token>` over HTTP. It acts with the account's roles, or only with
+/// the ones it was created with, and stops working as soon as it is revoked or the account is
+/// dropped. Only a hash of it is stored, so it is shown once, when it is created; the secret is
+/// 32 random bytes, so a fast hash is enough where passwords need argon2.
+///
+/// Create token `name` for account `user`, limited to `roles` if given (which the account must
+/// hold), and return it.
+pub fn create(db: &mut Database, name: &str, user: &str, roles: Option<&[&str]>) -> Result<String> {
+    load(db)?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(DatabaseError::InvalidTokenName(name.to_string()));
+    }
+    if auth::stored_hash(db, user).is_none() {
+        return Err(DatabaseError::UserDoesNotExist(user.to_string()));
+    }
+    if db.tables.get(TOKEN_TABLE).is_some_and(|table| table.rows.contains_key(name)) {
+        return Err(DatabaseError::TokenAlreadyExists(name.to_string()));
+    }
+    let held = auth::roles(db, user);
+    if let Some(role) = roles.into_iter().flatten().find(|role| !held.iter().any(|held| held == *role)) {
+        return Err(DatabaseError::RoleNotHeld(user.to_string(), role.to_string()));
+    }
+    if !db.check_table(TOKEN_TABLE) {
+        db.create_table(TOKEN_TABLE)?;
+        db.add_typed_column(TOKEN_TABLE, "token_hash", ColumnSpec { not_null: true, ..ColumnSpec::default() })?;
+        db.add_typed_column(TOKEN_TABLE, "user", ColumnSpec { not_null: true, ..ColumnSpec::default() })?;
+        db.add_typed_column(TOKEN_TABLE, "roles", ColumnSpec::default())?;
+        db.add_typed_column(TOKEN_TABLE, "created_at", ColumnSpec { column_type: ColumnType::Timestamp, ..ColumnSpec::default() })?;
+    }
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let token = format!("{}{}", PREFIX, secret.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    let data = HashMap::from([
+        ("token_hash".to_string(), hash(&token)),
+        ("user".to_string(), user.to_string()),
+        ("roles".to_string(), roles.map_or_else(|| NULL_TEXT.to_string(), |roles| roles.join(","))),
+        ("created_at".to_string(), value::now().to_string()),
+    ]);
+    db.insert_row(TOKEN_TABLE, name, data)?;
+    db.persist_table(TOKEN_TABLE)?;
+    info!(token = name, user; "Token created");
+    Ok(token)
+}
+
+/// `REVOKE TOKEN <name>`, by account `by`: admins may revoke any token, others their own.
+pub fn revoke(db: &mut Database, name: &str, by: Option<&str>) -> Result<()> {
+    load(db)?;
+    let owner = user(db, name).ok_or_else(|| DatabaseError::TokenDoesNotExist(name.to_string()))?;
+    if let Some(by) = by.filter(|by| *by != owner && !auth::roles(db, by).iter().any(|role| role == ADMIN_ROLE)) {
+        return Err(DatabaseError::PermissionDenied(format!("'{}' may only revoke its own tokens", by)));
+    }
+    db.delete_row(TOKEN_TABLE, name)?;
+    db.persist_table(TOKEN_TABLE)?;
+    info!(token = name, user = owner; "Token revoked");
+    Ok(())
+}
+
+/// Revoke every token of account `user`, which is being dropped.
+pub fn revoke_all(db: &mut Database, user: &str) -> Result<()> {
+    load(db)?;
+    let names: Vec<String> = names(db).into_iter().filter(|name| self::user(db, name).as_deref() == Some(user)).collect();
+    for name in &names {
+        db.delete_row(TOKEN_TABLE, name)?;
+    }
+    if !names.is_empty() {
+        db.persist_table(TOKEN_TABLE)?;
+    }
+    Ok(())
+}
+
+/// The tokens (never their secrets) of account `user`, or of every account.
+pub fn list(db: &mut Database, user: Option<&str>) -> Result<serde_json::Value> {
+    load(db)?;
+    let Some(table) = db.tables.get(TOKEN_TABLE) else {
+        return Ok(json!([]));
+    };
+    Ok(names(db)
+        .iter()
+        .filter(|name| user.is_none() || self::user(db, name).as_deref() == user)
+        .map(|name| {
+            let row = &table.rows[name];
+            let text = |column: &str| row.get(column).map(|value| value.to_string());
+            json!({ "token": name, "user": text("user"), "roles": text("roles"), "created_at": text("created_at") })
+        })
+        .collect())
+}
+
+/// The name of the token `token` is the secret of.
+pub fn authenticate(db: &Database, token: &str) -> Result<String> {
+    let hashed = hash(token);
+    let table = db.tables.get(TOKEN_TABLE).ok_or(DatabaseError::InvalidToken)?;
+    let (name, _) = table
+        .rows
+        .iter()
+        .find(|(_, row)| matches!(row.get("token_hash"), Some(Value::Text(stored)) if *stored == hashed))
+        .ok_or(DatabaseError::InvalidToken)?;
+    Ok(name.clone())
+}
+
+/// The account token `name` acts as.
+pub fn user(db: &Database, name: &str) -> Option<String> {
+    match db.tables.get(TOKEN_TABLE)?.rows.get(name)?.get("user")? {
+        Value::Text(user) => Some(user.clone()),
+        _ => None,
+    }
+}
+
+/// The roles token `name` acts with: those its account holds now, limited to the token's own. Fails
+/// once the token is revoked, so sessions that logged in with it lose their access at once.
+pub fn roles(db: &Database, name: &str) -> Result<Vec<String>> {
+    let row = db.tables.get(TOKEN_TABLE).and_then(|table| table.rows.get(name).cloned()).ok_or(DatabaseError::InvalidToken)?;
+    let user = user(db, name).ok_or(DatabaseError::InvalidToken)?;
+    let held = auth::roles(db, &user);
+    Ok(match row.get("roles") {
+        Some(Value::Text(limited)) => held.into_iter().filter(|role| limited.split(',').any(|limited| limited == role)).collect(),
+        _ => held,
+    })
+}
+
+/// Load the token table if it has been stored, so `authenticate` sees every token.
+pub fn load(db: &mut Database) -> Result<()> {
+    if !db.check_table(TOKEN_TABLE) && db.storage.get_mut().table_names()?.iter().any(|name| name == TOKEN_TABLE) {
+        db.ensure_table_loaded(TOKEN_TABLE)?;
+    }
+    Ok(())
+}
+
+/// The token names, sorted.
+fn names(db: &Database) -> Vec<String> {
+    let mut names: Vec<String> = db.tables.get(TOKEN_TABLE).map(|table| table.rows.keys().cloned().collect()).unwrap_or_default();
+    names.sort();
+    names
+}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Blake2b512::digest(token.as_bytes()))
+}
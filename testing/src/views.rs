@@ -0,0 +1,155 @@
+use crate::commands::db::{Database, DatabaseError, Result, RowIter};
+use crate::table::table::{Row, Table};
+use crate::table::value::Value;
+use log::info;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+
+/// Table holding the view definitions: one row per view (row_id), with the table it reads and its
+/// condition and columns (comma-separated), each NULL if the view has none.
+pub const VIEW_TABLE: &str = "__views";
+
+/// **Views**
+/// A named query over one table, e.g. `CREATE VIEW adults AS users WHERE age >= 18 COLUMNS name,age`.
+/// Only the definition is stored; reading a view runs it against the current rows of its table,
+/// so callers share one filter instead of each repeating it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    pub name: String,
+    pub table: String,
+    /// Condition as in `Database::search_rows_by_condition_in_table`
+    pub condition: Option<String>,
+    /// Columns to keep; all of the table's if `None`
+    pub columns: Option<Vec<String>>,
+}
+
+impl View {
+    fn from_row(name: &str, row: &Row) -> View {
+        let text = |column: &str| row.get(column).map(Value::to_string);
+        View {
+            name: name.to_string(),
+            table: text("table").unwrap_or_default(),
+            condition: text("condition"),
+            columns: text("columns").map(|columns| columns.split(',').map(str::to_string).collect()),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({ "name": self.name, "table": self.table, "condition": self.condition, "columns": self.columns })
+    }
+}
+
+/// Load the view table if it has been stored, so `get` and `list` see every view.
+pub fn load(db: &mut Database) -> Result<()> {
+    if !db.check_table(VIEW_TABLE) && db.storage.get_mut().table_names()?.iter().any(|name| name == VIEW_TABLE) {
+        db.ensure_table_loaded(VIEW_TABLE)?;
+    }
+    Ok(())
+}
+
+/// The view called `name`, if one is defined. Only sees the views once the view table is in
+/// memory; see `load`.
+pub fn get(db: &Database, name: &str) -> Option<View> {
+    let views = db.tables.get(VIEW_TABLE)?;
+    let row = views.rows.get(name)?;
+    Some(View::from_row(name, row))
+}
+
+/// The names of the views, once the view table is in memory; see `load`.
+pub fn names(db: &Database) -> Vec<String> {
+    db.tables.get(VIEW_TABLE).map(|table| table.rows.keys().cloned().collect()).unwrap_or_default()
+}
+
+/// Every view, by name.
+pub fn list(db: &mut Database) -> Result<Vec<View>> {
+    load(db)?;
+    let Some(table) = db.tables.get(VIEW_TABLE) else {
+        return Ok(Vec::new());
+    };
+    Ok(table.rows.iter().map(|(name, row)| View::from_row(name, row)).collect())
+}
+
+/// Define a view. Its name must not belong to a table or another view, and it is run once so a
+/// missing table, column or malformed condition fails here rather than on every read.
+pub fn create(db: &mut Database, view: &View) -> Result<()> {
+    load(db)?;
+    if get(db, &view.name).is_some() {
+        return Err(DatabaseError::ViewAlreadyExists(view.name.clone()));
+    }
+    if db.check_table(&view.name) || db.storage.get_mut().table_names()?.contains(&view.name) {
+        return Err(DatabaseError::TableAlreadyExists(view.name.clone()));
+    }
+    db.ensure_table_loaded(&view.table)?;
+    let condition_column = view.condition.as_deref().and_then(|condition| condition.split_whitespace().next());
+    if let Some(column) = condition_column.filter(|column| !db.get_table(&view.table).is_ok_and(|table| table.columns.contains(*column))) {
+        return Err(DatabaseError::ColumnDoesNotExist(column.to_string(), view.table.clone()));
+    }
+    query(db, view, None)?;
+
+    if !db.check_table(VIEW_TABLE) {
+        db.create_table(VIEW_TABLE)?;
+        for column in ["table", "condition", "columns"] {
+            db.add_column(VIEW_TABLE, column, None)?;
+        }
+    }
+    let mut data = HashMap::from([("table".to_string(), view.table.clone())]);
+    if let Some(condition) = &view.condition {
+        data.insert("condition".to_string(), condition.clone());
+    }
+    if let Some(columns) = &view.columns {
+        data.insert("columns".to_string(), columns.join(","));
+    }
+    db.insert_row(VIEW_TABLE, &view.name, data)?;
+    db.persist_table(VIEW_TABLE)?;
+    info!(view = view.name, table = view.table; "View created");
+    Ok(())
+}
+
+/// Forget a view. Its table is left alone.
+pub fn drop(db: &mut Database, name: &str) -> Result<()> {
+    load(db)?;
+    if get(db, name).is_none() {
+        return Err(DatabaseError::ViewDoesNotExist(name.to_string()));
+    }
+    db.delete_row(VIEW_TABLE, name)?;
+    db.persist_table(VIEW_TABLE)?;
+    info!(view = name; "View dropped");
+    Ok(())
+}
+
+/// Run a view: the rows of its table that are not soft-deleted and match its condition, and
+/// `condition` too if given, with only the view's columns. `condition` may only use those columns.
+/// The view's table must be in memory.
+pub fn query(db: &Database, view: &View, condition: Option<&str>) -> Result<Table> {
+    let table = db.get_table(&view.table)?;
+    let (mut result, rows) = scan(db, &table, view, condition)?;
+    let rows: Vec<(String, Row)> = rows.map(|(row_id, row)| (row_id.clone(), result.project_row(row))).collect();
+    for (row_id, cells) in rows {
+        result.insert_values(&row_id, cells);
+    }
+    Ok(result)
+}
+
+/// Run a view without copying its result: an empty table declaring the view's columns, and the
+/// matching rows of the view's table, read in place (render them through that table, e.g. with
+/// `Table::row_to_json`, to keep only the view's columns). Rows as `query` returns them.
+/// `table` is the view's table, held by the caller.
+pub fn scan<'a>(db: &Database, table: &'a Table, view: &View, condition: Option<&str>) -> Result<(Table, RowIter<'a>)> {
+    let result = match &view.columns {
+        Some(columns) => db.projection(&view.table, &columns.iter().map(String::as_str).collect::<Vec<_>>())?,
+        None => table.projection(&table.columns.iter().map(String::as_str).collect::<Vec<_>>()),
+    };
+    let rows: RowIter<'a> = match &view.condition {
+        Some(view_condition) => Database::rows_matching(table, view_condition)?,
+        None => Box::new(table.live_rows()),
+    };
+    let Some(condition) = condition else {
+        return Ok((result, rows));
+    };
+    let column = condition.split_whitespace().next().unwrap_or_default();
+    if !result.columns.contains(column) {
+        return Err(DatabaseError::ColumnDoesNotExist(column.to_string(), view.name.clone()));
+    }
+    let matching: HashSet<&String> = Database::rows_matching(table, condition)?.map(|(row_id, _)| row_id).collect();
+    Ok((result, Box::new(rows.filter(move |(row_id, _)| matching.contains(row_id)))))
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::sync::{Arc, RwLock};
+use testing::commands::db::DatabaseError;
+use testing::commands::executor::Response;
+use testing::commands::walengine::WalEngineConfig;
+use testing::session::{Catalog, Session};
+
+/// A catalog over the database in `dir`.
+fn catalog_on(dir: &str) -> Result<Arc<Catalog>, DatabaseError> {
+    Catalog::new(Arc::new(RwLock::new(common::open(dir))), WalEngineConfig::default()).map(Arc::new)
+}
+
+fn run(session: &mut Session, line: &str) -> Response {
+    let response = session.handle(line);
+    assert!(!matches!(response, Response::Error(_)), "'{}' failed: {:?}", line, response);
+    response
+}
+
+fn refused(response: Response) -> bool {
+    matches!(response, Response::Error(e) if e == DatabaseError::AuthenticationRequired.to_string())
+}
+
+#[test]
+fn accounts_stored_but_not_loaded_still_require_a_login() {
+    let dir = common::fresh_dir("auth-not-loaded");
+    let catalog = catalog_on(&dir).unwrap();
+    let mut session = Session::new(Arc::clone(&catalog));
+    run(&mut session, "CREATE TABLE notes");
+
+    // Another process (or a restored file) stores the first account meanwhile.
+    let mut admin = Session::new(catalog_on(&dir).unwrap());
+    run(&mut admin, "CREATE USER ada PASSWORD secret");
+
+    let mut stranger = Session::new(catalog);
+    assert!(refused(stranger.handle("INSERT notes 1 text=hello")));
+    assert!(refused(stranger.handle("CREATE TABLE other")));
+}
+
+#[test]
+fn stored_accounts_require_a_login_after_a_restart() {
+    let dir = common::fresh_dir("auth-restart");
+    let mut admin = Session::new(catalog_on(&dir).unwrap());
+    run(&mut admin, "CREATE USER ada PASSWORD secret");
+    drop(admin);
+
+    let mut session = Session::new(catalog_on(&dir).unwrap());
+    assert!(refused(session.handle("CREATE TABLE notes")));
+    run(&mut session, "LOGIN ada secret");
+    run(&mut session, "CREATE TABLE notes");
+}
+
+#[test]
+fn an_unreadable_user_table_refuses_to_open() {
+    let dir = common::fresh_dir("auth-unreadable");
+    // Sealed with a key this process does not have.
+    std::fs::write(format!("{}/__users.csv", dir), "aes:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\n").unwrap();
+    assert!(matches!(catalog_on(&dir), Err(DatabaseError::Undecryptable(..))));
+}
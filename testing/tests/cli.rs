@@ -0,0 +1,108 @@
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// The `testing` binary, run in `dir` with its servers on free ports and checkpoints far apart,
+/// so that only what the test does writes the tables out.
+fn testing(dir: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_testing"));
+    command
+        .current_dir(dir)
+        .env("RUSTDB_LISTEN", "127.0.0.1:0")
+        .env("RUSTDB_HTTP", "127.0.0.1:0")
+        .env("RUSTDB_CHECKPOINT_EVERY", "1h");
+    command
+}
+
+fn run_script(dir: &str, script: &str) -> Output {
+    fs::write(format!("{}/script.rdb", dir), script).unwrap();
+    testing(dir).args(["run", "script.rdb"]).output().expect("cannot run the binary")
+}
+
+fn lines(output: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(output).lines().map(str::to_string).collect()
+}
+
+#[test]
+fn a_script_stops_at_the_first_failing_command() {
+    let dir = common::fresh_dir("cli-script");
+    let output = run_script(&dir, "-- set up\nCREATE TABLE t;\nADD COLUMN t n int\nINSERT t 1 n=1\n\nINSERT t 1 n=2;\nINSERT t 2 n=2;\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = lines(&output.stdout);
+    assert_eq!(stdout.len(), 4, "ran past the failure: {:?}", stdout);
+    assert!(stdout[3].contains("\"status\":\"error\""));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("script.rdb:6:"), "the failing line is not named");
+    assert!(!fs::read_to_string(format!("{}/t.csv", dir)).unwrap().contains("2,"), "the command after the failure ran");
+}
+
+#[test]
+fn a_script_picks_the_output_format_per_command() {
+    let dir = common::fresh_dir("cli-format");
+    let output = run_script(&dir, "CREATE TABLE t\nADD COLUMN t n int\nADD COLUMN t name\nINSERT t 1 n=1 name=ann\nPRINT t FORMAT csv\nPRINT t FORMAT table\n");
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = lines(&output.stdout);
+    assert_eq!(stdout[4..6], ["row_id,n,name", "1,1,ann"]);
+    assert!(stdout[6].starts_with("Row ID") && stdout[6].contains("| name"), "no grid: {:?}", stdout);
+}
+
+#[test]
+fn migrations_run_up_and_down_in_order() {
+    let dir = common::fresh_dir("cli-migrate");
+    fs::create_dir_all(format!("{}/migrations", dir)).unwrap();
+    fs::write(format!("{}/migrations/0001_users.migration", dir), "-- up\nCREATE TABLE users\nADD COLUMN users name\n-- down\nDROP TABLE users\n").unwrap();
+    fs::write(format!("{}/migrations/0002_email.migration", dir), "-- up\nADD COLUMN users email DEFAULT none\n-- down\nDROP COLUMN users email\n").unwrap();
+    let output = run_script(&dir, "MIGRATE UP\nINSERT users 1 name=ann\nMIGRATE DOWN 1\nMIGRATE STATUS\nGET users 1\n");
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = lines(&output.stdout);
+    assert_eq!(stdout[0], r#"{"data":{"applied":[1,2]},"status":"ok"}"#);
+    assert_eq!(stdout[2], r#"{"data":{"reverted":[2]},"status":"ok"}"#);
+    assert!(stdout[3].contains(r#""version":1"#) && stdout[3].contains(r#""pending":[2]"#), "{}", stdout[3]);
+    assert_eq!(stdout[4], r#"{"data":{"data":{"name":"ann"},"row_id":"1"},"status":"ok"}"#);
+}
+
+// Without a terminal the prompt reads standard input as typed lines.
+#[test]
+fn the_prompt_runs_statements_ended_by_a_semicolon() {
+    let dir = common::fresh_dir("cli-prompt");
+    let mut prompt = testing(&dir).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().unwrap();
+    let input = "CREATE TABLE t;\nADD COLUMN t\n  n int;\n.tables\n.schema t\nNOPE;\nexit\n";
+    prompt.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = prompt.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = lines(&output.stdout);
+    assert_eq!(stdout[2], r#"{"data":["n","t"],"status":"ok"}"#, "the statement was not joined across lines");
+    assert_eq!(stdout[3..6], ["t", "CREATE TABLE t;", "ADD COLUMN t n int;"]);
+    assert!(stdout[6].contains("Unknown command"));
+}
+
+#[test]
+fn ctrl_c_writes_out_the_tables_before_exiting() {
+    let dir = common::fresh_dir("cli-interrupt");
+    let mut prompt = testing(&dir).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().unwrap();
+    let mut input = prompt.stdin.take().unwrap();
+    input.write_all(b"CREATE TABLE t;\nADD COLUMN t n int;\nINSERT t 1 n=1 FORMAT json;\n").unwrap();
+    let (inserted, insert_done) = mpsc::channel();
+    let stdout = BufReader::new(prompt.stdout.take().unwrap());
+    let reader = thread::spawn(move || {
+        let mut lines = Vec::new();
+        for line in stdout.lines().map_while(|line| line.ok()) {
+            if line.contains("\"row_id\":\"1\"") {
+                let _ = inserted.send(());
+            }
+            lines.push(line);
+        }
+        lines
+    });
+    insert_done.recv_timeout(Duration::from_secs(30)).expect("the insert never ran");
+    let status = Command::new("kill").args(["-INT", &prompt.id().to_string()]).status().unwrap();
+    assert!(status.success());
+    assert_eq!(prompt.wait().unwrap().code(), Some(0));
+    drop(input);
+    assert!(reader.join().unwrap().iter().any(|line| line == "Interrupted."));
+    assert!(fs::read_to_string(format!("{}/t.csv", dir)).unwrap().contains("1,1"), "the row was not written out");
+}
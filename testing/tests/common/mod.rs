@@ -0,0 +1,21 @@
+// Each test file uses only some of these.
+#![allow(dead_code)]
+
+use testing::commands::db::Database;
+use testing::storage::csv::CsvStorage;
+
+/// An empty directory for `test` to keep its files in, under the system's temporary directory.
+pub fn fresh_dir(test: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("rustdb-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.display().to_string()
+}
+
+/// A database keeping its table files and WAL in `dir`, as a restart would open it.
+pub fn open(dir: &str) -> Database {
+    let mut db = Database::with_storage(Box::new(CsvStorage::in_dir(dir).unwrap()));
+    db.wal_file = format!("{}/wal.log", dir);
+    db.wal_archive_file = format!("{}/wal_archive.log", dir);
+    db
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use testing::commands::db::Database;
+use testing::commands::executor::{self, Response};
+
+const THREADS: usize = 8;
+const ROWS: usize = 200;
+
+fn shared(name: &str, tables: &[&str]) -> Arc<RwLock<Database>> {
+    let mut db = common::open(&common::fresh_dir(name));
+    db.save_threshold = usize::MAX;
+    for table in tables {
+        for command in [format!("CREATE TABLE {}", table), format!("ADD COLUMN {} n int", table)] {
+            assert!(!matches!(executor::execute(&mut db, &command), Response::Error(_)), "'{}' failed", command);
+        }
+    }
+    Arc::new(RwLock::new(db))
+}
+
+fn row(n: usize) -> HashMap<String, String> {
+    HashMap::from([("n".to_string(), n.to_string())])
+}
+
+// A writer holds its table only, so a write to another table does not wait for it.
+#[test]
+fn a_writer_holds_up_only_its_own_table() {
+    let db = shared("concurrency-tables", &["a", "b"]);
+    let held = db.read().unwrap();
+    let mut writer = held.row_writer("a").unwrap();
+    writer.insert_row("1", row(1)).unwrap();
+
+    let (done, finished) = mpsc::channel();
+    let other = Arc::clone(&db);
+    thread::spawn(move || {
+        let db = other.read().unwrap();
+        let result = db.row_writer("b").and_then(|mut writer| writer.insert_row("1", row(1)));
+        let _ = done.send(result.map(|_| ()));
+    });
+    finished.recv_timeout(Duration::from_secs(10)).expect("the write to 'b' waited for the writer of 'a'").unwrap();
+    drop(writer);
+    drop(held);
+    // The two tables and their columns, then a row in each.
+    assert_eq!(db.read().unwrap().wal_pending().0, 6);
+}
+
+// Readers get the version of the table as it was before the writer started, whole, until the
+// writer is done; then they get all its writes at once.
+#[test]
+fn readers_see_a_table_as_of_before_its_writer() {
+    let db = shared("concurrency-snapshot", &["t"]);
+    let held = db.read().unwrap();
+    let before = held.get_table("t").unwrap();
+    let mut writer = held.row_writer("t").unwrap();
+    for n in 0..3 {
+        writer.insert_row(&n.to_string(), row(n)).unwrap();
+    }
+    let reader = Arc::clone(&db);
+    let during = thread::spawn(move || reader.read().unwrap().get_table("t").unwrap().rows.len()).join().unwrap();
+    assert_eq!(during, 0, "a reader saw the writes of a writer still at work");
+    drop(writer);
+    assert_eq!(held.get_table("t").unwrap().rows.len(), 3);
+    assert!(before.rows.is_empty(), "a version already read changed");
+}
+
+// With its rows sharded, point writes to one table from many threads all land, and the table
+// keeps its shards.
+#[test]
+fn point_writes_to_a_sharded_table_all_land() {
+    let db = shared("concurrency-shards", &["t"]);
+    let response = executor::execute(&mut db.write().unwrap(), "ALTER TABLE t SHARDS 4");
+    assert!(!matches!(response, Response::Error(_)), "{:?}", response);
+    let writers: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let db = Arc::clone(&db);
+            thread::spawn(move || {
+                let db = db.read().unwrap();
+                for n in (thread..ROWS).step_by(THREADS) {
+                    db.point_writer("t", &n.to_string()).and_then(|mut writer| writer.insert_row(&n.to_string(), row(n))).unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    let db = db.read().unwrap();
+    assert_eq!(db.tables.row_shards("t"), 4);
+    let table = db.get_table("t").unwrap();
+    assert_eq!(table.rows.len(), ROWS);
+    assert!((0..ROWS).all(|n| table.get_row(&n.to_string()).is_some_and(|row| row["n"].to_string() == n.to_string())));
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use testing::commands::db::Database;
+use testing::commands::executor::{self, Response};
+
+/// Rounds to run: round `n` kills the writer during its `n`th checkpoint, and again between it
+/// and the next one.
+const ROUNDS: usize = 8;
+
+/// Table the writer fills.
+const TABLE: &str = "crash";
+
+/// Rows the writer inserts between two checkpoints.
+const CHECKPOINT_EVERY: usize = 25;
+
+/// Padding in every row, so that rewriting the table takes long enough to be interrupted.
+const PAYLOAD: usize = 200;
+
+/// Set for the writer process only: the directory it writes to.
+const WRITER_DIR: &str = "RUSTDB_CRASH_TEST_DIR";
+
+/// The writer the crash test kills, run in a process of its own (see `write_and_kill`): insert
+/// rows into a fresh table until killed, persisting the WAL after every row and checkpointing
+/// (persisting the table and archiving the WAL) every `CHECKPOINT_EVERY` rows, besides the
+/// threshold saves in between. Prints `acked <rows>` once a row is in the WAL file, and
+/// `checkpoint <rows>` as a checkpoint starts.
+#[test]
+#[ignore = "started and killed by killed_while_writing_loses_no_acknowledged_row"]
+fn crash_test_writer() {
+    let Ok(dir) = std::env::var(WRITER_DIR) else {
+        return;
+    };
+    let mut db = common::open(&dir);
+    execute(&mut db, &format!("CREATE TABLE {}", TABLE));
+    execute(&mut db, &format!("ADD COLUMN {} n int", TABLE));
+    let payload = "x".repeat(PAYLOAD);
+    for row in 0.. {
+        execute(&mut db, &format!("INSERT {} {} n={} payload={}", TABLE, row, row, payload));
+        db.persist_wal().unwrap_or_else(|e| panic!("cannot persist the WAL: {}", e));
+        println!("acked {}", row + 1);
+        if (row + 1) % CHECKPOINT_EVERY == 0 {
+            println!("checkpoint {}", row + 1);
+            db.checkpoint().and_then(|_| db.commit_wal()).unwrap_or_else(|e| panic!("checkpoint failed: {}", e));
+        }
+    }
+}
+
+fn execute(db: &mut Database, command: &str) {
+    if let Response::Error(e) = executor::execute(db, command) {
+        panic!("'{}' failed: {}", command, e);
+    }
+}
+
+/// Start the writer (`crash_test_writer`, in this test binary) on a fresh directory, kill it
+/// (SIGKILL) `delay` after it prints `target`, so that it dies writing out the table file or the
+/// WAL or soon after, and return how many rows it had acknowledged by then.
+fn write_and_kill(dir: &Path, target: &str, delay: Duration) -> usize {
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let mut writer = Command::new(std::env::current_exe().unwrap())
+        .args(["crash_test_writer", "--exact", "--ignored", "--nocapture"])
+        .env(WRITER_DIR, dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("cannot start the writer");
+    let output = writer.stdout.take().expect("the writer's output is piped");
+    let acked = Arc::new(AtomicUsize::new(0));
+    let reported = Arc::clone(&acked);
+    let (reached_target, target_reached) = mpsc::channel();
+    let awaited = target.to_string();
+    let reader = thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(|line| line.ok()) {
+            if let Some(rows) = line.strip_prefix("acked ").and_then(|rows| rows.parse().ok()) {
+                reported.store(rows, Ordering::SeqCst);
+            }
+            if line == awaited {
+                let _ = reached_target.send(());
+            }
+        }
+    });
+    let reached = target_reached.recv_timeout(Duration::from_secs(30));
+    thread::sleep(delay);
+    writer.kill().expect("cannot kill the writer");
+    let _ = writer.wait();
+    let _ = reader.join();
+    assert!(reached.is_ok(), "the writer never got to '{}'", target);
+    acked.load(Ordering::SeqCst)
+}
+
+/// Recover `dir` as `testing verify --repair` and a restart would, and check that nothing is
+/// left to repair and every row acknowledged is still there, whether a checkpoint saved it or
+/// only the WAL holds it.
+fn recover(dir: &Path, acked: usize) {
+    let dir = dir.display().to_string();
+    let mut db = common::open(&dir);
+    let issues = db.verify(true).unwrap();
+    let unrepaired: Vec<String> = issues.iter().filter(|issue| issue.repaired.is_none()).map(|issue| issue.to_json().to_string()).collect();
+    assert!(unrepaired.is_empty(), "cannot repair {}", unrepaired.join(", "));
+    assert!(db.verify(false).unwrap().is_empty(), "problems left after repairing");
+    db.load_wal().expect("cannot replay the WAL");
+    db.ensure_table_loaded(TABLE).expect("the table is gone");
+    let table = db.get_table(TABLE).unwrap();
+    if let Some(lost) = (0..acked).find(|row| table.get_row(&row.to_string()).is_none()) {
+        panic!("row {} was acknowledged but is lost ({} rows acknowledged)", lost, acked);
+    }
+}
+
+#[test]
+fn killed_while_writing_loses_no_acknowledged_row() {
+    let root = std::env::temp_dir().join(format!("rustdb-crash-recovery-{}", std::process::id()));
+    for round in 1..=ROUNDS {
+        let dir = root.join(format!("round-{}", round));
+        // Kill at once, or a little into the checkpoint, further in each round.
+        let delay = Duration::from_micros(((round - 1) * 25) as u64);
+        let acked = write_and_kill(&dir, &format!("checkpoint {}", round * CHECKPOINT_EVERY), delay);
+        assert!(acked >= round * CHECKPOINT_EVERY, "round {}: only {} rows acknowledged", round, acked);
+        recover(&dir, acked);
+        // Between checkpoints the rows since the last one are only in the WAL.
+        let since = round * 3 % CHECKPOINT_EVERY + 1;
+        let acked = write_and_kill(&dir, &format!("acked {}", round * CHECKPOINT_EVERY + since), delay);
+        assert!(acked > round * CHECKPOINT_EVERY, "round {}: only {} rows acknowledged", round, acked);
+        recover(&dir, acked);
+    }
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use testing::commands::executor::{self, Response};
+use testing::storage::encryption;
+
+/// Values that start like a sealed line (`aes:`) or an escaped one (`plain:`).
+const LOOKALIKES: [&str; 3] = ["aes:1", "plain:2", "plain:aes:3"];
+
+/// Write the lookalikes as row ids and values of a fresh table in `dir`, and save it.
+fn write_lookalikes(dir: &str) {
+    let mut db = common::open(dir);
+    for command in ["CREATE TABLE t".to_string(), "ADD COLUMN t v".to_string()].into_iter().chain(LOOKALIKES.map(|value| format!("INSERT t {} v={}", value, value))) {
+        assert!(!matches!(executor::execute(&mut db, &command), Response::Error(_)), "'{}' failed", command);
+    }
+    db.checkpoint().and_then(|_| db.commit_wal()).unwrap();
+}
+
+/// Check that the table in `dir` reads back as `write_lookalikes` wrote it.
+fn check_lookalikes(dir: &str) {
+    let mut db = common::open(dir);
+    db.load_wal().unwrap();
+    db.ensure_table_loaded("t").unwrap();
+    let table = db.get_table("t").unwrap();
+    assert_eq!(table.rows.len(), LOOKALIKES.len());
+    for value in LOOKALIKES {
+        let row = table.get_row(value).unwrap_or_else(|| panic!("row '{}' is missing", value));
+        assert_eq!(row.get("v").map(|v| v.to_string()).as_deref(), Some(value));
+    }
+}
+
+// The key is installed for the whole process, so the unsealed and sealed halves run in order.
+#[test]
+fn lines_that_look_sealed_round_trip_with_and_without_a_key() {
+    let plain = common::fresh_dir("encryption-plain");
+    write_lookalikes(&plain);
+    check_lookalikes(&plain);
+
+    encryption::install(&[[7; 32]]);
+    // Written unsealed, still read as written once a key is set.
+    check_lookalikes(&plain);
+    let sealed = common::fresh_dir("encryption-sealed");
+    write_lookalikes(&sealed);
+    let file = std::fs::read_to_string(format!("{}/t.csv", sealed)).unwrap();
+    assert!(file.lines().all(|line| line.starts_with("aes:")), "not every line is sealed:\n{}", file);
+    check_lookalikes(&sealed);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use std::collections::HashMap;
+use testing::storage::lsm::LsmStorage;
+use testing::storage::StorageEngine;
+use testing::table::table::Table;
+
+/// A table with one text column `v` holding each row's id.
+fn table_of(row_ids: &[&str]) -> Table {
+    let mut table = Table::new();
+    table.declare_column("v").unwrap();
+    for row_id in row_ids {
+        table.insert_row(row_id, HashMap::from([("v".to_string(), row_id.to_string())])).unwrap();
+    }
+    table
+}
+
+/// The row ids of a stored table, checking that every row still holds its own id.
+fn row_ids(storage: &LsmStorage, table_name: &str) -> Vec<String> {
+    let table = storage.load_table(table_name).unwrap().unwrap();
+    let mut row_ids = Vec::new();
+    for (row_id, row) in &table.rows {
+        assert_eq!(row.get("v").map(|v| v.to_string()).as_deref(), Some(row_id.as_str()));
+        row_ids.push(row_id.clone());
+    }
+    row_ids
+}
+
+#[test]
+fn keys_keep_tables_and_row_ids_apart() {
+    let dir = common::fresh_dir("lsm-keys");
+    let mut ids = ["a:b", "c/d", "50%", "%3A", "plain"];
+    ids.sort();
+    {
+        let mut storage = LsmStorage::new(&dir);
+        storage.save_table("t", &table_of(&ids)).unwrap();
+        // Its rows must not show up among those of `t`.
+        storage.save_table("t/x", &table_of(&["other"])).unwrap();
+        assert_eq!(row_ids(&storage, "t"), ids);
+        assert_eq!(row_ids(&storage, "t/x"), ["other"]);
+    }
+
+    // Reopened, the keys come back through the tree's `key:value` lines.
+    let mut storage = LsmStorage::new(&dir);
+    assert_eq!(storage.table_names().unwrap(), ["t", "t/x"]);
+    assert_eq!(row_ids(&storage, "t"), ids);
+    storage.drop_table("t").unwrap();
+    assert_eq!(storage.table_names().unwrap(), ["t/x"]);
+    assert_eq!(row_ids(&storage, "t/x"), ["other"]);
+}
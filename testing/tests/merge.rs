@@ -0,0 +1,37 @@
+mod common;
+
+use testing::commands::db::Database;
+use testing::commands::executor::{self, Response};
+use testing::table::merge::{Conflict, Resolution};
+
+fn run(db: &mut Database, command: &str) {
+    assert!(!matches!(executor::execute(db, command), Response::Error(_)), "'{}' failed", command);
+}
+
+#[test]
+fn a_resolver_settles_conflicts_and_other_changes_merge_in() {
+    let dir = common::fresh_dir("merge-resolver");
+    let (base, theirs) = (format!("{}/base.csv", dir), format!("{}/theirs.csv", dir));
+    let mut db = common::open(&dir);
+    for command in ["CREATE TABLE t", "ADD COLUMN t email", "ADD COLUMN t name", "INSERT t 1 email=a@example.com name=Ada"] {
+        run(&mut db, command);
+    }
+    db.save_table("t", &base).unwrap();
+    // Their copy changes both cells; ours only the email, differently.
+    run(&mut db, "UPDATE t 1 email theirs@example.com");
+    run(&mut db, "UPDATE t 1 name Ada_Lovelace");
+    db.save_table("t", &theirs).unwrap();
+    run(&mut db, "UPDATE t 1 email ours@example.com");
+    run(&mut db, "UPDATE t 1 name Ada");
+
+    let keep_our_emails = |conflict: &Conflict| match conflict.column.as_deref() {
+        Some("email") => Resolution::Ours,
+        _ => Resolution::Theirs,
+    };
+    let conflicts = db.merge_table_with("t", &theirs, Some(&base), keep_our_emails).unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!((conflicts[0].0.column.as_deref(), conflicts[0].1), (Some("email"), Resolution::Ours));
+    let row = db.get_table("t").unwrap().get_row("1").unwrap().clone();
+    assert_eq!(row.get("email").map(|v| v.to_string()).as_deref(), Some("ours@example.com"));
+    assert_eq!(row.get("name").map(|v| v.to_string()).as_deref(), Some("Ada_Lovelace"));
+}
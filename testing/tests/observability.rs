@@ -0,0 +1,179 @@
+mod common;
+
+use log::kv::{Key, VisitSource};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use testing::commands::changes::ChangeKind;
+use testing::commands::db::Database;
+use testing::commands::executor::{self, Response};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+fn ok(db: &mut Database, command: &str) -> Value {
+    match executor::execute(db, command) {
+        Response::Ok(value) => value,
+        other => panic!("'{}' failed: {:?}", command, other),
+    }
+}
+
+/// Log records as `(level, message, key-values)`.
+type Records = Vec<(log::Level, String, HashMap<String, String>)>;
+
+thread_local! {
+    // Each test reads only what its own thread logged.
+    static RECORDS: RefCell<Records> = const { RefCell::new(Vec::new()) };
+}
+
+struct Capture;
+
+impl log::Log for Capture {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        struct Fields(HashMap<String, String>);
+        impl<'kvs> VisitSource<'kvs> for Fields {
+            fn visit_pair(&mut self, key: Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+                self.0.insert(key.to_string(), value.to_string());
+                Ok(())
+            }
+        }
+        let mut fields = Fields(HashMap::new());
+        let _ = record.key_values().visit(&mut fields);
+        RECORDS.with(|records| records.borrow_mut().push((record.level(), record.args().to_string(), fields.0)));
+    }
+
+    fn flush(&self) {}
+}
+
+// Writes log at levels with the table and row as fields rather than print to stdout.
+#[test]
+fn writes_are_logged_with_structured_fields() {
+    let _ = log::set_boxed_logger(Box::new(Capture)).map(|_| log::set_max_level(log::LevelFilter::Trace));
+    let mut db = common::open(&common::fresh_dir("observability-log"));
+    for command in ["CREATE TABLE t", "ADD COLUMN t n int", "INSERT t 1 n=1"] {
+        ok(&mut db, command);
+    }
+    let records = RECORDS.with(|records| records.take());
+    let insert = records
+        .iter()
+        .find(|(_, _, fields)| fields.get("row_id").map(String::as_str) == Some("1"))
+        .unwrap_or_else(|| panic!("no record of the insert in {:?}", records));
+    assert_eq!(insert.2.get("table").map(String::as_str), Some("t"));
+    assert!(insert.0 >= log::Level::Info, "a routine write logged at {}", insert.0);
+}
+
+/// A span opened: its name and fields.
+type Opened = (String, HashMap<String, String>);
+
+/// Records the spans opened.
+#[derive(Clone, Default)]
+struct Spans {
+    opened: Arc<Mutex<Vec<Opened>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+struct Fields<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value).trim_matches('"').to_string());
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = HashMap::new();
+        span.record(&mut Fields(&mut fields));
+        self.opened.lock().unwrap().push((span.metadata().name().to_string(), fields));
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn operations_open_spans_down_to_the_storage() {
+    let spans = Spans::default();
+    let mut db = common::open(&common::fresh_dir("observability-spans"));
+    tracing::subscriber::with_default(spans.clone(), || {
+        for command in ["CREATE TABLE t", "ADD COLUMN t n int", "INSERT t 1 n=1", "INSERT t 2 n=2"] {
+            ok(&mut db, command);
+        }
+        db.checkpoint().unwrap();
+    });
+    let opened = spans.opened.lock().unwrap();
+    let span = |name: &str, field: &str, value: &str| opened.iter().any(|(n, fields)| n == name && fields.get(field).map(String::as_str) == Some(value));
+    assert!(span("create_table", "table", "t"));
+    assert!(span("insert_row", "row_id", "2"));
+    assert!(opened.iter().any(|(name, _)| name == "checkpoint"));
+    assert!(span("storage.save", "rows", "2"), "no span for saving the table: {:?}", opened);
+}
+
+#[test]
+fn status_reports_tables_rows_and_the_wal_backlog() {
+    let mut db = common::open(&common::fresh_dir("observability-status"));
+    for command in ["CREATE TABLE a", "INSERT a 1 n=1", "INSERT a 2 n=2", "CREATE TABLE b"] {
+        ok(&mut db, command);
+    }
+    let status = ok(&mut db, "STATUS");
+    assert_eq!((status["healthy"].clone(), status["tables"].clone(), status["rows"].clone()), (json!(true), json!(2), json!(2)));
+    assert_eq!(status["wal_backlog"], json!(4));
+    assert_eq!(status["last_checkpoint"], Value::Null);
+    db.checkpoint().unwrap();
+    db.commit_wal().unwrap();
+    let status = ok(&mut db, "STATUS");
+    assert_eq!(status["wal_backlog"], json!(0));
+    assert!(status["last_checkpoint"].is_string() && status["disk_bytes"].as_u64() > Some(0));
+}
+
+// Hooks see a change once it is committed, not as it is made, and only for their table.
+#[test]
+fn hooks_fire_on_committed_changes_of_their_table() {
+    let mut db = common::open(&common::fresh_dir("observability-hooks"));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let hooked = Arc::clone(&seen);
+    db.on_change("t", move |event| hooked.lock().unwrap().push(event.kind.clone()));
+    for command in ["CREATE TABLE t", "CREATE TABLE u", "ADD COLUMN t n int", "INSERT t 1 n=1", "INSERT u 1 n=1", "DELETE t 1"] {
+        ok(&mut db, command);
+    }
+    assert!(seen.lock().unwrap().is_empty(), "a hook ran before the commit");
+    db.commit_wal().unwrap();
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 4, "{:?}", seen);
+    assert_eq!(seen[0], ChangeKind::CreateTable);
+    assert!(matches!(&seen[2], ChangeKind::Insert { row_id, data } if row_id == "1" && data["n"] == "1"));
+    assert_eq!(seen[3], ChangeKind::Delete { row_id: "1".to_string() });
+}
+
+// BENCH runs on a scratch database, so the one in use is left as it was.
+#[test]
+fn bench_reports_the_workload_it_ran() {
+    let mut db = common::open(&common::fresh_dir("observability-bench"));
+    let report = ok(&mut db, "BENCH 200 ROWS 50 MIX get=3,insert=1");
+    assert_eq!(report["total"]["count"], json!(200));
+    let mut operations: Vec<&String> = report["by_operation"].as_object().unwrap().keys().collect();
+    operations.sort();
+    assert_eq!(operations, ["get", "insert"]);
+    let p50 = report["total"]["p50_micros"].as_u64().unwrap();
+    assert!(p50 <= report["total"]["p99_micros"].as_u64().unwrap());
+    assert!(db.table_names().unwrap().is_empty(), "the benchmark left a table behind");
+    assert_eq!(db.wal_pending().0, 0, "the benchmark wrote to the WAL in use");
+}
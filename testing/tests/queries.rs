@@ -0,0 +1,135 @@
+mod common;
+
+use serde_json::{json, Value};
+use testing::commands::db::Database;
+use testing::commands::executor::{self, Response};
+
+fn ok(db: &mut Database, command: &str) -> Value {
+    match executor::execute(db, command) {
+        Response::Ok(value) => value,
+        other => panic!("'{}' failed: {:?}", command, other),
+    }
+}
+
+/// A table `t` of `rows` rows: row `i` has `n` = i, `name` = `name<i % 3>` and, on even rows
+/// only, `tag` = `tag<i>`.
+fn table(name: &str, rows: usize) -> Database {
+    let mut db = common::open(&common::fresh_dir(name));
+    for command in ["CREATE TABLE t", "ADD COLUMN t n int", "ADD COLUMN t name", "ADD COLUMN t tag"] {
+        ok(&mut db, command);
+    }
+    for i in 0..rows {
+        let tag = if i % 2 == 0 { format!(" tag=tag{}", i) } else { String::new() };
+        ok(&mut db, &format!("INSERT t {} n={} name=name{}{}", i, i, i % 3, tag));
+    }
+    db
+}
+
+fn row_ids(rows: &Value) -> Vec<&str> {
+    rows.as_array().unwrap().iter().map(|row| row["row_id"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn pages_cover_the_rows_once_in_order() {
+    let mut db = table("queries-page", 7);
+    let mut seen = Vec::new();
+    let mut command = "PAGE t 3".to_string();
+    loop {
+        let page = ok(&mut db, &command);
+        seen.extend(row_ids(&page["rows"]).into_iter().map(String::from));
+        match page["next"].as_str() {
+            Some(cursor) => command = format!("PAGE t 3 AFTER {}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["0", "1", "2", "3", "4", "5", "6"]);
+    let filtered = ok(&mut db, "PAGE t 10 WHERE n >= 5");
+    assert_eq!(row_ids(&filtered["rows"]), ["5", "6"]);
+}
+
+#[test]
+fn distinct_counts_each_value_once() {
+    let mut db = table("queries-distinct", 5);
+    let counts = ok(&mut db, "DISTINCT t name COUNTS");
+    assert_eq!(counts, json!([{"value": "name0", "count": 2}, {"value": "name1", "count": 2}, {"value": "name2", "count": 1}]));
+    assert_eq!(ok(&mut db, "DISTINCT t tag").as_array().unwrap().len(), 4, "NULL is one distinct value");
+}
+
+#[test]
+fn like_and_regex_match_text() {
+    let mut db = table("queries-like", 12);
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH t tag LIKE tag1_")), ["10"]);
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH t tag ILIKE TAG1%")), ["10"]);
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH t tag ~ ^tag[48]$")), ["4", "8"]);
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH t name ~* ^NAME2")), ["11", "2", "5", "8"]);
+}
+
+#[test]
+fn get_returns_only_the_columns_asked_for() {
+    let mut db = table("queries-projection", 1);
+    assert_eq!(ok(&mut db, "GET t 0 COLUMNS n,tag"), json!({"row_id": "0", "data": {"n": 0, "tag": "tag0"}}));
+}
+
+#[test]
+fn stats_count_rows_and_values() {
+    let mut db = table("queries-stats", 4);
+    let stats = ok(&mut db, "STATS t");
+    assert_eq!(stats["rows"], json!(4));
+    assert_eq!(stats["columns"]["tag"], json!({"non_null": 2, "cardinality": 2}));
+    assert_eq!(stats["columns"]["name"], json!({"non_null": 4, "cardinality": 3}));
+}
+
+#[test]
+fn a_view_reads_the_table_as_it_is_now() {
+    let mut db = table("queries-view", 4);
+    ok(&mut db, "CREATE VIEW even AS t WHERE tag IS NOT NULL COLUMNS n");
+    assert_eq!(ok(&mut db, "PRINT even")["total"], json!(2));
+    ok(&mut db, "INSERT t 4 n=4 tag=tag4");
+    assert_eq!(ok(&mut db, "GET even 4"), json!({"row_id": "4", "data": {"n": 4}}));
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH even n > 1")), ["2", "4"]);
+}
+
+// Numbers compare as numbers, not as text: 10 > 9.
+#[test]
+fn conditions_compare_by_the_column_type() {
+    let mut db = table("queries-typed", 12);
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH t n > 9")), ["10", "11"]);
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH t n == 07")), ["7"]);
+    assert_eq!(row_ids(&ok(&mut db, "SEARCH t tag < tag2")), ["0", "10"]);
+}
+
+#[test]
+fn explain_analyze_reports_the_search_it_ran() {
+    let mut db = table("queries-explain", 10);
+    let report = ok(&mut db, "EXPLAIN ANALYZE SEARCH t n >= 7");
+    assert_eq!(report["access_path"], json!("full scan"));
+    assert_eq!(report["rows_scanned"], json!(10));
+    assert_eq!(report["rows_matched"], json!(3));
+    assert_eq!(report["estimated_rows"], Value::Null, "an estimate without statistics");
+    let stages: Vec<&str> = report["stages"].as_array().unwrap().iter().map(|stage| stage["stage"].as_str().unwrap()).collect();
+    assert_eq!(stages, ["plan", "scan", "fetch", "render"]);
+}
+
+#[test]
+fn analyze_gives_explain_its_estimates() {
+    let mut db = table("queries-analyze", 100);
+    let columns = ok(&mut db, "ANALYZE t");
+    let n = columns.as_array().unwrap().iter().find(|column| column["column"] == "n").expect("no statistics for n");
+    assert_eq!((n["rows"].clone(), n["nulls"].clone(), n["distinct"].clone()), (json!(100), json!(0), json!(100)));
+    assert!(!n["histogram"].as_array().unwrap().is_empty(), "no histogram");
+    let estimate = ok(&mut db, "EXPLAIN ANALYZE SEARCH t n >= 75")["estimated_rows"].as_u64().expect("no estimate");
+    assert!((15..=35).contains(&estimate), "estimated {} rows of 25", estimate);
+}
+
+// Scanning in parallel changes nothing but the speed.
+#[test]
+fn parallel_and_serial_scans_agree() {
+    let mut db = table("queries-parallel", 500);
+    let commands = ["SEARCH t n >= 250", "SEARCH t tag LIKE tag1%", "DISTINCT t name COUNTS", "STATS t", "DELETE t WHERE n < 10"];
+    let mut serial = table("queries-serial", 500);
+    db.parallel_scan = Some(1);
+    serial.parallel_scan = None;
+    for command in commands {
+        assert_eq!(ok(&mut db, command), ok(&mut serial, command), "'{}' differs in parallel", command);
+    }
+}
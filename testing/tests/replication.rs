@@ -0,0 +1,49 @@
+mod common;
+
+use serde_json::Value;
+use std::sync::{Arc, RwLock};
+use testing::commands::executor::Response;
+use testing::commands::walengine::WalEngineConfig;
+use testing::replication::{Primary, ShippingMetrics};
+use testing::session::{Catalog, Session};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+fn run(session: &mut Session, line: &str) {
+    let response = session.handle(line);
+    assert!(!matches!(response, Response::Error(_)), "'{}' failed: {:?}", line, response);
+}
+
+/// Send `lines` to the replication port at `addr` and return the first message back.
+async fn first_message(addr: &str, lines: &str) -> Value {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(lines.as_bytes()).await.unwrap();
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).await.unwrap();
+    serde_json::from_str(&reply).unwrap()
+}
+
+// Replicas are sent every table, the accounts included, so once there are accounts only an admin
+// may replicate.
+#[tokio::test(flavor = "multi_thread")]
+async fn only_an_admin_may_replicate_once_there_are_accounts() {
+    let dir = common::fresh_dir("replication-login");
+    let db = Arc::new(RwLock::new(common::open(&dir)));
+    let mut session = Session::new(Arc::new(Catalog::new(Arc::clone(&db), WalEngineConfig::default()).unwrap()));
+    run(&mut session, "CREATE USER ada PASSWORD secret");
+    run(&mut session, "LOGIN ada secret");
+    run(&mut session, "CREATE USER bob PASSWORD hunter2");
+    run(&mut session, "CREATE TABLE notes");
+
+    let primary = Primary::bind("127.0.0.1:0", db, Arc::new(ShippingMetrics::default())).await.unwrap();
+    let addr = primary.local_addr().unwrap().to_string();
+    tokio::spawn(primary.run());
+
+    for lines in ["REPLICATE 0\n", "LOGIN ada wrong\nREPLICATE 0\n", "LOGIN bob hunter2\nREPLICATE 0\n"] {
+        let message = first_message(&addr, lines).await;
+        assert_eq!(message["type"], "error", "{:?} was let in", lines);
+    }
+    let message = first_message(&addr, "LOGIN ada secret\nREPLICATE 0\n").await;
+    assert_eq!(message["type"], "snapshot");
+    assert!(message["tables"]["notes"].is_object());
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::collections::HashMap;
+use testing::commands::db::{Database, DatabaseError, Result};
+use testing::storage::csv::CsvStorage;
+use testing::storage::StorageEngine;
+use testing::table::table::Table;
+
+/// CSV storage that fails to save tables whose name ends with `failing`.
+struct FailingStorage {
+    csv: CsvStorage,
+    failing: &'static str,
+}
+
+impl StorageEngine for FailingStorage {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    fn load_table(&self, table_name: &str) -> Result<Option<Table>> {
+        self.csv.load_table(table_name)
+    }
+
+    fn table_names(&self) -> Result<Vec<String>> {
+        self.csv.table_names()
+    }
+
+    fn save_table(&mut self, table_name: &str, table: &Table) -> Result<()> {
+        if table_name.ends_with(self.failing) {
+            return Err(DatabaseError::StorageError(table_name.to_string(), "disk full".to_string()));
+        }
+        self.csv.save_table(table_name, table)
+    }
+
+    fn rename_table(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.csv.rename_table(old_name, new_name)
+    }
+
+    fn drop_table(&mut self, table_name: &str) -> Result<()> {
+        self.csv.drop_table(table_name)
+    }
+
+    fn stored_size(&self, table_name: &str) -> Result<Option<u64>> {
+        self.csv.stored_size(table_name)
+    }
+}
+
+fn row(value: &str) -> HashMap<String, String> {
+    HashMap::from([("n".to_string(), value.to_string())])
+}
+
+// Restoring replaces every table, so failing to write one of them must leave all of them as they
+// were, not some restored and some not.
+#[test]
+fn a_restore_that_fails_part_way_changes_nothing() {
+    let source = common::fresh_dir("restore-source");
+    let mut db = common::open(&source);
+    for (table, value) in [("a", "restored"), ("b", "restored")] {
+        db.create_table(table).unwrap();
+        db.add_column(table, "n", None).unwrap();
+        db.insert_row(table, "1", row(value)).unwrap();
+    }
+    let archive = format!("{}/backup.tar", source);
+    db.dump(&archive).unwrap();
+
+    let dir = common::fresh_dir("restore-target");
+    let mut db = Database::with_storage(Box::new(FailingStorage { csv: CsvStorage::in_dir(&dir).unwrap(), failing: "b" }));
+    db.wal_file = format!("{}/wal.log", dir);
+    db.wal_archive_file = format!("{}/wal_archive.log", dir);
+    for table in ["a", "c"] {
+        db.create_table(table).unwrap();
+        db.add_column(table, "n", None).unwrap();
+        db.insert_row(table, "1", row("old")).unwrap();
+        db.persist_table(table).unwrap();
+    }
+    assert!(db.restore(&archive).is_err(), "storing 'b' did not fail");
+
+    let mut stored = common::open(&dir);
+    assert_eq!(stored.table_names().unwrap().into_iter().collect::<Vec<_>>(), ["a", "c"]);
+    stored.ensure_table_loaded("a").unwrap();
+    let a = stored.get_table("a").unwrap();
+    assert_eq!(a.get_row("1").unwrap().get("n").map(|value| value.to_string()).as_deref(), Some("old"), "'a' was restored");
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use serde_json::{json, Value};
+use testing::commands::db::Database;
+use testing::commands::executor::{self, Response};
+use testing::table::value;
+
+fn ok(db: &mut Database, command: &str) -> Value {
+    match executor::execute(db, command) {
+        Response::Ok(value) => value,
+        other => panic!("'{}' failed: {:?}", command, other),
+    }
+}
+
+fn error(db: &mut Database, command: &str) -> String {
+    match executor::execute(db, command) {
+        Response::Error(e) => e,
+        other => panic!("'{}' should have failed: {:?}", command, other),
+    }
+}
+
+fn table(name: &str, commands: &[&str]) -> Database {
+    let mut db = common::open(&common::fresh_dir(name));
+    for command in ["CREATE TABLE t", "ADD COLUMN t n int", "ADD COLUMN t name"].iter().chain(commands) {
+        ok(&mut db, command);
+    }
+    db
+}
+
+// INSERT only adds, UPSERT merges into an existing row and REPLACE overwrites it whole.
+#[test]
+fn insert_upsert_and_replace_differ_on_existing_rows() {
+    let mut db = table("rows-upsert", &["INSERT t 1 n=1 name=ann"]);
+    assert!(error(&mut db, "INSERT t 1 n=2").contains("already exists"));
+    assert_eq!(ok(&mut db, "UPSERT t 1 n=5")["data"], json!({"n": 5, "name": "ann"}));
+    assert_eq!(ok(&mut db, "REPLACE t 1 n=7")["data"], json!({"n": 7, "name": null}));
+    assert_eq!(ok(&mut db, "UPSERT t 2 name=bob")["data"], json!({"n": null, "name": "bob"}));
+}
+
+#[test]
+fn insert_returns_the_stored_row() {
+    let mut db = table("rows-returning", &["ALTER COLUMN t n TYPE int", "ADD COLUMN t kind DEFAULT plain"]);
+    assert_eq!(ok(&mut db, "INSERT t 1 n=01"), json!({"row_id": "1", "data": {"n": 1, "name": null, "kind": "plain"}}));
+}
+
+#[test]
+fn rows_past_their_ttl_are_deleted() {
+    let mut db = table("rows-ttl", &["INSERT t 1 n=1 TTL 60", "INSERT t 2 n=2", "INSERT t 3 n=3"]);
+    ok(&mut db, "EXPIRE t 3 120");
+    assert_eq!(ok(&mut db, "GET t 1")["data"]["n"], json!(1), "the row expired early");
+    assert_eq!(db.delete_expired_rows(value::now() + 90), 1);
+    assert!(error(&mut db, "GET t 1").starts_with("Row '1'"));
+    assert_eq!(db.delete_expired_rows(value::now() + 150), 1);
+    assert_eq!(ok(&mut db, "GET t 2")["data"]["n"], json!(2), "a row without a TTL expired");
+}
+
+#[test]
+fn soft_deleted_rows_are_hidden_until_restored_or_purged() {
+    let mut db = table("rows-soft-delete", &["INSERT t 1 n=1", "INSERT t 2 n=2"]);
+    ok(&mut db, "DELETE t 1 SOFT");
+    assert_eq!(ok(&mut db, "PRINT t")["total"], json!(1));
+    assert!(error(&mut db, "GET t 1").starts_with("Row '1'"));
+    assert_eq!(ok(&mut db, "RESTORE t 1")["data"]["n"], json!(1));
+    assert_eq!(ok(&mut db, "PRINT t")["total"], json!(2));
+
+    ok(&mut db, "DELETE t 2 SOFT");
+    assert_eq!(ok(&mut db, "PURGE t"), json!(1));
+    assert!(error(&mut db, "RESTORE t 2").contains("not found"), "a purged row came back");
+}
+
+#[test]
+fn one_update_sets_several_columns_or_none() {
+    let mut db = table("rows-multi-update", &["INSERT t 1 n=1 name=ann"]);
+    assert_eq!(ok(&mut db, "UPDATE t 1 n=2 name=anna")["data"], json!({"n": 2, "name": "anna"}));
+    error(&mut db, "UPDATE t 1 name=bob n=two");
+    assert_eq!(ok(&mut db, "GET t 1")["data"], json!({"n": 2, "name": "anna"}), "a failed update was half applied");
+}
+
+#[test]
+fn increment_and_decrement_change_numbers_only() {
+    let mut db = table("rows-increment", &["INSERT t 1 n=10 name=ann"]);
+    assert_eq!(ok(&mut db, "INCREMENT t 1 n 5")["data"]["n"], json!(15));
+    assert_eq!(ok(&mut db, "DECREMENT t 1 n")["data"]["n"], json!(14));
+    error(&mut db, "INCREMENT t 1 name");
+    assert_eq!(ok(&mut db, "GET t 1")["data"]["name"], json!("ann"));
+}
+
+#[test]
+fn a_conditional_update_applies_only_on_the_expected_value() {
+    let mut db = table("rows-cas", &["INSERT t 1 n=6"]);
+    assert_eq!(ok(&mut db, "UPDATE t 1 n 9 IF 6"), json!(true));
+    assert_eq!(ok(&mut db, "UPDATE t 1 n 11 IF 6"), json!(false));
+    assert_eq!(ok(&mut db, "GET t 1")["data"]["n"], json!(9));
+}
+
+// Rows are updated in place: the other cells stay, the primary key index follows the new key, and
+// a version of the table read before the update does not change.
+#[test]
+fn an_update_in_place_keeps_the_index_and_earlier_snapshots_right() {
+    let mut db = table("rows-in-place", &["ADD COLUMN t code text PRIMARY KEY", "INSERT t 1 n=1 name=ann code=a"]);
+    let before = db.get_table("t").unwrap();
+    ok(&mut db, "UPDATE t 1 code b");
+    assert_eq!(ok(&mut db, "LOOKUP t b")["data"], json!({"n": 1, "name": "ann", "code": "b"}));
+    error(&mut db, "LOOKUP t a");
+    assert_eq!(before.get_row("1").unwrap()["code"].to_string(), "a", "the earlier snapshot changed");
+}
+
+#[test]
+fn get_row_returns_the_typed_values() {
+    let mut db = table("rows-get", &["INSERT t 1 n=7 name=ann", "INSERT t 2 n=8", "DELETE t 2 SOFT"]);
+    let row = db.get_row("t", "1").unwrap();
+    assert_eq!(row.get("n"), Some(&value::Value::Int(7)));
+    assert_eq!(row.get("name"), Some(&value::Value::Text("ann".to_string())));
+    assert!(db.get_row("t", "2").is_err(), "a soft-deleted row was returned");
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use std::sync::{Arc, RwLock};
+use testing::commands::executor::Response;
+use testing::commands::walengine::WalEngineConfig;
+use testing::server::Server;
+use testing::session::{Catalog, Session};
+use testing::sharding::{RemoteShard, Shard, ShardRouter};
+
+// The router's clients reach the nodes through it, so a node with accounts must still see each
+// client log in.
+#[tokio::test(flavor = "multi_thread")]
+async fn a_router_passes_logins_on_to_the_nodes() {
+    let dir = common::fresh_dir("sharding-login");
+    let catalog = Arc::new(Catalog::new(Arc::new(RwLock::new(common::open(&dir))), WalEngineConfig::default()).unwrap());
+    let mut admin = Session::new(Arc::clone(&catalog));
+    assert!(matches!(admin.handle("CREATE USER ada PASSWORD secret"), Response::Ok(_)));
+    let node = Server::bind("127.0.0.1:0", catalog).await.unwrap();
+    let addr = node.local_addr().unwrap().to_string();
+    tokio::spawn(node.run());
+
+    tokio::task::spawn_blocking(move || {
+        let shards: Vec<Box<dyn Shard>> = vec![Box::new(RemoteShard::with_replicas(&addr, &[]))];
+        let router = ShardRouter::new(shards).session();
+        assert!(matches!(router.execute("CREATE TABLE notes"), Response::Error(_)), "the node let a stranger in");
+        assert!(matches!(router.execute("LOGIN ada wrong"), Response::Error(_)), "a wrong password was taken");
+        assert!(matches!(router.execute("LOGIN ada secret"), Response::Ok(_)));
+        assert!(matches!(router.execute("CREATE TABLE notes"), Response::Ok(_)));
+        assert!(matches!(router.execute("LOGOUT"), Response::Ok(_)));
+        assert!(matches!(router.execute("INSERT notes 1 text=hello"), Response::Error(_)), "still logged in");
+    })
+    .await
+    .unwrap();
+}
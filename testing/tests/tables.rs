@@ -0,0 +1,162 @@
+mod common;
+
+use testing::commands::db::Database;
+use testing::commands::executor::{self, Response};
+
+fn run(db: &mut Database, command: &str) -> Response {
+    let response = executor::execute(db, command);
+    assert!(!matches!(response, Response::Error(_)), "'{}' failed: {:?}", command, response);
+    response
+}
+
+// A WAL cycle clears the WAL once the dirty tables are saved, so a renamed table's unsaved rows
+// must be saved under its new name by then.
+#[test]
+fn a_renamed_table_keeps_its_unsaved_rows_over_a_restart() {
+    let dir = common::fresh_dir("tables-rename");
+    let mut db = common::open(&dir);
+    db.save_threshold = usize::MAX;
+    for command in ["CREATE TABLE t", "ADD COLUMN t n int", "INSERT t 1 n=1", "INSERT t 2 n=2", "RENAME TABLE t u"] {
+        run(&mut db, command);
+    }
+    db.checkpoint().unwrap();
+    db.commit_wal().unwrap();
+    drop(db);
+
+    let mut restarted = common::open(&dir);
+    restarted.load_wal().unwrap();
+    restarted.ensure_table_loaded("u").unwrap();
+    assert_eq!(restarted.get_table("u").unwrap().rows.len(), 2, "rows were lost in the rename");
+    assert!(restarted.ensure_table_loaded("t").is_err(), "the old name is still stored");
+}
+
+// Tables evicted to make room are still there, so they are still listed.
+#[test]
+fn tables_lists_evicted_tables() {
+    let dir = common::fresh_dir("tables-evicted");
+    let mut db = common::open(&dir);
+    db.table_cache = Some(1);
+    for command in ["CREATE TABLE a", "INSERT a 1 n=1", "CREATE TABLE b", "INSERT b 1 n=1"] {
+        run(&mut db, command);
+    }
+    assert!(!db.check_table("a"), "'a' was not evicted");
+    match run(&mut db, "TABLES") {
+        Response::Ok(names) => assert_eq!(names, serde_json::json!(["a", "b"])),
+        other => panic!("unexpected response {:?}", other),
+    }
+}
+
+fn ok(db: &mut Database, command: &str) -> serde_json::Value {
+    match run(db, command) {
+        Response::Ok(value) => value,
+        other => panic!("unexpected response {:?}", other),
+    }
+}
+
+fn table(name: &str) -> Database {
+    let mut db = common::open(&common::fresh_dir(name));
+    for command in ["CREATE TABLE t", "ADD COLUMN t n int", "ADD COLUMN t name", "INSERT t 1 n=1 name=ann", "INSERT t 2 n=2 name=bob"] {
+        run(&mut db, command);
+    }
+    db
+}
+
+#[test]
+fn a_dropped_column_leaves_no_values_behind() {
+    let mut db = table("tables-drop-column");
+    run(&mut db, "DROP COLUMN t name");
+    let t = db.get_table("t").unwrap();
+    assert!(!t.columns.contains("name"));
+    assert!(t.rows.values().all(|row| !row.contains_key("name")), "values of the dropped column are left");
+    run(&mut db, "ADD COLUMN t name");
+    assert_eq!(ok(&mut db, "GET t 1")["data"]["name"], serde_json::Value::Null, "the old values came back");
+}
+
+#[test]
+fn a_renamed_column_keeps_its_values() {
+    let mut db = table("tables-rename-column");
+    run(&mut db, "RENAME COLUMN t name label");
+    assert_eq!(ok(&mut db, "GET t 1")["data"], serde_json::json!({"n": 1, "label": "ann"}));
+    assert!(matches!(executor::execute(&mut db, "RENAME COLUMN t n label"), Response::Error(_)), "renamed onto an existing column");
+}
+
+#[test]
+fn truncate_keeps_the_schema() {
+    let mut db = table("tables-truncate");
+    run(&mut db, "TRUNCATE TABLE t");
+    let t = db.get_table("t").unwrap();
+    assert!(t.rows.is_empty());
+    assert_eq!(t.columns.len(), 2, "the columns went with the rows");
+}
+
+#[test]
+fn altering_a_column_type_converts_its_values() {
+    let mut db = table("tables-alter-column");
+    run(&mut db, "INSERT t 3 n=3 name=7");
+    assert_eq!(ok(&mut db, "ALTER COLUMN t n TYPE text")["unconverted"], serde_json::json!([]));
+    assert_eq!(ok(&mut db, "GET t 1")["data"]["n"], serde_json::json!("1"));
+    let altered = ok(&mut db, "ALTER COLUMN t name TYPE int");
+    let unconverted = serde_json::json!([{"row_id": "1", "value": "ann"}, {"row_id": "2", "value": "bob"}]);
+    assert_eq!(altered["unconverted"], unconverted, "unconvertible values not reported");
+    assert_eq!(ok(&mut db, "GET t 3")["data"]["name"], serde_json::json!(7));
+}
+
+// The schema is stored apart from the rows, so an empty table keeps its columns and types.
+#[test]
+fn an_empty_table_keeps_its_schema_over_a_restart() {
+    let dir = common::fresh_dir("tables-schema");
+    let mut db = common::open(&dir);
+    for command in ["CREATE TABLE t", "ADD COLUMN t n int NOT NULL", "ADD COLUMN t name DEFAULT none"] {
+        run(&mut db, command);
+    }
+    db.checkpoint().unwrap();
+    db.commit_wal().unwrap();
+    drop(db);
+
+    let mut restarted = common::open(&dir);
+    restarted.ensure_table_loaded("t").unwrap();
+    let t = restarted.get_table("t").unwrap();
+    assert_eq!(t.columns.len(), 2);
+    assert!(t.not_null.contains("n"));
+    assert_eq!(ok(&mut restarted, "INSERT t 1 n=1")["data"], serde_json::json!({"n": 1, "name": "none"}));
+}
+
+#[test]
+fn copy_table_copies_the_matching_rows() {
+    let mut db = table("tables-copy");
+    assert_eq!(ok(&mut db, "COPY TABLE t u WHERE n == 2"), serde_json::json!(["u", "1"]));
+    run(&mut db, "UPDATE t 2 name=bert");
+    assert_eq!(ok(&mut db, "GET u 2")["data"], serde_json::json!({"n": 2, "name": "bob"}), "the copy shares rows with the original");
+    assert!(matches!(executor::execute(&mut db, "GET u 1"), Response::Error(_)));
+}
+
+#[test]
+fn delete_where_deletes_only_the_matching_rows() {
+    let mut db = table("tables-delete-where");
+    run(&mut db, "INSERT t 3 n=10 name=cy");
+    assert_eq!(ok(&mut db, "DELETE t WHERE n > 1"), serde_json::json!(2));
+    let t = db.get_table("t").unwrap();
+    assert_eq!(t.rows.keys().collect::<Vec<_>>(), ["1"]);
+}
+
+// Over the memory limit the least recently used tables spill to disk, unsaved rows and all, and
+// come back when next used.
+#[test]
+fn tables_over_the_memory_limit_spill_and_come_back() {
+    let dir = common::fresh_dir("tables-spill");
+    let mut db = common::open(&dir);
+    db.save_threshold = usize::MAX;
+    for table in ["a", "b", "c"] {
+        run(&mut db, &format!("CREATE TABLE {}", table));
+        for row in 0..50 {
+            run(&mut db, &format!("INSERT {} {} n={}", table, row, "x".repeat(100)));
+        }
+    }
+    let one_table = db.memory_bytes() / 3;
+    db.memory_limit = Some(one_table * 2);
+    assert!(db.over_memory_limit());
+    assert_eq!(db.evict_tables().unwrap(), ["a"]);
+    assert!(!db.over_memory_limit() && !db.check_table("a"));
+    assert_eq!(ok(&mut db, "PRINT a")["total"], serde_json::json!(50), "rows were lost in the spill");
+    assert!(db.check_table("a") && !db.check_table("b"), "loading 'a' did not spill the coldest table");
+}
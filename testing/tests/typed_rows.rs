@@ -0,0 +1,37 @@
+mod common;
+
+use serde::{Deserialize, Serialize};
+use testing::commands::db::DatabaseError;
+use testing::commands::executor::{self, Response};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct User {
+    name: String,
+    age: i64,
+    email: Option<String>,
+}
+
+#[test]
+fn structs_round_trip_through_rows() {
+    let mut db = common::open(&common::fresh_dir("typed-rows"));
+    db.create_table("users").unwrap();
+    for column in ["name text", "age int", "email text"] {
+        assert!(!matches!(executor::execute(&mut db, &format!("ADD COLUMN users {}", column)), Response::Error(_)));
+    }
+
+    let ada = User { name: "Ada".to_string(), age: 36, email: Some("ada@example.com".to_string()) };
+    let bob = User { name: "Bob".to_string(), age: 41, email: None };
+    db.insert_struct("users", "1", &ada).unwrap();
+    db.insert_struct("users", "2", &bob).unwrap();
+
+    assert_eq!(db.get_row_as::<User>("users", "1").unwrap(), ada);
+    assert_eq!(db.get_row_as::<User>("users", "2").unwrap(), bob);
+    assert!(matches!(db.get_row_as::<User>("users", "3"), Err(DatabaseError::RowDoesNotExist(..))));
+}
+
+#[test]
+fn only_objects_make_rows() {
+    let mut db = common::open(&common::fresh_dir("typed-rows-objects"));
+    db.create_table("numbers").unwrap();
+    assert!(matches!(db.insert_struct("numbers", "1", &42), Err(DatabaseError::RowConversionError(..))));
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use testing::commands::executor::{self, Response};
+use testing::commands::walengine::{Trigger, WalEngine, WalEngineConfig, WalEngineHandle};
+
+const ROWS: usize = 20;
+
+// A WAL cycle empties the WAL file, which is all a restart replays, so the tables must be saved
+// before it: a crash right after the cycle loses nothing.
+#[tokio::test(flavor = "multi_thread")]
+async fn a_crash_after_a_wal_cycle_loses_no_row() {
+    let dir = common::fresh_dir("wal-cycle");
+    let mut db = common::open(&dir);
+    // No saves on the way, so only the cycle can persist the rows.
+    db.save_threshold = usize::MAX;
+    for command in ["CREATE TABLE t".to_string(), "ADD COLUMN t n".to_string()].into_iter().chain((0..ROWS).map(|row| format!("INSERT t {} n={}", row, row))) {
+        assert!(!matches!(executor::execute(&mut db, &command), Response::Error(_)), "'{}' failed", command);
+    }
+    let db = Arc::new(RwLock::new(db));
+    let config = WalEngineConfig { max_entries: Some(0), ..WalEngineConfig::every(Duration::from_secs(3600)) };
+    let engine = WalEngine::new(Arc::clone(&db), config);
+    let metrics = db.read().unwrap().wal_engine.clone().unwrap();
+    // Never stopped, so it does not save on the way out either: the runtime just ends with the test.
+    let _handle = engine.start();
+    while metrics.last_cycle().is_none() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(std::fs::metadata(format!("{}/wal.log", dir)).unwrap().len(), 0, "the cycle left the WAL file");
+
+    let mut restarted = common::open(&dir);
+    restarted.load_wal().unwrap();
+    restarted.ensure_table_loaded("t").unwrap();
+    let table = restarted.get_table("t").unwrap();
+    assert!((0..ROWS).all(|row| table.get_row(&row.to_string()).is_some()), "rows were lost: {} of {} left", table.rows.len(), ROWS);
+}
+
+// A restart replays the WAL file onto tables that are only on disk, as they were saved before the
+// rows came in; values with ':' in them (times, URLs) must survive the replay, and the replayed
+// rows must be saved before the next WAL cycle empties the file.
+#[test]
+fn a_restart_replays_rows_never_saved() {
+    let dir = common::fresh_dir("wal-replay");
+    let mut db = common::open(&dir);
+    db.save_threshold = usize::MAX;
+    for command in ["CREATE TABLE t", "ADD COLUMN t at", "INSERT t 0 at=12:30", "INSERT t 1 at=https://example.com:8080/", "UPDATE t 0 at 13:45:10"] {
+        assert!(!matches!(executor::execute(&mut db, command), Response::Error(_)), "'{}' failed", command);
+    }
+    db.persist_wal().unwrap();
+    drop(db);
+
+    let mut restarted = common::open(&dir);
+    restarted.load_wal().unwrap();
+    let expected = [("0", "13:45:10"), ("1", "https://example.com:8080/")];
+    let check = |db: &testing::commands::db::Database| {
+        let table = db.get_table("t").expect("the table was not replayed");
+        for (row_id, at) in expected {
+            let row = table.get_row(row_id).unwrap_or_else(|| panic!("row {} was lost", row_id));
+            assert_eq!(row.get("at").map(|value| value.to_string()).as_deref(), Some(at), "row {}", row_id);
+        }
+    };
+    check(&restarted);
+    restarted.checkpoint().unwrap();
+    restarted.commit_wal().unwrap();
+    drop(restarted);
+
+    let mut again = common::open(&dir);
+    again.load_wal().unwrap();
+    again.ensure_table_loaded("t").unwrap();
+    check(&again);
+}
+
+fn insert(db: &RwLock<testing::commands::db::Database>, rows: std::ops::Range<usize>) {
+    let mut db = db.write().unwrap();
+    for row in rows {
+        let command = format!("INSERT t {} n={}", row, row);
+        assert!(!matches!(executor::execute(&mut db, &command), Response::Error(_)), "'{}' failed", command);
+    }
+}
+
+/// A database in `dir` with an empty table `t`, run by a WalEngine configured by `config`, once
+/// the engine's first cycle (which comes at once) is over.
+async fn engine_on(dir: &str, config: WalEngineConfig) -> (Arc<RwLock<testing::commands::db::Database>>, WalEngineHandle) {
+    let mut db = common::open(dir);
+    db.save_threshold = usize::MAX;
+    for command in ["CREATE TABLE t", "ADD COLUMN t n int"] {
+        assert!(!matches!(executor::execute(&mut db, command), Response::Error(_)), "'{}' failed", command);
+    }
+    let db = Arc::new(RwLock::new(db));
+    let handle = WalEngine::new(Arc::clone(&db), config).start();
+    let metrics = db.read().unwrap().wal_engine.clone().unwrap();
+    while metrics.last_cycle().is_none() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    (db, handle)
+}
+
+// Between cycles on time, one comes as soon as more entries wait than the policy allows.
+#[tokio::test(flavor = "multi_thread")]
+async fn a_backlog_over_the_limit_starts_a_cycle() {
+    let dir = common::fresh_dir("wal-policy");
+    let config = WalEngineConfig { max_entries: Some(5), ..WalEngineConfig::every(Duration::from_secs(3600)) };
+    let (db, _handle) = engine_on(&dir, config).await;
+    let metrics = db.read().unwrap().wal_engine.clone().unwrap();
+    let cycles = || metrics.report()["cycles"].as_u64().unwrap();
+    let before = cycles();
+    insert(&db, 0..5);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(cycles(), before, "a cycle came under the limit");
+    insert(&db, 5..6);
+    while cycles() == before {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let cycle = metrics.last_cycle().unwrap();
+    assert_eq!((cycle.trigger, cycle.entries), (Trigger::Entries, 6));
+    assert_eq!(db.read().unwrap().wal_pending().0, 0);
+}
+
+// Stopping the engine archives the WAL and saves the tables, so nothing is left to replay.
+#[tokio::test(flavor = "multi_thread")]
+async fn a_stopped_engine_leaves_everything_saved() {
+    let dir = common::fresh_dir("wal-stop");
+    let (db, handle) = engine_on(&dir, WalEngineConfig::every(Duration::from_secs(3600))).await;
+    insert(&db, 0..ROWS);
+    handle.stop();
+    handle.join().await;
+    let cycle = db.read().unwrap().wal_engine.clone().unwrap().last_cycle().unwrap();
+    assert_eq!(cycle.trigger, Trigger::Shutdown);
+    assert!(db.read().unwrap().dirty.lock().is_empty(), "tables were left unsaved");
+    assert_eq!(std::fs::metadata(format!("{}/wal.log", dir)).map_or(0, |file| file.len()), 0, "the WAL file was left");
+
+    let mut restarted = common::open(&dir);
+    restarted.ensure_table_loaded("t").unwrap();
+    assert_eq!(restarted.get_table("t").unwrap().rows.len(), ROWS);
+}
+
+// A cycle that fails shows in the metrics until one succeeds.
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_cycles_are_reported() {
+    let dir = common::fresh_dir("wal-metrics");
+    let config = WalEngineConfig { max_entries: Some(0), ..WalEngineConfig::every(Duration::from_millis(50)) };
+    let (db, _handle) = engine_on(&dir, config).await;
+    let metrics = db.read().unwrap().wal_engine.clone().unwrap();
+    let archive = db.read().unwrap().wal_archive_file.clone();
+    db.write().unwrap().wal_archive_file = format!("{}/missing/wal_archive.log", dir);
+    insert(&db, 0..1);
+    while metrics.consecutive_failures() == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert!(metrics.last_cycle().unwrap().error.is_some());
+    assert!(!metrics.is_healthy());
+    assert_eq!(metrics.report()["consecutive_failures"], serde_json::json!(metrics.consecutive_failures()));
+
+    db.write().unwrap().wal_archive_file = archive;
+    while metrics.consecutive_failures() > 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert!(metrics.is_healthy());
+    assert_eq!(db.read().unwrap().wal_pending().0, 0, "the failed cycle's entries were not retried");
+}